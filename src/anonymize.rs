@@ -0,0 +1,539 @@
+//! Redaction of identifying strings in STDF files
+//!
+//! An `Anonymizer` rewrites an STDF file with selected string fields of the `MIR`, `SDR`,
+//! `WIR`/`WRR` and `PRR` replaced, optionally along with the test names and the free text of the
+//! `DTR`s and `GDR`s. Every other byte of the file, including all of the numeric data, is left
+//! untouched.
+//!
+//! Fields are replaced either by a fixed token or by a pseudonym, the first 8 bytes of the
+//! HMAC-SHA256 of the original value under a secret key, in hex. The pseudonyms only depend on
+//! the key and the value, so the same value always maps to the same pseudonym, across fields and
+//! across files. E.g. a `wafer_id` gets the same pseudonym in its `WIR` and `WRR`. Empty fields
+//! are left empty.
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    str::FromStr,
+};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    filter::decode,
+    record_types::RecordType,
+    records::{RawRecord, Records, records::*},
+    util::*,
+    writer::StdfWriter,
+};
+
+/// Every field that can be redacted, as `<record>.<field>`
+pub const REDACTABLE_FIELDS: &[&str] = &[
+    "MIR.lot_id",
+    "MIR.part_typ",
+    "MIR.node_nam",
+    "MIR.tstr_typ",
+    "MIR.job_nam",
+    "MIR.job_rev",
+    "MIR.sblot_id",
+    "MIR.oper_nam",
+    "MIR.exec_typ",
+    "MIR.exec_ver",
+    "MIR.test_cod",
+    "MIR.tst_temp",
+    "MIR.user_txt",
+    "MIR.aux_file",
+    "MIR.pkg_typ",
+    "MIR.famly_id",
+    "MIR.date_cod",
+    "MIR.facil_id",
+    "MIR.floor_id",
+    "MIR.proc_id",
+    "MIR.oper_frq",
+    "MIR.spec_nam",
+    "MIR.spec_ver",
+    "MIR.flow_id",
+    "MIR.setup_id",
+    "MIR.dsgn_rev",
+    "MIR.eng_id",
+    "MIR.rom_cod",
+    "MIR.serl_num",
+    "MIR.supr_nam",
+    "SDR.hand_typ",
+    "SDR.hand_id",
+    "SDR.card_typ",
+    "SDR.card_id",
+    "SDR.load_typ",
+    "SDR.load_id",
+    "SDR.dib_typ",
+    "SDR.dib_id",
+    "SDR.cabl_typ",
+    "SDR.cabl_id",
+    "SDR.cont_typ",
+    "SDR.cont_id",
+    "SDR.lasr_typ",
+    "SDR.lasr_id",
+    "SDR.extr_typ",
+    "SDR.extr_i",
+    "WIR.wafer_id",
+    "WRR.wafer_id",
+    "WRR.fabwf_id",
+    "WRR.frame_id",
+    "WRR.mask_id",
+    "WRR.usr_desc",
+    "WRR.exc_desc",
+    "PRR.part_id",
+    "PRR.part_txt",
+];
+
+/// The fields redacted by default: everything naming the customer, product, lot, wafer,
+/// part, site or hardware, including the `node_nam` of the tester, but not the tester type,
+/// program name and revision or test conditions
+///
+/// The `job_nam` is kept so that anonymized files can still be matched by program, e.g. when
+/// diffing or merging them.
+pub const DEFAULT_FIELDS: &[&str] = &[
+    "MIR.lot_id",
+    "MIR.part_typ",
+    "MIR.node_nam",
+    "MIR.sblot_id",
+    "MIR.oper_nam",
+    "MIR.user_txt",
+    "MIR.aux_file",
+    "MIR.pkg_typ",
+    "MIR.famly_id",
+    "MIR.facil_id",
+    "MIR.floor_id",
+    "MIR.proc_id",
+    "MIR.spec_nam",
+    "MIR.flow_id",
+    "MIR.setup_id",
+    "MIR.dsgn_rev",
+    "MIR.eng_id",
+    "MIR.rom_cod",
+    "MIR.serl_num",
+    "MIR.supr_nam",
+    "SDR.hand_id",
+    "SDR.card_id",
+    "SDR.load_id",
+    "SDR.dib_id",
+    "SDR.cabl_id",
+    "SDR.cont_id",
+    "SDR.lasr_id",
+    "SDR.extr_i",
+    "WIR.wafer_id",
+    "WRR.wafer_id",
+    "WRR.fabwf_id",
+    "WRR.frame_id",
+    "WRR.mask_id",
+    "WRR.usr_desc",
+    "WRR.exc_desc",
+    "PRR.part_id",
+    "PRR.part_txt",
+];
+
+/// How redacted fields are replaced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Redaction {
+    /// By the keyed hash of their value
+    #[default]
+    Hash,
+    /// By the fixed `token` of the `Anonymizer`
+    Token,
+}
+
+impl fmt::Display for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Hash => write!(f, "hash"),
+            Self::Token => write!(f, "token"),
+        }
+    }
+}
+
+/// Parses a `Redaction` from `hash` or `token`
+impl FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hash" => Ok(Self::Hash),
+            "token" => Ok(Self::Token),
+            _ => Err(format!(
+                "unknown redaction {s}, expected one of hash, token"
+            )),
+        }
+    }
+}
+
+/// Rewrites STDF files with identifying strings redacted
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    /// The secret key of the pseudonyms
+    pub key: Vec<u8>,
+    /// How redacted fields are replaced
+    pub redaction: Redaction,
+    /// The replacement of every redacted field for `Redaction::Token`
+    pub token: String,
+    /// The fields to redact, as `<record>.<field>`, see `REDACTABLE_FIELDS`
+    pub fields: Vec<String>,
+    /// Whether to also redact the test names, i.e. the `test_nam` and `test_lbl` of the `TSR`s
+    /// and the `test_txt` of the `PTR`s, `MPR`s and `FTR`s
+    pub test_names: bool,
+    /// Whether to also redact the text of the `DTR`s and the strings in the `GDR`s
+    pub text: bool,
+}
+
+impl Anonymizer {
+    /// An `Anonymizer` hashing the `DEFAULT_FIELDS` under `key`
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            key: key.to_vec(),
+            redaction: Redaction::Hash,
+            token: "REDACTED".to_string(),
+            fields: DEFAULT_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+            test_names: false,
+            text: false,
+        }
+    }
+
+    /// The replacement of `value`
+    pub fn pseudonym(&self, value: &[u8]) -> String {
+        match self.redaction {
+            Redaction::Hash => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&self.key)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(value);
+                mac.finalize().into_bytes()[..8]
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect()
+            }
+            Redaction::Token => self.token.clone(),
+        }
+    }
+
+    /// Replace `value` if it is not empty
+    ///
+    /// Returns whether it was replaced.
+    fn replace(&self, value: &mut String) -> bool {
+        if value.is_empty() {
+            return false;
+        }
+        *value = self.pseudonym(value.as_bytes());
+        true
+    }
+
+    /// Replace each of the `fields` of the `rtype` record that is selected
+    ///
+    /// Returns the number of fields replaced.
+    fn replace_fields(&self, rtype: &str, fields: Vec<(&str, &mut String)>) -> usize {
+        fields
+            .into_iter()
+            .filter(|(name, _)| self.fields.contains(&format!("{rtype}.{name}")))
+            .map(|(_, value)| self.replace(value))
+            .filter(|replaced| *replaced)
+            .count()
+    }
+
+    /// Redact the selected fields of a concrete `Record` in place
+    ///
+    /// Returns the number of fields replaced.
+    pub fn anonymize_record(&self, record: &mut Record) -> usize {
+        match record {
+            Record::MIR(mir) => self.replace_fields(
+                "MIR",
+                vec![
+                    ("lot_id", &mut mir.lot_id),
+                    ("part_typ", &mut mir.part_typ),
+                    ("node_nam", &mut mir.node_nam),
+                    ("tstr_typ", &mut mir.tstr_typ),
+                    ("job_nam", &mut mir.job_nam),
+                    ("job_rev", &mut mir.job_rev),
+                    ("sblot_id", &mut mir.sblot_id),
+                    ("oper_nam", &mut mir.oper_nam),
+                    ("exec_typ", &mut mir.exec_typ),
+                    ("exec_ver", &mut mir.exec_ver),
+                    ("test_cod", &mut mir.test_cod),
+                    ("tst_temp", &mut mir.tst_temp),
+                    ("user_txt", &mut mir.user_txt),
+                    ("aux_file", &mut mir.aux_file),
+                    ("pkg_typ", &mut mir.pkg_typ),
+                    ("famly_id", &mut mir.famly_id),
+                    ("date_cod", &mut mir.date_cod),
+                    ("facil_id", &mut mir.facil_id),
+                    ("floor_id", &mut mir.floor_id),
+                    ("proc_id", &mut mir.proc_id),
+                    ("oper_frq", &mut mir.oper_frq),
+                    ("spec_nam", &mut mir.spec_nam),
+                    ("spec_ver", &mut mir.spec_ver),
+                    ("flow_id", &mut mir.flow_id),
+                    ("setup_id", &mut mir.setup_id),
+                    ("dsgn_rev", &mut mir.dsgn_rev),
+                    ("eng_id", &mut mir.eng_id),
+                    ("rom_cod", &mut mir.rom_cod),
+                    ("serl_num", &mut mir.serl_num),
+                    ("supr_nam", &mut mir.supr_nam),
+                ],
+            ),
+            Record::SDR(sdr) => self.replace_fields(
+                "SDR",
+                vec![
+                    ("hand_typ", &mut sdr.hand_typ),
+                    ("hand_id", &mut sdr.hand_id),
+                    ("card_typ", &mut sdr.card_typ),
+                    ("card_id", &mut sdr.card_id),
+                    ("load_typ", &mut sdr.load_typ),
+                    ("load_id", &mut sdr.load_id),
+                    ("dib_typ", &mut sdr.dib_typ),
+                    ("dib_id", &mut sdr.dib_id),
+                    ("cabl_typ", &mut sdr.cabl_typ),
+                    ("cabl_id", &mut sdr.cabl_id),
+                    ("cont_typ", &mut sdr.cont_typ),
+                    ("cont_id", &mut sdr.cont_id),
+                    ("lasr_typ", &mut sdr.lasr_typ),
+                    ("lasr_id", &mut sdr.lasr_id),
+                    ("extr_typ", &mut sdr.extr_typ),
+                    ("extr_i", &mut sdr.extr_i),
+                ],
+            ),
+            Record::WIR(wir) => self.replace_fields("WIR", vec![("wafer_id", &mut wir.wafer_id)]),
+            Record::WRR(wrr) => self.replace_fields(
+                "WRR",
+                vec![
+                    ("wafer_id", &mut wrr.wafer_id),
+                    ("fabwf_id", &mut wrr.fabwf_id),
+                    ("frame_id", &mut wrr.frame_id),
+                    ("mask_id", &mut wrr.mask_id),
+                    ("usr_desc", &mut wrr.usr_desc),
+                    ("exc_desc", &mut wrr.exc_desc),
+                ],
+            ),
+            Record::PRR(prr) => self.replace_fields(
+                "PRR",
+                vec![
+                    ("part_id", &mut prr.part_id),
+                    ("part_txt", &mut prr.part_txt),
+                ],
+            ),
+            Record::TSR(tsr) if self.test_names => {
+                usize::from(self.replace(&mut tsr.test_nam))
+                    + usize::from(self.replace(&mut tsr.test_lbl))
+            }
+            Record::PTR(ptr) if self.test_names => usize::from(self.replace(&mut ptr.test_txt)),
+            Record::MPR(mpr) if self.test_names => usize::from(self.replace(&mut mpr.test_txt)),
+            Record::FTR(ftr) if self.test_names => usize::from(self.replace(&mut ftr.test_txt)),
+            _ => 0,
+        }
+    }
+
+    /// Redact the text of a `DTR`, or the strings of a `GDR`, in place
+    ///
+    /// Works on the raw bytes, so that text that is not valid UTF-8 is redacted as well. A `GDR`
+    /// that cannot be decoded is left as is. Returns the number of strings replaced.
+    ///
+    /// # Error
+    /// If the redacted record is longer than the 65535 bytes a record can hold, returns a
+    /// `std::io::Error` and leaves the `raw_record` as is
+    pub fn anonymize_text(&self, raw_record: &mut RawRecord) -> io::Result<usize> {
+        let contents = match raw_record.rtype {
+            RecordType::DTR => self.redact_dtr(&raw_record.contents),
+            RecordType::GDR => self.redact_gdr(&raw_record.contents),
+            _ => None,
+        };
+        match contents {
+            Some((contents, n_replaced)) => {
+                *raw_record = RawRecord::try_new(raw_record.rtype, contents)?;
+                Ok(n_replaced)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Redact the single `Cn` of the contents of a `DTR`
+    fn redact_dtr(&self, contents: &[u8]) -> Option<(Vec<u8>, usize)> {
+        let mut redacted = Vec::with_capacity(contents.len());
+        let mut offset = 0;
+        let n_replaced = self.redact_cn(contents, &mut offset, &mut redacted)?;
+        redacted.extend_from_slice(contents.get(offset..)?);
+        Some((redacted, n_replaced))
+    }
+
+    /// Copy the `Cn` at `offset` in `contents` onto `redacted`, replacing it if it is not empty
+    ///
+    /// Returns the number of strings replaced, or `None` if the `Cn` runs past `contents`.
+    fn redact_cn(
+        &self,
+        contents: &[u8],
+        offset: &mut usize,
+        redacted: &mut Vec<u8>,
+    ) -> Option<usize> {
+        let length = *contents.get(*offset)? as usize;
+        let value = contents.get(*offset + 1..*offset + 1 + length)?;
+        *offset += 1 + length;
+        if value.is_empty() {
+            redacted.push(0);
+            return Some(0);
+        }
+        push_Cn(redacted, &self.pseudonym(value));
+        Some(1)
+    }
+
+    /// Redact the `Cn` fields of the contents of a `GDR`, copying all other fields as is
+    fn redact_gdr(&self, contents: &[u8]) -> Option<(Vec<u8>, usize)> {
+        let mut redacted = Vec::with_capacity(contents.len());
+        let mut offset = 0;
+        let mut n_replaced = 0;
+        let fld_cnt = u16::from_le_bytes(contents.get(0..2)?.try_into().ok()?);
+        redacted.extend_from_slice(&contents[0..2]);
+        offset += 2;
+        for _ in 0..fld_cnt {
+            let code = *contents.get(offset)?;
+            redacted.push(code);
+            offset += 1;
+            let length = match code {
+                0 => 0,
+                1 | 4 | 13 => 1,
+                2 | 5 => 2,
+                3 | 6 | 7 => 4,
+                8 => 8,
+                10 => {
+                    n_replaced += self.redact_cn(contents, &mut offset, &mut redacted)?;
+                    continue;
+                }
+                11 => 1 + *contents.get(offset)? as usize,
+                12 => {
+                    let nbits =
+                        u16::from_le_bytes(contents.get(offset..offset + 2)?.try_into().ok()?);
+                    2 + (nbits as usize).div_ceil(8)
+                }
+                _ => return None,
+            };
+            redacted.extend_from_slice(contents.get(offset..offset + length)?);
+            offset += length;
+        }
+        redacted.extend_from_slice(contents.get(offset..)?);
+        Some((redacted, n_replaced))
+    }
+
+    /// Check that every selected field is one of the `REDACTABLE_FIELDS`
+    ///
+    /// # Error
+    /// If a field is unknown, returns a `std::io::Error`
+    pub fn check_fields(&self) -> io::Result<()> {
+        match self
+            .fields
+            .iter()
+            .find(|field| !REDACTABLE_FIELDS.contains(&field.as_str()))
+        {
+            Some(field) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown field {field}, expected <record>.<field>, e.g. MIR.lot_id"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Anonymize the STDF file at `fname`, writing the result to `output`
+    ///
+    /// Returns the number of strings replaced.
+    ///
+    /// # Error
+    /// If either file cannot be read or written, a selected field is unknown, a record holding a
+    /// redacted field cannot be decoded, or the records in `fname` are not well-ordered, returns a
+    /// `std::io::Error`
+    pub fn apply_fname(&self, fname: &str, output: &str) -> io::Result<usize> {
+        let records = Records::new(fname)?;
+        let writer = BufWriter::new(File::create(output)?);
+        self.apply(records, writer)
+    }
+
+    /// Anonymize the `records` of an STDF file, writing the result to `writer`
+    ///
+    /// The first record must be the `FAR`. Only the records holding a redacted field are
+    /// re-encoded, all others are copied as is without being decoded. Optional fields a
+    /// re-encoded record omitted stay omitted. Returns the number of strings replaced.
+    ///
+    /// # Error
+    /// If `writer` cannot be written, a selected field is unknown, a record holding a redacted
+    /// field cannot be decoded, or the `records` are not well-ordered, returns a `std::io::Error`
+    pub fn apply<W: Write>(
+        &self,
+        mut records: impl Iterator<Item = RawRecord>,
+        writer: W,
+    ) -> io::Result<usize> {
+        self.check_fields()?;
+        let far = match records
+            .next()
+            .map(|raw_record| decode(&raw_record))
+            .transpose()?
+        {
+            Some(Some(Record::FAR(far))) => far,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "STDF file does not start with a FAR",
+                ));
+            }
+        };
+        let mut writer = StdfWriter::with_far(writer, &far)?;
+        let mut n_replaced = 0;
+        for mut raw_record in records {
+            match raw_record.rtype {
+                RecordType::MIR
+                | RecordType::SDR
+                | RecordType::WIR
+                | RecordType::WRR
+                | RecordType::PRR
+                    if self.redacts(raw_record.rtype) =>
+                {
+                    self.write_anonymized(&raw_record, &mut writer, &mut n_replaced)?
+                }
+                RecordType::TSR | RecordType::PTR | RecordType::MPR | RecordType::FTR
+                    if self.test_names =>
+                {
+                    self.write_anonymized(&raw_record, &mut writer, &mut n_replaced)?
+                }
+                RecordType::DTR | RecordType::GDR if self.text => {
+                    n_replaced += self.anonymize_text(&mut raw_record)?;
+                    writer.write_raw_record(&raw_record)?;
+                }
+                _ => writer.write_raw_record(&raw_record)?,
+            }
+        }
+        writer.finish()?;
+        Ok(n_replaced)
+    }
+
+    /// Whether any of the selected `fields` belongs to records of type `rtype`
+    fn redacts(&self, rtype: RecordType) -> bool {
+        let prefix = format!("{rtype:?}.");
+        self.fields.iter().any(|field| field.starts_with(&prefix))
+    }
+
+    /// Resolve, anonymize and write a single record, adding the number of strings replaced to
+    /// `n_replaced`
+    ///
+    /// Records without any replaced field are written as is.
+    fn write_anonymized<W: Write>(
+        &self,
+        raw_record: &RawRecord,
+        writer: &mut StdfWriter<W>,
+        n_replaced: &mut usize,
+    ) -> io::Result<()> {
+        match decode(raw_record)? {
+            Some(mut record) => match self.anonymize_record(&mut record) {
+                0 => writer.write_raw_record(raw_record),
+                n => {
+                    *n_replaced += n;
+                    writer.write_record(&record)
+                }
+            },
+            None => writer.write_raw_record(raw_record),
+        }
+    }
+}
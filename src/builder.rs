@@ -0,0 +1,775 @@
+//! A fluent builder for synthesizing small STDF files, e.g. as fixtures in tests
+//!
+//! The MIR fields, sites, pins, tests and bins are declared up front, then the parts are added
+//! with their results, optionally grouped into wafers. The file is written in the order
+//!
+//! `FAR`, `MIR`, `SDR`, `PMR`s, then for each wafer `WIR`, (`PIR`, test records, `PRR`) per
+//! part, `WRR`, and finally the `TSR`s, `HBR`s, `SBR`s and `PCR`s per site and for all sites,
+//! and the `MRR`.
+//!
+//! The pass/fail flags of the test records, parts, bins and summaries are all derived from the
+//! results and the test limits.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    filter::{PartCounts, TestCounts},
+    records::{RawRecord, records::*},
+    test_information::TestType,
+    writer::StdfWriter,
+};
+
+/// The result of a single test on a part
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestResult {
+    /// The measured value of a parametric test
+    Parametric(f32),
+    /// Whether a functional test passed
+    Functional(bool),
+    /// The measured values of a multi-pin test, one per pin
+    MultiPin(Vec<f32>),
+}
+
+impl TestResult {
+    /// The type of the tests the result may belong to
+    pub fn test_type(&self) -> TestType {
+        match self {
+            Self::Parametric(_) => TestType::P,
+            Self::Functional(_) => TestType::F,
+            Self::MultiPin(_) => TestType::M,
+        }
+    }
+}
+
+impl From<f32> for TestResult {
+    fn from(result: f32) -> Self {
+        Self::Parametric(result)
+    }
+}
+
+impl From<bool> for TestResult {
+    fn from(passed: bool) -> Self {
+        Self::Functional(passed)
+    }
+}
+
+impl From<Vec<f32>> for TestResult {
+    fn from(results: Vec<f32>) -> Self {
+        Self::MultiPin(results)
+    }
+}
+
+impl From<&[f32]> for TestResult {
+    fn from(results: &[f32]) -> Self {
+        Self::MultiPin(results.to_vec())
+    }
+}
+
+/// A part to add to a `StdfBuilder`
+#[derive(Debug, Clone)]
+pub struct Part {
+    site_num: u8,
+    part_id: Option<String>,
+    x_coord: i16,
+    y_coord: i16,
+    hard_bin: Option<u16>,
+    soft_bin: Option<u16>,
+    retest: bool,
+    results: Vec<(u32, TestResult)>,
+}
+
+impl Part {
+    /// Create a part tested on `site_num`, with no coordinates and no results
+    pub fn new(site_num: u8) -> Self {
+        Self {
+            site_num,
+            part_id: None,
+            x_coord: i16::MIN,
+            y_coord: i16::MIN,
+            hard_bin: None,
+            soft_bin: None,
+            retest: false,
+            results: Vec::new(),
+        }
+    }
+
+    /// Set the `part_id`, which otherwise counts up from 1 through the file
+    pub fn id(mut self, part_id: &str) -> Self {
+        self.part_id = Some(part_id.to_string());
+        self
+    }
+
+    /// Set the wafer coordinates of the part
+    pub fn at(mut self, x_coord: i16, y_coord: i16) -> Self {
+        self.x_coord = x_coord;
+        self.y_coord = y_coord;
+        self
+    }
+
+    /// Set the bins, which otherwise are 1 for passing and 0 for failing parts
+    pub fn bins(mut self, hard_bin: u16, soft_bin: u16) -> Self {
+        self.hard_bin = Some(hard_bin);
+        self.soft_bin = Some(soft_bin);
+        self
+    }
+
+    /// Mark the part as a retest, superseding an earlier part with the same `part_id`
+    pub fn retest(mut self) -> Self {
+        self.retest = true;
+        self
+    }
+
+    /// Add the `result` of the test `test_num`
+    ///
+    /// The results are written in the order they are added. An `f32` is a parametric result, a
+    /// `bool` a functional result and a `Vec<f32>` a multi-pin result, with one value per pin of
+    /// the test. Where tests of different types share the `test_num`, the result goes to the test
+    /// of its type.
+    pub fn result(mut self, test_num: u32, result: impl Into<TestResult>) -> Self {
+        self.results.push((test_num, result.into()));
+        self
+    }
+}
+
+/// The declaration of a test in a `StdfBuilder`
+#[derive(Debug, Clone)]
+struct TestDefinition {
+    test_num: u32,
+    test_typ: TestType,
+    test_nam: String,
+    units: String,
+    lo_limit: f32,
+    hi_limit: f32,
+    pins: Vec<u16>,
+}
+
+impl TestDefinition {
+    fn within_limits(&self, result: f32) -> bool {
+        self.lo_limit <= result && result <= self.hi_limit
+    }
+}
+
+/// A fluent builder of STDF files
+///
+/// # Example
+/// ```no_run
+/// use stupidf::builder::{Part, StdfBuilder};
+///
+/// let bytes = StdfBuilder::new()
+///     .lot_id("LOT1")
+///     .sites(&[1, 2])
+///     .parametric_test(100, "VDD", "V", 0.9, 1.1)
+///     .functional_test(200, "FUNC")
+///     .wafer("W01")
+///     .part(Part::new(1).at(0, 0).result(100, 1.0).result(200, true))
+///     .part(Part::new(2).at(1, 0).result(100, 1.2).result(200, true))
+///     .to_bytes()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct StdfBuilder {
+    mir: MIR,
+    mrr: MRR,
+    head_num: u8,
+    site_nums: Vec<u8>,
+    pins: Vec<(u16, String)>,
+    tests: Vec<TestDefinition>,
+    hard_bins: BTreeMap<u16, (String, char)>,
+    soft_bins: BTreeMap<u16, (String, char)>,
+    // The parts, grouped by the wafer they are on, if any
+    wafers: Vec<(Option<String>, Vec<Part>)>,
+}
+
+impl Default for StdfBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StdfBuilder {
+    /// Create a builder for a file with a default `MIR` and `MRR`, and a single site 1 on head 1
+    pub fn new() -> Self {
+        Self {
+            mir: MIR::default(),
+            mrr: MRR::default(),
+            head_num: 1,
+            site_nums: vec![1],
+            pins: Vec::new(),
+            tests: Vec::new(),
+            hard_bins: BTreeMap::new(),
+            soft_bins: BTreeMap::new(),
+            wafers: Vec::new(),
+        }
+    }
+
+    /// Replace the whole `MIR`
+    pub fn mir(mut self, mir: MIR) -> Self {
+        self.mir = mir;
+        self
+    }
+
+    /// Set the `lot_id` of the `MIR`
+    pub fn lot_id(mut self, lot_id: &str) -> Self {
+        self.mir.lot_id = lot_id.to_string();
+        self
+    }
+
+    /// Set the `part_typ` of the `MIR`
+    pub fn part_typ(mut self, part_typ: &str) -> Self {
+        self.mir.part_typ = part_typ.to_string();
+        self
+    }
+
+    /// Set the `job_nam` (test program name) of the `MIR`
+    pub fn job_nam(mut self, job_nam: &str) -> Self {
+        self.mir.job_nam = job_nam.to_string();
+        self
+    }
+
+    /// Set the `node_nam` (tester name) of the `MIR`
+    pub fn node_nam(mut self, node_nam: &str) -> Self {
+        self.mir.node_nam = node_nam.to_string();
+        self
+    }
+
+    /// Set the `tstr_typ` of the `MIR`
+    pub fn tstr_typ(mut self, tstr_typ: &str) -> Self {
+        self.mir.tstr_typ = tstr_typ.to_string();
+        self
+    }
+
+    /// Set the `setup_t` and `start_t` of the `MIR`, also used for the `WIR`s
+    pub fn start_t(mut self, start_t: u32) -> Self {
+        self.mir.setup_t = start_t;
+        self.mir.start_t = start_t;
+        self
+    }
+
+    /// Replace the whole `MRR`
+    pub fn mrr(mut self, mrr: MRR) -> Self {
+        self.mrr = mrr;
+        self
+    }
+
+    /// Set the `head_num` of all sites
+    pub fn head_num(mut self, head_num: u8) -> Self {
+        self.head_num = head_num;
+        self
+    }
+
+    /// Set the sites parts may be tested on
+    pub fn sites(mut self, site_nums: &[u8]) -> Self {
+        self.site_nums = site_nums.to_vec();
+        self
+    }
+
+    /// Declare a pin with the `pmr_indx` used by multi-pin tests
+    pub fn pin(mut self, pmr_indx: u16, name: &str) -> Self {
+        self.pins.push((pmr_indx, name.to_string()));
+        self
+    }
+
+    /// Declare a parametric test, failing outside of [`lo_limit`, `hi_limit`]
+    pub fn parametric_test(
+        mut self,
+        test_num: u32,
+        test_nam: &str,
+        units: &str,
+        lo_limit: f32,
+        hi_limit: f32,
+    ) -> Self {
+        self.tests.push(TestDefinition {
+            test_num,
+            test_typ: TestType::P,
+            test_nam: test_nam.to_string(),
+            units: units.to_string(),
+            lo_limit,
+            hi_limit,
+            pins: Vec::new(),
+        });
+        self
+    }
+
+    /// Declare a functional test
+    pub fn functional_test(mut self, test_num: u32, test_nam: &str) -> Self {
+        self.tests.push(TestDefinition {
+            test_num,
+            test_typ: TestType::F,
+            test_nam: test_nam.to_string(),
+            units: String::new(),
+            lo_limit: f32::NAN,
+            hi_limit: f32::NAN,
+            pins: Vec::new(),
+        });
+        self
+    }
+
+    /// Declare a multi-pin test on the pins with the `pmr_indx`s in `pins`, failing if any pin is
+    /// outside of [`lo_limit`, `hi_limit`]
+    pub fn multi_pin_test(
+        mut self,
+        test_num: u32,
+        test_nam: &str,
+        units: &str,
+        lo_limit: f32,
+        hi_limit: f32,
+        pins: &[u16],
+    ) -> Self {
+        self.tests.push(TestDefinition {
+            test_num,
+            test_typ: TestType::M,
+            test_nam: test_nam.to_string(),
+            units: units.to_string(),
+            lo_limit,
+            hi_limit,
+            pins: pins.to_vec(),
+        });
+        self
+    }
+
+    /// Name a hard bin, and declare whether it holds passing parts
+    pub fn hard_bin(mut self, hbin_num: u16, hbin_nam: &str, passing: bool) -> Self {
+        let hbin_pf = if passing { 'P' } else { 'F' };
+        self.hard_bins
+            .insert(hbin_num, (hbin_nam.to_string(), hbin_pf));
+        self
+    }
+
+    /// Name a soft bin, and declare whether it holds passing parts
+    pub fn soft_bin(mut self, sbin_num: u16, sbin_nam: &str, passing: bool) -> Self {
+        let sbin_pf = if passing { 'P' } else { 'F' };
+        self.soft_bins
+            .insert(sbin_num, (sbin_nam.to_string(), sbin_pf));
+        self
+    }
+
+    /// Start a new wafer, to which the following parts are added
+    pub fn wafer(mut self, wafer_id: &str) -> Self {
+        self.wafers.push((Some(wafer_id.to_string()), Vec::new()));
+        self
+    }
+
+    /// Add a part, to the current wafer if there is one
+    pub fn part(mut self, part: Part) -> Self {
+        match self.wafers.last_mut() {
+            Some((_, parts)) => parts.push(part),
+            None => self.wafers.push((None, vec![part])),
+        }
+        self
+    }
+
+    /// Build the STDF file into a byte buffer
+    ///
+    /// # Error
+    /// If a test is declared twice with the same `test_num` and type, or a part is on an
+    /// undeclared site, has a result for an undeclared test, a result of the wrong type or a
+    /// multi-pin result without one value per pin, returns a `std::io::Error`
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        self.write(Vec::new())
+    }
+
+    /// Build the STDF file and write it to `fname`
+    ///
+    /// # Error
+    /// If the file cannot be written or the parts are invalid (see `to_bytes`), returns a
+    /// `std::io::Error`
+    pub fn write_fname(&self, fname: &str) -> io::Result<()> {
+        let mut writer = self.write(BufWriter::new(File::create(fname)?))?;
+        writer.flush()
+    }
+
+    /// Build the STDF file and write it to `writer`, returning the `writer`
+    ///
+    /// # Error
+    /// If `writer` cannot be written or the parts are invalid (see `to_bytes`), returns a
+    /// `std::io::Error`
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<W> {
+        let head_num = self.head_num;
+        let mut writer = StdfWriter::new(writer)?;
+        writer.write_raw_record(&RawRecord::try_from(&self.mir)?)?;
+        let sdr = SDR {
+            head_num,
+            site_grp: 1,
+            site_cnt: self.site_nums.len() as u8,
+            site_num: self.site_nums.clone(),
+            hand_typ: String::new(),
+            hand_id: String::new(),
+            card_typ: String::new(),
+            card_id: String::new(),
+            load_typ: String::new(),
+            load_id: String::new(),
+            dib_typ: String::new(),
+            dib_id: String::new(),
+            cabl_typ: String::new(),
+            cabl_id: String::new(),
+            cont_typ: String::new(),
+            cont_id: String::new(),
+            lasr_typ: String::new(),
+            lasr_id: String::new(),
+            extr_typ: String::new(),
+            extr_i: String::new(),
+            n_optional: u8::MAX,
+        };
+        writer.write_raw_record(&RawRecord::try_from(&sdr)?)?;
+        for (pmr_indx, name) in &self.pins {
+            let pmr = PMR {
+                pmr_indx: *pmr_indx,
+                chan_typ: 0,
+                chan_nam: String::new(),
+                phy_nam: name.clone(),
+                log_nam: name.clone(),
+                head_num,
+                site_num: self.site_nums.first().copied().unwrap_or(1),
+                n_optional: u8::MAX,
+            };
+            writer.write_raw_record(&RawRecord::try_from(&pmr)?)?;
+        }
+
+        let mut tests: HashMap<(u32, TestType), &TestDefinition> = HashMap::new();
+        for test in &self.tests {
+            if tests.insert((test.test_num, test.test_typ), test).is_some() {
+                return Err(invalid_input(format!(
+                    "test {} of type {} declared more than once",
+                    test.test_num, test.test_typ
+                )));
+            }
+        }
+        let sites: Vec<(u8, u8)> = self
+            .site_nums
+            .iter()
+            .map(|site_num| (head_num, *site_num))
+            .chain([(255, 255)])
+            .collect();
+        let mut part_counts: HashMap<(u8, u8), PartCounts> = HashMap::new();
+        let mut test_counts: HashMap<(u8, u8, u32, TestType), TestCounts> = HashMap::new();
+        let mut hbin_counts: BTreeMap<(u8, u8, u16), u32> = BTreeMap::new();
+        let mut sbin_counts: BTreeMap<(u8, u8, u16), u32> = BTreeMap::new();
+        // (`site_num`, `test_num`, `test_typ`) of the tests already written with their limits
+        let mut limits_written: HashSet<(u8, u32, TestType)> = HashSet::new();
+        let mut n_parts: usize = 0;
+        for (wafer_id, parts) in &self.wafers {
+            if let Some(wafer_id) = wafer_id {
+                let wir = WIR {
+                    head_num,
+                    site_grp: 255,
+                    start_t: self.mir.start_t,
+                    wafer_id: wafer_id.clone(),
+                    n_optional: u8::MAX,
+                };
+                writer.write_raw_record(&RawRecord::try_from(&wir)?)?;
+            }
+            let mut wafer_counts = PartCounts::default();
+            for part in parts {
+                let site_num = part.site_num;
+                if !self.site_nums.contains(&site_num) {
+                    return Err(invalid_input(format!("part on undeclared site {site_num}")));
+                }
+                n_parts += 1;
+                writer.write_raw_record(&RawRecord::try_from(&PIR { head_num, site_num })?)?;
+                let mut part_passed = true;
+                for (test_num, result) in &part.results {
+                    // a result of the wrong type is rejected when encoding it
+                    let test = tests
+                        .get(&(*test_num, result.test_type()))
+                        .copied()
+                        .or_else(|| self.tests.iter().find(|test| test.test_num == *test_num))
+                        .ok_or_else(|| {
+                            invalid_input(format!("result for undeclared test {test_num}"))
+                        })?;
+                    let with_limits = limits_written.insert((site_num, *test_num, test.test_typ));
+                    let (raw_record, passed, value) =
+                        encode_result(test, head_num, site_num, result, with_limits)?;
+                    writer.write_raw_record(&raw_record)?;
+                    part_passed &= passed;
+                    for (head_num, site_num) in [(head_num, site_num), (255, 255)] {
+                        test_counts
+                            .entry((head_num, site_num, *test_num, test.test_typ))
+                            .or_default()
+                            .add(passed, value);
+                    }
+                }
+
+                let default_bin = if part_passed { 1 } else { 0 };
+                let prr = PRR {
+                    head_num,
+                    site_num,
+                    part_flg: if part.retest { 0b1 } else { 0 }
+                        | if part_passed { 0 } else { 0b1000 },
+                    num_test: part.results.len() as u16,
+                    hard_bin: part.hard_bin.unwrap_or(default_bin),
+                    soft_bin: part.soft_bin.unwrap_or(default_bin),
+                    x_coord: part.x_coord,
+                    y_coord: part.y_coord,
+                    test_t: 0,
+                    part_id: part.part_id.clone().unwrap_or_else(|| n_parts.to_string()),
+                    part_txt: String::new(),
+                    part_fix: Vec::new(),
+                    n_optional: u8::MAX,
+                };
+                writer.write_raw_record(&RawRecord::try_from(&prr)?)?;
+                wafer_counts.add(&prr);
+                for (head_num, site_num) in [(head_num, site_num), (255, 255)] {
+                    part_counts
+                        .entry((head_num, site_num))
+                        .or_default()
+                        .add(&prr);
+                    *hbin_counts
+                        .entry((head_num, site_num, prr.hard_bin))
+                        .or_default() += 1;
+                    *sbin_counts
+                        .entry((head_num, site_num, prr.soft_bin))
+                        .or_default() += 1;
+                }
+            }
+            if let Some(wafer_id) = wafer_id {
+                let wrr = WRR {
+                    head_num,
+                    site_grp: 255,
+                    finish_t: self.mrr.finish_t,
+                    part_cnt: wafer_counts.part_cnt,
+                    rtst_cnt: wafer_counts.rtst_cnt,
+                    abrt_cnt: wafer_counts.abrt_cnt,
+                    good_cnt: wafer_counts.good_cnt,
+                    func_cnt: u32::MAX,
+                    wafer_id: wafer_id.clone(),
+                    fabwf_id: String::new(),
+                    frame_id: String::new(),
+                    mask_id: String::new(),
+                    usr_desc: String::new(),
+                    exc_desc: String::new(),
+                    n_optional: u8::MAX,
+                };
+                writer.write_raw_record(&RawRecord::try_from(&wrr)?)?;
+            }
+        }
+
+        for &(head_num, site_num) in &sites {
+            for test in &self.tests {
+                let key = (head_num, site_num, test.test_num, test.test_typ);
+                let Some(counts) = test_counts.get(&key) else {
+                    continue;
+                };
+                let valid_stats = counts.n_results > 0;
+                let tsr = TSR {
+                    head_num,
+                    site_num,
+                    test_typ: test.test_typ.into(),
+                    test_num: test.test_num,
+                    exec_cnt: counts.exec_cnt,
+                    fail_cnt: counts.fail_cnt,
+                    alrm_cnt: 0,
+                    test_nam: test.test_nam.clone(),
+                    seq_name: String::new(),
+                    test_lbl: String::new(),
+                    opt_flag: if valid_stats {
+                        0b0000_0100
+                    } else {
+                        0b0011_0111
+                    },
+                    test_tim: 0.,
+                    test_min: counts.test_min,
+                    test_max: counts.test_max,
+                    tst_sums: counts.tst_sums,
+                    tst_sqrs: counts.tst_sqrs,
+                    n_optional: u8::MAX,
+                };
+                writer.write_raw_record(&RawRecord::try_from(&tsr)?)?;
+            }
+        }
+        for ((head_num, site_num, hbin_num), hbin_cnt) in &hbin_counts {
+            let (hbin_nam, hbin_pf) = self
+                .hard_bins
+                .get(hbin_num)
+                .cloned()
+                .unwrap_or((String::new(), ' '));
+            let hbr = HBR {
+                head_num: *head_num,
+                site_num: *site_num,
+                hbin_num: *hbin_num,
+                hbin_cnt: *hbin_cnt,
+                hbin_pf,
+                hbin_nam,
+                n_optional: u8::MAX,
+            };
+            writer.write_raw_record(&RawRecord::try_from(&hbr)?)?;
+        }
+        for ((head_num, site_num, sbin_num), sbin_cnt) in &sbin_counts {
+            let (sbin_nam, sbin_pf) = self
+                .soft_bins
+                .get(sbin_num)
+                .cloned()
+                .unwrap_or((String::new(), ' '));
+            let sbr = SBR {
+                head_num: *head_num,
+                site_num: *site_num,
+                sbin_num: *sbin_num,
+                sbin_cnt: *sbin_cnt,
+                sbin_pf,
+                sbin_nam,
+                n_optional: u8::MAX,
+            };
+            writer.write_raw_record(&RawRecord::try_from(&sbr)?)?;
+        }
+        for &(head_num, site_num) in &sites {
+            let counts = part_counts
+                .remove(&(head_num, site_num))
+                .unwrap_or_default();
+            let pcr = PCR {
+                head_num,
+                site_num,
+                part_cnt: counts.part_cnt,
+                rtst_cnt: counts.rtst_cnt,
+                abrt_cnt: counts.abrt_cnt,
+                good_cnt: counts.good_cnt,
+                func_cnt: u32::MAX,
+                n_optional: u8::MAX,
+            };
+            writer.write_raw_record(&RawRecord::try_from(&pcr)?)?;
+        }
+        writer.write_raw_record(&RawRecord::try_from(&self.mrr)?)?;
+        writer.finish()
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Encode the `result` of `test` into a test record
+///
+/// The limits of parametric tests are only written `with_limits`. Returns the record, whether
+/// the test passed and the value to use for the `TSR` statistics, if any.
+fn encode_result(
+    test: &TestDefinition,
+    head_num: u8,
+    site_num: u8,
+    result: &TestResult,
+    with_limits: bool,
+) -> io::Result<(RawRecord, bool, Option<f32>)> {
+    let test_flg = |passed: bool| if passed { 0 } else { 0b1000_0000 };
+    match (test.test_typ, result) {
+        (TestType::P, TestResult::Parametric(result)) => {
+            let passed = test.within_limits(*result);
+            let mut ptr = PTR {
+                test_num: test.test_num,
+                head_num,
+                site_num,
+                test_flg: test_flg(passed),
+                parm_flg: 0,
+                result: *result,
+                test_txt: test.test_nam.clone(),
+                alarm_id: String::new(),
+                opt_flag: 0,
+                res_scal: 0,
+                llm_scal: 0,
+                hlm_scal: 0,
+                lo_limit: 0.,
+                hi_limit: 0.,
+                units: String::new(),
+                c_resfmt: String::new(),
+                c_llmfmt: String::new(),
+                c_hlmfmt: String::new(),
+                lo_spec: 0.,
+                hi_spec: 0.,
+                // only `test_txt` and `alarm_id`
+                n_optional: 2,
+            };
+            if with_limits {
+                // no lo_spec or hi_spec
+                ptr.n_optional = u8::MAX;
+                ptr.opt_flag = 0b0000_1110;
+                ptr.lo_limit = test.lo_limit;
+                ptr.hi_limit = test.hi_limit;
+                ptr.units = test.units.clone();
+            }
+            Ok((RawRecord::try_from(&ptr)?, passed, Some(*result)))
+        }
+        (TestType::F, TestResult::Functional(passed)) => {
+            let ftr = FTR {
+                test_num: test.test_num,
+                head_num,
+                site_num,
+                test_flg: test_flg(*passed),
+                // none of the optional counts or addresses are valid
+                opt_flag: 0b1111_1111,
+                cycl_cnt: 0,
+                rel_vadr: 0,
+                rept_cnt: 0,
+                num_fail: 0,
+                xfail_ad: 0,
+                yfail_ad: 0,
+                vect_off: 0,
+                rtn_icnt: 0,
+                pgm_icnt: 0,
+                rtn_indx: Vec::new(),
+                rtn_stat: Vec::new(),
+                pgm_indx: Vec::new(),
+                pgm_stat: Vec::new(),
+                fail_pin_nbits: 0,
+                fail_pin: Vec::new(),
+                vect_nam: String::new(),
+                time_set: String::new(),
+                op_code: String::new(),
+                test_txt: test.test_nam.clone(),
+                alarm_id: String::new(),
+                prog_txt: String::new(),
+                rslt_txt: String::new(),
+                patg_num: 255,
+                spin_map_nbits: 0,
+                spin_map: Vec::new(),
+                n_optional: u8::MAX,
+            };
+            Ok((RawRecord::try_from(&ftr)?, *passed, None))
+        }
+        (TestType::M, TestResult::MultiPin(results)) => {
+            if results.len() != test.pins.len() {
+                return Err(invalid_input(format!(
+                    "{} results for the {} pins of test {}",
+                    results.len(),
+                    test.pins.len(),
+                    test.test_num
+                )));
+            }
+            let passed = results.iter().all(|result| test.within_limits(*result));
+            let mpr = MPR {
+                test_num: test.test_num,
+                head_num,
+                site_num,
+                test_flg: test_flg(passed),
+                parm_flg: 0,
+                rtn_icnt: test.pins.len() as u16,
+                rslt_cnt: results.len() as u16,
+                rtn_stat: vec![0; test.pins.len()],
+                rtn_rslt: results.clone(),
+                test_txt: test.test_nam.clone(),
+                alarm_id: String::new(),
+                // no start_in, incr_in, lo_spec or hi_spec
+                opt_flag: 0b0000_1110,
+                res_scal: 0,
+                llm_scal: 0,
+                hlm_scal: 0,
+                lo_limit: test.lo_limit,
+                hi_limit: test.hi_limit,
+                start_in: 0.,
+                incr_in: 0.,
+                rtn_indx: test.pins.clone(),
+                units: test.units.clone(),
+                units_in: String::new(),
+                c_resfmt: String::new(),
+                c_llmfmt: String::new(),
+                c_hlmfmt: String::new(),
+                lo_spec: 0.,
+                hi_spec: 0.,
+                n_optional: u8::MAX,
+            };
+            Ok((RawRecord::try_from(&mpr)?, passed, None))
+        }
+        (test_typ, result) => Err(invalid_input(format!(
+            "result {result:?} does not match the type {test_typ:?} of test {}",
+            test.test_num
+        ))),
+    }
+}
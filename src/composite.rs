@@ -0,0 +1,259 @@
+//! Composite wafer maps, stacking the wafers of one or more `STDF`s
+//!
+//! The dies of every wafer are aligned by (`x_coord`, `y_coord`). For each position the composite
+//! keeps the number of wafers with a die there, how many of those failed, the most common soft
+//! and hard bins, and the median result of a parametric test across the wafers.
+//!
+//! A `CompositeMap` converts into a `DataFrame` with a row per position, or into a `Wafermap` so
+//! that it may be rendered as a single wafer map is. The orientation is that of the first `STDF`.
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+};
+
+use polars::prelude::*;
+
+use crate::{
+    data::STDF,
+    spatial::Orientation,
+    stats,
+    wafermap::{Die, MapBin, MapLayer, Wafermap, wafermaps},
+};
+
+/// The wafers stacked at one die position
+#[derive(Debug, Clone, Default)]
+pub struct CompositeDie {
+    /// The number of wafers with a die at the position
+    pub n_wafers: u32,
+    /// The number of those dies that failed
+    pub n_fail: u32,
+    /// The number of dies in each soft bin
+    pub sbin_counts: HashMap<u16, u32>,
+    /// The number of dies in each hard bin
+    pub hbin_counts: HashMap<u16, u32>,
+    /// The results of the test of the composite, where it was run
+    pub results: Vec<f64>,
+}
+
+impl CompositeDie {
+    /// The fraction of the wafers where the die failed
+    pub fn fail_rate(&self) -> f64 {
+        match self.n_wafers {
+            0 => 0.,
+            n_wafers => self.n_fail as f64 / n_wafers as f64,
+        }
+    }
+
+    /// The most common soft bin, the lowest if tied
+    pub fn sbin_mode(&self) -> Option<u16> {
+        mode(&self.sbin_counts)
+    }
+
+    /// The most common hard bin, the lowest if tied
+    pub fn hbin_mode(&self) -> Option<u16> {
+        mode(&self.hbin_counts)
+    }
+
+    /// The median of the `results`
+    pub fn median(&self) -> Option<f64> {
+        let mut sorted = self.results.clone();
+        sorted.sort_by(f64::total_cmp);
+        stats::median(&sorted)
+    }
+}
+
+/// The most common bin of `counts`, the lowest if tied
+fn mode(counts: &HashMap<u16, u32>) -> Option<u16> {
+    counts
+        .iter()
+        .max_by(|(bin_a, count_a), (bin_b, count_b)| count_a.cmp(count_b).then(bin_b.cmp(bin_a)))
+        .map(|(&bin, _)| bin)
+}
+
+/// The wafers of one or more `STDF`s stacked by die position
+#[derive(Debug, Clone)]
+pub struct CompositeMap {
+    /// The `test_num` of the parametric test whose results are stacked, if any
+    pub test_num: Option<u32>,
+    /// The number of wafers stacked
+    pub n_wafers: usize,
+    pub orientation: Orientation,
+    /// The stacked dies indexed by (`x_coord`, `y_coord`)
+    pub dies: BTreeMap<(i16, i16), CompositeDie>,
+    /// The name and pass/fail of each soft bin, from the first `STDF` with an SBR for it
+    soft_bins: BTreeMap<u16, MapBin>,
+    /// The name and pass/fail of each hard bin, from the first `STDF` with an HBR for it
+    hard_bins: BTreeMap<u16, MapBin>,
+}
+
+impl CompositeMap {
+    /// Stack every wafer of the `stdfs`, along with the results of the parametric test `test_num`
+    ///
+    /// # Error
+    /// Returns an `std::io::Error` if `test_num` is not a parametric test of every `STDF`
+    pub fn new(stdfs: &[&STDF], test_num: Option<u32>) -> io::Result<Self> {
+        let orientation = stdfs
+            .first()
+            .map(|stdf| Orientation::from_wcr(stdf.wafer_configuration.as_ref()))
+            .unwrap_or_default();
+        let mut n_wafers = 0;
+        let mut dies: BTreeMap<(i16, i16), CompositeDie> = BTreeMap::new();
+        let mut soft_bins: BTreeMap<u16, MapBin> = BTreeMap::new();
+        let mut hard_bins: BTreeMap<u16, MapBin> = BTreeMap::new();
+        for stdf in stdfs {
+            let soft_maps = wafermaps(stdf, MapLayer::SoftBin)?;
+            let hard_maps = wafermaps(stdf, MapLayer::HardBin)?;
+            let test_maps = match test_num {
+                Some(test_num) => Some(wafermaps(stdf, MapLayer::Test(test_num))?),
+                None => None,
+            };
+            for (i, (soft_map, hard_map)) in soft_maps.iter().zip(&hard_maps).enumerate() {
+                if soft_map.dies.is_empty() {
+                    continue;
+                }
+                n_wafers += 1;
+                merge_bins(&mut soft_bins, &soft_map.bins);
+                merge_bins(&mut hard_bins, &hard_map.bins);
+                for (&position, die) in &soft_map.dies {
+                    let composite = dies.entry(position).or_default();
+                    composite.n_wafers += 1;
+                    let pass = die
+                        .part_index
+                        .is_some_and(|part_index| stdf.test_data.data[part_index].pass());
+                    if !pass {
+                        composite.n_fail += 1;
+                    }
+                    *composite.sbin_counts.entry(die.bin).or_default() += 1;
+                    if let Some(hard_die) = hard_map.dies.get(&position) {
+                        *composite.hbin_counts.entry(hard_die.bin).or_default() += 1;
+                    }
+                    let result = test_maps
+                        .as_ref()
+                        .and_then(|test_maps| test_maps[i].dies.get(&position)?.result);
+                    if let Some(result) = result {
+                        composite.results.push(f64::from(result));
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            test_num,
+            n_wafers,
+            orientation,
+            dies,
+            soft_bins,
+            hard_bins,
+        })
+    }
+
+    /// Convert the composite into a `DataFrame`, one row per die position
+    ///
+    /// The columns are `x_coord`, `y_coord`, `n_wafers`, `n_fail`, `fail_rate`, `sbin_mode`,
+    /// `hbin_mode` and `median`, the last being null without a test or results.
+    pub fn to_df(&self) -> DataFrame {
+        let mut x_coords: Vec<i16> = Vec::new();
+        let mut y_coords: Vec<i16> = Vec::new();
+        let mut n_wafers: Vec<u32> = Vec::new();
+        let mut n_fails: Vec<u32> = Vec::new();
+        let mut fail_rates: Vec<f64> = Vec::new();
+        let mut sbin_modes: Vec<Option<u16>> = Vec::new();
+        let mut hbin_modes: Vec<Option<u16>> = Vec::new();
+        let mut medians: Vec<Option<f64>> = Vec::new();
+        for (&(x, y), die) in &self.dies {
+            x_coords.push(x);
+            y_coords.push(y);
+            n_wafers.push(die.n_wafers);
+            n_fails.push(die.n_fail);
+            fail_rates.push(die.fail_rate());
+            sbin_modes.push(die.sbin_mode());
+            hbin_modes.push(die.hbin_mode());
+            medians.push(die.median());
+        }
+        let columns = vec![
+            Column::new("x_coord".into(), x_coords),
+            Column::new("y_coord".into(), y_coords),
+            Column::new("n_wafers".into(), n_wafers),
+            Column::new("n_fail".into(), n_fails),
+            Column::new("fail_rate".into(), fail_rates),
+            Column::new("sbin_mode".into(), sbin_modes),
+            Column::new("hbin_mode".into(), hbin_modes),
+            Column::new("median".into(), medians),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Convert the `layer` of the composite into a `Wafermap` for rendering
+    ///
+    /// `MapLayer::FailRate` shows the failure rate of each die, `MapLayer::SoftBin` and
+    /// `MapLayer::HardBin` its most common bin, and `MapLayer::Test` the median result of the test
+    /// of the composite. The `wafer_id` of the map is `composite of <n_wafers> wafers` and the
+    /// `head_num` 255.
+    ///
+    /// # Error
+    /// Returns an `std::io::Error` if the `layer` is a test other than the test of the composite
+    pub fn to_wafermap(&self, layer: MapLayer) -> io::Result<Wafermap> {
+        if let MapLayer::Test(test_num) = layer
+            && self.test_num != Some(test_num)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("test {test_num} is not the test of the composite map"),
+            ));
+        }
+        let mut dies = BTreeMap::new();
+        for (&position, die) in &self.dies {
+            let bin = match layer {
+                MapLayer::HardBin => die.hbin_mode(),
+                _ => die.sbin_mode(),
+            };
+            let result = match layer {
+                MapLayer::FailRate => Some(die.fail_rate() as f32),
+                MapLayer::Test(_) => die.median().map(|median| median as f32),
+                _ => None,
+            };
+            let die = Die {
+                part_index: None,
+                bin: bin.unwrap_or_default(),
+                result,
+            };
+            dies.insert(position, die);
+        }
+
+        let mut bins = BTreeMap::new();
+        if layer.is_bins() {
+            let summaries = match layer {
+                MapLayer::HardBin => &self.hard_bins,
+                _ => &self.soft_bins,
+            };
+            for die in dies.values() {
+                let map_bin = bins.entry(die.bin).or_insert_with(|| MapBin {
+                    name: summaries
+                        .get(&die.bin)
+                        .map(|map_bin| map_bin.name.clone())
+                        .unwrap_or_default(),
+                    pass: summaries.get(&die.bin).is_some_and(|map_bin| map_bin.pass),
+                    count: 0,
+                });
+                map_bin.count += 1;
+            }
+        }
+
+        Ok(Wafermap {
+            wafer_id: format!("composite of {} wafers", self.n_wafers),
+            wafer_index: None,
+            head_num: 255,
+            layer,
+            orientation: self.orientation,
+            dies,
+            bins,
+        })
+    }
+}
+
+/// Add the bins of one wafer map to those of the composite, keeping the first name and pass/fail
+fn merge_bins(composite: &mut BTreeMap<u16, MapBin>, bins: &BTreeMap<u16, MapBin>) {
+    for (&bin, map_bin) in bins {
+        composite.entry(bin).or_insert_with(|| map_bin.clone());
+    }
+}
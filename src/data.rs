@@ -11,27 +11,42 @@ use serde::Serialize;
 use crate::records::{Records, records::*};
 use crate::{
     records::records::{FTR, PIR, PRR, PTR, Record},
-    test_information::{FullMergedTestInformation, FullTestInformation, TestType},
+    stats,
+    test_information::{
+        Collision, ColumnNaming, Complete, FullMergedTestInformation, FullTestInformation,
+        TestIdentity, TestKey, TestType,
+    },
 };
 
 /// `Row` describes the test results for an individually tested device
 ///
-/// Defaults `x_coord` = `y_coord` = -5000 and `sbin` = `hbin` = 0. Parametric tests have a
-/// default value of `NAN` and functional tests default to `false`.
+/// Defaults `x_coord` = `y_coord` = -5000 and `sbin` = `hbin` = `part_flg` = 0. Parametric tests
+/// have a default value of `NAN` and functional tests default to `false`.
+///
+/// The `passed_*` fields parallel the `results_*` fields and hold the pass/fail flag of each
+/// test, or `None` if the test was not executed for this device.
+///
+/// `wafer_index` is the index in file order of the `WIR` of the wafer the device was tested on,
+/// matching `WaferInformation.wafer_index`, or `None` if the device is outside of any wafer.
 #[derive(Debug, IntoPyObject, Serialize)]
 pub struct Row {
     pub part_id: String,
     pub part_txt: String,
     pub wafer_id: String,
+    pub wafer_index: Option<u32>,
     pub x_coord: i16,
     pub y_coord: i16,
     pub head_num: u8,
     pub site_num: u8,
     pub sbin: u16,
     pub hbin: u16,
+    pub part_flg: u8,
     pub results_parametric: Vec<f32>,
     pub results_functional: Vec<bool>,
     pub results_multi_pin: Vec<Vec<f32>>,
+    pub passed_parametric: Vec<Option<bool>>,
+    pub passed_functional: Vec<Option<bool>>,
+    pub passed_multi_pin: Vec<Option<bool>>,
 }
 
 impl Row {
@@ -46,33 +61,74 @@ impl Row {
     /// Space for the test results for every test is pre-allocated, but they are stored in a
     /// `Vec` for efficiency. The `test_number` -> index lookup is not contained in the `Row`, so
     /// a higher layer of abstraction (`TestData`) is required to actually add new data.
+    ///
+    /// `wafer` is the index and `WIR` of the wafer open on the head of the `PIR`, if any.
     pub fn new(
         pir: &PIR,
         num_tests_parametric: usize,
         num_tests_functional: usize,
         num_tests_multi_pin: usize,
-        wir: &Option<WIR>,
+        wafer: Option<(u32, &WIR)>,
     ) -> Self {
-        let wafer_id: String;
-        if let Some(w) = wir {
-            wafer_id = w.wafer_id.clone();
-        } else {
-            wafer_id = String::new();
-        }
+        let (wafer_index, wafer_id) = match wafer {
+            Some((wafer_index, wir)) => (Some(wafer_index), wir.wafer_id.clone()),
+            None => (None, String::new()),
+        };
         Self {
             part_id: String::new(),
             part_txt: String::new(),
             wafer_id,
+            wafer_index,
             x_coord: -5000,
             y_coord: -5000,
             head_num: pir.head_num,
             site_num: pir.site_num,
             sbin: 0,
             hbin: 0,
+            part_flg: 0,
             results_parametric: vec![f32::NAN; num_tests_parametric as usize],
             results_functional: vec![false; num_tests_functional as usize],
             results_multi_pin: vec![Vec::new(); num_tests_multi_pin as usize],
+            passed_parametric: vec![None; num_tests_parametric],
+            passed_functional: vec![None; num_tests_functional],
+            passed_multi_pin: vec![None; num_tests_multi_pin],
+        }
+    }
+
+    /// Whether the part passed according to the `part_flg` of its `PRR`
+    pub fn pass(&self) -> bool {
+        self.part_flg & 0b1_1000 == 0
+    }
+
+    /// Whether the part was retested according to the `part_flg` of its `PRR`
+    pub fn retest(&self) -> bool {
+        self.part_flg & 0b11 != 0
+    }
+
+    /// Whether the part has die coordinates, i.e. they are neither the default of -5000 nor
+    /// the `i16::MIN` a `PRR` writes for a missing coordinate
+    pub fn has_coords(&self) -> bool {
+        self.x_coord != -5000 && self.x_coord != i16::MIN && self.y_coord != i16::MIN
+    }
+
+    /// Fail a passing part, e.g. a PAT or spatial outlier, and move it to the `sbin` and `hbin`
+    /// where set
+    ///
+    /// The part is marked as failed in its `part_flg`. Returns whether the part was rebinned, a
+    /// part that already failed is left as is.
+    pub fn rebin(&mut self, sbin: Option<u16>, hbin: Option<u16>) -> bool {
+        if !self.pass() {
+            return false;
         }
+        self.part_flg |= 0b1000;
+        self.sbin = sbin.unwrap_or(self.sbin);
+        self.hbin = hbin.unwrap_or(self.hbin);
+        true
+    }
+
+    /// Whether testing of the part was aborted according to the `part_flg` of its `PRR`
+    pub fn abort(&self) -> bool {
+        self.part_flg & 0b100 != 0
     }
 }
 
@@ -81,10 +137,13 @@ impl Row {
 /// Both the merged (`test_information`) and unmerged (`full_test_information`) test metadata is
 /// stored.
 ///
-/// `index_lookup` maps the `test_num` -> index in the contained `Row`s. Since the test result
+/// `index_lookup` maps the `test_key` -> index in the contained `Row`s. Since the test result
 /// record (`PTR` or `FTR`) already specify whether it's a parametric or functional test, it's not
 /// necessary to store this information. Therefore `index_lookup` is not one-to-one. Each
-/// `test_num` will be either a parametric or a functional test.
+/// `test_key` will be either a parametric or a functional test.
+///
+/// The `test_key` is built from each result record according to the `TestIdentity` of the
+/// `full_test_information`, so tests that reuse a `test_num` can be kept apart.
 ///
 /// A set of temporary `Row`s is held during iteration to track results. By the end of the STDF,
 /// all temporary `Row`s should have been moved into `data`.
@@ -95,18 +154,18 @@ impl Row {
 /// `test_information` to a `DataFrame`.
 #[derive(Debug, IntoPyObject)]
 pub struct TestData {
-    /// The test information metadata indexed by (`test_num`, `site_num`, `head_num`)
+    /// The test information metadata indexed by (`test_key`, `site_num`, `head_num`)
     pub full_test_information: FullTestInformation,
-    /// The test information metadata indexed by `test_num`
+    /// The test information metadata indexed by `test_key`
     pub test_information: FullMergedTestInformation,
-    /// Mapping the `test_num` to `Row.results_parametric` or `Row.results_functional` or
+    /// Mapping the `test_key` to `Row.results_parametric` or `Row.results_functional` or
     /// `Row.results_multi_pin`
-    pub index_lookup: HashMap<u32, usize>,
+    pub index_lookup: HashMap<TestKey, usize>,
     /// The list of test results contained in `Row`s
     pub data: Vec<Row>,
     /// For multi-pin tests,text-align: ->style="caret- where the="caret-colorder matches that of
     /// in the test results
-    pub mpr_index_lookup: HashMap<u32, Vec<u16>>,
+    pub mpr_index_lookup: HashMap<TestKey, Vec<u16>>,
     // The temporary rows indexed by (`test_num`, `site_num`, `head_num`)
     temp_rows: HashMap<(u8, u8), Row>,
     // The number of parametric tests
@@ -115,18 +174,26 @@ pub struct TestData {
     n_func: usize,
     // The number of multi-pin tests
     n_mult: usize,
-    // The mapping of index in `Row.results_parametric` to `test_num`
-    reverse_lookup_para: HashMap<usize, u32>,
-    // The mapping of index in `Row.results_functional` to `test_num`
-    reverse_lookup_func: HashMap<usize, u32>,
-    // The mapping of index in `Row.results_multi_pin` to `test_num`
-    reverse_lookup_mult: HashMap<usize, u32>,
-    // The current active wafer
-    wir: Option<WIR>,
+    // The mapping of index in `Row.results_parametric` to `test_key`
+    reverse_lookup_para: HashMap<usize, TestKey>,
+    // The mapping of index in `Row.results_functional` to `test_key`
+    reverse_lookup_func: HashMap<usize, TestKey>,
+    // The mapping of index in `Row.results_multi_pin` to `test_key`
+    reverse_lookup_mult: HashMap<usize, TestKey>,
+    // The index of the only test with a (`test_num`, `test_type`), for records whose `test_key`
+    // is unknown
+    fallback_index: HashMap<(u32, TestType), usize>,
+    // The open wafers indexed by `head_num`, with their index in file order
+    wirs: HashMap<u8, (u32, WIR)>,
+    // The number of wafers opened so far
+    n_wafers: u32,
 }
 
 impl TestData {
     /// Generates a `TestData` struct from the test information metadata
+    ///
+    /// Each test told apart by the `TestIdentity` of `full_test_information` gets its own
+    /// column. The `test_num`s shared by more than one test are listed by `collisions`.
     pub fn new(full_test_information: FullTestInformation) -> Self {
         let test_information = full_test_information.merge();
 
@@ -137,26 +204,37 @@ impl TestData {
         let mut n_para: usize = 0;
         let mut n_func: usize = 0;
         let mut n_mult: usize = 0;
-        for (tnum, mti) in test_information.test_infos.iter().sorted_by_key(|x| x.0) {
+        for (test_key, mti) in test_information.test_infos.iter().sorted_by_key(|x| x.0) {
             match mti.test_type {
                 TestType::P => {
-                    index_lookup.insert(*tnum, n_para);
-                    reverse_lookup_para.insert(n_para, *tnum);
+                    index_lookup.insert(test_key.clone(), n_para);
+                    reverse_lookup_para.insert(n_para, test_key.clone());
                     n_para += 1;
                 }
                 TestType::F => {
-                    index_lookup.insert(*tnum, n_func);
-                    reverse_lookup_func.insert(n_func, *tnum);
+                    index_lookup.insert(test_key.clone(), n_func);
+                    reverse_lookup_func.insert(n_func, test_key.clone());
                     n_func += 1;
                 }
                 TestType::M => {
-                    index_lookup.insert(*tnum, n_mult);
-                    reverse_lookup_mult.insert(n_mult, *tnum);
+                    index_lookup.insert(test_key.clone(), n_mult);
+                    reverse_lookup_mult.insert(n_mult, test_key.clone());
                     n_mult += 1;
                 }
                 _ => {}
             }
         }
+        let fallback_index = test_information
+            .test_infos
+            .iter()
+            .map(|(test_key, mti)| ((test_key.test_num(), mti.test_type), test_key))
+            .into_group_map()
+            .into_iter()
+            .filter_map(|(key, test_keys)| match test_keys.as_slice() {
+                [test_key] => index_lookup.get(*test_key).map(|index| (key, *index)),
+                _ => None,
+            })
+            .collect();
 
         let mpr_index_lookup = HashMap::new();
         let data = Vec::new();
@@ -174,10 +252,35 @@ impl TestData {
             reverse_lookup_para,
             reverse_lookup_func,
             reverse_lookup_mult,
-            wir: None,
+            fallback_index,
+            wirs: HashMap::new(),
+            n_wafers: 0,
         }
     }
 
+    /// Find the index in the `Row`s of the test with the given `test_num`, `test_txt` and
+    /// `test_type`
+    ///
+    /// Some testers only write the `test_txt` on the first result record of a test. If the exact
+    /// `test_key` is unknown, falls back to the only test with a matching `test_num` and
+    /// `test_type`, if there is exactly one.
+    fn lookup_index(&self, test_num: u32, test_txt: &str, test_type: TestType) -> Option<usize> {
+        let identity = self.full_test_information.identity;
+        let test_key = identity.key(test_num, test_txt, test_type);
+        self.index_lookup
+            .get(&test_key)
+            .or_else(|| self.fallback_index.get(&(test_num, test_type)))
+            .copied()
+    }
+
+    /// The `test_num`s shared by more than one test, told apart by their raw `test_txt` and
+    /// `test_type`
+    ///
+    /// See `FullTestInformation::collisions`.
+    pub fn collisions(&self) -> Vec<Collision> {
+        self.full_test_information.collisions()
+    }
+
     /// Initializes a temporary new `Row` from a `PIR` indexed by
     /// (`test_num`, `site_num`, `head_num`)
     ///
@@ -186,13 +289,11 @@ impl TestData {
     pub fn new_part(&mut self, pir: &PIR) {
         let key = (pir.head_num, pir.site_num);
         if let Vacant(row) = self.temp_rows.entry(key) {
-            row.insert(Row::new(
-                &pir,
-                self.n_para,
-                self.n_func,
-                self.n_mult,
-                &self.wir,
-            ));
+            let wafer = self
+                .wirs
+                .get(&pir.head_num)
+                .map(|(wafer_index, wir)| (*wafer_index, wir));
+            row.insert(Row::new(&pir, self.n_para, self.n_func, self.n_mult, wafer));
         } else {
             panic!("opening a specific head_num/site_num before closing the previous one!")
         }
@@ -204,13 +305,13 @@ impl TestData {
     /// to add to, otherwise panics. Temporary rows are created by ingesting a `PIR`.
     pub fn add_data_ptr(&mut self, ptr: &PTR) {
         let key = (ptr.head_num, ptr.site_num);
+        let index = self
+            .lookup_index(ptr.test_num, &ptr.test_txt, TestType::P)
+            .expect("found PTR with unknown test_num!");
         if let Occupied(mut row) = self.temp_rows.entry(key) {
-            let index = self
-                .index_lookup
-                .get(&ptr.test_num)
-                .expect("found PTR with unknown test_num!");
-            let results = &mut row.get_mut().results_parametric;
-            results[*index] = ptr.result;
+            let row = row.get_mut();
+            row.results_parametric[index] = ptr.result;
+            row.passed_parametric[index] = Some(ptr.pass());
         } else {
             panic!("trying to add data to a head_num/site_num that is not open!")
         }
@@ -223,13 +324,13 @@ impl TestData {
     pub fn add_data_ftr(&mut self, ftr: &FTR) {
         let key = (ftr.head_num, ftr.site_num);
         let result = ftr.get_passfail();
+        let index = self
+            .lookup_index(ftr.test_num, &ftr.test_txt, TestType::F)
+            .expect("found FTR with unknown test_num!");
         if let Occupied(mut row) = self.temp_rows.entry(key) {
-            let results = &mut row.get_mut().results_functional;
-            let index = self
-                .index_lookup
-                .get(&ftr.test_num)
-                .expect("found FTR with unknown test_num!");
-            results[*index] = result;
+            let row = row.get_mut();
+            row.results_functional[index] = result;
+            row.passed_functional[index] = Some(result);
         } else {
             panic!("trying to add data to a head_num/site_num that is not open!")
         }
@@ -241,17 +342,18 @@ impl TestData {
     pub fn add_data_mpr(&mut self, mpr: &MPR) {
         let key = (mpr.head_num, mpr.site_num);
         let result = mpr.rtn_rslt.clone();
-        if let Vacant(pin_ids) = self.mpr_index_lookup.entry(mpr.test_num) {
+        let index = self
+            .lookup_index(mpr.test_num, &mpr.test_txt, TestType::M)
+            .expect("found MPR with unknown test_num!");
+        let test_key = self.reverse_lookup_mult[&index].clone();
+        if let Vacant(pin_ids) = self.mpr_index_lookup.entry(test_key) {
             let rtn_indx = mpr.rtn_indx.clone();
             pin_ids.insert(rtn_indx);
         }
         if let Occupied(mut row) = self.temp_rows.entry(key) {
-            let results = &mut row.get_mut().results_multi_pin;
-            let index = self
-                .index_lookup
-                .get(&mpr.test_num)
-                .expect("found MPR with unknown test_num!");
-            results[*index] = result;
+            let row = row.get_mut();
+            row.results_multi_pin[index] = result;
+            row.passed_multi_pin[index] = Some(mpr.pass());
         } else {
             panic!("trying to add data to a head_num/site_num that is not open!");
         }
@@ -274,25 +376,234 @@ impl TestData {
             row.y_coord = prr.y_coord;
             row.sbin = prr.soft_bin;
             row.hbin = prr.hard_bin;
+            row.part_flg = prr.part_flg;
             self.data.push(row);
         } else {
             panic!("trying to close out a head_num/site_num that is not open!")
         }
     }
 
-    /// Starts a new wafer in the `TestData`
+    /// The number of executions and failures of each test
     ///
-    /// This allows the `wafer_id` field to be populated
+    /// Indexed by (`test_key`, `site_num`, `head_num`) like the `full_test_information`, with
+    /// `site_num` = `head_num` = 255 for the counts over all sites.
+    pub fn test_counts(&self) -> HashMap<(TestKey, u8, u8), (u32, u32)> {
+        let mut counts: HashMap<(TestKey, u8, u8), (u32, u32)> = HashMap::new();
+        for row in &self.data {
+            let passed = [
+                (&row.passed_parametric, &self.reverse_lookup_para),
+                (&row.passed_functional, &self.reverse_lookup_func),
+                (&row.passed_multi_pin, &self.reverse_lookup_mult),
+            ];
+            for (passed, reverse_lookup) in passed {
+                for (i, passed) in passed.iter().enumerate() {
+                    let Some(passed) = passed else { continue };
+                    let test_key = &reverse_lookup[&i];
+                    for (site_num, head_num) in [(row.site_num, row.head_num), (255, 255)] {
+                        let count = counts
+                            .entry((test_key.clone(), site_num, head_num))
+                            .or_default();
+                        count.0 += 1;
+                        if !passed {
+                            count.1 += 1;
+                        }
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// The `test_key` of each parametric test, in the order of `Row.results_parametric`
+    pub fn parametric_test_keys(&self) -> Vec<&TestKey> {
+        (0..self.n_para)
+            .map(|i| &self.reverse_lookup_para[&i])
+            .collect()
+    }
+
+    /// The results of each parametric test that was executed, in part order
+    ///
+    /// Indexed like `test_counts`. Results that are `NAN` are left out.
+    pub fn parametric_results(&self) -> HashMap<(TestKey, u8, u8), Vec<f32>> {
+        let mut results: HashMap<(TestKey, u8, u8), Vec<f32>> = HashMap::new();
+        for row in &self.data {
+            let executed = row.results_parametric.iter().zip(&row.passed_parametric);
+            for (i, (&result, passed)) in executed.enumerate() {
+                if passed.is_none() || result.is_nan() {
+                    continue;
+                }
+                let test_key = &self.reverse_lookup_para[&i];
+                for (site_num, head_num) in [(row.site_num, row.head_num), (255, 255)] {
+                    results
+                        .entry((test_key.clone(), site_num, head_num))
+                        .or_default()
+                        .push(result);
+                }
+            }
+        }
+        results
+    }
+
+    /// Starts a new wafer on the head of the `WIR` in the `TestData`
+    ///
+    /// This allows the `wafer_id` and `wafer_index` fields of the parts on that head to be
+    /// populated. Wafers on different heads may be open at the same time.
     pub fn new_wafer(&mut self, wir: &WIR) {
-        self.wir = Some(wir.clone());
+        self.wirs.insert(wir.head_num, (self.n_wafers, wir.clone()));
+        self.n_wafers += 1;
     }
 
-    /// Closes out a wafer in the `TestData`
+    /// Closes out the wafer on the head of the `WRR` in the `TestData`
+    pub fn close_wafer(&mut self, wrr: &WRR) {
+        self.wirs.remove(&wrr.head_num);
+    }
+
+    /// The number of wafers started so far, i.e. the number of `WIR`s read
+    pub fn n_wafers(&self) -> u32 {
+        self.n_wafers
+    }
+
+    /// Concatenate several `TestData`s into one holding the union of their tests
+    ///
+    /// The parts are kept in order, and the results of every test are moved to the column of
+    /// its `test_key` in the union, so tests are aligned across the `TestData`s. Tests missing
+    /// from a `TestData` are left unexecuted for its parts.
     ///
-    /// Triggered by receiving a WRR, but no WRR data is needed for the `TestData`, so we do not
-    /// pass in the WRR.
-    pub fn close_wafer(&mut self) {
-        self.wir = None;
+    /// The test information metadata of a test comes from the first `TestData` that has it,
+    /// with the `execution_count`s summed. The `Row.wafer_index` of each part is offset by the
+    /// `n_wafers` of the `TestData`s before it, so wafers stay distinct. Tests are told apart
+    /// by the `TestIdentity` of the first `TestData`. A test whose `test_type` differs from that
+    /// of its `test_key` in an earlier `TestData`, e.g. a parametric test made functional in a
+    /// later revision, is kept apart under the `TestKey::NumType` of its `test_num` and
+    /// `test_type`.
+    pub fn concat(test_datas: Vec<TestData>) -> Self {
+        let identity = test_datas
+            .first()
+            .map(|test_data| test_data.full_test_information.identity)
+            .unwrap_or_default();
+        let mut full_test_information = FullTestInformation::with_identity(identity);
+        let mut mpr_index_lookup = HashMap::new();
+        let mut test_types: HashMap<TestKey, TestType> = HashMap::new();
+        let mut test_datas = test_datas;
+        for test_data in &mut test_datas {
+            let mut renamed = HashMap::new();
+            for ((test_key, _, _), test_info) in &test_data.full_test_information.test_infos {
+                let test_type = *test_types
+                    .entry(test_key.clone())
+                    .or_insert(test_info.test_type);
+                if test_type != test_info.test_type {
+                    let new_key = TestKey::NumType(test_key.test_num(), test_info.test_type);
+                    renamed.insert(test_key.clone(), new_key);
+                }
+            }
+            test_data.rename_tests(&renamed);
+            full_test_information
+                .raw_tests
+                .append(&mut test_data.full_test_information.raw_tests);
+            let test_infos = std::mem::take(&mut test_data.full_test_information.test_infos);
+            for (key, test_info) in test_infos {
+                match full_test_information.test_infos.entry(key) {
+                    Occupied(mut entry) => {
+                        entry.get_mut().execution_count += test_info.execution_count;
+                    }
+                    Vacant(entry) => {
+                        entry.insert(test_info);
+                    }
+                }
+            }
+            for (test_key, pins) in &test_data.mpr_index_lookup {
+                mpr_index_lookup
+                    .entry(test_key.clone())
+                    .or_insert_with(|| pins.clone());
+            }
+        }
+
+        let mut concatenated = Self::new(full_test_information);
+        concatenated.mpr_index_lookup = mpr_index_lookup;
+        for test_data in test_datas {
+            let wafer_offset = concatenated.n_wafers;
+            // the index in the union of each column of `test_data`
+            let remap = |reverse_lookup: &HashMap<usize, TestKey>| {
+                (0..reverse_lookup.len())
+                    .map(|i| concatenated.index_lookup[&reverse_lookup[&i]])
+                    .collect::<Vec<_>>()
+            };
+            let para = remap(&test_data.reverse_lookup_para);
+            let func = remap(&test_data.reverse_lookup_func);
+            let mult = remap(&test_data.reverse_lookup_mult);
+            for mut row in test_data.data {
+                let results = std::mem::replace(
+                    &mut row.results_parametric,
+                    vec![f32::NAN; concatenated.n_para],
+                );
+                let passed =
+                    std::mem::replace(&mut row.passed_parametric, vec![None; concatenated.n_para]);
+                for ((result, passed), &j) in results.into_iter().zip(passed).zip(&para) {
+                    row.results_parametric[j] = result;
+                    row.passed_parametric[j] = passed;
+                }
+                let results = std::mem::replace(
+                    &mut row.results_functional,
+                    vec![false; concatenated.n_func],
+                );
+                let passed =
+                    std::mem::replace(&mut row.passed_functional, vec![None; concatenated.n_func]);
+                for ((result, passed), &j) in results.into_iter().zip(passed).zip(&func) {
+                    row.results_functional[j] = result;
+                    row.passed_functional[j] = passed;
+                }
+                let results = std::mem::replace(
+                    &mut row.results_multi_pin,
+                    vec![Vec::new(); concatenated.n_mult],
+                );
+                let passed =
+                    std::mem::replace(&mut row.passed_multi_pin, vec![None; concatenated.n_mult]);
+                for ((result, passed), &j) in results.into_iter().zip(passed).zip(&mult) {
+                    row.results_multi_pin[j] = result;
+                    row.passed_multi_pin[j] = passed;
+                }
+                row.wafer_index = row
+                    .wafer_index
+                    .map(|wafer_index| wafer_index + wafer_offset);
+                concatenated.data.push(row);
+            }
+            concatenated.n_wafers += test_data.n_wafers;
+        }
+        concatenated.normalize_multipin_results();
+        concatenated
+    }
+
+    /// Move the tests whose `test_key` is in `renamed` to their new `TestKey`
+    fn rename_tests(&mut self, renamed: &HashMap<TestKey, TestKey>) {
+        if renamed.is_empty() {
+            return;
+        }
+        let rename = |test_key: TestKey| renamed.get(&test_key).cloned().unwrap_or(test_key);
+        let test_infos = std::mem::take(&mut self.full_test_information.test_infos);
+        self.full_test_information.test_infos = test_infos
+            .into_iter()
+            .map(|((test_key, site_num, head_num), test_info)| {
+                ((rename(test_key), site_num, head_num), test_info)
+            })
+            .collect();
+        self.test_information = self.full_test_information.merge();
+        for reverse_lookup in [
+            &mut self.reverse_lookup_para,
+            &mut self.reverse_lookup_func,
+            &mut self.reverse_lookup_mult,
+        ] {
+            for test_key in reverse_lookup.values_mut() {
+                *test_key = rename(test_key.clone());
+            }
+        }
+        self.index_lookup = std::mem::take(&mut self.index_lookup)
+            .into_iter()
+            .map(|(test_key, index)| (rename(test_key), index))
+            .collect();
+        self.mpr_index_lookup = std::mem::take(&mut self.mpr_index_lookup)
+            .into_iter()
+            .map(|(test_key, pins)| (rename(test_key), pins))
+            .collect();
     }
 
     /// Normalize the shape of the multipin Vec<Vec<f32>>
@@ -345,9 +656,25 @@ impl TestData {
     /// If for some reason the file specified by `fname` cannot be parsed, returns a
     /// `std::io::Error`
     pub fn from_fname(fname: &str, verbose: bool) -> std::io::Result<Self> {
-        let test_info = FullTestInformation::from_fname(fname, verbose)?;
+        Self::from_fname_with_identity(fname, verbose, TestIdentity::default())
+    }
+
+    /// Generate the `TestData` from an STDF file specified by `fname`, telling tests apart by
+    /// `identity`
+    ///
+    /// See `from_fname`.
+    ///
+    /// # Error
+    /// If for some reason the file specified by `fname` cannot be parsed, returns a
+    /// `std::io::Error`
+    pub fn from_fname_with_identity(
+        fname: &str,
+        verbose: bool,
+        identity: TestIdentity,
+    ) -> std::io::Result<Self> {
+        let test_info = FullTestInformation::from_fname_with_identity(fname, verbose, identity)?;
         let mut test_data = Self::new(test_info);
-        let records = Records::new(&fname)?;
+        let records = Records::new(fname)?;
 
         for record in records {
             if let Some(resolved) = record.resolve() {
@@ -369,31 +696,175 @@ impl TestData {
                 if let Record::PRR(ref prr) = resolved {
                     test_data.finish_part(prr);
                 }
-                if let Record::WRR(ref _wrr) = resolved {
-                    test_data.close_wafer();
+                if let Record::WRR(ref wrr) = resolved {
+                    test_data.close_wafer(wrr);
                 }
             }
         }
         test_data.normalize_multipin_results();
         Ok(test_data)
     }
+
+    /// Convert into a long (tidy) `DataFrame` with one row per part per executed test
+    ///
+    /// Multi-pin tests produce one row per pin, with the pin index from the `MPR` `rtn_indx` in
+    /// the `pin` column. The `pin` column is null for parametric and functional tests, and the
+    /// `value` column is null for functional tests. Tests that were not executed for a part are
+    /// skipped.
+    ///
+    /// `part_index` is the index of the part in `data`, so the long `DataFrame` can be joined
+    /// back to the row order of the wide `DataFrame`. The test name, units and limits come from
+    /// the merged `test_information`.
+    pub fn to_long_df(&self) -> DataFrame {
+        let mut part_indexes: Vec<u32> = Vec::new();
+        let mut part_ids: Vec<String> = Vec::new();
+        let mut wafer_ids: Vec<String> = Vec::new();
+        let mut wafer_indexes: Vec<Option<u32>> = Vec::new();
+        let mut x_coords: Vec<i16> = Vec::new();
+        let mut y_coords: Vec<i16> = Vec::new();
+        let mut head_nums: Vec<u8> = Vec::new();
+        let mut site_nums: Vec<u8> = Vec::new();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut test_keys: Vec<String> = Vec::new();
+        let mut test_names: Vec<String> = Vec::new();
+        let mut pins: Vec<Option<u16>> = Vec::new();
+        let mut values: Vec<Option<f32>> = Vec::new();
+        let mut passeds: Vec<bool> = Vec::new();
+        let mut unitss: Vec<String> = Vec::new();
+        let mut lo_limits: Vec<f32> = Vec::new();
+        let mut hi_limits: Vec<f32> = Vec::new();
+
+        let column_names = self.test_information.column_names();
+        let tests = self
+            .index_lookup
+            .iter()
+            .filter_map(|(test_key, index)| {
+                let mti = self.test_information.test_infos.get(test_key)?;
+                Some((test_key, mti, *index))
+            })
+            .sorted_by_key(|(test_key, _, _)| *test_key)
+            .collect::<Vec<_>>();
+
+        for (part_index, row) in self.data.iter().enumerate() {
+            for (test_key, mti, index) in &tests {
+                let (passed, results) = match mti.test_type {
+                    TestType::P => (
+                        row.passed_parametric[*index],
+                        vec![(None, Some(row.results_parametric[*index]))],
+                    ),
+                    TestType::F => (row.passed_functional[*index], vec![(None, None)]),
+                    TestType::M => {
+                        let pin_ids = self.mpr_index_lookup.get(*test_key);
+                        let results = row.results_multi_pin[*index]
+                            .iter()
+                            .enumerate()
+                            .map(|(i, value)| {
+                                let pin = pin_ids.and_then(|ids| ids.get(i)).copied();
+                                (pin, Some(*value))
+                            })
+                            .collect();
+                        (row.passed_multi_pin[*index], results)
+                    }
+                    _ => continue,
+                };
+                let Some(passed) = passed else {
+                    continue;
+                };
+                let test_name = if mti.test_name.is_empty() {
+                    &mti.test_text
+                } else {
+                    &mti.test_name
+                };
+                for (pin, value) in results {
+                    part_indexes.push(part_index as u32);
+                    part_ids.push(row.part_id.clone());
+                    wafer_ids.push(row.wafer_id.clone());
+                    wafer_indexes.push(row.wafer_index);
+                    x_coords.push(row.x_coord);
+                    y_coords.push(row.y_coord);
+                    head_nums.push(row.head_num);
+                    site_nums.push(row.site_num);
+                    test_nums.push(mti.test_num);
+                    test_keys.push(column_names[*test_key].clone());
+                    test_names.push(test_name.clone());
+                    pins.push(pin);
+                    values.push(value);
+                    passeds.push(passed);
+                    unitss.push(mti.units.clone());
+                    lo_limits.push(mti.low_limit);
+                    hi_limits.push(mti.high_limit);
+                }
+            }
+        }
+
+        let columns = vec![
+            Column::new("part_index".into(), part_indexes),
+            Column::new("part_id".into(), part_ids),
+            Column::new("wafer_id".into(), wafer_ids),
+            Column::new("wafer_index".into(), wafer_indexes),
+            Column::new("x_coord".into(), x_coords),
+            Column::new("y_coord".into(), y_coords),
+            Column::new("head_num".into(), head_nums),
+            Column::new("site_num".into(), site_nums),
+            Column::new("test_num".into(), test_nums),
+            Column::new("test_key".into(), test_keys),
+            Column::new("test_name".into(), test_names),
+            Column::new("pin".into(), pins),
+            Column::new("value".into(), values),
+            Column::new("passed".into(), passeds),
+            Column::new("units".into(), unitss),
+            Column::new("lo_limit".into(), lo_limits),
+            Column::new("hi_limit".into(), hi_limits),
+        ];
+        DataFrame::new(columns).unwrap()
+    }
 }
 
 /// Converts a `&TestData` into a `DataFrame` containing a tabular listing of all test results
+///
+/// The test result columns are named by `test_num`, see `TestData::to_df` for other options.
 impl Into<DataFrame> for &TestData {
     fn into(self) -> DataFrame {
+        self.to_df(&ColumnNaming::default())
+    }
+}
+
+/// The names of the per-part columns of the `DataFrame` made from a `TestData`
+pub const ROW_COLUMNS: [&str; 10] = [
+    "part_id",
+    "part_txt",
+    "wafer_id",
+    "wafer_index",
+    "x_coord",
+    "y_coord",
+    "head_num",
+    "site_num",
+    "sbin",
+    "hbin",
+];
+
+impl TestData {
+    /// Convert into a wide `DataFrame` with one row per part and one column per test
+    ///
+    /// The test result columns are named according to `naming`. Names are always unique and never
+    /// clash with the per-part columns in `ROW_COLUMNS`.
+    pub fn to_df(&self, naming: &ColumnNaming) -> DataFrame {
         let mut part_ids: Vec<String> = Vec::new();
         let mut part_txts: Vec<String> = Vec::new();
         let mut wafer_ids: Vec<String> = Vec::new();
+        let mut wafer_indexes: Vec<Option<u32>> = Vec::new();
         let mut x_coords: Vec<i16> = Vec::new();
         let mut y_coords: Vec<i16> = Vec::new();
         let mut head_nums: Vec<u8> = Vec::new();
         let mut site_nums: Vec<u8> = Vec::new();
         let mut sbins: Vec<u16> = Vec::new();
         let mut hbins: Vec<u16> = Vec::new();
-        let mut vecs_para: HashMap<u32, Vec<f32>> = HashMap::new(); // hashmap to later sort by key
-        let mut vecs_func: HashMap<u32, Vec<bool>> = HashMap::new();
-        let mut vecs_mult: HashMap<u32, Vec<AnyValue>> = HashMap::new();
+        let mut vecs_para: HashMap<&TestKey, Vec<f32>> = HashMap::new(); // hashmap to later sort by key
+        let mut vecs_func: HashMap<&TestKey, Vec<bool>> = HashMap::new();
+        let mut vecs_mult: HashMap<&TestKey, Vec<AnyValue>> = HashMap::new();
+        let column_names = self
+            .test_information
+            .column_names_with(naming, &ROW_COLUMNS);
         let ncols_para = self.n_para;
         let ncols_func = self.n_func;
         let ncols_mult = self.n_mult;
@@ -401,6 +872,7 @@ impl Into<DataFrame> for &TestData {
             part_ids.push(row.part_id.clone());
             part_txts.push(row.part_txt.clone());
             wafer_ids.push(row.wafer_id.clone());
+            wafer_indexes.push(row.wafer_index);
             x_coords.push(row.x_coord);
             y_coords.push(row.y_coord);
             head_nums.push(row.head_num);
@@ -408,22 +880,22 @@ impl Into<DataFrame> for &TestData {
             sbins.push(row.sbin);
             hbins.push(row.hbin);
             for i in 0..ncols_para {
-                let test_num = self.reverse_lookup_para.get(&i).unwrap();
+                let test_key = self.reverse_lookup_para.get(&i).unwrap();
                 vecs_para
-                    .entry(*test_num)
+                    .entry(test_key)
                     .or_insert(Vec::new())
                     .push(row.results_parametric[i]);
             }
             for i in 0..ncols_func {
-                let test_num = self.reverse_lookup_func.get(&i).unwrap();
+                let test_key = self.reverse_lookup_func.get(&i).unwrap();
                 vecs_func
-                    .entry(*test_num)
+                    .entry(test_key)
                     .or_insert(Vec::new())
                     .push(row.results_functional[i]);
             }
             for i in 0..ncols_mult {
-                let test_num = self.reverse_lookup_mult.get(&i).unwrap();
-                vecs_mult.entry(*test_num).or_insert(Vec::new()).push({
+                let test_key = self.reverse_lookup_mult.get(&i).unwrap();
+                vecs_mult.entry(test_key).or_insert(Vec::new()).push({
                     let results: Series = row.results_multi_pin[i].clone().into_iter().collect();
                     let len = results.len();
                     AnyValue::Array(results, len)
@@ -434,20 +906,67 @@ impl Into<DataFrame> for &TestData {
         columns.push(Column::new("part_id".into(), part_ids));
         columns.push(Column::new("part_txt".into(), part_txts));
         columns.push(Column::new("wafer_id".into(), wafer_ids));
+        columns.push(Column::new("wafer_index".into(), wafer_indexes));
         columns.push(Column::new("x_coord".into(), x_coords));
         columns.push(Column::new("y_coord".into(), y_coords));
         columns.push(Column::new("head_num".into(), head_nums));
         columns.push(Column::new("site_num".into(), site_nums));
         columns.push(Column::new("sbin".into(), sbins));
         columns.push(Column::new("hbin".into(), hbins));
-        for (test_num, vec) in vecs_para.iter().sorted_by_key(|(key, _)| *key) {
-            columns.push(Column::new(test_num.to_string().into(), vec));
+        for (test_key, vec) in vecs_para.iter().sorted_by_key(|(key, _)| *key) {
+            columns.push(Column::new(column_names[*test_key].as_str().into(), vec));
         }
-        for (test_num, vec) in vecs_func.iter().sorted_by_key(|(key, _)| *key) {
-            columns.push(Column::new(test_num.to_string().into(), vec));
+        for (test_key, vec) in vecs_func.iter().sorted_by_key(|(key, _)| *key) {
+            columns.push(Column::new(column_names[*test_key].as_str().into(), vec));
         }
-        for (test_num, vec) in vecs_mult.iter().sorted_by_key(|(key, _)| *key) {
-            columns.push(Column::new(test_num.to_string().into(), vec));
+        for (test_key, vec) in vecs_mult.iter().sorted_by_key(|(key, _)| *key) {
+            columns.push(Column::new(column_names[*test_key].as_str().into(), vec));
+        }
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Convert into a wide `DataFrame` like `to_df`, but with every multi-pin test split into one
+    /// `f32` column per pin
+    ///
+    /// Formats such as CSV cannot hold the fixed-size array columns of the multi-pin tests. The
+    /// pin columns are named `<test column>[<pin>]`, where `<pin>` is the `pmr_indx` of the pin
+    /// from `mpr_index_lookup`, or its position in the results if the pin is unknown.
+    pub fn to_flat_df(&self, naming: &ColumnNaming) -> DataFrame {
+        let df = self.to_df(naming);
+        let column_names = self
+            .test_information
+            .column_names_with(naming, &ROW_COLUMNS);
+        let mut pin_columns: HashMap<&str, Vec<Column>> = HashMap::new();
+        for i in 0..self.n_mult {
+            let test_key = self.reverse_lookup_mult.get(&i).unwrap();
+            let name = column_names[test_key].as_str();
+            let pins = self.mpr_index_lookup.get(test_key);
+            let n_pins = self
+                .data
+                .first()
+                .map_or(0, |row| row.results_multi_pin[i].len());
+            let columns = (0..n_pins)
+                .map(|j| {
+                    let pin = pins
+                        .and_then(|pins| pins.get(j))
+                        .map_or(j, |pin| *pin as usize);
+                    let results: Vec<f32> = self
+                        .data
+                        .iter()
+                        .map(|row| row.results_multi_pin[i][j])
+                        .collect();
+                    Column::new(format!("{name}[{pin}]").into(), results)
+                })
+                .collect();
+            pin_columns.insert(name, columns);
+        }
+
+        let mut columns: Vec<Column> = Vec::new();
+        for column in df.get_columns() {
+            match pin_columns.remove(column.name().as_str()) {
+                Some(pin_columns) => columns.extend(pin_columns),
+                None => columns.push(column.clone()),
+            }
         }
         DataFrame::new(columns).unwrap()
     }
@@ -552,8 +1071,12 @@ impl MasterInformation {
     }
 }
 
+/// The `WIR` and `WRR` of a wafer, paired by `head_num`
+///
+/// `wafer_index` is the index of the `WIR` in file order, matching `Row.wafer_index`.
 #[derive(Debug, IntoPyObject, Clone, Serialize)]
 pub struct WaferInformation {
+    pub wafer_index: u32,
     // From WIR
     pub head_num: u8,
     pub site_grp: u8,
@@ -574,8 +1097,9 @@ pub struct WaferInformation {
 }
 
 impl WaferInformation {
-    pub fn new(wir: WIR, wrr: WRR) -> Self {
+    pub fn new(wafer_index: u32, wir: WIR, wrr: WRR) -> Self {
         Self {
+            wafer_index,
             // From WIR
             head_num: wir.head_num,
             site_grp: wir.site_grp,
@@ -615,14 +1139,23 @@ pub struct STDF {
     pub master_information: MasterInformation,
     ///// The STDF file metadata
     pub wafer_information: Vec<WaferInformation>,
+    /// The wafer configuration, giving the orientation of the die coordinates
+    pub wafer_configuration: Option<WCR>,
     /// The site information
     pub site_information: SDR,
-    /// Soft bin information indexed by soft bin number
-    pub soft_bins: HashMap<u16, SBR>,
-    /// Hard bin information indexed by hard bin number
-    pub hard_bins: HashMap<u16, HBR>,
+    /// Soft bin information indexed by (`head_num`, `site_num`, `sbin_num`), where `head_num` 255
+    /// holds the counts over all sites
+    pub soft_bins: HashMap<(u8, u8, u16), SBR>,
+    /// Hard bin information indexed by (`head_num`, `site_num`, `hbin_num`), where `head_num` 255
+    /// holds the counts over all sites
+    pub hard_bins: HashMap<(u8, u8, u16), HBR>,
     /// Pin mapping
     pub pins: HashMap<u16, PMR>,
+    /// Part counts indexed by (`head_num`, `site_num`), where `head_num` 255 holds the counts
+    /// over all sites
+    pub part_counts: HashMap<(u8, u8), PCR>,
+    /// The test synopses, in file order
+    pub test_summaries: Vec<TSR>,
     /// The test results and test information metadata
     pub test_data: TestData,
 }
@@ -644,18 +1177,38 @@ impl STDF {
     /// # Error
     /// If for some reason the file cannot be parsed, returns an `std::io::Error`
     pub fn from_fname(fname: &str, verbose: bool) -> std::io::Result<Self> {
-        let test_info = FullTestInformation::from_fname(fname, verbose)?;
+        Self::from_fname_with_identity(fname, verbose, TestIdentity::default())
+    }
+
+    /// Parses an STDF file from the file specified by `fname`, telling tests apart by `identity`
+    ///
+    /// Tests that reuse a `test_num` are kept in separate columns when `identity` distinguishes
+    /// them. See `TestIdentity`.
+    ///
+    /// # Error
+    /// If for some reason the file cannot be parsed, returns an `std::io::Error`
+    pub fn from_fname_with_identity(
+        fname: &str,
+        verbose: bool,
+        identity: TestIdentity,
+    ) -> std::io::Result<Self> {
+        let test_info = FullTestInformation::from_fname_with_identity(fname, verbose, identity)?;
         let mut test_data = TestData::new(test_info);
         let mut wirs = Vec::new();
-        let mut wrrs = Vec::new();
+        let mut wrrs = HashMap::new();
+        // The index in `wirs` of the open wafer on each head
+        let mut open_wafers = HashMap::new();
         let mut soft_bins = HashMap::new();
         let mut hard_bins = HashMap::new();
         let mut pins = HashMap::new();
+        let mut part_counts = HashMap::new();
+        let mut test_summaries = Vec::new();
         let records = Records::new(&fname)?;
 
         let mut opt_mir: Option<MIR> = None;
         let mut opt_mrr: Option<MRR> = None;
         let mut opt_sdr: Option<SDR> = None;
+        let mut opt_wcr: Option<WCR> = None;
         for record in records {
             if let Some(resolved) = record.resolve() {
                 match resolved {
@@ -669,21 +1222,33 @@ impl STDF {
                         opt_sdr = Some(sdr);
                     }
                     Record::SBR(sbr) => {
-                        soft_bins.insert(sbr.sbin_num, sbr);
+                        soft_bins.insert((sbr.head_num, sbr.site_num, sbr.sbin_num), sbr);
                     }
                     Record::HBR(hbr) => {
-                        hard_bins.insert(hbr.hbin_num, hbr);
+                        hard_bins.insert((hbr.head_num, hbr.site_num, hbr.hbin_num), hbr);
                     }
                     Record::PMR(pmr) => {
                         pins.insert(pmr.pmr_indx, pmr);
                     }
+                    Record::PCR(pcr) => {
+                        part_counts.insert((pcr.head_num, pcr.site_num), pcr);
+                    }
+                    Record::TSR(tsr) => {
+                        test_summaries.push(tsr);
+                    }
                     Record::WIR(wir) => {
                         test_data.new_wafer(&wir);
+                        open_wafers.insert(wir.head_num, wirs.len());
                         wirs.push(wir);
                     }
                     Record::WRR(wrr) => {
-                        test_data.close_wafer();
-                        wrrs.push(wrr);
+                        test_data.close_wafer(&wrr);
+                        if let Some(i) = open_wafers.remove(&wrr.head_num) {
+                            wrrs.insert(i, wrr);
+                        }
+                    }
+                    Record::WCR(wcr) => {
+                        opt_wcr = Some(wcr);
                     }
                     Record::PIR(ref pir) => {
                         test_data.new_part(pir);
@@ -707,18 +1272,25 @@ impl STDF {
         test_data.normalize_multipin_results();
         if let (Some(mir), Some(mrr), Some(site_information)) = (opt_mir, opt_mrr, opt_sdr) {
             let master_information = MasterInformation::new(mir, mrr);
+            // wafers without a WRR are left out
             let wafer_information = wirs
                 .into_iter()
-                .zip(wrrs.into_iter())
-                .map(|(wir, wrr)| WaferInformation::new(wir, wrr))
+                .enumerate()
+                .filter_map(|(i, wir)| {
+                    let wrr = wrrs.remove(&i)?;
+                    Some(WaferInformation::new(i as u32, wir, wrr))
+                })
                 .collect();
             Ok(Self {
                 master_information,
                 wafer_information,
+                wafer_configuration: opt_wcr,
                 site_information,
                 soft_bins,
                 hard_bins,
                 pins,
+                part_counts,
+                test_summaries,
                 test_data,
             })
         } else {
@@ -730,57 +1302,223 @@ impl STDF {
     }
 
     /// Convert the HashMap `soft_bins` into a `DataFrame` format
+    ///
+    /// Has one row per `SBR`, sorted by `head_num`, `site_num` and `sbin_num`. `is_summary` marks
+    /// the `SBR`s with `head_num` 255, which hold the counts over all sites.
     pub fn soft_bins_to_df(&self) -> DataFrame {
+        let bins = self.soft_bins.values().map(BinSummary::from).collect();
+        bins_to_df("sbin", bins)
+    }
+
+    /// Convert the HashMap `hard_bins` into a `DataFrame` format
+    ///
+    /// Has one row per `HBR`, sorted by `head_num`, `site_num` and `hbin_num`. `is_summary` marks
+    /// the `HBR`s with `head_num` 255, which hold the counts over all sites.
+    pub fn hard_bins_to_df(&self) -> DataFrame {
+        let bins = self.hard_bins.values().map(BinSummary::from).collect();
+        bins_to_df("hbin", bins)
+    }
+
+    /// The soft bin counts over all sites, one row per `sbin_num`
+    ///
+    /// Taken from the summary `SBR`s if there are any, otherwise summed over the per-site `SBR`s.
+    pub fn overall_soft_bins_to_df(&self) -> DataFrame {
+        let bins = self.soft_bins.values().map(BinSummary::from).collect();
+        bins_to_df("sbin", overall_bins(bins))
+    }
+
+    /// The hard bin counts over all sites, one row per `hbin_num`
+    ///
+    /// Taken from the summary `HBR`s if there are any, otherwise summed over the per-site `HBR`s.
+    pub fn overall_hard_bins_to_df(&self) -> DataFrame {
+        let bins = self.hard_bins.values().map(BinSummary::from).collect();
+        bins_to_df("hbin", overall_bins(bins))
+    }
+
+    /// Pivot the per-site `SBR`s into one row per `sbin_num` and one count column per site
+    ///
+    /// See `bins_by_site_to_df` for the column names.
+    pub fn soft_bins_by_site_to_df(&self) -> DataFrame {
+        let bins = self.soft_bins.values().map(BinSummary::from).collect();
+        bins_by_site_to_df("sbin", bins)
+    }
+
+    /// Pivot the per-site `HBR`s into one row per `hbin_num` and one count column per site
+    ///
+    /// See `bins_by_site_to_df` for the column names.
+    pub fn hard_bins_by_site_to_df(&self) -> DataFrame {
+        let bins = self.hard_bins.values().map(BinSummary::from).collect();
+        bins_by_site_to_df("hbin", bins)
+    }
+
+    /// Convert the HashMap `part_counts` into a `DataFrame` format
+    ///
+    /// Has one row per `PCR`, sorted by `head_num` and `site_num`. `is_summary` marks the `PCR`s
+    /// with `head_num` 255, which hold the counts over all sites. The optional counts a `PCR` leaves
+    /// out (4294967295) are null.
+    pub fn part_counts_to_df(&self) -> DataFrame {
+        let optional = |cnt: u32| Some(cnt).filter(|&cnt| cnt != u32::MAX);
         let mut head_nums: Vec<u8> = Vec::new();
         let mut site_nums: Vec<u8> = Vec::new();
-        let mut sbin_nums: Vec<u16> = Vec::new();
-        let mut sbin_cnts: Vec<u32> = Vec::new();
-        let mut sbin_pfs: Vec<String> = Vec::new();
-        let mut sbin_nams: Vec<String> = Vec::new();
-
-        for sbin in self.soft_bins.values() {
-            head_nums.push(sbin.head_num);
-            site_nums.push(sbin.site_num);
-            sbin_nums.push(sbin.sbin_num);
-            sbin_cnts.push(sbin.sbin_cnt);
-            sbin_pfs.push(sbin.sbin_pf.to_string());
-            sbin_nams.push(sbin.sbin_nam.clone());
+        let mut is_summaries: Vec<bool> = Vec::new();
+        let mut part_cnts: Vec<u32> = Vec::new();
+        let mut rtst_cnts: Vec<Option<u32>> = Vec::new();
+        let mut abrt_cnts: Vec<Option<u32>> = Vec::new();
+        let mut good_cnts: Vec<Option<u32>> = Vec::new();
+        let mut func_cnts: Vec<Option<u32>> = Vec::new();
+
+        for (_, pcr) in self.part_counts.iter().sorted_by_key(|(key, _)| **key) {
+            head_nums.push(pcr.head_num);
+            site_nums.push(pcr.site_num);
+            is_summaries.push(pcr.head_num == 255);
+            part_cnts.push(pcr.part_cnt);
+            rtst_cnts.push(optional(pcr.rtst_cnt));
+            abrt_cnts.push(optional(pcr.abrt_cnt));
+            good_cnts.push(optional(pcr.good_cnt));
+            func_cnts.push(optional(pcr.func_cnt));
         }
-        let mut columns = Vec::new();
-        columns.push(Column::new("sbin_num".into(), sbin_nums));
-        columns.push(Column::new("head_num".into(), head_nums));
-        columns.push(Column::new("site_num".into(), site_nums));
-        columns.push(Column::new("sbin_cnt".into(), sbin_cnts));
-        columns.push(Column::new("sbin_pf".into(), sbin_pfs));
-        columns.push(Column::new("sbin_nam".into(), sbin_nams));
+        let columns = vec![
+            Column::new("head_num".into(), head_nums),
+            Column::new("site_num".into(), site_nums),
+            Column::new("is_summary".into(), is_summaries),
+            Column::new("part_cnt".into(), part_cnts),
+            Column::new("rtst_cnt".into(), rtst_cnts),
+            Column::new("abrt_cnt".into(), abrt_cnts),
+            Column::new("good_cnt".into(), good_cnts),
+            Column::new("func_cnt".into(), func_cnts),
+        ];
 
         DataFrame::new(columns).unwrap()
     }
 
-    /// Convert the HashMap `soft_bins` into a `DataFrame` format
-    pub fn hard_bins_to_df(&self) -> DataFrame {
+    /// Convert the `test_summaries` into a `DataFrame` with every `TSR` field, one row per `TSR`
+    ///
+    /// Values the `opt_flag` marks invalid, and counts the `TSR` leaves out (4294967295), are null.
+    /// `mean` and `std` (the sample standard deviation) are derived from `tst_sums`, `tst_sqrs`
+    /// and `exec_cnt`.
+    ///
+    /// The `actual_*` columns hold the same statistics computed from the parametric results in
+    /// the `test_data` for the `test_key` of the `TSR`, and are null for other tests.
+    /// `stats_match` is whether every statistic available in both agrees within a relative
+    /// tolerance of 1e-3, and is null if there is nothing to compare.
+    pub fn test_summaries_to_df(&self) -> DataFrame {
+        let identity = &self.test_data.full_test_information.identity;
+        let results = self.test_data.parametric_results();
+        let count = |cnt: u32| Some(cnt).filter(|&cnt| cnt != u32::MAX);
+        let valid = |tsr: &TSR, bit: u8, value: f32| (tsr.opt_flag & bit == 0).then_some(value);
+
         let mut head_nums: Vec<u8> = Vec::new();
         let mut site_nums: Vec<u8> = Vec::new();
-        let mut hbin_nums: Vec<u16> = Vec::new();
-        let mut hbin_cnts: Vec<u32> = Vec::new();
-        let mut hbin_pfs: Vec<String> = Vec::new();
-        let mut hbin_nams: Vec<String> = Vec::new();
-
-        for hbin in self.hard_bins.values() {
-            head_nums.push(hbin.head_num);
-            site_nums.push(hbin.site_num);
-            hbin_nums.push(hbin.hbin_num);
-            hbin_cnts.push(hbin.hbin_cnt);
-            hbin_pfs.push(hbin.hbin_pf.to_string());
-            hbin_nams.push(hbin.hbin_nam.clone());
+        let mut is_summaries: Vec<bool> = Vec::new();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut test_typs: Vec<String> = Vec::new();
+        let mut test_keys: Vec<String> = Vec::new();
+        let mut exec_cnts: Vec<Option<u32>> = Vec::new();
+        let mut fail_cnts: Vec<Option<u32>> = Vec::new();
+        let mut alrm_cnts: Vec<Option<u32>> = Vec::new();
+        let mut test_nams: Vec<String> = Vec::new();
+        let mut seq_names: Vec<String> = Vec::new();
+        let mut test_lbls: Vec<String> = Vec::new();
+        let mut opt_flags: Vec<u8> = Vec::new();
+        let mut test_tims: Vec<Option<f32>> = Vec::new();
+        let mut test_mins: Vec<Option<f32>> = Vec::new();
+        let mut test_maxs: Vec<Option<f32>> = Vec::new();
+        let mut tst_sumss: Vec<Option<f32>> = Vec::new();
+        let mut tst_sqrss: Vec<Option<f32>> = Vec::new();
+        let mut means: Vec<Option<f64>> = Vec::new();
+        let mut stds: Vec<Option<f64>> = Vec::new();
+        let mut actual_mins: Vec<Option<f64>> = Vec::new();
+        let mut actual_maxs: Vec<Option<f64>> = Vec::new();
+        let mut actual_means: Vec<Option<f64>> = Vec::new();
+        let mut actual_stds: Vec<Option<f64>> = Vec::new();
+        let mut stats_matches: Vec<Option<bool>> = Vec::new();
+
+        for tsr in &self.test_summaries {
+            let test_key = identity.key(tsr.test_num, &tsr.test_nam, tsr.test_typ.into());
+            let exec_cnt = count(tsr.exec_cnt);
+            let test_min = valid(tsr, 0b1, tsr.test_min);
+            let test_max = valid(tsr, 0b10, tsr.test_max);
+            let tst_sums = valid(tsr, 0b1_0000, tsr.tst_sums);
+            let tst_sqrs = valid(tsr, 0b10_0000, tsr.tst_sqrs);
+            let summary = SummaryStats::from_sums(exec_cnt, tst_sums, tst_sqrs);
+
+            let (site_num, head_num) = match tsr.head_num {
+                255 => (255, 255),
+                _ => (tsr.site_num, tsr.head_num),
+            };
+            let actual = results
+                .get(&(test_key.clone(), site_num, head_num))
+                .map(|values| SummaryStats::from_values(values));
+            let reported = [
+                test_min.map(f64::from),
+                test_max.map(f64::from),
+                summary.mean,
+                summary.std,
+            ];
+            let stats_match = actual.as_ref().and_then(|actual| {
+                let computed = [actual.min, actual.max, actual.mean, actual.std];
+                let compared: Vec<bool> = reported
+                    .iter()
+                    .zip(computed)
+                    .filter_map(|(reported, computed)| Some(close(*reported.as_ref()?, computed?)))
+                    .collect();
+                (!compared.is_empty()).then(|| compared.iter().all(|&matches| matches))
+            });
+            let actual = actual.unwrap_or_default();
+
+            head_nums.push(tsr.head_num);
+            site_nums.push(tsr.site_num);
+            is_summaries.push(tsr.head_num == 255);
+            test_nums.push(tsr.test_num);
+            test_typs.push(tsr.test_typ.to_string());
+            test_keys.push(test_key.to_string());
+            exec_cnts.push(exec_cnt);
+            fail_cnts.push(count(tsr.fail_cnt));
+            alrm_cnts.push(count(tsr.alrm_cnt));
+            test_nams.push(tsr.test_nam.clone());
+            seq_names.push(tsr.seq_name.clone());
+            test_lbls.push(tsr.test_lbl.clone());
+            opt_flags.push(tsr.opt_flag);
+            test_tims.push(valid(tsr, 0b100, tsr.test_tim));
+            test_mins.push(test_min);
+            test_maxs.push(test_max);
+            tst_sumss.push(tst_sums);
+            tst_sqrss.push(tst_sqrs);
+            means.push(summary.mean);
+            stds.push(summary.std);
+            actual_mins.push(actual.min);
+            actual_maxs.push(actual.max);
+            actual_means.push(actual.mean);
+            actual_stds.push(actual.std);
+            stats_matches.push(stats_match);
         }
-        let mut columns = Vec::new();
-        columns.push(Column::new("hbin_num".into(), hbin_nums));
-        columns.push(Column::new("head_num".into(), head_nums));
-        columns.push(Column::new("site_num".into(), site_nums));
-        columns.push(Column::new("hbin_cnt".into(), hbin_cnts));
-        columns.push(Column::new("hbin_pf".into(), hbin_pfs));
-        columns.push(Column::new("hbin_nam".into(), hbin_nams));
+        let columns = vec![
+            Column::new("head_num".into(), head_nums),
+            Column::new("site_num".into(), site_nums),
+            Column::new("is_summary".into(), is_summaries),
+            Column::new("test_num".into(), test_nums),
+            Column::new("test_typ".into(), test_typs),
+            Column::new("test_key".into(), test_keys),
+            Column::new("exec_cnt".into(), exec_cnts),
+            Column::new("fail_cnt".into(), fail_cnts),
+            Column::new("alrm_cnt".into(), alrm_cnts),
+            Column::new("test_nam".into(), test_nams),
+            Column::new("seq_name".into(), seq_names),
+            Column::new("test_lbl".into(), test_lbls),
+            Column::new("opt_flag".into(), opt_flags),
+            Column::new("test_tim".into(), test_tims),
+            Column::new("test_min".into(), test_mins),
+            Column::new("test_max".into(), test_maxs),
+            Column::new("tst_sums".into(), tst_sumss),
+            Column::new("tst_sqrs".into(), tst_sqrss),
+            Column::new("mean".into(), means),
+            Column::new("std".into(), stds),
+            Column::new("actual_min".into(), actual_mins),
+            Column::new("actual_max".into(), actual_maxs),
+            Column::new("actual_mean".into(), actual_means),
+            Column::new("actual_std".into(), actual_stds),
+            Column::new("stats_match".into(), stats_matches),
+        ];
 
         DataFrame::new(columns).unwrap()
     }
@@ -815,4 +1553,373 @@ impl STDF {
 
         DataFrame::new(columns).unwrap()
     }
+
+    /// Convert the `mpr_index_lookup` of the `test_data` into a `DataFrame` with one row per pin
+    /// of each multi-pin test
+    ///
+    /// The columns are `test_num`, `test_key`, the name of the results column of the test,
+    /// `pin_index`, the index of the pin in the results of the test, and `pmr_indx`.
+    pub fn mpr_index_lookup_to_df(&self) -> DataFrame {
+        let column_names = self.test_data.test_information.column_names();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut test_keys: Vec<String> = Vec::new();
+        let mut pin_indexes: Vec<u32> = Vec::new();
+        let mut pmr_indxs: Vec<u16> = Vec::new();
+
+        let mpr_index_lookup = self
+            .test_data
+            .mpr_index_lookup
+            .iter()
+            .sorted_by_key(|x| x.0);
+        for (test_key, pins) in mpr_index_lookup {
+            let column_name = column_names
+                .get(test_key)
+                .cloned()
+                .unwrap_or_else(|| test_key.to_string());
+            for (pin_index, pmr_indx) in pins.iter().enumerate() {
+                test_nums.push(test_key.test_num());
+                test_keys.push(column_name.clone());
+                pin_indexes.push(pin_index as u32);
+                pmr_indxs.push(*pmr_indx);
+            }
+        }
+        let columns = vec![
+            Column::new("test_num".into(), test_nums),
+            Column::new("test_key".into(), test_keys),
+            Column::new("pin_index".into(), pin_indexes),
+            Column::new("pmr_indx".into(), pmr_indxs),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Convert the `full_test_information` of the `test_data` into a `DataFrame` with one row per
+    /// test, site and head
+    ///
+    /// The columns are those of the `FullMergedTestInformation` `DataFrame`, with the `head_num`
+    /// and `site_num` after the `test_key` and a trailing `complete` column, one of `PTR`, `TSR`
+    /// or `Complete`.
+    pub fn full_test_information_to_df(&self) -> DataFrame {
+        let column_names = self.test_data.test_information.column_names();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut test_keys: Vec<String> = Vec::new();
+        let mut head_nums: Vec<u8> = Vec::new();
+        let mut site_nums: Vec<u8> = Vec::new();
+        let mut test_types: Vec<String> = Vec::new();
+        let mut execution_counts: Vec<u32> = Vec::new();
+        let mut test_names: Vec<String> = Vec::new();
+        let mut sequence_names: Vec<String> = Vec::new();
+        let mut test_labels: Vec<String> = Vec::new();
+        let mut test_times: Vec<f32> = Vec::new();
+        let mut test_texts: Vec<String> = Vec::new();
+        let mut llm_scals: Vec<i32> = Vec::new();
+        let mut hlm_scals: Vec<i32> = Vec::new();
+        let mut res_scals: Vec<i32> = Vec::new();
+        let mut lo_specs: Vec<f32> = Vec::new();
+        let mut hi_specs: Vec<f32> = Vec::new();
+        let mut low_limits: Vec<f32> = Vec::new();
+        let mut high_limits: Vec<f32> = Vec::new();
+        let mut unitss: Vec<String> = Vec::new();
+        let mut completes: Vec<&str> = Vec::new();
+
+        let test_infos = self
+            .test_data
+            .full_test_information
+            .test_infos
+            .iter()
+            .sorted_by_key(|x| x.0);
+        for ((test_key, site_num, head_num), ti) in test_infos {
+            test_nums.push(ti.test_num);
+            test_keys.push(
+                column_names
+                    .get(test_key)
+                    .cloned()
+                    .unwrap_or_else(|| test_key.to_string()),
+            );
+            head_nums.push(*head_num);
+            site_nums.push(*site_num);
+            test_types.push(ti.test_type.to_string());
+            execution_counts.push(ti.execution_count);
+            test_names.push(ti.test_name.clone());
+            sequence_names.push(ti.sequence_name.clone());
+            test_labels.push(ti.test_label.clone());
+            test_times.push(ti.test_time);
+            test_texts.push(ti.test_text.clone());
+            llm_scals.push(ti.llm_scal as i32);
+            hlm_scals.push(ti.hlm_scal as i32);
+            res_scals.push(ti.res_scal as i32);
+            lo_specs.push(ti.lo_spec);
+            hi_specs.push(ti.hi_spec);
+            low_limits.push(ti.low_limit);
+            high_limits.push(ti.high_limit);
+            unitss.push(ti.units.clone());
+            completes.push(match ti.complete {
+                Complete::PTR => "PTR",
+                Complete::TSR => "TSR",
+                Complete::Complete => "Complete",
+            });
+        }
+        let columns = vec![
+            Column::new("test_num".into(), test_nums),
+            Column::new("test_key".into(), test_keys),
+            Column::new("head_num".into(), head_nums),
+            Column::new("site_num".into(), site_nums),
+            Column::new("test_type".into(), test_types),
+            Column::new("execution_count".into(), execution_counts),
+            Column::new("test_name".into(), test_names),
+            Column::new("sequence_name".into(), sequence_names),
+            Column::new("test_label".into(), test_labels),
+            Column::new("test_time".into(), test_times),
+            Column::new("test_text".into(), test_texts),
+            Column::new("llm_scal".into(), llm_scals),
+            Column::new("hlm_scal".into(), hlm_scals),
+            Column::new("res_scal".into(), res_scals),
+            Column::new("lo_spec".into(), lo_specs),
+            Column::new("hi_spec".into(), hi_specs),
+            Column::new("low_limit".into(), low_limits),
+            Column::new("high_limit".into(), high_limits),
+            Column::new("units".into(), unitss),
+            Column::new("complete".into(), completes),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Convert the `master_information` into a single row `DataFrame`
+    pub fn master_information_to_df(&self) -> DataFrame {
+        serialized_to_df(&[&self.master_information])
+    }
+
+    /// Convert the `site_information` into a single row `DataFrame`
+    pub fn site_information_to_df(&self) -> DataFrame {
+        serialized_to_df(&[&self.site_information])
+    }
+
+    /// Convert the `wafer_information` into a `DataFrame` with one row per wafer
+    pub fn wafer_information_to_df(&self) -> DataFrame {
+        serialized_to_df(&self.wafer_information)
+    }
+
+    /// Reconcile the summary records with the parts and test results, one row per mismatch
+    ///
+    /// See `reconcile::reconcile` for which counts are compared.
+    pub fn reconciliation_to_df(&self) -> DataFrame {
+        crate::reconcile::mismatches_to_df(&crate::reconcile::reconcile(self))
+    }
+}
+
+/// The fields common to an `SBR` and an `HBR`
+#[derive(Debug, Clone)]
+struct BinSummary {
+    head_num: u8,
+    site_num: u8,
+    bin_num: u16,
+    bin_cnt: u32,
+    bin_pf: char,
+    bin_nam: String,
+}
+
+impl BinSummary {
+    /// Whether this holds the counts over all sites
+    fn is_summary(&self) -> bool {
+        self.head_num == 255
+    }
+}
+
+impl From<&SBR> for BinSummary {
+    fn from(sbr: &SBR) -> Self {
+        Self {
+            head_num: sbr.head_num,
+            site_num: sbr.site_num,
+            bin_num: sbr.sbin_num,
+            bin_cnt: sbr.sbin_cnt,
+            bin_pf: sbr.sbin_pf,
+            bin_nam: sbr.sbin_nam.clone(),
+        }
+    }
+}
+
+impl From<&HBR> for BinSummary {
+    fn from(hbr: &HBR) -> Self {
+        Self {
+            head_num: hbr.head_num,
+            site_num: hbr.site_num,
+            bin_num: hbr.hbin_num,
+            bin_cnt: hbr.hbin_cnt,
+            bin_pf: hbr.hbin_pf,
+            bin_nam: hbr.hbin_nam.clone(),
+        }
+    }
+}
+
+/// Convert the `bins` into a `DataFrame` with columns named after `prefix`, e.g. `sbin_num`
+fn bins_to_df(prefix: &str, mut bins: Vec<BinSummary>) -> DataFrame {
+    bins.sort_by_key(|bin| (bin.head_num, bin.site_num, bin.bin_num));
+    let mut bin_nums: Vec<u16> = Vec::new();
+    let mut head_nums: Vec<u8> = Vec::new();
+    let mut site_nums: Vec<u8> = Vec::new();
+    let mut is_summaries: Vec<bool> = Vec::new();
+    let mut bin_cnts: Vec<u32> = Vec::new();
+    let mut bin_pfs: Vec<String> = Vec::new();
+    let mut bin_nams: Vec<String> = Vec::new();
+
+    for bin in bins {
+        bin_nums.push(bin.bin_num);
+        head_nums.push(bin.head_num);
+        site_nums.push(bin.site_num);
+        is_summaries.push(bin.is_summary());
+        bin_cnts.push(bin.bin_cnt);
+        bin_pfs.push(bin.bin_pf.to_string());
+        bin_nams.push(bin.bin_nam);
+    }
+    let columns = vec![
+        Column::new(format!("{prefix}_num").into(), bin_nums),
+        Column::new("head_num".into(), head_nums),
+        Column::new("site_num".into(), site_nums),
+        Column::new("is_summary".into(), is_summaries),
+        Column::new(format!("{prefix}_cnt").into(), bin_cnts),
+        Column::new(format!("{prefix}_pf").into(), bin_pfs),
+        Column::new(format!("{prefix}_nam").into(), bin_nams),
+    ];
+
+    DataFrame::new(columns).unwrap()
+}
+
+/// The bins over all sites: the summary bins if there are any, otherwise the per-site bins summed
+/// by bin number
+fn overall_bins(bins: Vec<BinSummary>) -> Vec<BinSummary> {
+    if bins.iter().any(BinSummary::is_summary) {
+        return bins.into_iter().filter(BinSummary::is_summary).collect();
+    }
+    let mut overall: HashMap<u16, BinSummary> = HashMap::new();
+    for bin in bins {
+        match overall.entry(bin.bin_num) {
+            Occupied(mut entry) => entry.get_mut().bin_cnt += bin.bin_cnt,
+            Vacant(entry) => {
+                entry.insert(BinSummary {
+                    head_num: 255,
+                    site_num: 255,
+                    ..bin
+                });
+            }
+        }
+    }
+    overall.into_values().collect()
+}
+
+/// Pivot the per-site `bins` into one row per bin number and one count column per site
+///
+/// The count columns are named `site_<site_num>`, or `head_<head_num>_site_<site_num>` if the
+/// bins span more than one head. A site without a bin gets a count of 0 for it.
+fn bins_by_site_to_df(prefix: &str, bins: Vec<BinSummary>) -> DataFrame {
+    let bins: Vec<BinSummary> = bins.into_iter().filter(|bin| !bin.is_summary()).collect();
+    let sites: Vec<(u8, u8)> = bins
+        .iter()
+        .map(|bin| (bin.head_num, bin.site_num))
+        .sorted()
+        .dedup()
+        .collect();
+    let multi_head = sites.iter().map(|site| site.0).dedup().count() > 1;
+    let mut names: HashMap<u16, (char, String)> = HashMap::new();
+    let mut counts: HashMap<(u16, (u8, u8)), u32> = HashMap::new();
+    for bin in bins {
+        names
+            .entry(bin.bin_num)
+            .or_insert((bin.bin_pf, bin.bin_nam));
+        *counts
+            .entry((bin.bin_num, (bin.head_num, bin.site_num)))
+            .or_default() += bin.bin_cnt;
+    }
+    let bin_nums: Vec<u16> = names.keys().copied().sorted().collect();
+
+    let mut columns = vec![
+        Column::new(format!("{prefix}_num").into(), &bin_nums),
+        Column::new(
+            format!("{prefix}_pf").into(),
+            bin_nums
+                .iter()
+                .map(|bin_num| names[bin_num].0.to_string())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            format!("{prefix}_nam").into(),
+            bin_nums
+                .iter()
+                .map(|bin_num| names[bin_num].1.clone())
+                .collect::<Vec<_>>(),
+        ),
+    ];
+    for site in sites {
+        let name = match multi_head {
+            true => format!("head_{}_site_{}", site.0, site.1),
+            false => format!("site_{}", site.1),
+        };
+        let site_counts: Vec<u32> = bin_nums
+            .iter()
+            .map(|&bin_num| counts.get(&(bin_num, site)).copied().unwrap_or(0))
+            .collect();
+        columns.push(Column::new(name.into(), site_counts));
+    }
+
+    DataFrame::new(columns).unwrap()
+}
+
+/// The statistics of a test a `TSR` summarizes, any of which may be unavailable
+#[derive(Debug, Default)]
+struct SummaryStats {
+    min: Option<f64>,
+    max: Option<f64>,
+    mean: Option<f64>,
+    std: Option<f64>,
+}
+
+impl SummaryStats {
+    /// The mean and sample standard deviation from the sum and sum of squares of `n` results
+    fn from_sums(n: Option<u32>, sums: Option<f32>, sqrs: Option<f32>) -> Self {
+        let n = n.filter(|&n| n > 0).map(f64::from);
+        let mean = n.zip(sums).map(|(n, sums)| f64::from(sums) / n);
+        let std = n
+            .filter(|&n| n > 1.0)
+            .zip(sums.zip(sqrs))
+            .map(|(n, (sums, sqrs))| {
+                let (sums, sqrs) = (f64::from(sums), f64::from(sqrs));
+                ((sqrs - sums * sums / n) / (n - 1.0)).max(0.0).sqrt()
+            });
+        Self {
+            min: None,
+            max: None,
+            mean,
+            std,
+        }
+    }
+
+    /// The statistics of the `values`
+    fn from_values(values: &[f32]) -> Self {
+        let values: Vec<f64> = values.iter().map(|&value| f64::from(value)).collect();
+        Self {
+            min: values.iter().copied().reduce(f64::min),
+            max: values.iter().copied().reduce(f64::max),
+            mean: stats::mean(&values),
+            std: stats::std_dev(&values),
+        }
+    }
+}
+
+/// Whether `a` and `b` agree within a relative tolerance of 1e-3
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-3 * a.abs().max(b.abs()).max(1e-6)
+}
+
+/// Convert a slice of `Serialize` structs into a `DataFrame` with one row per struct
+///
+/// Goes through JSON, so the column types are inferred: integers become `i64` and `char`s become
+/// strings. An empty slice gives an empty `DataFrame`.
+fn serialized_to_df<T: Serialize>(items: &[T]) -> DataFrame {
+    if items.is_empty() {
+        return DataFrame::empty();
+    }
+    let json = serde_json::to_vec(items).unwrap();
+    JsonReader::new(std::io::Cursor::new(json))
+        .finish()
+        .unwrap()
 }
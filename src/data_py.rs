@@ -11,11 +11,22 @@
 use std::collections::HashMap;
 
 use crate::{
+    anonymize::{Anonymizer, DEFAULT_FIELDS, Redaction},
+    composite::CompositeMap,
     data::{MasterInformation, Row, STDF, TestData, WaferInformation},
+    diff::{ChangeKind, ProgramDiff},
+    map_export::{BinCodes, MapExport},
+    merge::MergedStdf,
+    pat::Pat,
     records::records::*,
-    test_information::TestInformation,
+    spatial::{Gdbn, Nnr, SpatialScreen},
+    stats::{TestStatistics, check_percentiles},
+    test_information::{Collision, ColumnNaming, TestIdentity, TestInformation, TestKey},
+    validate::validate_fname,
+    wafermap::{self, MapLayer},
+    zones::{Reticle, ZoneGroupBy, Zoning},
 };
-use pyo3::prelude::*;
+use pyo3::{exceptions::PyValueError, prelude::*};
 use pyo3_polars::PyDataFrame;
 
 /// A wrapper for the STDF suitable for throwing across the barrier to Python land
@@ -27,41 +38,55 @@ struct PySTDF {
     wafers: Vec<WaferInformation>,
     /// The site information
     site_information: SDR,
-    /// The soft-bin information
+    /// The soft-bin information, one row per SBR with an `is_summary` flag
     soft_bins: PyDataFrame,
-    /// The hard-bin information
+    /// The hard-bin information, one row per HBR with an `is_summary` flag
     hard_bins: PyDataFrame,
+    /// The part counts, one row per PCR with an `is_summary` flag
+    part_counts: PyDataFrame,
+    /// The test summaries, one row per TSR with the derived and actual statistics
+    test_summaries: PyDataFrame,
     /// The pin mapping information
     pins: PyDataFrame,
-    /// The test number -> [pin_id] mapping
-    pin_mapping: HashMap<u32, Vec<u16>>,
+    /// The test key -> [pin_id] mapping
+    pin_mapping: HashMap<TestKey, Vec<u16>>,
     /// The `DataFrame` containing the test results (corresponds to `TestData`)
     df: PyDataFrame,
     /// The `DataFrame` containing the test information metadata (corresponds to
     /// `FullMergedTestInformation`)
     test_information: PyDataFrame,
     /// A dict containing the full test information metadata indexed by
-    /// (`test_num`, `site_num`, `head_num`)
-    full_test_information: HashMap<(u32, u8, u8), TestInformation>,
+    /// (`test_key`, `site_num`, `head_num`)
+    full_test_information: HashMap<(TestKey, u8, u8), TestInformation>,
+    /// The `test_num`s shared by more than one test
+    collisions: Vec<Collision>,
 }
 
 impl PySTDF {
     /// Generates the PySTDF from a file specified by `fname`
     ///
-    /// Analagous to `STDF::from_fname`
-    fn from_fname(fname: &str) -> std::io::Result<Self> {
-        let stdf = STDF::from_fname(&fname, false)?;
+    /// Analagous to `STDF::from_fname_with_identity`, with the test result columns of `df` named
+    /// according to `naming`
+    fn from_fname(
+        fname: &str,
+        identity: TestIdentity,
+        naming: &ColumnNaming,
+    ) -> std::io::Result<Self> {
+        let stdf = STDF::from_fname_with_identity(fname, false, identity)?;
         let metadata = stdf.master_information.clone();
         let wafers = stdf.wafer_information.clone();
         let site_information = stdf.site_information.clone();
         let soft_bins = PyDataFrame(stdf.soft_bins_to_df());
         let hard_bins = PyDataFrame(stdf.hard_bins_to_df());
+        let part_counts = PyDataFrame(stdf.part_counts_to_df());
+        let test_summaries = PyDataFrame(stdf.test_summaries_to_df());
         let pins = PyDataFrame(stdf.pin_mapping_to_df());
         let pin_mapping = stdf.test_data.mpr_index_lookup.clone();
         let test_data = &stdf.test_data;
         let test_info = &test_data.test_information;
-        let df = PyDataFrame(test_data.into());
+        let df = PyDataFrame(test_data.to_df(naming));
         let test_information = PyDataFrame(test_info.into());
+        let collisions = test_data.collisions();
         let full_test_information = stdf.test_data.full_test_information.test_infos;
         Ok(Self {
             metadata,
@@ -69,27 +94,42 @@ impl PySTDF {
             site_information,
             soft_bins,
             hard_bins,
+            part_counts,
+            test_summaries,
             pins,
             pin_mapping,
             df,
             test_information,
             full_test_information,
+            collisions,
         })
     }
 }
 
-/// parse_stdf(fname: str)
+/// parse_stdf(fname: str, identity: str = "test_num", naming: str = "num")
 /// --
 ///
 /// Parse an STDF file specified by `fname`
 ///
 /// `fname` must be a `str` and may not be a `Path`-like object.
 ///
+/// `identity` determines how tests that reuse a test number are told apart, and may be one of
+/// `"test_num"`, `"test_num_txt"` or `"test_num_type"`.
+///
+/// `naming` determines how the test result columns of `df` are named, and may be one of `"num"`,
+/// `"name"`, `"num:name"` or a template such as `"{test_num}_{test_name}[{units}]"`. The template
+/// fields are `test_num`, `test_key`, `test_txt`, `test_name`, `units` and `sequence_name`.
+///
 /// Returns a dict with keys and values:
 ///    `mir`: `dict` describing the Master Infomation Record (file metadata)
 ///    `df`: `DataFrame` containing the test results
+///    `part_counts`: `DataFrame` containing the per-site and overall PCR counts
+///    `test_summaries`: `DataFrame` containing every TSR field, the mean and standard deviation
+///        derived from them and the same statistics computed from `df`
 ///    `test_information`: `DataFrame` containing the merged test information metadata
 ///    `full_test_information`: `dict` containing the full test information metadata
+///    `collisions`: `list` of `dict`s with each `test_num` shared by more than one test, the
+///        (`test_txt`, `test_type`) of those tests and whether `identity` keeps them `separated`
 ///
 /// # Example
 /// ```
@@ -98,18 +138,28 @@ impl PySTDF {
 ///    stdf['df']
 /// ````
 #[pyfunction]
-fn parse_stdf(fname: &str) -> PyResult<PySTDF> {
-    let pystdf = PySTDF::from_fname(&fname)?;
+#[pyo3(signature = (fname, identity = "test_num", naming = "num"))]
+fn parse_stdf(fname: &str, identity: &str, naming: &str) -> PyResult<PySTDF> {
+    let identity = parse_identity(identity)?;
+    let naming: ColumnNaming = naming.parse().map_err(PyValueError::new_err)?;
+    let pystdf = PySTDF::from_fname(fname, identity, &naming)?;
     Ok(pystdf)
 }
 
-/// get_rows(fname: str)
+/// Parse the `identity` argument of the Python functions into a `TestIdentity`
+fn parse_identity(identity: &str) -> PyResult<TestIdentity> {
+    identity.parse().map_err(PyValueError::new_err)
+}
+
+/// get_rows(fname: str, identity: str = "test_num")
 /// --
 ///
 /// Parse an STDF file specified by `fname` and return a list of rows
 ///
 /// `fname` must be a `str` and may not be a `Path`-like object.
 ///
+/// `identity` is as in `parse_stdf`.
+///
 /// Returns a list of dicts, where each dict represent a single row (i.e. part).
 /// Useful if you need only the row-formatted data. The list is fully realized,
 /// i.e. a proper list, not a generator.
@@ -121,18 +171,22 @@ fn parse_stdf(fname: &str) -> PyResult<PySTDF> {
 ///    rows[0]
 /// ````
 #[pyfunction]
-fn get_rows(fname: &str) -> PyResult<Vec<Row>> {
-    let test_data = TestData::from_fname(fname, false)?;
+#[pyo3(signature = (fname, identity = "test_num"))]
+fn get_rows(fname: &str, identity: &str) -> PyResult<Vec<Row>> {
+    let identity = parse_identity(identity)?;
+    let test_data = TestData::from_fname_with_identity(fname, false, identity)?;
     Ok(test_data.data)
 }
 
-/// get_raw_stdf(fname: str)
+/// get_raw_stdf(fname: str, identity: str = "test_num")
 /// --
 ///
 /// Parse an STDF file specified by `fname` into a dict structure
 ///
 /// `fname` must be a `str` and may not be a `Path`-like object.
 ///
+/// `identity` is as in `parse_stdf`.
+///
 /// Returns a nested `dict` representing the raw rust STDF object. Useful if you
 /// do not need the DataFrame representation and prefer a row-formatted representation.
 /// The entire `dict` is fully realized, i.e. there are no generators.
@@ -140,10 +194,13 @@ fn get_rows(fname: &str) -> PyResult<Vec<Row>> {
 ///        Results Record (file metadata)
 ///    `wafer_information`: `dict` describing the Wafer Information Records and Wafer
 ///        Results Records (wafer metadata)
+///    `wafer_configuration`: `dict` describing the Wafer Configuration Record, or `None`
 ///    `site_information`: `dict` describing site information
-///    `soft_bins`: `dict` of {sbin: SBR}
-///    `hard_bins`: `dict` of {hbin: HBR}
+///    `soft_bins`: `dict` of {(head_num, site_num, sbin): SBR}
+///    `hard_bins`: `dict` of {(head_num, site_num, hbin): HBR}
 ///    `pins`: `dict` of {pin_index: PMR}
+///    `part_counts`: `dict` of {(head_num, site_num): PCR}
+///    `test_summaries`: `list` of TSR
 ///    `test_data`: a `dict` describing all of the test results
 ///
 /// # Example
@@ -153,11 +210,742 @@ fn get_rows(fname: &str) -> PyResult<Vec<Row>> {
 ///    raw_stdf['master_information']
 /// ````
 #[pyfunction]
-fn get_raw_stdf(fname: &str) -> PyResult<STDF> {
-    let stdf = STDF::from_fname(fname, false)?;
+#[pyo3(signature = (fname, identity = "test_num"))]
+fn get_raw_stdf(fname: &str, identity: &str) -> PyResult<STDF> {
+    let identity = parse_identity(identity)?;
+    let stdf = STDF::from_fname_with_identity(fname, false, identity)?;
     Ok(stdf)
 }
 
+/// The result of validating an STDF file, for passing to Python
+#[derive(IntoPyObject)]
+struct PyValidationReport {
+    /// Whether the file has no errors
+    valid: bool,
+    /// The number of records read
+    n_records: usize,
+    n_errors: usize,
+    n_warnings: usize,
+    /// The `DataFrame` of the issues found, see `ValidationReport::to_df`
+    issues: PyDataFrame,
+}
+
+/// validate_stdf(fname: str)
+/// --
+///
+/// Check the STDF file `fname` against the STDF V4 specification
+///
+/// `fname` must be a `str` and may not be a `Path`-like object.
+///
+/// Returns a dict with keys and values:
+///    `valid`: `bool`, whether the file has no errors
+///    `n_records`: the number of records read
+///    `n_errors`: the number of errors found
+///    `n_warnings`: the number of warnings found
+///    `issues`: `DataFrame` of the issues found, with the `severity`, `offset`, `record_index`,
+///        `rtype` and `message` of each
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    report = sf.validate_stdf("my_stdf.stdf")
+///    report['issues']
+/// ````
+#[pyfunction]
+fn validate_stdf(fname: &str) -> PyResult<PyValidationReport> {
+    let report = validate_fname(fname)?;
+    Ok(PyValidationReport {
+        valid: report.is_valid(),
+        n_records: report.n_records,
+        n_errors: report.errors().count(),
+        n_warnings: report.warnings().count(),
+        issues: PyDataFrame(report.to_df()),
+    })
+}
+
+/// anonymize_stdf(fname: str, output: str, key: str, redaction: str = "hash",
+///     token: str = "REDACTED", fields: list[str] | None = None, test_names: bool = False,
+///     text: bool = False)
+/// --
+///
+/// Write a copy of the STDF file `fname` to `output` with the identifying strings redacted
+///
+/// `fname` and `output` must be `str`s and may not be `Path`-like objects.
+///
+/// `key` is the secret the pseudonyms are derived from, the same `key` always gives the same
+/// pseudonym for the same value.
+///
+/// `redaction` must be one of
+///    `hash`: replace fields by the keyed hash of their value
+///    `token`: replace fields by `token`
+///
+/// `fields` are the fields to redact, like `MIR.lot_id`. Defaults to the MIR, SDR, WIR, WRR and
+/// PRR fields identifying the customer, product, lot, wafer, part and hardware.
+///
+/// `test_names` and `text` also redact the test names and the DTR and GDR text respectively.
+///
+/// Returns the number of strings replaced. All numeric data is left untouched.
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    sf.anonymize_stdf("my_stdf.stdf", "anonymous.stdf", "secret")
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fname, output, key, redaction = "hash", token = "REDACTED", fields = None, test_names = false, text = false))]
+#[allow(clippy::too_many_arguments)]
+fn anonymize_stdf(
+    fname: &str,
+    output: &str,
+    key: &str,
+    redaction: &str,
+    token: &str,
+    fields: Option<Vec<String>>,
+    test_names: bool,
+    text: bool,
+) -> PyResult<usize> {
+    let redaction: Redaction = redaction.parse().map_err(PyValueError::new_err)?;
+    let anonymizer = Anonymizer {
+        key: key.as_bytes().to_vec(),
+        redaction,
+        token: token.to_string(),
+        fields: fields.unwrap_or_else(|| {
+            DEFAULT_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect()
+        }),
+        test_names,
+        text,
+    };
+    Ok(anonymizer.apply_fname(fname, output)?)
+}
+
+/// test_statistics(fname: str, group_by: str = "none", estimator: str = "classic",
+///     percentiles: list[float] | None = None, identity: str = "test_num")
+/// --
+///
+/// Compute the statistics and process capability of each parametric test in the STDF file `fname`
+///
+/// `fname` must be a `str` and may not be a `Path`-like object.
+///
+/// `group_by` may be one of `"none"`, `"head"`, `"site"` or `"wafer"`, giving one row per test
+/// and group.
+///
+/// `estimator` chooses the center and spread used for `cp` and `cpk`:
+///    `classic`: the mean and the sample standard deviation
+///    `robust`: the median and the median absolute deviation scaled to a standard deviation
+///
+/// `percentiles` are between 0 and 100, each giving a column like `p95`, and repeated ones are
+/// computed once. Defaults to 1, 5, 25, 50, 75, 95 and 99.
+///
+/// `identity` is as in `parse_stdf`.
+///
+/// Returns a `DataFrame` with the counts, `mean`, `std`, `median`, `robust_sigma`, `min`, `max`,
+/// percentiles, limits, and `cp` and `cpk` against the test limits and the spec limits.
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    stats = sf.test_statistics("my_stdf.stdf", group_by="site", estimator="robust")
+///    stats.sort("cpk")
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fname, group_by = "none", estimator = "classic", percentiles = None, identity = "test_num"))]
+fn test_statistics(
+    fname: &str,
+    group_by: &str,
+    estimator: &str,
+    percentiles: Option<Vec<f64>>,
+    identity: &str,
+) -> PyResult<PyDataFrame> {
+    let identity = parse_identity(identity)?;
+    let mut statistics = TestStatistics {
+        group_by: group_by.parse().map_err(PyValueError::new_err)?,
+        estimator: estimator.parse().map_err(PyValueError::new_err)?,
+        ..Default::default()
+    };
+    if let Some(percentiles) = percentiles {
+        statistics.percentiles = check_percentiles(&percentiles).map_err(PyValueError::new_err)?;
+    }
+    let stdf = STDF::from_fname_with_identity(fname, false, identity)?;
+    Ok(PyDataFrame(statistics.to_df(&stdf.test_data)))
+}
+
+/// The result of PAT on an STDF file, for passing to Python
+#[derive(IntoPyObject)]
+struct PyPatReport {
+    /// The `DataFrame` of the PAT limits, see `PatReport::limits_to_df`
+    limits: PyDataFrame,
+    /// The `DataFrame` of the outliers, see `PatReport::outliers_to_df`
+    outliers: PyDataFrame,
+    /// The number of parts rebinned
+    n_rebinned: usize,
+    /// The `DataFrame` containing the test results with the outliers rebinned
+    df: PyDataFrame,
+}
+
+/// pat_stdf(fname: str, mode: str = "static", reference: str | None = None, k: float = 6.0,
+///     estimator: str = "robust", min_parts: int = 30, test_nums: list[int] | None = None,
+///     pat_sbin: int | None = None, pat_hbin: int | None = None, identity: str = "test_num",
+///     naming: str = "num")
+/// --
+///
+/// Run Part Average Testing (PAT) per AEC-Q001 on the STDF file `fname`
+///
+/// `fname` and `reference` must be `str`s and may not be `Path`-like objects.
+///
+/// `mode` must be one of
+///    `static`: one set of limits from the parts of `reference`, or of `fname` if not given
+///    `dynamic_wafer`: a set of limits per wafer, from the parts of that wafer
+///    `dynamic_lot`: one set of limits from all parts of `fname`
+///
+/// The limits are `k` sigmas either side of the center, narrowed to the test limits. `estimator`
+/// is `robust` for the median and the interquartile range divided by 1.35, or `classic` for the
+/// mean and standard deviation. A test with fewer than `min_parts` passing results gets no
+/// limits. `test_nums` restricts PAT to those tests.
+///
+/// Outlier parts are failed and given `pat_sbin` and `pat_hbin`, where set.
+///
+/// `identity` and `naming` are as in `parse_stdf`.
+///
+/// Returns a dict with keys and values:
+///    `limits`: `DataFrame` of the PAT limits of each test (and wafer)
+///    `outliers`: `DataFrame` of each result of a passing part outside its PAT limits
+///    `n_rebinned`: the number of parts rebinned
+///    `df`: `DataFrame` containing the test results with the outliers rebinned
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    pat = sf.pat_stdf("my_stdf.stdf", mode="dynamic_wafer", pat_sbin=99, pat_hbin=9)
+///    pat['outliers']
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fname, mode = "static", reference = None, k = 6.0, estimator = "robust", min_parts = 30, test_nums = None, pat_sbin = None, pat_hbin = None, identity = "test_num", naming = "num"))]
+#[allow(clippy::too_many_arguments)]
+fn pat_stdf(
+    fname: &str,
+    mode: &str,
+    reference: Option<&str>,
+    k: f64,
+    estimator: &str,
+    min_parts: usize,
+    test_nums: Option<Vec<u32>>,
+    pat_sbin: Option<u16>,
+    pat_hbin: Option<u16>,
+    identity: &str,
+    naming: &str,
+) -> PyResult<PyPatReport> {
+    let identity = parse_identity(identity)?;
+    let naming: ColumnNaming = naming.parse().map_err(PyValueError::new_err)?;
+    let pat = Pat {
+        mode: mode.parse().map_err(PyValueError::new_err)?,
+        k,
+        estimator: estimator.parse().map_err(PyValueError::new_err)?,
+        min_parts,
+        test_nums,
+        pat_sbin,
+        pat_hbin,
+    };
+    let mut stdf = STDF::from_fname_with_identity(fname, false, identity)?;
+    let reference = reference
+        .map(|reference| STDF::from_fname_with_identity(reference, false, identity))
+        .transpose()?;
+    let report = pat.run(
+        &stdf.test_data,
+        reference.as_ref().map(|stdf| &stdf.test_data),
+    );
+    let n_rebinned = pat.rebin(&mut stdf.test_data, &report);
+    Ok(PyPatReport {
+        limits: PyDataFrame(report.limits_to_df()),
+        outliers: PyDataFrame(report.outliers_to_df()),
+        n_rebinned,
+        df: PyDataFrame(stdf.test_data.to_df(&naming)),
+    })
+}
+
+/// The result of spatial screening of an STDF file, for passing to Python
+#[derive(IntoPyObject)]
+struct PySpatialReport {
+    /// The `DataFrame` of the flagged dies, see `SpatialReport::to_df`
+    flags: PyDataFrame,
+    /// The number of dies inked
+    n_inked: usize,
+    /// The `DataFrame` containing the test results with the flagged dies inked
+    df: PyDataFrame,
+}
+
+/// spatial_screen(fname: str, gdbn: bool = True, nnr: bool = True,
+///     weights: list[list[float]] | None = None, threshold: float = 0.5, radius: int = 2,
+///     k: float = 6.0, test_nums: list[int] | None = None, ink_sbin: int | None = None,
+///     ink_hbin: int | None = None, identity: str = "test_num", naming: str = "num")
+/// --
+///
+/// Screen the dies of each wafer in the STDF file `fname` for spatial outliers
+///
+/// `fname` must be a `str` and may not be a `Path`-like object.
+///
+/// `gdbn` runs Good Die in Bad Neighborhood screening, flagging passing dies where the weighted
+/// fraction of failing neighbors is at least `threshold`. `weights` is a square matrix with an
+/// odd number of rows centered on the die, as the wafer map is viewed. Defaults to the 8 closest
+/// neighbors weighted equally.
+///
+/// `nnr` runs Nearest Neighbor Residual screening, flagging results of passing dies that differ
+/// from the median of their neighbors at most `radius` dies away by more than `k` robust sigmas of
+/// the wafer. `test_nums` restricts NNR to those tests.
+///
+/// The die orientation is taken from the WCR if there is one. Flagged dies are failed and given
+/// `ink_sbin` and `ink_hbin`, where set.
+///
+/// `identity` and `naming` are as in `parse_stdf`.
+///
+/// Returns a dict with keys and values:
+///    `flags`: `DataFrame` of the flagged dies with the `method` and `score` of each
+///    `n_inked`: the number of dies inked
+///    `df`: `DataFrame` containing the test results with the flagged dies inked
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    screen = sf.spatial_screen("my_stdf.stdf", nnr=False, ink_sbin=98)
+///    screen['flags']
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fname, gdbn = true, nnr = true, weights = None, threshold = 0.5, radius = 2, k = 6.0, test_nums = None, ink_sbin = None, ink_hbin = None, identity = "test_num", naming = "num"))]
+#[allow(clippy::too_many_arguments)]
+fn spatial_screen(
+    fname: &str,
+    gdbn: bool,
+    nnr: bool,
+    weights: Option<Vec<Vec<f64>>>,
+    threshold: f64,
+    radius: i16,
+    k: f64,
+    test_nums: Option<Vec<u32>>,
+    ink_sbin: Option<u16>,
+    ink_hbin: Option<u16>,
+    identity: &str,
+    naming: &str,
+) -> PyResult<PySpatialReport> {
+    let identity = parse_identity(identity)?;
+    let naming: ColumnNaming = naming.parse().map_err(PyValueError::new_err)?;
+    if let Some(weights) = &weights {
+        let n = weights.len();
+        if n.is_multiple_of(2) || weights.iter().any(|row| row.len() != n) {
+            return Err(PyValueError::new_err(
+                "weights must be a square matrix with an odd number of rows",
+            ));
+        }
+    }
+    let screen = SpatialScreen {
+        gdbn: gdbn.then(|| Gdbn {
+            weights: weights.unwrap_or(Gdbn::default().weights),
+            threshold,
+            ..Default::default()
+        }),
+        nnr: nnr.then(|| Nnr {
+            radius,
+            k,
+            test_nums,
+            ..Default::default()
+        }),
+        ink_sbin,
+        ink_hbin,
+    };
+    let mut stdf = STDF::from_fname_with_identity(fname, false, identity)?;
+    let report = screen.run(&stdf);
+    let n_inked = screen.ink(&mut stdf.test_data, &report);
+    Ok(PySpatialReport {
+        flags: PyDataFrame(report.to_df()),
+        n_inked,
+        df: PyDataFrame(stdf.test_data.to_df(&naming)),
+    })
+}
+
+/// The map of one wafer, for passing to Python
+#[derive(IntoPyObject)]
+struct PyWafermap {
+    wafer_id: String,
+    wafer_index: Option<u32>,
+    head_num: u8,
+    /// The `DataFrame` of the map laid out as the wafer is viewed, see `Wafermap::to_grid_df`
+    grid: PyDataFrame,
+    /// The map as text, see `Wafermap::to_ascii`
+    ascii: String,
+    /// The map as an SVG image, see `Wafermap::to_svg`
+    svg: String,
+}
+
+/// wafermaps(fname: str, layer: str = "sbin", identity: str = "test_num")
+/// --
+///
+/// Draw a map of each wafer in the STDF file `fname`
+///
+/// `fname` must be a `str` and may not be a `Path`-like object.
+///
+/// `layer` is what the dies show, one of `"sbin"`, `"hbin"`, `"fail_rate"` (1 for failing dies) or
+/// the `test_num` of a parametric test like `"1003"`. Bins are colored by the pass/fail of their
+/// SBR or HBR. The maps are laid out as the wafer is viewed, using the orientation of the WCR if
+/// there is one.
+///
+/// `identity` is as in `parse_stdf`.
+///
+/// Returns a list with a dict per wafer, with keys and values:
+///    `wafer_id`: the `wafer_id` of the wafer
+///    `wafer_index`: the index of the wafer in file order
+///    `head_num`: the `head_num` of the wafer
+///    `grid`: `DataFrame` with a `y_coord` column and a column per `x_coord` holding the bin or
+///        test result of each die
+///    `ascii`: the map as text
+///    `svg`: the map as an SVG image with a legend
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    maps = sf.wafermaps("my_stdf.stdf", layer="1003")
+///    print(maps[0]['ascii'])
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fname, layer = "sbin", identity = "test_num"))]
+fn wafermaps(fname: &str, layer: &str, identity: &str) -> PyResult<Vec<PyWafermap>> {
+    let identity = parse_identity(identity)?;
+    let layer: MapLayer = layer.parse().map_err(PyValueError::new_err)?;
+    let stdf = STDF::from_fname_with_identity(fname, false, identity)?;
+    let maps = wafermap::wafermaps(&stdf, layer)?;
+    Ok(maps
+        .iter()
+        .map(|map| PyWafermap {
+            wafer_id: map.wafer_id.clone(),
+            wafer_index: map.wafer_index,
+            head_num: map.head_num,
+            grid: PyDataFrame(map.to_grid_df()),
+            ascii: map.to_ascii(false),
+            svg: map.to_svg(),
+        })
+        .collect())
+}
+
+/// A composite map of the wafers of several STDF files, for passing to Python
+#[derive(IntoPyObject)]
+struct PyCompositeMap {
+    /// The number of wafers stacked
+    n_wafers: usize,
+    /// The `DataFrame` of the composite, one row per die position, see `CompositeMap::to_df`
+    df: PyDataFrame,
+    /// The `DataFrame` of the layer laid out as the wafer is viewed, see `Wafermap::to_grid_df`
+    grid: PyDataFrame,
+    /// The layer as text, see `Wafermap::to_ascii`
+    ascii: String,
+    /// The layer as an SVG image, see `Wafermap::to_svg`
+    svg: String,
+}
+
+/// composite_map(fnames: list[str], layer: str = "fail_rate", identity: str = "test_num")
+/// --
+///
+/// Stack the wafers of the STDF files `fnames` into a composite map, aligning dies by
+/// `x_coord` and `y_coord`
+///
+/// `fnames` must be `str`s and may not be `Path`-like objects.
+///
+/// `layer` is what the dies of the rendered map show, one of `"fail_rate"`, `"sbin"` or `"hbin"`
+/// for the most common bin, or the `test_num` of a parametric test like `"1003"` for the median
+/// result across the wafers. The maps are laid out as in `wafermaps`.
+///
+/// `identity` is as in `parse_stdf`.
+///
+/// Returns a dict with keys and values:
+///    `n_wafers`: the number of wafers stacked
+///    `df`: `DataFrame` with a row per die position and the columns `x_coord`, `y_coord`,
+///        `n_wafers`, `n_fail`, `fail_rate`, `sbin_mode`, `hbin_mode` and `median`
+///    `grid`: `DataFrame` of the `layer` with a `y_coord` column and a column per `x_coord`
+///    `ascii`: the `layer` as text
+///    `svg`: the `layer` as an SVG image with a legend
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    composite = sf.composite_map(["lot_a.stdf", "lot_b.stdf"])
+///    print(composite['ascii'])
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fnames, layer = "fail_rate", identity = "test_num"))]
+fn composite_map(fnames: Vec<String>, layer: &str, identity: &str) -> PyResult<PyCompositeMap> {
+    let identity = parse_identity(identity)?;
+    let layer: MapLayer = layer.parse().map_err(PyValueError::new_err)?;
+    let stdfs = fnames
+        .iter()
+        .map(|fname| STDF::from_fname_with_identity(fname, false, identity))
+        .collect::<std::io::Result<Vec<STDF>>>()?;
+    let stdfs: Vec<&STDF> = stdfs.iter().collect();
+    let test_num = match layer {
+        MapLayer::Test(test_num) => Some(test_num),
+        _ => None,
+    };
+    let composite = CompositeMap::new(&stdfs, test_num)?;
+    let map = composite.to_wafermap(layer)?;
+    Ok(PyCompositeMap {
+        n_wafers: composite.n_wafers,
+        df: PyDataFrame(composite.to_df()),
+        grid: PyDataFrame(map.to_grid_df()),
+        ascii: map.to_ascii(false),
+        svg: map.to_svg(),
+    })
+}
+
+/// The zone and reticle aggregation of an STDF file, for passing to Python
+#[derive(IntoPyObject)]
+struct PyZoneSummary {
+    /// The `DataFrame` of the location of each die, see `Zoning::locations_to_df`
+    locations: PyDataFrame,
+    /// The `DataFrame` of the yield per group, see `Zoning::yield_to_df`
+    #[pyo3(item("yield"))]
+    yield_: PyDataFrame,
+    /// The `DataFrame` of the parametric statistics per group, see `Zoning::statistics_to_df`
+    statistics: PyDataFrame,
+}
+
+/// zone_summary(fname: str, group_by: str = "zone", center_radius: float = 0.5,
+///     middle_radius: float = 0.8, reticle_step: tuple[int, int] = (1, 1),
+///     reticle_offset: tuple[int, int] = (0, 0), identity: str = "test_num")
+/// --
+///
+/// Aggregate the yield and parametric statistics of the STDF file `fname` per radial zone or per
+/// position within the reticle shot
+///
+/// `fname` must be a `str` and may not be a `Path`-like object.
+///
+/// Dies at most `center_radius` of the wafer radius from the center are in the `center` zone,
+/// other dies at most `middle_radius` in the `middle` zone, and the rest on the `edge`. The wafer
+/// center and size come from the WCR if there is one.
+///
+/// Reticle shots are `reticle_step` (x, y) dies, with a shot starting at the die
+/// `reticle_offset`.
+///
+/// `group_by` may be one of `"zone"` or `"reticle"`, the position within the shot.
+///
+/// `identity` is as in `parse_stdf`.
+///
+/// Returns a dict with keys and values:
+///    `locations`: `DataFrame` with the `radius`, `zone`, shot and reticle position of each die
+///    `yield`: `DataFrame` with `n_parts`, `n_pass` and `yield` per group
+///    `statistics`: `DataFrame` with the statistics of each parametric test per group
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    zones = sf.zone_summary("my_stdf.stdf", group_by="reticle", reticle_step=(2, 3))
+///    zones['yield']
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fname, group_by = "zone", center_radius = 0.5, middle_radius = 0.8, reticle_step = (1, 1), reticle_offset = (0, 0), identity = "test_num"))]
+fn zone_summary(
+    fname: &str,
+    group_by: &str,
+    center_radius: f64,
+    middle_radius: f64,
+    reticle_step: (i16, i16),
+    reticle_offset: (i16, i16),
+    identity: &str,
+) -> PyResult<PyZoneSummary> {
+    let identity = parse_identity(identity)?;
+    let group_by: ZoneGroupBy = group_by.parse().map_err(PyValueError::new_err)?;
+    if reticle_step.0 < 1 || reticle_step.1 < 1 {
+        return Err(PyValueError::new_err("reticle_step must be positive"));
+    }
+    let zoning = Zoning {
+        center_radius,
+        middle_radius,
+        reticle: Reticle {
+            step_x: reticle_step.0,
+            step_y: reticle_step.1,
+            offset_x: reticle_offset.0,
+            offset_y: reticle_offset.1,
+        },
+    };
+    let stdf = STDF::from_fname_with_identity(fname, false, identity)?;
+    Ok(PyZoneSummary {
+        locations: PyDataFrame(zoning.locations_to_df(&stdf)),
+        yield_: PyDataFrame(zoning.yield_to_df(&stdf, group_by)),
+        statistics: PyDataFrame(zoning.statistics_to_df(&stdf, group_by)),
+    })
+}
+
+/// export_wafermaps(fname: str, output: str, format: str = "tsk", hbin: bool = False,
+///     bin_codes: dict[int, str] | None = None, pass_code: str | None = None,
+///     fail_code: str | None = None, null_code: str = ".")
+/// --
+///
+/// Write a map file of the bins of each wafer in the STDF file `fname` into the directory `output`
+///
+/// `fname` must be a `str` and may not be a `Path`-like object.
+///
+/// `format` may be one of `"klarf"` (KLARF 1.2, with a defect at each failing die), `"e142"`
+/// (SEMI E142 XML) or `"tsk"` (a TSK-style text map). Each file is named after its `wafer_id`,
+/// with path separators replaced by `_` and the `wafer_index` appended if the `wafer_id` is
+/// shared by more than one wafer.
+///
+/// The soft bins are mapped, or the hard bins with `hbin`. Each die is written as the code of
+/// its bin in `bin_codes`, otherwise as `pass_code` or `fail_code` by the pass/fail of the bin,
+/// otherwise as the bin number. `null_code` is written where there is no die.
+///
+/// Returns the list of paths written.
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    sf.export_wafermaps("my_stdf.stdf", "maps", format="e142", pass_code="P", fail_code="X")
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fname, output, format = "tsk", hbin = false, bin_codes = None, pass_code = None, fail_code = None, null_code = "."))]
+#[allow(clippy::too_many_arguments)]
+fn export_wafermaps(
+    fname: &str,
+    output: &str,
+    format: &str,
+    hbin: bool,
+    bin_codes: Option<BinCodes>,
+    pass_code: Option<String>,
+    fail_code: Option<String>,
+    null_code: &str,
+) -> PyResult<Vec<String>> {
+    let map_export = MapExport {
+        format: format.parse().map_err(PyValueError::new_err)?,
+        hard_bins: hbin,
+        bin_codes: bin_codes.unwrap_or_default(),
+        pass_code,
+        fail_code,
+        null_code: null_code.to_string(),
+    };
+    let stdf = STDF::from_fname(fname, false)?;
+    let paths = map_export.write(&stdf, output)?;
+    Ok(paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect())
+}
+
+/// Several STDF files merged into one dataset, for passing to Python
+#[derive(IntoPyObject)]
+struct PyMergedStdf {
+    /// The `DataFrame` of the test results of every file, see `MergedStdf::to_df`
+    df: PyDataFrame,
+    /// The `DataFrame` of the merged test information metadata
+    test_information: PyDataFrame,
+    /// WIR and WRR information of every file
+    wafers: Vec<WaferInformation>,
+    /// The `DataFrame` of the test metadata conflicts, see `MergedStdf::conflicts_to_df`
+    conflicts: PyDataFrame,
+    /// The `test_num`s shared by more than one test
+    collisions: Vec<Collision>,
+}
+
+/// merge_stdfs(fnames: list[str], identity: str = "test_num", naming: str = "num",
+///     n_threads: int = 0)
+/// --
+///
+/// Parse the STDF files `fnames` and merge them into one dataset with the union of their tests
+///
+/// Each entry of `fnames` must be a `str` and may not be a `Path`-like object. The files are
+/// parsed on up to `n_threads` threads at once, or one per CPU if `n_threads` is 0.
+///
+/// The test columns are aligned by test, as told apart by `identity`. The test information of
+/// each test comes from the first file with it, and every later file with different limits,
+/// units or test name for it is listed in `conflicts`. The `wafer_index` of each wafer is unique
+/// across the files.
+///
+/// `identity` and `naming` are as in `parse_stdf`.
+///
+/// Returns a dict with keys and values:
+///    `df`: `DataFrame` containing the test results, with the `source_file` and
+///        `insertion_index` of each part
+///    `test_information`: `DataFrame` containing the merged test information metadata
+///    `wafers`: `list` of `dict`s describing the Wafer Information and Results Records
+///    `conflicts`: `DataFrame` with the `test_key`, `field`, `first_file`, `first_value`,
+///        `file` and `value` of each conflict
+///    `collisions`: `list` of `dict`s with each `test_num` shared by more than one test, as in
+///        `parse_stdf`
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    merged = sf.merge_stdfs(["wafer_1.stdf", "wafer_2.stdf", "retest.stdf"])
+///    merged['df']
+/// ````
+#[pyfunction]
+#[pyo3(signature = (fnames, identity = "test_num", naming = "num", n_threads = 0))]
+fn merge_stdfs(
+    fnames: Vec<String>,
+    identity: &str,
+    naming: &str,
+    n_threads: usize,
+) -> PyResult<PyMergedStdf> {
+    let identity = parse_identity(identity)?;
+    let naming: ColumnNaming = naming.parse().map_err(PyValueError::new_err)?;
+    let merged = MergedStdf::from_fnames(&fnames, identity, n_threads)?;
+    Ok(PyMergedStdf {
+        df: PyDataFrame(merged.to_df(&naming, false)),
+        test_information: PyDataFrame((&merged.test_data.test_information).into()),
+        conflicts: PyDataFrame(merged.conflicts_to_df()),
+        collisions: merged.test_data.collisions(),
+        wafers: merged.wafer_information,
+    })
+}
+
+/// The test program changes between two STDF files, for passing to Python
+#[derive(IntoPyObject)]
+struct PyProgramDiff {
+    n_added: usize,
+    n_removed: usize,
+    n_changed: usize,
+    /// The `DataFrame` of the changes, see `ProgramDiff::to_df`
+    changes: PyDataFrame,
+    /// The readable report of the changes
+    report: String,
+}
+
+/// diff_programs(old: str, new: str, identity: str = "test_num")
+/// --
+///
+/// Compare the test programs of the STDF files `old` and `new`
+///
+/// `old` and `new` must be `str`s and may not be `Path`-like objects.
+///
+/// Lists the tests added and removed, and the changed names, limits, units, scaling exponents and
+/// test types of the tests in both files, along with changes to the `job_nam` and `job_rev` of
+/// the MIR.
+///
+/// `identity` is as in `parse_stdf`.
+///
+/// Returns a dict with keys and values:
+///    `n_added`: the number of tests only in `new`
+///    `n_removed`: the number of tests only in `old`
+///    `n_changed`: the number of tests in both files that changed
+///    `changes`: `DataFrame` with the `kind`, `test_key`, `test_num`, `field`, `old` and `new`
+///        value of each change
+///    `report`: `str`, the changes as readable text
+///
+/// # Example
+/// ```python
+///    import stupidf as sf
+///    diff = sf.diff_programs("rev_a.stdf", "rev_b.stdf")
+///    print(diff['report'])
+/// ````
+#[pyfunction]
+#[pyo3(signature = (old, new, identity = "test_num"))]
+fn diff_programs(old: &str, new: &str, identity: &str) -> PyResult<PyProgramDiff> {
+    let identity = parse_identity(identity)?;
+    let diff = ProgramDiff::from_fnames(old, new, identity)?;
+    Ok(PyProgramDiff {
+        n_added: diff.count(ChangeKind::Added),
+        n_removed: diff.count(ChangeKind::Removed),
+        n_changed: diff.n_changed_tests(),
+        changes: PyDataFrame(diff.to_df()),
+        report: diff.to_string(),
+    })
+}
+
 #[pyfunction]
 fn get_mir(fname: &str) -> PyResult<MIR> {
     let mir = MIR::from_fname(&fname)?;
@@ -170,5 +958,16 @@ fn stupidf(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(parse_stdf, m)?)?;
     m.add_function(wrap_pyfunction!(get_rows, m)?)?;
     m.add_function(wrap_pyfunction!(get_raw_stdf, m)?)?;
+    m.add_function(wrap_pyfunction!(anonymize_stdf, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_stdf, m)?)?;
+    m.add_function(wrap_pyfunction!(test_statistics, m)?)?;
+    m.add_function(wrap_pyfunction!(pat_stdf, m)?)?;
+    m.add_function(wrap_pyfunction!(spatial_screen, m)?)?;
+    m.add_function(wrap_pyfunction!(wafermaps, m)?)?;
+    m.add_function(wrap_pyfunction!(export_wafermaps, m)?)?;
+    m.add_function(wrap_pyfunction!(composite_map, m)?)?;
+    m.add_function(wrap_pyfunction!(zone_summary, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_stdfs, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_programs, m)?)?;
     Ok(())
 }
@@ -0,0 +1,334 @@
+//! Comparison of the test programs of two STDF files, e.g. before and after a new revision
+//!
+//! The `FullMergedTestInformation` of the two files is compared test by test, along with the
+//! `job_nam` and `job_rev` of their `MIR`s. A `Change` is listed for:
+//!
+//! * every test only in the old file (removed) or only in the new file (added)
+//! * a changed test name, from the `TSR` or else the `test_txt` of the `PTR` (renamed)
+//! * changed test or specification limits
+//! * changed units
+//! * changed limit or result scaling exponents
+//! * a changed test type
+//!
+//! Tests are matched by their test key, so with a `TestIdentity` that includes the `test_txt` a
+//! renamed test shows up as removed and added instead.
+use std::{collections::BTreeSet, fmt, io};
+
+use polars::prelude::*;
+
+use crate::{
+    records::records::MIR,
+    test_information::{
+        FullMergedTestInformation, FullTestInformation, MergedTestInformation, TestIdentity,
+        TestKey, format_limit,
+    },
+};
+
+/// The kind of a `Change` between two test programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    /// A changed `job_nam` or `job_rev` in the `MIR`
+    Program,
+    /// A test only in the new file
+    Added,
+    /// A test only in the old file
+    Removed,
+    /// A changed test name
+    Renamed,
+    /// A changed test or specification limit
+    Limit,
+    /// Changed units
+    Units,
+    /// A changed scaling exponent
+    Scale,
+    /// A changed test type
+    TestType,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Program => write!(f, "program"),
+            Self::Added => write!(f, "added"),
+            Self::Removed => write!(f, "removed"),
+            Self::Renamed => write!(f, "renamed"),
+            Self::Limit => write!(f, "limit"),
+            Self::Units => write!(f, "units"),
+            Self::Scale => write!(f, "scale"),
+            Self::TestType => write!(f, "test_type"),
+        }
+    }
+}
+
+/// A single difference between two test programs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The test that changed, `None` for a `ChangeKind::Program` change
+    pub test_key: Option<TestKey>,
+    /// The changed field, e.g. `high_limit`, or `test_name` for an added or removed test
+    pub field: &'static str,
+    /// The value in the old file, empty if there is none
+    pub old: String,
+    /// The value in the new file, empty if there is none
+    pub new: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let none_if_empty = |value: &str| match value.is_empty() {
+            true => "none".to_string(),
+            false => format!("'{value}'"),
+        };
+        let test_key = self
+            .test_key
+            .as_ref()
+            .map(|test_key| test_key.to_string())
+            .unwrap_or_default();
+        match self.kind {
+            ChangeKind::Program => write!(
+                f,
+                "{} changed from {} to {}",
+                self.field,
+                none_if_empty(&self.old),
+                none_if_empty(&self.new)
+            ),
+            ChangeKind::Added => write!(f, "added test {test_key} {}", none_if_empty(&self.new)),
+            ChangeKind::Removed => {
+                write!(f, "removed test {test_key} {}", none_if_empty(&self.old))
+            }
+            _ => write!(
+                f,
+                "test {test_key}: {} changed from {} to {}",
+                self.field,
+                none_if_empty(&self.old),
+                none_if_empty(&self.new)
+            ),
+        }
+    }
+}
+
+/// The differences between the test programs of an old and a new STDF file
+#[derive(Debug, Clone, Default)]
+pub struct ProgramDiff {
+    /// The changes, `MIR` changes first and then by test key
+    pub changes: Vec<Change>,
+}
+
+impl ProgramDiff {
+    /// Compare the `MIR` and test information of the old file with those of the new file
+    pub fn new(
+        old_mir: &MIR,
+        old: &FullMergedTestInformation,
+        new_mir: &MIR,
+        new: &FullMergedTestInformation,
+    ) -> Self {
+        let mut changes = Vec::new();
+        let program_fields = [
+            ("job_nam", &old_mir.job_nam, &new_mir.job_nam),
+            ("job_rev", &old_mir.job_rev, &new_mir.job_rev),
+        ];
+        for (field, old_value, new_value) in program_fields {
+            if old_value != new_value {
+                changes.push(Change {
+                    kind: ChangeKind::Program,
+                    test_key: None,
+                    field,
+                    old: old_value.clone(),
+                    new: new_value.clone(),
+                });
+            }
+        }
+
+        let test_keys: BTreeSet<&TestKey> =
+            old.test_infos.keys().chain(new.test_infos.keys()).collect();
+        for test_key in test_keys {
+            match (old.test_infos.get(test_key), new.test_infos.get(test_key)) {
+                (Some(old_mti), Some(new_mti)) => {
+                    changes.extend(test_changes(test_key, old_mti, new_mti));
+                }
+                (Some(old_mti), None) => changes.push(Change {
+                    kind: ChangeKind::Removed,
+                    test_key: Some(test_key.clone()),
+                    field: "test_name",
+                    old: name(old_mti),
+                    new: String::new(),
+                }),
+                (None, Some(new_mti)) => changes.push(Change {
+                    kind: ChangeKind::Added,
+                    test_key: Some(test_key.clone()),
+                    field: "test_name",
+                    old: String::new(),
+                    new: name(new_mti),
+                }),
+                (None, None) => unreachable!("test keys come from one of the two files"),
+            }
+        }
+        Self { changes }
+    }
+
+    /// Compare the test programs of the STDF files `old_fname` and `new_fname`, telling tests
+    /// apart by `identity`
+    ///
+    /// Only the test information metadata and `MIR` are read, not the test results.
+    ///
+    /// # Error
+    /// If either file cannot be parsed or has no `MIR`, returns an `std::io::Error`
+    pub fn from_fnames(
+        old_fname: &str,
+        new_fname: &str,
+        identity: TestIdentity,
+    ) -> io::Result<Self> {
+        let old_mir = MIR::from_fname(old_fname)?;
+        let new_mir = MIR::from_fname(new_fname)?;
+        let old = FullTestInformation::from_fname_with_identity(old_fname, false, identity)?;
+        let new = FullTestInformation::from_fname_with_identity(new_fname, false, identity)?;
+        Ok(Self::new(&old_mir, &old.merge(), &new_mir, &new.merge()))
+    }
+
+    /// Whether the test programs are the same
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The number of changes of the `kind`
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .count()
+    }
+
+    /// The number of tests in both files that changed in any way
+    pub fn n_changed_tests(&self) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| !matches!(change.kind, ChangeKind::Added | ChangeKind::Removed))
+            .filter_map(|change| change.test_key.as_ref())
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+
+    /// Convert the `changes` into a `DataFrame`
+    ///
+    /// The columns are `kind`, `test_key`, `test_num`, `field`, `old` and `new`, with `test_key`
+    /// and `test_num` null for `MIR` changes.
+    pub fn to_df(&self) -> DataFrame {
+        let mut kinds: Vec<String> = Vec::new();
+        let mut test_keys: Vec<Option<String>> = Vec::new();
+        let mut test_nums: Vec<Option<u32>> = Vec::new();
+        let mut fields: Vec<&str> = Vec::new();
+        let mut olds: Vec<&str> = Vec::new();
+        let mut news: Vec<&str> = Vec::new();
+        for change in &self.changes {
+            kinds.push(change.kind.to_string());
+            test_keys.push(
+                change
+                    .test_key
+                    .as_ref()
+                    .map(|test_key| test_key.to_string()),
+            );
+            test_nums.push(change.test_key.as_ref().map(|test_key| test_key.test_num()));
+            fields.push(change.field);
+            olds.push(&change.old);
+            news.push(&change.new);
+        }
+        let columns = vec![
+            Column::new("kind".into(), kinds),
+            Column::new("test_key".into(), test_keys),
+            Column::new("test_num".into(), test_nums),
+            Column::new("field".into(), fields),
+            Column::new("old".into(), olds),
+            Column::new("new".into(), news),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+}
+
+impl fmt::Display for ProgramDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        write!(
+            f,
+            "{} tests added, {} removed, {} changed",
+            self.count(ChangeKind::Added),
+            self.count(ChangeKind::Removed),
+            self.n_changed_tests()
+        )
+    }
+}
+
+/// The changes to a test in both files
+fn test_changes(
+    test_key: &TestKey,
+    old: &MergedTestInformation,
+    new: &MergedTestInformation,
+) -> Vec<Change> {
+    let shared = old.differences(new).into_iter().map(|(field, old, new)| {
+        let kind = match field {
+            "test_name" => ChangeKind::Renamed,
+            "units" => ChangeKind::Units,
+            _ => ChangeKind::Limit,
+        };
+        (kind, field, old, new)
+    });
+    let fields = [
+        (
+            ChangeKind::Limit,
+            "lo_spec",
+            format_limit(old.lo_spec),
+            format_limit(new.lo_spec),
+        ),
+        (
+            ChangeKind::Limit,
+            "hi_spec",
+            format_limit(old.hi_spec),
+            format_limit(new.hi_spec),
+        ),
+        (
+            ChangeKind::Scale,
+            "llm_scal",
+            old.llm_scal.to_string(),
+            new.llm_scal.to_string(),
+        ),
+        (
+            ChangeKind::Scale,
+            "hlm_scal",
+            old.hlm_scal.to_string(),
+            new.hlm_scal.to_string(),
+        ),
+        (
+            ChangeKind::Scale,
+            "res_scal",
+            old.res_scal.to_string(),
+            new.res_scal.to_string(),
+        ),
+        (
+            ChangeKind::TestType,
+            "test_type",
+            old.test_type.to_string(),
+            new.test_type.to_string(),
+        ),
+    ];
+    let others = fields
+        .into_iter()
+        .filter(|(_, _, old_value, new_value)| old_value != new_value);
+    shared
+        .chain(others)
+        .map(|(kind, field, old, new)| Change {
+            kind,
+            test_key: Some(test_key.clone()),
+            field,
+            old,
+            new,
+        })
+        .collect()
+}
+
+/// The name of a test, empty if it has none
+fn name(mti: &MergedTestInformation) -> String {
+    mti.display_name().unwrap_or_default().to_string()
+}
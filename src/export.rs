@@ -0,0 +1,298 @@
+//! Export of a parsed `STDF` to Parquet, Arrow IPC (Feather) or CSV
+//!
+//! The test results are written as the wide `DataFrame` made from `TestData`, with the test
+//! columns named by `test_num` (see `ColumnNaming::Number`). Everything else in the `STDF` is
+//! written as a set of tables alongside it, so the whole `STDF` can be reconstructed:
+//!
+//! | table                   | contents                                                  |
+//! |-------------------------|-----------------------------------------------------------|
+//! | `master_information`    | `STDF::master_information_to_df`, a single row            |
+//! | `site_information`      | `STDF::site_information_to_df`, a single row              |
+//! | `wafer_information`     | `STDF::wafer_information_to_df`, one row per wafer        |
+//! | `soft_bins`             | `STDF::soft_bins_to_df`                                   |
+//! | `hard_bins`             | `STDF::hard_bins_to_df`                                   |
+//! | `part_counts`           | `STDF::part_counts_to_df`                                 |
+//! | `test_summaries`        | `STDF::test_summaries_to_df`                              |
+//! | `pins`                  | `STDF::pin_mapping_to_df`                                 |
+//! | `mpr_index_lookup`      | `STDF::mpr_index_lookup_to_df`, one row per multi-pin pin |
+//! | `test_information`      | the `FullMergedTestInformation` `DataFrame`               |
+//! | `full_test_information` | `STDF::full_test_information_to_df`, per site and head    |
+//!
+//! The `test_key` column of `test_information`, `mpr_index_lookup` and `full_test_information`
+//! holds the name of the results column of each test.
+//!
+//! The file format is chosen by `ExportFormat`. For Parquet, where the tables go is chosen by
+//! `ParquetLayout`:
+//!
+//! * `Metadata` stores each table in the key-value metadata of the results file, under the key
+//!   `stupidf.<table>`, as a JSON array with one object per row.
+//! * `Sidecar` writes each table to its own Parquet file next to the results file, named
+//!   `<stem>.<table>.parquet`. E.g. `lot.parquet` gets `lot.master_information.parquet`,
+//!   `lot.soft_bins.parquet`, etc.
+//!
+//! Arrow IPC and CSV always use sidecar files, with the extension `arrow` or `csv`.
+//!
+//! CSV cannot hold nested values, so the multi-pin test columns of the results are split into
+//! one column per pin (see `TestData::to_flat_df`), and the list columns of the other tables are
+//! written as their elements joined by `;`.
+use std::{fmt, fs::File, path::Path, str::FromStr};
+
+use itertools::Itertools;
+use polars::{frame::chunk_df_for_writing, prelude::*};
+use polars_parquet::parquet::metadata::KeyValue;
+
+use crate::{data::STDF, test_information::ColumnNaming};
+
+/// The prefix of the key-value metadata keys used by `ParquetLayout::Metadata`
+pub const METADATA_KEY_PREFIX: &str = "stupidf.";
+
+/// The number of rows per Parquet row group
+const ROW_GROUP_SIZE: usize = 512 * 512;
+
+/// The file format of an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Apache Parquet
+    #[default]
+    Parquet,
+    /// Arrow IPC, a.k.a. Feather v2
+    Ipc,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl ExportFormat {
+    /// The file extension used for sidecar files of this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Parquet => "parquet",
+            Self::Ipc => "arrow",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Parquet => write!(f, "parquet"),
+            Self::Ipc => write!(f, "ipc"),
+            Self::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+/// Parses an `ExportFormat` from `parquet`, `ipc` (or `arrow`, `feather`) or `csv`
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parquet" => Ok(Self::Parquet),
+            "ipc" | "arrow" | "feather" => Ok(Self::Ipc),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!(
+                "unknown export format {s}, expected one of parquet, ipc, csv"
+            )),
+        }
+    }
+}
+
+/// Where the non-result tables of an `STDF` are written when exporting to Parquet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParquetLayout {
+    /// Into the key-value metadata of the results file
+    #[default]
+    Metadata,
+    /// Into sibling Parquet files next to the results file
+    Sidecar,
+}
+
+impl fmt::Display for ParquetLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Metadata => write!(f, "metadata"),
+            Self::Sidecar => write!(f, "sidecar"),
+        }
+    }
+}
+
+/// Parses a `ParquetLayout` from `metadata` or `sidecar`
+impl FromStr for ParquetLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "metadata" => Ok(Self::Metadata),
+            "sidecar" => Ok(Self::Sidecar),
+            _ => Err(format!(
+                "unknown parquet layout {s}, expected one of metadata, sidecar"
+            )),
+        }
+    }
+}
+
+/// The named non-result tables of an `STDF`, in the order they are written
+pub fn stdf_tables(stdf: &STDF) -> Vec<(&'static str, DataFrame)> {
+    vec![
+        ("master_information", stdf.master_information_to_df()),
+        ("site_information", stdf.site_information_to_df()),
+        ("wafer_information", stdf.wafer_information_to_df()),
+        ("soft_bins", stdf.soft_bins_to_df()),
+        ("hard_bins", stdf.hard_bins_to_df()),
+        ("part_counts", stdf.part_counts_to_df()),
+        ("test_summaries", stdf.test_summaries_to_df()),
+        ("pins", stdf.pin_mapping_to_df()),
+        ("mpr_index_lookup", stdf.mpr_index_lookup_to_df()),
+        (
+            "test_information",
+            (&stdf.test_data.test_information).into(),
+        ),
+        ("full_test_information", stdf.full_test_information_to_df()),
+    ]
+}
+
+/// The name of the sibling file holding `table` for the results file `fname`
+///
+/// E.g. `out/lot.parquet`, `soft_bins` and `ExportFormat::Parquet` give
+/// `out/lot.soft_bins.parquet`.
+pub fn sidecar_fname(fname: &str, table: &str, format: ExportFormat) -> String {
+    let path = Path::new(fname);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{table}.{}", format.extension()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Write the `STDF` to `fname` in the given `format`
+///
+/// `layout` only applies to `ExportFormat::Parquet`. See the module documentation for the layout
+/// of the output.
+///
+/// # Error
+/// If any of the files cannot be written, returns a `PolarsError`
+pub fn write(
+    stdf: &STDF,
+    fname: &str,
+    format: ExportFormat,
+    layout: ParquetLayout,
+) -> PolarsResult<()> {
+    match format {
+        ExportFormat::Parquet => write_parquet(stdf, fname, layout),
+        ExportFormat::Ipc => write_ipc(stdf, fname),
+        ExportFormat::Csv => write_csv(stdf, fname),
+    }
+}
+
+/// Write the `STDF` to the Parquet file `fname`, with the other tables placed according to
+/// `layout`
+///
+/// See the module documentation for the layout of the output.
+///
+/// # Error
+/// If any of the files cannot be written, returns a `PolarsError`
+pub fn write_parquet(stdf: &STDF, fname: &str, layout: ParquetLayout) -> PolarsResult<()> {
+    let mut df: DataFrame = (&stdf.test_data).into();
+    let tables = stdf_tables(stdf);
+    match layout {
+        ParquetLayout::Metadata => {
+            let mut key_value_metadata = Vec::new();
+            for (table, mut table_df) in tables {
+                let mut json = Vec::new();
+                JsonWriter::new(&mut json)
+                    .with_json_format(JsonFormat::Json)
+                    .finish(&mut table_df)?;
+                let key = format!("{METADATA_KEY_PREFIX}{table}");
+                let value = String::from_utf8(json).expect("polars wrote invalid UTF-8 JSON");
+                key_value_metadata.push(KeyValue::new(key, value));
+            }
+            write_parquet_file(&mut df, fname, Some(key_value_metadata))
+        }
+        ParquetLayout::Sidecar => {
+            write_parquet_file(&mut df, fname, None)?;
+            for (table, mut table_df) in tables {
+                let table_fname = sidecar_fname(fname, table, ExportFormat::Parquet);
+                write_parquet_file(&mut table_df, &table_fname, None)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Write the `STDF` to the Arrow IPC file `fname`, with the other tables in sidecar files
+///
+/// # Error
+/// If any of the files cannot be written, returns a `PolarsError`
+pub fn write_ipc(stdf: &STDF, fname: &str) -> PolarsResult<()> {
+    let mut df: DataFrame = (&stdf.test_data).into();
+    IpcWriter::new(File::create(fname)?).finish(&mut df)?;
+    for (table, mut table_df) in stdf_tables(stdf) {
+        let table_fname = sidecar_fname(fname, table, ExportFormat::Ipc);
+        IpcWriter::new(File::create(table_fname)?).finish(&mut table_df)?;
+    }
+    Ok(())
+}
+
+/// Write the `STDF` to the CSV file `fname`, with the other tables in sidecar files
+///
+/// The multi-pin test results are split into one column per pin, and list columns of the other
+/// tables are joined by `;`.
+///
+/// # Error
+/// If any of the files cannot be written, returns a `PolarsError`
+pub fn write_csv(stdf: &STDF, fname: &str) -> PolarsResult<()> {
+    let mut df = stdf.test_data.to_flat_df(&ColumnNaming::Number);
+    CsvWriter::new(File::create(fname)?).finish(&mut df)?;
+    for (table, table_df) in stdf_tables(stdf) {
+        let mut table_df = join_list_columns(table_df)?;
+        let table_fname = sidecar_fname(fname, table, ExportFormat::Csv);
+        CsvWriter::new(File::create(table_fname)?).finish(&mut table_df)?;
+    }
+    Ok(())
+}
+
+/// Replace every list column of `df` with a string column of its elements joined by `;`
+fn join_list_columns(df: DataFrame) -> PolarsResult<DataFrame> {
+    let mut columns = Vec::with_capacity(df.width());
+    for column in df.get_columns() {
+        if !matches!(column.dtype(), DataType::List(_)) {
+            columns.push(column.clone());
+            continue;
+        }
+        let joined: Vec<Option<String>> = column
+            .list()?
+            .into_iter()
+            .map(|elements| {
+                elements.map(|elements| {
+                    elements
+                        .iter()
+                        .map(|value| match value.get_str() {
+                            Some(s) => s.to_string(),
+                            None => value.to_string(),
+                        })
+                        .join(";")
+                })
+            })
+            .collect();
+        columns.push(Column::new(column.name().clone(), joined));
+    }
+    DataFrame::new(columns)
+}
+
+/// Write a single `DataFrame` to the Parquet file `fname` with optional key-value metadata
+fn write_parquet_file(
+    df: &mut DataFrame,
+    fname: &str,
+    key_value_metadata: Option<Vec<KeyValue>>,
+) -> PolarsResult<()> {
+    let file = File::create(fname)?;
+    let chunked_df = chunk_df_for_writing(df, ROW_GROUP_SIZE)?;
+    let mut batched = ParquetWriter::new(file).batched(chunked_df.schema())?;
+    batched.write_batch(&chunked_df)?;
+    batched
+        .get_writer()
+        .lock()
+        .unwrap()
+        .end(key_value_metadata)?;
+    Ok(())
+}
@@ -0,0 +1,717 @@
+//! Rewriting of an STDF file keeping only a subset of wafers, sites, parts or tests
+//!
+//! Every record of a part (`PIR` through `PRR`) is buffered until its `PRR` decides whether the
+//! part is kept, including records like `DTR`s and `GDR`s read while the part is open.
+//! Everything else is kept consistent with the parts that remain:
+//!
+//! * The first kept `PTR` of each test gets the default limits, units and scaling of the first
+//!   `PTR` read, in case that one was in a removed part.
+//! * `WIR`/`WRR` pairs are only written for wafers with kept parts, and the `WRR` counts are
+//!   recomputed.
+//! * `SBR`, `HBR` and `PCR` counts are recomputed from the kept parts, both per site and for the
+//!   summary records with `head_num` 255.
+//! * `TSR`s of removed tests or sites are dropped, and the execution and fail counts of the rest
+//!   are recomputed. The statistics of parametric tests are recomputed from the kept `PTR`s,
+//!   while those of other tests are marked invalid in `opt_flag`.
+//! * `SDR`s only list the kept sites.
+//!
+//! The summary records are written just before the `MRR`, in their original order. All other
+//! records are passed through untouched.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+use crate::{
+    record_types::RecordType,
+    records::{RawRecord, Records, records::*},
+    writer::StdfWriter,
+};
+
+/// Which parts to keep according to their pass/fail result in the `PRR`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PartResult {
+    #[default]
+    All,
+    Passing,
+    Failing,
+}
+
+impl fmt::Display for PartResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Passing => write!(f, "pass"),
+            Self::Failing => write!(f, "fail"),
+        }
+    }
+}
+
+/// Parses a `PartResult` from `all`, `pass` or `fail`
+impl FromStr for PartResult {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "pass" => Ok(Self::Passing),
+            "fail" => Ok(Self::Failing),
+            _ => Err(format!(
+                "unknown part result {s}, expected one of all, pass, fail"
+            )),
+        }
+    }
+}
+
+/// Parse an inclusive range of test numbers, either a single `test_num` like `1000` or a range
+/// like `1000-2000`
+pub fn parse_test_range(s: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse = |x: &str| {
+        x.trim()
+            .parse::<u32>()
+            .map_err(|e| format!("invalid test_num {x}: {e}"))
+    };
+    match s.split_once('-') {
+        Some((start, end)) => Ok(parse(start)?..=parse(end)?),
+        None => {
+            let test_num = parse(s)?;
+            Ok(test_num..=test_num)
+        }
+    }
+}
+
+/// The subset of an STDF file to keep
+///
+/// Each `None` keeps everything. A part is kept only if it passes all of the wafer, site and
+/// part filters. The test filter only removes test records, never whole parts.
+#[derive(Debug, Clone, Default)]
+pub struct StdfFilter {
+    /// Keep only the parts on wafers with these `wafer_id`s
+    pub wafer_ids: Option<Vec<String>>,
+    /// Keep only the parts tested on these `site_num`s
+    pub site_nums: Option<Vec<u8>>,
+    /// Keep only the parts with these `part_id`s
+    pub part_ids: Option<Vec<String>>,
+    /// Keep only the passing or failing parts
+    pub part_result: PartResult,
+    /// Keep only the tests with a `test_num` in one of these ranges
+    pub test_nums: Option<Vec<RangeInclusive<u32>>>,
+}
+
+impl StdfFilter {
+    /// Whether the wafer with `wafer_id` is kept
+    pub fn keeps_wafer(&self, wafer_id: &str) -> bool {
+        self.wafer_ids
+            .as_ref()
+            .is_none_or(|wafer_ids| wafer_ids.iter().any(|w| w == wafer_id))
+    }
+
+    /// Whether the site `site_num` is kept
+    pub fn keeps_site(&self, site_num: u8) -> bool {
+        self.site_nums
+            .as_ref()
+            .is_none_or(|site_nums| site_nums.contains(&site_num))
+    }
+
+    /// Whether the test `test_num` is kept
+    pub fn keeps_test(&self, test_num: u32) -> bool {
+        self.test_nums
+            .as_ref()
+            .is_none_or(|ranges| ranges.iter().any(|range| range.contains(&test_num)))
+    }
+
+    /// Whether the part finished by `prr` on the wafer `wafer_id` is kept
+    ///
+    /// Parts outside of any wafer have no `wafer_id`, and are removed by a wafer filter.
+    pub fn keeps_part(&self, prr: &PRR, wafer_id: Option<&str>) -> bool {
+        let keeps_wafer = match wafer_id {
+            Some(wafer_id) => self.keeps_wafer(wafer_id),
+            None => self.wafer_ids.is_none(),
+        };
+        let keeps_result = match self.part_result {
+            PartResult::All => true,
+            PartResult::Passing => prr.pass(),
+            PartResult::Failing => !prr.pass(),
+        };
+        let keeps_part_id = self
+            .part_ids
+            .as_ref()
+            .is_none_or(|part_ids| part_ids.contains(&prr.part_id));
+        keeps_wafer && self.keeps_site(prr.site_num) && keeps_result && keeps_part_id
+    }
+
+    /// Filter the STDF file at `fname` into a new STDF file at `output`
+    ///
+    /// Returns the number of parts kept.
+    ///
+    /// # Error
+    /// If either file cannot be read or written, or `fname` is not a well-ordered STDF file with
+    /// decodable records, returns a `std::io::Error`
+    pub fn apply_fname(&self, fname: &str, output: &str) -> io::Result<usize> {
+        let records = Records::new(fname)?;
+        let writer = BufWriter::new(File::create(output)?);
+        self.apply(records, writer)
+    }
+
+    /// Filter the `records` of an STDF file, writing the result to `writer`
+    ///
+    /// The first record must be the `FAR`. Returns the number of parts kept.
+    ///
+    /// # Error
+    /// If `writer` cannot be written, a record cannot be decoded, or the `records` are not
+    /// well-ordered, returns a `std::io::Error`
+    pub fn apply<W: Write>(
+        &self,
+        mut records: impl Iterator<Item = RawRecord>,
+        writer: W,
+    ) -> io::Result<usize> {
+        let far = match records
+            .next()
+            .map(|raw_record| decode(&raw_record))
+            .transpose()?
+        {
+            Some(Some(Record::FAR(far))) => far,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "STDF file does not start with a FAR",
+                ));
+            }
+        };
+        let mut state = FilterState::new(self, StdfWriter::with_far(writer, &far)?);
+        for raw_record in records {
+            state.add(raw_record)?;
+        }
+        state.finish()
+    }
+}
+
+/// The part counts of a `PCR`, `WRR` or bin
+#[derive(Debug, Default)]
+pub(crate) struct PartCounts {
+    pub(crate) part_cnt: u32,
+    pub(crate) rtst_cnt: u32,
+    pub(crate) abrt_cnt: u32,
+    pub(crate) good_cnt: u32,
+}
+
+impl PartCounts {
+    pub(crate) fn add(&mut self, prr: &PRR) {
+        self.add_part_flg(prr.part_flg);
+    }
+
+    /// Count a part with the `part_flg` of its `PRR`
+    pub(crate) fn add_part_flg(&mut self, part_flg: u8) {
+        self.part_cnt += 1;
+        if part_flg & 0b11 != 0 {
+            self.rtst_cnt += 1;
+        }
+        if part_flg & 0b100 != 0 {
+            self.abrt_cnt += 1;
+        }
+        if part_flg & 0b1_1000 == 0 {
+            self.good_cnt += 1;
+        }
+    }
+}
+
+/// The execution counts and statistics of a test, as in a `TSR`
+#[derive(Debug, Default)]
+pub(crate) struct TestCounts {
+    pub(crate) exec_cnt: u32,
+    pub(crate) fail_cnt: u32,
+    pub(crate) n_results: u32,
+    pub(crate) test_min: f32,
+    pub(crate) test_max: f32,
+    pub(crate) tst_sums: f32,
+    pub(crate) tst_sqrs: f32,
+}
+
+impl TestCounts {
+    pub(crate) fn add(&mut self, passed: bool, result: Option<f32>) {
+        self.exec_cnt += 1;
+        if !passed {
+            self.fail_cnt += 1;
+        }
+        if let Some(result) = result {
+            if self.n_results == 0 || result < self.test_min {
+                self.test_min = result;
+            }
+            if self.n_results == 0 || result > self.test_max {
+                self.test_max = result;
+            }
+            self.n_results += 1;
+            self.tst_sums += result;
+            self.tst_sqrs += result * result;
+        }
+    }
+
+    /// Add the counts of `other`, e.g. of another test type sharing the `test_num`
+    pub(crate) fn merge(&mut self, other: &TestCounts) {
+        self.exec_cnt += other.exec_cnt;
+        self.fail_cnt += other.fail_cnt;
+        if other.n_results > 0 {
+            if self.n_results == 0 || other.test_min < self.test_min {
+                self.test_min = other.test_min;
+            }
+            if self.n_results == 0 || other.test_max > self.test_max {
+                self.test_max = other.test_max;
+            }
+            self.n_results += other.n_results;
+            self.tst_sums += other.tst_sums;
+            self.tst_sqrs += other.tst_sqrs;
+        }
+    }
+}
+
+/// A wafer whose `WIR` has been read but not necessarily written yet
+struct OpenWafer {
+    wir: RawRecord,
+    wafer_id: String,
+    written: bool,
+    n_parts: usize,
+    counts: PartCounts,
+}
+
+/// The state of a filter while iterating through the records
+struct FilterState<'a, W: Write> {
+    filter: &'a StdfFilter,
+    writer: StdfWriter<W>,
+    // The records of the parts in progress indexed by (`head_num`, `site_num`)
+    parts: HashMap<(u8, u8), Vec<RawRecord>>,
+    // The (`head_num`, `site_num`) of the part that got the last record
+    last_part: Option<(u8, u8)>,
+    // The first `PTR` of each test indexed by (`test_num`, `head_num`, `site_num`), holding the
+    // defaults of the optional fields of the later ones
+    ptr_defaults: HashMap<(u32, u8, u8), PTR>,
+    // The tests indexed like `ptr_defaults` with a `PTR` already written
+    written_ptrs: HashSet<(u32, u8, u8)>,
+    // The open wafers indexed by `head_num`
+    wafers: HashMap<u8, OpenWafer>,
+    // The summary records, written just before the MRR
+    summaries: Vec<RawRecord>,
+    // The part counts indexed by (`head_num`, `site_num`), or (255, 255) for all parts
+    part_counts: HashMap<(u8, u8), PartCounts>,
+    // The soft bin counts indexed by (`head_num`, `site_num`, `sbin_num`)
+    sbin_counts: HashMap<(u8, u8, u16), u32>,
+    // The hard bin counts indexed by (`head_num`, `site_num`, `hbin_num`)
+    hbin_counts: HashMap<(u8, u8, u16), u32>,
+    // The test counts indexed by (`head_num`, `site_num`, `test_num`, `test_typ`)
+    test_counts: HashMap<(u8, u8, u32, char), TestCounts>,
+    n_parts: usize,
+}
+
+impl<'a, W: Write> FilterState<'a, W> {
+    fn new(filter: &'a StdfFilter, writer: StdfWriter<W>) -> Self {
+        Self {
+            filter,
+            writer,
+            parts: HashMap::new(),
+            last_part: None,
+            ptr_defaults: HashMap::new(),
+            written_ptrs: HashSet::new(),
+            wafers: HashMap::new(),
+            summaries: Vec::new(),
+            part_counts: HashMap::new(),
+            sbin_counts: HashMap::new(),
+            hbin_counts: HashMap::new(),
+            test_counts: HashMap::new(),
+            n_parts: 0,
+        }
+    }
+
+    fn add(&mut self, raw_record: RawRecord) -> io::Result<()> {
+        match raw_record.rtype {
+            RecordType::SDR => {
+                let Some(Record::SDR(mut sdr)) = decode(&raw_record)? else {
+                    unreachable!()
+                };
+                sdr.site_num
+                    .retain(|site_num| self.filter.keeps_site(*site_num));
+                if !sdr.site_num.is_empty() {
+                    sdr.site_cnt = sdr.site_num.len() as u8;
+                    self.writer.write_raw_record(&RawRecord::try_from(&sdr)?)?;
+                }
+            }
+            RecordType::WIR => {
+                let Some(Record::WIR(wir)) = decode(&raw_record)? else {
+                    unreachable!()
+                };
+                let wafer = OpenWafer {
+                    wir: raw_record,
+                    wafer_id: wir.wafer_id,
+                    written: false,
+                    n_parts: 0,
+                    counts: PartCounts::default(),
+                };
+                self.wafers.insert(wir.head_num, wafer);
+            }
+            RecordType::WRR => {
+                let Some(Record::WRR(mut wrr)) = decode(&raw_record)? else {
+                    unreachable!()
+                };
+                if let Some(mut wafer) = self.wafers.remove(&wrr.head_num) {
+                    if wafer.n_parts == 0 && self.filter.keeps_wafer(&wafer.wafer_id) {
+                        self.writer.write_raw_record(&wafer.wir)?;
+                        wafer.written = true;
+                    }
+                    if wafer.written {
+                        wrr.part_cnt = wafer.counts.part_cnt;
+                        wrr.rtst_cnt = wafer.counts.rtst_cnt;
+                        wrr.abrt_cnt = wafer.counts.abrt_cnt;
+                        wrr.good_cnt = wafer.counts.good_cnt;
+                        wrr.func_cnt = u32::MAX;
+                        // up to `good_cnt`, even if the original omitted the counts
+                        wrr.n_optional = wrr.n_optional.max(3);
+                        self.writer.write_raw_record(&RawRecord::try_from(&wrr)?)?;
+                    }
+                }
+            }
+            RecordType::PIR => {
+                let Some(Record::PIR(pir)) = decode(&raw_record)? else {
+                    unreachable!()
+                };
+                self.parts
+                    .insert((pir.head_num, pir.site_num), vec![raw_record]);
+                self.last_part = Some((pir.head_num, pir.site_num));
+            }
+            RecordType::PTR | RecordType::MPR | RecordType::FTR => {
+                let (test_num, head_num, site_num) = test_record_ids(&raw_record)?;
+                if !self.filter.keeps_test(test_num) {
+                    return Ok(());
+                }
+                let key = (test_num, head_num, site_num);
+                if raw_record.rtype == RecordType::PTR && !self.ptr_defaults.contains_key(&key) {
+                    match decode(&raw_record)? {
+                        Some(Record::PTR(ptr)) if ptr.has_optional_data() => {
+                            self.ptr_defaults.insert(key, ptr);
+                        }
+                        _ => {}
+                    }
+                }
+                match self.parts.get_mut(&(head_num, site_num)) {
+                    Some(part) => {
+                        part.push(raw_record);
+                        self.last_part = Some((head_num, site_num));
+                    }
+                    None if self.filter.keeps_site(site_num) => {
+                        self.write_with_defaults(&raw_record)?
+                    }
+                    None => {}
+                }
+            }
+            RecordType::PRR => {
+                let Some(Record::PRR(prr)) = decode(&raw_record)? else {
+                    unreachable!()
+                };
+                let part = self
+                    .parts
+                    .remove(&(prr.head_num, prr.site_num))
+                    .unwrap_or_default();
+                self.finish_part(raw_record, prr, part)?;
+            }
+            RecordType::SBR | RecordType::HBR | RecordType::PCR | RecordType::TSR => {
+                self.summaries.push(raw_record);
+            }
+            RecordType::MRR => {
+                self.write_summaries()?;
+                self.writer.write_raw_record(&raw_record)?;
+            }
+            _ => match self.last_part.and_then(|key| self.parts.get_mut(&key)) {
+                Some(part) => part.push(raw_record),
+                None => self.writer.write_raw_record(&raw_record)?,
+            },
+        }
+        Ok(())
+    }
+
+    /// Write a record, filling in the first written `PTR` of each test from its `ptr_defaults`
+    fn write_with_defaults(&mut self, raw_record: &RawRecord) -> io::Result<()> {
+        let default = match raw_record.rtype {
+            RecordType::PTR => {
+                let key = test_record_ids(raw_record)?;
+                match self.written_ptrs.insert(key) {
+                    true => self.ptr_defaults.get(&key),
+                    false => None,
+                }
+            }
+            _ => None,
+        };
+        let record = match default {
+            Some(_) => decode(raw_record)?,
+            None => None,
+        };
+        match (default, record) {
+            (Some(default), Some(Record::PTR(mut ptr))) => {
+                fill_defaults(&mut ptr, default);
+                self.writer.write_raw_record(&RawRecord::try_from(&ptr)?)
+            }
+            _ => self.writer.write_raw_record(raw_record),
+        }
+    }
+
+    /// Write out or drop a part, depending on the filter
+    ///
+    /// The `PRR` is only re-encoded from `prr` if the test filter changes its `num_test`,
+    /// otherwise `raw_prr` is written untouched.
+    fn finish_part(
+        &mut self,
+        raw_prr: RawRecord,
+        mut prr: PRR,
+        part: Vec<RawRecord>,
+    ) -> io::Result<()> {
+        let wafer_id = self.wafers.get_mut(&prr.head_num).map(|wafer| {
+            // every part is counted, to tell wafers emptied by the filter from empty wafers
+            wafer.n_parts += 1;
+            wafer.wafer_id.clone()
+        });
+        if !self.filter.keeps_part(&prr, wafer_id.as_deref()) {
+            return Ok(());
+        }
+
+        if let Some(wafer) = self.wafers.get_mut(&prr.head_num) {
+            if !wafer.written {
+                self.writer.write_raw_record(&wafer.wir)?;
+                wafer.written = true;
+            }
+            wafer.counts.add(&prr);
+        }
+        let mut num_test: u16 = 0;
+        for raw_record in &part {
+            self.write_with_defaults(raw_record)?;
+            let record = decode(raw_record)?;
+            if let Some((test_typ, test_num, passed, result)) =
+                record.as_ref().and_then(test_result)
+            {
+                num_test = num_test.saturating_add(1);
+                for (head_num, site_num) in [(prr.head_num, prr.site_num), (255, 255)] {
+                    self.test_counts
+                        .entry((head_num, site_num, test_num, test_typ))
+                        .or_default()
+                        .add(passed, result);
+                }
+            }
+        }
+        if self.filter.test_nums.is_some() && prr.num_test != num_test {
+            prr.num_test = num_test;
+            self.writer.write_raw_record(&RawRecord::try_from(&prr)?)?;
+        } else {
+            self.writer.write_raw_record(&raw_prr)?;
+        }
+
+        for (head_num, site_num) in [(prr.head_num, prr.site_num), (255, 255)] {
+            self.part_counts
+                .entry((head_num, site_num))
+                .or_default()
+                .add(&prr);
+            *self
+                .sbin_counts
+                .entry((head_num, site_num, prr.soft_bin))
+                .or_default() += 1;
+            *self
+                .hbin_counts
+                .entry((head_num, site_num, prr.hard_bin))
+                .or_default() += 1;
+        }
+        self.n_parts += 1;
+        Ok(())
+    }
+
+    /// Write the summary records with recomputed counts
+    fn write_summaries(&mut self) -> io::Result<()> {
+        for raw_record in std::mem::take(&mut self.summaries) {
+            let record = decode(&raw_record)?;
+            let key = |head_num: u8, site_num: u8| match head_num {
+                255 => (255, 255),
+                _ => (head_num, site_num),
+            };
+            let record = match record {
+                Some(Record::SBR(mut sbr)) => {
+                    let (head_num, site_num) = key(sbr.head_num, sbr.site_num);
+                    let count = self.sbin_counts.get(&(head_num, site_num, sbr.sbin_num));
+                    sbr.sbin_cnt = count.copied().unwrap_or(0);
+                    self.keeps_summary(head_num, site_num)
+                        .then_some(Record::SBR(sbr))
+                }
+                Some(Record::HBR(mut hbr)) => {
+                    let (head_num, site_num) = key(hbr.head_num, hbr.site_num);
+                    let count = self.hbin_counts.get(&(head_num, site_num, hbr.hbin_num));
+                    hbr.hbin_cnt = count.copied().unwrap_or(0);
+                    self.keeps_summary(head_num, site_num)
+                        .then_some(Record::HBR(hbr))
+                }
+                Some(Record::PCR(mut pcr)) => {
+                    let (head_num, site_num) = key(pcr.head_num, pcr.site_num);
+                    let default = PartCounts::default();
+                    let counts = self
+                        .part_counts
+                        .get(&(head_num, site_num))
+                        .unwrap_or(&default);
+                    pcr.part_cnt = counts.part_cnt;
+                    pcr.rtst_cnt = counts.rtst_cnt;
+                    pcr.abrt_cnt = counts.abrt_cnt;
+                    pcr.good_cnt = counts.good_cnt;
+                    pcr.func_cnt = u32::MAX;
+                    pcr.n_optional = pcr.n_optional.max(3);
+                    self.keeps_summary(head_num, site_num)
+                        .then_some(Record::PCR(pcr))
+                }
+                Some(Record::TSR(mut tsr)) => {
+                    let (head_num, site_num) = key(tsr.head_num, tsr.site_num);
+                    // a TSR without a test type summarizes every test type of its test_num
+                    let test_typs = match tsr.test_typ {
+                        'P' | 'F' | 'M' => vec![tsr.test_typ],
+                        _ => vec!['P', 'F', 'M'],
+                    };
+                    let mut counts = TestCounts::default();
+                    for test_typ in test_typs {
+                        if let Some(test_counts) =
+                            self.test_counts
+                                .get(&(head_num, site_num, tsr.test_num, test_typ))
+                        {
+                            counts.merge(test_counts);
+                        }
+                    }
+                    tsr.exec_cnt = counts.exec_cnt;
+                    tsr.fail_cnt = counts.fail_cnt;
+                    tsr.n_optional = tsr.n_optional.max(2);
+                    if counts.n_results > 0 {
+                        // up to `tst_sqrs`, with the `opt_flag` marking them valid
+                        tsr.n_optional = u8::MAX;
+                        tsr.test_min = counts.test_min;
+                        tsr.test_max = counts.test_max;
+                        tsr.tst_sums = counts.tst_sums;
+                        tsr.tst_sqrs = counts.tst_sqrs;
+                        tsr.opt_flag &= !0b0011_0011;
+                    } else {
+                        tsr.opt_flag |= 0b0011_0011;
+                    }
+                    (self.keeps_summary(head_num, site_num) && self.filter.keeps_test(tsr.test_num))
+                        .then_some(Record::TSR(tsr))
+                }
+                record => record,
+            };
+            if let Some(record) = record {
+                self.writer.write_record(&record)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the summary record for (`head_num`, `site_num`) is kept
+    fn keeps_summary(&self, head_num: u8, site_num: u8) -> bool {
+        head_num == 255 || self.filter.keeps_site(site_num)
+    }
+
+    fn finish(mut self) -> io::Result<usize> {
+        self.write_summaries()?;
+        self.writer.finish()?;
+        Ok(self.n_parts)
+    }
+}
+
+/// Get the (`test_num`, `head_num`, `site_num`) of a `PTR`, `MPR` or `FTR` without resolving it
+///
+/// All three start with the same fields.
+fn test_record_ids(raw_record: &RawRecord) -> io::Result<(u32, u8, u8)> {
+    match raw_record.contents.get(..6) {
+        Some(&[b0, b1, b2, b3, head_num, site_num]) => {
+            Ok((u32::from_le_bytes([b0, b1, b2, b3]), head_num, site_num))
+        }
+        _ => Err(decode_error(
+            raw_record,
+            format!(
+                "expected 6 bytes, but there are only {}",
+                raw_record.contents.len()
+            ),
+        )),
+    }
+}
+
+/// Resolve a `RawRecord`, naming the record type and offset if it cannot be decoded
+pub(crate) fn decode(raw_record: &RawRecord) -> io::Result<Option<Record>> {
+    raw_record
+        .try_resolve()
+        .map_err(|e| decode_error(raw_record, e))
+}
+
+fn decode_error(raw_record: &RawRecord, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "cannot decode the {:?} at offset {}: {e}",
+            raw_record.rtype, raw_record.offset
+        ),
+    )
+}
+
+/// Fill in the optional fields `ptr` omits from `default`, the first `PTR` of the same test
+///
+/// A record without any optional fields gets all of them. Otherwise the result scaling, either
+/// limit with its scaling, the units and the formats are taken from `default` where the
+/// `opt_flag` or an empty string marks them as using the default, and the `opt_flag` bits are
+/// set to those of `default`.
+fn fill_defaults(ptr: &mut PTR, default: &PTR) {
+    if !ptr.has_optional_data() {
+        ptr.n_optional = default.n_optional;
+        ptr.opt_flag = default.opt_flag;
+        ptr.res_scal = default.res_scal;
+        ptr.llm_scal = default.llm_scal;
+        ptr.hlm_scal = default.hlm_scal;
+        ptr.lo_limit = default.lo_limit;
+        ptr.hi_limit = default.hi_limit;
+        ptr.units = default.units.clone();
+        ptr.c_resfmt = default.c_resfmt.clone();
+        ptr.c_llmfmt = default.c_llmfmt.clone();
+        ptr.c_hlmfmt = default.c_hlmfmt.clone();
+        ptr.lo_spec = default.lo_spec;
+        ptr.hi_spec = default.hi_spec;
+        return;
+    }
+    let copy_flag = |opt_flag: u8, bits: u8| opt_flag & !bits | default.opt_flag & bits;
+    if ptr.opt_flag & 0b0000_0001 != 0 {
+        ptr.res_scal = default.res_scal;
+        ptr.opt_flag = copy_flag(ptr.opt_flag, 0b0000_0001);
+    }
+    if ptr.opt_flag & 0b0001_0000 != 0 {
+        ptr.llm_scal = default.llm_scal;
+        ptr.lo_limit = default.lo_limit;
+        ptr.opt_flag = copy_flag(ptr.opt_flag, 0b0101_0000);
+    }
+    if ptr.opt_flag & 0b0010_0000 != 0 {
+        ptr.hlm_scal = default.hlm_scal;
+        ptr.hi_limit = default.hi_limit;
+        ptr.opt_flag = copy_flag(ptr.opt_flag, 0b1010_0000);
+    }
+    for (value, default) in [
+        (&mut ptr.units, &default.units),
+        (&mut ptr.c_resfmt, &default.c_resfmt),
+        (&mut ptr.c_llmfmt, &default.c_llmfmt),
+        (&mut ptr.c_hlmfmt, &default.c_hlmfmt),
+    ] {
+        if value.is_empty() {
+            value.clone_from(default);
+        }
+    }
+    // the fields filled in past the end of `ptr` are encoded too
+    ptr.n_optional = ptr.n_optional.max(default.n_optional);
+}
+
+/// Get the (`test_typ`, `test_num`, passed, result) of a test record
+///
+/// Only `PTR`s with a valid `result` have a result. Returns `None` for other record types.
+pub(crate) fn test_result(record: &Record) -> Option<(char, u32, bool, Option<f32>)> {
+    match record {
+        Record::PTR(ptr) => {
+            let result = (ptr.test_flg & 0b10 == 0).then_some(ptr.result);
+            Some(('P', ptr.test_num, ptr.pass(), result))
+        }
+        Record::MPR(mpr) => Some(('M', mpr.test_num, mpr.pass(), None)),
+        Record::FTR(ftr) => Some(('F', ftr.test_num, ftr.get_passfail(), None)),
+        _ => None,
+    }
+}
@@ -0,0 +1,509 @@
+//! Generation of synthetic, realistic looking STDF files
+//!
+//! A `GeneratorConfig` describes the lots, wafers, dies, sites and tests, and is turned into one
+//! STDF file per lot through the `StdfBuilder`. Every die is tested on the sites in turn, the
+//! results are drawn from the distribution of each test, and parts failing any test are binned
+//! by the `BinRule` matching their first failing test. A fraction of the failing dies of each
+//! wafer are retested at the end of the wafer.
+//!
+//! The generation is reproducible: the same `GeneratorConfig`, including its `seed`, always gives
+//! the same files.
+//!
+//! The config can be read from JSON, where every field is optional. E.g.
+//!
+//! ```json
+//! {
+//!     "lot_id": "DEMO",
+//!     "n_wafers": 2,
+//!     "n_dies": 200,
+//!     "tests": [
+//!         {"type": "parametric", "test_num": 100, "test_nam": "VDD", "units": "V",
+//!          "lo_limit": 0.9, "hi_limit": 1.1,
+//!          "distribution": {"kind": "normal", "mean": 1.0, "std_dev": 0.03}},
+//!         {"type": "functional", "test_num": 200, "test_nam": "SCAN", "fail_rate": 0.01}
+//!     ],
+//!     "bin_rules": [
+//!         {"first_test": 100, "last_test": 199, "hard_bin": 2, "soft_bin": 20, "name": "DC"}
+//!     ]
+//! }
+//! ```
+use std::{fs::File, io, path::Path};
+
+use rand::{Rng, SeedableRng, distr::weighted::WeightedIndex, rngs::StdRng};
+use rand_distr::{Distribution, Normal};
+use serde::Deserialize;
+
+use crate::builder::{Part, StdfBuilder};
+
+/// The distribution of the values measured by a test
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValueDistribution {
+    /// A normal distribution
+    Normal { mean: f32, std_dev: f32 },
+    /// A uniform distribution over [`low`, `high`]
+    Uniform { low: f32, high: f32 },
+    /// A discrete distribution over `values`, each drawn with probability proportional to its
+    /// weight in `weights`
+    Custom { values: Vec<f32>, weights: Vec<f64> },
+}
+
+/// A test to generate results for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TestSpec {
+    /// A parametric test, failing when the drawn value is outside of the limits
+    Parametric {
+        test_num: u32,
+        test_nam: String,
+        #[serde(default)]
+        units: String,
+        lo_limit: f32,
+        hi_limit: f32,
+        distribution: ValueDistribution,
+    },
+    /// A functional test, failing with probability `fail_rate`
+    Functional {
+        test_num: u32,
+        test_nam: String,
+        fail_rate: f64,
+    },
+    /// A multi-pin test on `n_pins` pins, failing when any drawn value is outside of the limits
+    MultiPin {
+        test_num: u32,
+        test_nam: String,
+        #[serde(default)]
+        units: String,
+        lo_limit: f32,
+        hi_limit: f32,
+        n_pins: u16,
+        distribution: ValueDistribution,
+    },
+}
+
+impl TestSpec {
+    /// The `test_num` of the test
+    pub fn test_num(&self) -> u32 {
+        match self {
+            Self::Parametric { test_num, .. }
+            | Self::Functional { test_num, .. }
+            | Self::MultiPin { test_num, .. } => *test_num,
+        }
+    }
+}
+
+/// The bins of the failing parts whose first failing test is in [`first_test`, `last_test`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct BinRule {
+    pub first_test: u32,
+    pub last_test: u32,
+    pub hard_bin: u16,
+    pub soft_bin: u16,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// The description of the STDF files to generate
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GeneratorConfig {
+    /// The `lot_id`, suffixed with the lot number if there is more than one lot
+    pub lot_id: String,
+    /// The number of lots, i.e. files
+    pub n_lots: usize,
+    /// The number of wafers per lot
+    pub n_wafers: usize,
+    /// The number of dies per wafer, laid out in a disc
+    pub n_dies: usize,
+    /// The number of test sites
+    pub n_sites: u8,
+    /// The seed of the random number generator
+    pub seed: u64,
+    /// The `start_t` of each lot
+    pub start_t: u32,
+    /// The fraction of failing dies that are retested
+    pub retest_fraction: f64,
+    /// Whether testing of a part stops at its first failing test
+    pub stop_on_fail: bool,
+    /// The tests, in test order
+    pub tests: Vec<TestSpec>,
+    /// The bins of failing parts, the first matching rule applies
+    pub bin_rules: Vec<BinRule>,
+    /// The hard and soft bin of passing parts
+    pub pass_bin: u16,
+    /// The hard and soft bin of failing parts matching no `BinRule`
+    pub fail_bin: u16,
+}
+
+/// A single lot of 5 wafers of 500 dies on 4 sites, with a handful of parametric tests, a
+/// functional and a multi-pin test
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        let mut tests: Vec<TestSpec> = (0..5)
+            .map(|i| TestSpec::Parametric {
+                test_num: 1000 + i,
+                test_nam: format!("PARAM_{i}"),
+                units: "V".to_string(),
+                lo_limit: 0.85,
+                hi_limit: 1.15,
+                distribution: ValueDistribution::Normal {
+                    mean: 1.0,
+                    std_dev: 0.05,
+                },
+            })
+            .collect();
+        tests.push(TestSpec::Functional {
+            test_num: 2000,
+            test_nam: "FUNC_CORE".to_string(),
+            fail_rate: 0.02,
+        });
+        tests.push(TestSpec::MultiPin {
+            test_num: 3000,
+            test_nam: "PIN_LEAK".to_string(),
+            units: "nA".to_string(),
+            lo_limit: 0.,
+            hi_limit: 10.,
+            n_pins: 4,
+            distribution: ValueDistribution::Normal {
+                mean: 5.,
+                std_dev: 1.2,
+            },
+        });
+        let bin_rules = vec![
+            BinRule {
+                first_test: 1000,
+                last_test: 1999,
+                hard_bin: 2,
+                soft_bin: 20,
+                name: "PARAMETRIC".to_string(),
+            },
+            BinRule {
+                first_test: 2000,
+                last_test: 2999,
+                hard_bin: 3,
+                soft_bin: 30,
+                name: "FUNCTIONAL".to_string(),
+            },
+            BinRule {
+                first_test: 3000,
+                last_test: 3999,
+                hard_bin: 4,
+                soft_bin: 40,
+                name: "LEAKAGE".to_string(),
+            },
+        ];
+        Self {
+            lot_id: "SYNTH".to_string(),
+            n_lots: 1,
+            n_wafers: 5,
+            n_dies: 500,
+            n_sites: 4,
+            seed: 0,
+            start_t: 1_700_000_000,
+            retest_fraction: 0.1,
+            stop_on_fail: true,
+            tests,
+            bin_rules,
+            pass_bin: 1,
+            fail_bin: 0,
+        }
+    }
+}
+
+/// A `ValueDistribution` ready to sample from
+enum Sampler {
+    Normal(Normal<f32>),
+    Uniform(f32, f32),
+    Custom(Vec<f32>, WeightedIndex<f64>),
+}
+
+impl Sampler {
+    fn new(distribution: &ValueDistribution) -> io::Result<Self> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+        match distribution {
+            ValueDistribution::Normal { mean, std_dev } => Normal::new(*mean, *std_dev)
+                .map(Self::Normal)
+                .map_err(|e| invalid(format!("invalid normal distribution: {e}"))),
+            ValueDistribution::Uniform { low, high } if low <= high => {
+                Ok(Self::Uniform(*low, *high))
+            }
+            ValueDistribution::Uniform { low, high } => Err(invalid(format!(
+                "invalid uniform distribution: low {low} is above high {high}"
+            ))),
+            ValueDistribution::Custom { values, weights } if values.len() == weights.len() => {
+                WeightedIndex::new(weights)
+                    .map(|index| Self::Custom(values.clone(), index))
+                    .map_err(|e| invalid(format!("invalid custom distribution: {e}")))
+            }
+            ValueDistribution::Custom { .. } => Err(invalid(
+                "invalid custom distribution: values and weights differ in length".to_string(),
+            )),
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> f32 {
+        match self {
+            Self::Normal(normal) => normal.sample(rng),
+            Self::Uniform(low, high) => rng.random_range(*low..=*high),
+            Self::Custom(values, index) => values[index.sample(rng)],
+        }
+    }
+}
+
+impl GeneratorConfig {
+    /// Read a `GeneratorConfig` from the JSON file `fname`
+    ///
+    /// # Error
+    /// If the file cannot be read or is not a valid config, returns a `std::io::Error`
+    pub fn from_fname(fname: &str) -> io::Result<Self> {
+        let file = File::open(fname)?;
+        serde_json::from_reader(file).map_err(io::Error::from)
+    }
+
+    /// The `lot_id` of each lot
+    pub fn lot_ids(&self) -> Vec<String> {
+        match self.n_lots {
+            1 => vec![self.lot_id.clone()],
+            n_lots => (1..=n_lots)
+                .map(|i| format!("{}{i:02}", self.lot_id))
+                .collect(),
+        }
+    }
+
+    /// Generate the lot with index `lot_index` into a `StdfBuilder`
+    ///
+    /// # Error
+    /// If any of the distributions is invalid, the `retest_fraction` or a `fail_rate` is outside
+    /// of 0-1, or there are no sites, returns a `std::io::Error`
+    pub fn generate_lot(&self, lot_index: usize) -> io::Result<StdfBuilder> {
+        if self.n_sites == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot generate parts without sites",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.retest_fraction) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("retest_fraction {} is outside of 0-1", self.retest_fraction),
+            ));
+        }
+        let samplers = self
+            .tests
+            .iter()
+            .map(|test| match test {
+                TestSpec::Parametric { distribution, .. }
+                | TestSpec::MultiPin { distribution, .. } => Sampler::new(distribution).map(Some),
+                TestSpec::Functional {
+                    test_num,
+                    fail_rate,
+                    ..
+                } if !(0.0..=1.0).contains(fail_rate) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("fail_rate {fail_rate} of test {test_num} is outside of 0-1"),
+                )),
+                TestSpec::Functional { .. } => Ok(None),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(lot_index as u64));
+        let lot_id = &self.lot_ids()[lot_index];
+        let site_nums: Vec<u8> = (1..=self.n_sites).collect();
+
+        let mut builder = StdfBuilder::new()
+            .lot_id(lot_id)
+            .part_typ("SYNTHETIC")
+            .job_nam("stupidf-generate")
+            .start_t(self.start_t)
+            .sites(&site_nums)
+            .hard_bin(self.pass_bin, "PASS", true)
+            .soft_bin(self.pass_bin, "PASS", true)
+            .hard_bin(self.fail_bin, "FAIL", false)
+            .soft_bin(self.fail_bin, "FAIL", false);
+        let n_pins = self
+            .tests
+            .iter()
+            .filter_map(|test| match test {
+                TestSpec::MultiPin { n_pins, .. } => Some(*n_pins),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        for pmr_indx in 1..=n_pins {
+            builder = builder.pin(pmr_indx, &format!("P{pmr_indx}"));
+        }
+        for test in &self.tests {
+            builder = match test {
+                TestSpec::Parametric {
+                    test_num,
+                    test_nam,
+                    units,
+                    lo_limit,
+                    hi_limit,
+                    ..
+                } => builder.parametric_test(*test_num, test_nam, units, *lo_limit, *hi_limit),
+                TestSpec::Functional {
+                    test_num, test_nam, ..
+                } => builder.functional_test(*test_num, test_nam),
+                TestSpec::MultiPin {
+                    test_num,
+                    test_nam,
+                    units,
+                    lo_limit,
+                    hi_limit,
+                    n_pins,
+                    ..
+                } => {
+                    let pins: Vec<u16> = (1..=*n_pins).collect();
+                    builder.multi_pin_test(*test_num, test_nam, units, *lo_limit, *hi_limit, &pins)
+                }
+            };
+        }
+        for rule in &self.bin_rules {
+            builder = builder.hard_bin(rule.hard_bin, &rule.name, false).soft_bin(
+                rule.soft_bin,
+                &rule.name,
+                false,
+            );
+        }
+
+        let coords = die_coords(self.n_dies);
+        for wafer_index in 0..self.n_wafers {
+            builder = builder.wafer(&format!("{lot_id}-{:02}", wafer_index + 1));
+            let mut failing = Vec::new();
+            for (i, (x_coord, y_coord)) in coords.iter().enumerate() {
+                let site_num = site_nums[i % site_nums.len()];
+                let part_id = format!("{}-{}", wafer_index + 1, i + 1);
+                let part = Part::new(site_num).id(&part_id).at(*x_coord, *y_coord);
+                let (part, passed) = self.test_part(part, &samplers, &mut rng);
+                builder = builder.part(part);
+                if !passed {
+                    failing.push((site_num, part_id, *x_coord, *y_coord));
+                }
+            }
+            for (site_num, part_id, x_coord, y_coord) in failing {
+                if rng.random_bool(self.retest_fraction) {
+                    let part = Part::new(site_num)
+                        .id(&part_id)
+                        .at(x_coord, y_coord)
+                        .retest();
+                    let (part, _) = self.test_part(part, &samplers, &mut rng);
+                    builder = builder.part(part);
+                }
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Draw the results of every test for `part` and bin it
+    ///
+    /// Returns the `part` and whether it passed.
+    fn test_part(
+        &self,
+        mut part: Part,
+        samplers: &[Option<Sampler>],
+        rng: &mut StdRng,
+    ) -> (Part, bool) {
+        let mut first_fail = None;
+        for (test, sampler) in self.tests.iter().zip(samplers) {
+            let passed = match (test, sampler) {
+                (
+                    TestSpec::Parametric {
+                        lo_limit, hi_limit, ..
+                    },
+                    Some(sampler),
+                ) => {
+                    let result = sampler.sample(rng);
+                    part = part.result(test.test_num(), result);
+                    *lo_limit <= result && result <= *hi_limit
+                }
+                (TestSpec::Functional { fail_rate, .. }, _) => {
+                    let passed = !rng.random_bool(*fail_rate);
+                    part = part.result(test.test_num(), passed);
+                    passed
+                }
+                (
+                    TestSpec::MultiPin {
+                        lo_limit,
+                        hi_limit,
+                        n_pins,
+                        ..
+                    },
+                    Some(sampler),
+                ) => {
+                    let results: Vec<f32> = (0..*n_pins).map(|_| sampler.sample(rng)).collect();
+                    let passed = results
+                        .iter()
+                        .all(|result| *lo_limit <= *result && *result <= *hi_limit);
+                    part = part.result(test.test_num(), results);
+                    passed
+                }
+                _ => unreachable!("every parametric and multi-pin test has a sampler"),
+            };
+            if !passed && first_fail.is_none() {
+                first_fail = Some(test.test_num());
+                if self.stop_on_fail {
+                    break;
+                }
+            }
+        }
+        match first_fail {
+            None => (part.bins(self.pass_bin, self.pass_bin), true),
+            Some(test_num) => {
+                let rule = self
+                    .bin_rules
+                    .iter()
+                    .find(|rule| rule.first_test <= test_num && test_num <= rule.last_test);
+                let (hard_bin, soft_bin) = match rule {
+                    Some(rule) => (rule.hard_bin, rule.soft_bin),
+                    None => (self.fail_bin, self.fail_bin),
+                };
+                (part.bins(hard_bin, soft_bin), false)
+            }
+        }
+    }
+
+    /// Generate every lot and write each to its own STDF file
+    ///
+    /// A single lot is written to `fname`. Multiple lots are written next to it, with the
+    /// `lot_id` appended to the file stem, e.g. `out/synth_SYNTH01.stdf`. Returns the names of
+    /// the files written.
+    ///
+    /// # Error
+    /// If the config is invalid or a file cannot be written, returns a `std::io::Error`
+    pub fn write_fnames(&self, fname: &str) -> io::Result<Vec<String>> {
+        let path = Path::new(fname);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        let mut fnames = Vec::new();
+        for (lot_index, lot_id) in self.lot_ids().iter().enumerate() {
+            let lot_fname = match self.n_lots {
+                1 => fname.to_string(),
+                _ => path
+                    .with_file_name(format!("{stem}_{lot_id}.{extension}"))
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            self.generate_lot(lot_index)?.write_fname(&lot_fname)?;
+            fnames.push(lot_fname);
+        }
+        Ok(fnames)
+    }
+}
+
+/// The (`x_coord`, `y_coord`) of `n_dies` dies laid out in a disc, in row-by-row test order
+///
+/// The coordinates are non-negative, with the center of the wafer near (`r`, `r`) for a radius
+/// `r` in dies.
+fn die_coords(n_dies: usize) -> Vec<(i16, i16)> {
+    let radius = ((n_dies as f64 / std::f64::consts::PI).sqrt().ceil() as i16) + 1;
+    let mut coords: Vec<(i16, i16)> = (-radius..=radius)
+        .flat_map(|y| (-radius..=radius).map(move |x| (x, y)))
+        .collect();
+    coords.sort_by_key(|(x, y)| (i32::from(*x).pow(2) + i32::from(*y).pow(2), *y, *x));
+    coords.truncate(n_dies);
+    coords.sort_by_key(|(x, y)| (*y, *x));
+    coords
+        .into_iter()
+        .map(|(x, y)| (x + radius, y + radius))
+        .collect()
+}
@@ -21,9 +21,26 @@
 //!    stdf['df']
 //! ````
 
+pub mod anonymize;
+pub mod builder;
+pub mod composite;
 pub mod data;
 pub mod data_py;
+pub mod diff;
+pub mod export;
+pub mod filter;
+pub mod generate;
+pub mod map_export;
+pub mod merge;
+pub mod pat;
+pub mod reconcile;
 pub mod record_types;
 pub mod records;
+pub mod spatial;
+pub mod stats;
 pub mod test_information;
 mod util;
+pub mod validate;
+pub mod wafermap;
+pub mod writer;
+pub mod zones;
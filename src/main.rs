@@ -1,12 +1,43 @@
-use clap::Parser;
-use std::env;
+use clap::{Parser, Subcommand};
+use std::{
+    env,
+    fs::{self, File},
+    ops::RangeInclusive,
+    path::Path,
+};
 
-use polars::frame::DataFrame;
-use stupidf::{data::STDF, test_information::FullTestInformation};
+use polars::prelude::{CsvWriter, DataFrame, SerWriter};
+use stupidf::{
+    anonymize::{Anonymizer, DEFAULT_FIELDS, Redaction},
+    composite::CompositeMap,
+    data::{STDF, TestData},
+    diff::ProgramDiff,
+    export::{self, ExportFormat, ParquetLayout},
+    filter::{PartResult, StdfFilter, parse_test_range},
+    generate::GeneratorConfig,
+    map_export::{BinCodes, MapExport, MapFileFormat, parse_bin_codes},
+    merge::MergedStdf,
+    pat::{Pat, PatMode},
+    spatial::{Gdbn, Nnr, SpatialScreen, Weights, parse_weights},
+    stats::{Estimator, GroupBy, TestStatistics, check_percentiles},
+    test_information::{ColumnNaming, FullTestInformation, TestIdentity},
+    validate::{Severity, validate_fname},
+    wafermap::{MapFormat, MapLayer, Wafermap, file_stems, wafermaps},
+    zones::{Reticle, ZoneGroupBy, Zoning, parse_reticle_offset, parse_reticle_step},
+};
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     // print record information during construction
     #[arg(short, long)]
     verbose: bool,
@@ -15,10 +46,431 @@ struct Cli {
     #[arg(short, long)]
     df: bool,
 
+    // convert into and print out the long dataframe, one row per part and test
+    #[arg(short, long)]
+    long: bool,
+
     // print record summary information
     #[arg(short, long)]
     summarize: bool,
-    fname: String,
+
+    // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+    #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+    identity: TestIdentity,
+
+    // how to name the test columns: num, name, num:name or a template like "{test_num}_{units}"
+    #[arg(short, long, default_value_t = ColumnNaming::Number)]
+    naming: ColumnNaming,
+    #[arg(required = true)]
+    fname: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    // convert an STDF file into Parquet, Arrow IPC or CSV
+    Export {
+        fname: String,
+        output: String,
+
+        // the output format: parquet, ipc or csv
+        #[arg(short, long, default_value_t = ExportFormat::Parquet)]
+        format: ExportFormat,
+
+        // where to put the non-result tables of a parquet export: metadata or sidecar
+        #[arg(short, long, default_value_t = ParquetLayout::Metadata)]
+        layout: ParquetLayout,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+    },
+
+    // write a smaller STDF file with only some of the wafers, sites, parts or tests
+    Filter {
+        fname: String,
+        output: String,
+
+        // keep only these wafer_ids, may be repeated
+        #[arg(short, long)]
+        wafer: Vec<String>,
+
+        // keep only these site_nums, may be repeated
+        #[arg(short, long)]
+        site: Vec<u8>,
+
+        // keep only these part_ids, may be repeated
+        #[arg(short, long)]
+        part: Vec<String>,
+
+        // keep only the parts with this result: all, pass or fail
+        #[arg(short, long, default_value_t = PartResult::All)]
+        result: PartResult,
+
+        // keep only these test_nums or ranges like 1000-2000, may be repeated
+        #[arg(short, long, value_parser = parse_test_range)]
+        test: Vec<RangeInclusive<u32>>,
+    },
+
+    // write a copy of an STDF file with the identifying strings redacted
+    Anonymize {
+        fname: String,
+        output: String,
+
+        // the secret key the pseudonyms are derived from
+        #[arg(short, long)]
+        key: String,
+
+        // replace fields by their keyed hash or by a fixed token
+        #[arg(short, long, default_value_t = Redaction::Hash)]
+        redaction: Redaction,
+
+        // the fixed token for `--redaction token`
+        #[arg(long, default_value = "REDACTED")]
+        token: String,
+
+        // redact these fields like MIR.lot_id instead of the defaults, may be repeated
+        #[arg(short, long)]
+        field: Vec<String>,
+
+        // also redact the test names
+        #[arg(short, long)]
+        test_names: bool,
+
+        // also redact the text of the DTRs and GDRs
+        #[arg(short = 'x', long)]
+        text: bool,
+    },
+
+    // check an STDF file against the V4 specification, exiting with 1 if it has errors
+    Validate {
+        fname: String,
+
+        // print only the errors, not the warnings
+        #[arg(short, long)]
+        errors_only: bool,
+    },
+
+    // generate synthetic STDF files, one per lot
+    Generate {
+        output: String,
+
+        // a JSON generator config, otherwise the defaults are used
+        #[arg(short, long)]
+        config: Option<String>,
+
+        // the number of lots, each written to its own file
+        #[arg(short, long)]
+        lots: Option<usize>,
+
+        // the number of wafers per lot
+        #[arg(short, long)]
+        wafers: Option<usize>,
+
+        // the number of dies per wafer
+        #[arg(short, long)]
+        dies: Option<usize>,
+
+        // the number of test sites
+        #[arg(short = 'n', long)]
+        sites: Option<u8>,
+
+        // the seed of the random number generator
+        #[arg(short, long)]
+        seed: Option<u64>,
+
+        // the fraction of failing dies that are retested
+        #[arg(short, long)]
+        retest: Option<f64>,
+    },
+
+    // print the statistics and Cp/Cpk of each parametric test
+    Stats {
+        fname: String,
+
+        // one row per test and group: none, head, site or wafer
+        #[arg(short, long, default_value_t = GroupBy::None)]
+        group_by: GroupBy,
+
+        // the center and spread used for Cp/Cpk: classic or robust
+        #[arg(short, long, default_value_t = Estimator::Classic)]
+        estimator: Estimator,
+
+        // the percentiles to compute instead of 1, 5, 25, 50, 75, 95 and 99, may be repeated
+        #[arg(short, long)]
+        percentile: Vec<f64>,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // write the statistics to this CSV file instead of printing them
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    // print the Part Average Testing limits and outliers of each parametric test
+    Pat {
+        fname: String,
+
+        // where the limits come from: static, dynamic_wafer or dynamic_lot
+        #[arg(short, long, default_value_t = PatMode::Static)]
+        mode: PatMode,
+
+        // the STDF file of the reference population for static PAT, otherwise fname itself
+        #[arg(short, long)]
+        reference: Option<String>,
+
+        // the number of sigmas from the center to each limit
+        #[arg(short, default_value_t = 6.)]
+        k: f64,
+
+        // the center and spread of the population: classic or robust
+        #[arg(short, long, default_value_t = Estimator::Robust)]
+        estimator: Estimator,
+
+        // the smallest population to compute limits from
+        #[arg(short = 'n', long, default_value_t = 30)]
+        min_parts: usize,
+
+        // apply PAT to only these test_nums, may be repeated
+        #[arg(short, long)]
+        test: Vec<u32>,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // write the outliers to this CSV file instead of printing them
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    // print the dies flagged by spatial screening, GDBN and NNR unless only one is chosen
+    Spatial {
+        fname: String,
+
+        // run Good Die in Bad Neighborhood screening
+        #[arg(short, long)]
+        gdbn: bool,
+
+        // run Nearest Neighbor Residual screening
+        #[arg(short, long)]
+        nnr: bool,
+
+        // the GDBN neighbor weights as rows separated by ; like "1,2,1;2,0,2;1,2,1"
+        #[arg(short, long, value_parser = parse_weights)]
+        weights: Option<Weights>,
+
+        // flag good dies with at least this weighted fraction of failing neighbors
+        #[arg(long, default_value_t = 0.5)]
+        threshold: f64,
+
+        // the NNR neighbors are at most this many dies away in x and in y
+        #[arg(short, long, default_value_t = 2)]
+        radius: i16,
+
+        // flag results with a normalized NNR residual of more than this
+        #[arg(short, default_value_t = 6.)]
+        k: f64,
+
+        // screen only these test_nums with NNR, may be repeated
+        #[arg(short, long)]
+        test: Vec<u32>,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // write the flagged dies to this CSV file instead of printing them
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    // draw a map of the bins or of a parametric test for each wafer
+    Wafermap {
+        fname: String,
+
+        // what the dies show: sbin, hbin, fail_rate or a test_num
+        #[arg(short, long, default_value_t = MapLayer::SoftBin)]
+        layer: MapLayer,
+
+        // how to draw the maps: ascii, svg or grid
+        #[arg(short, long, default_value_t = MapFormat::Ascii)]
+        format: MapFormat,
+
+        // draw only the wafer with this wafer_id
+        #[arg(short, long)]
+        wafer: Option<String>,
+
+        // color the passing and failing bins of ascii maps
+        #[arg(short, long)]
+        color: bool,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // write each map into this directory as <wafer_id>.txt, .svg or .csv instead of printing
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    // write a map file of the bins of each wafer for inspection and inking tools
+    MapExport {
+        fname: String,
+
+        // the directory to write the map files into
+        output: String,
+
+        // the map file format: klarf, e142 or tsk
+        #[arg(short, long, default_value_t = MapFileFormat::Tsk)]
+        format: MapFileFormat,
+
+        // map the hard bins instead of the soft bins
+        #[arg(long)]
+        hbin: bool,
+
+        // the code written for each bin, like "1=A,2=B,20=X"
+        #[arg(short, long, value_parser = parse_bin_codes)]
+        bin_codes: Option<BinCodes>,
+
+        // the code written for passing bins without a code, otherwise the bin number
+        #[arg(short, long)]
+        pass_code: Option<String>,
+
+        // the code written for failing bins without a code, otherwise the bin number
+        #[arg(short = 'x', long)]
+        fail_code: Option<String>,
+
+        // the code written where there is no die
+        #[arg(short, long, default_value = ".")]
+        null_code: String,
+    },
+
+    // stack the wafers of one or more STDF files into a composite map
+    Composite {
+        #[arg(required = true)]
+        fnames: Vec<String>,
+
+        // what the dies show: fail_rate, sbin or hbin for the most common bin, or a test_num for
+        // the median result
+        #[arg(short, long, default_value_t = MapLayer::FailRate)]
+        layer: MapLayer,
+
+        // how to draw the map: ascii, svg or grid
+        #[arg(short, long, default_value_t = MapFormat::Ascii)]
+        format: MapFormat,
+
+        // print the composite DataFrame, one row per die position, instead of the map
+        #[arg(short, long)]
+        df: bool,
+
+        // color the passing and failing bins of ascii maps
+        #[arg(short, long)]
+        color: bool,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // write the map or DataFrame to this file instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    // print the test program changes between two STDF files, exiting with 1 if there are any
+    Diff {
+        old: String,
+        new: String,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // write the changes to this CSV file instead of printing them
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    // merge several STDF files into one dataset with the union of their tests
+    Merge {
+        #[arg(required = true)]
+        fnames: Vec<String>,
+
+        // write the merged results to this file instead of printing them
+        #[arg(short, long)]
+        output: Option<String>,
+
+        // the output format: parquet, ipc or csv
+        #[arg(short, long, default_value_t = ExportFormat::Parquet)]
+        format: ExportFormat,
+
+        // the number of files to parse at once, 0 for one per CPU
+        #[arg(short = 'j', long, default_value_t = 0)]
+        threads: usize,
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // how to name the test columns: num, name, num:name or a template like "{test_num}_{units}"
+        #[arg(short, long, default_value_t = ColumnNaming::Number)]
+        naming: ColumnNaming,
+    },
+
+    // print the yield or parametric statistics per radial zone or reticle position
+    Zones {
+        fname: String,
+
+        // aggregate by zone or by reticle position
+        #[arg(short, long, default_value_t = ZoneGroupBy::Zone)]
+        group_by: ZoneGroupBy,
+
+        // print the parametric statistics instead of the yield
+        #[arg(short, long)]
+        stats: bool,
+
+        // print the zone and reticle shot of each die instead of the yield
+        #[arg(short, long)]
+        dies: bool,
+
+        // the outer radius of the center zone as a fraction of the wafer radius
+        #[arg(short, long, default_value_t = 0.5)]
+        center: f64,
+
+        // the outer radius of the middle zone as a fraction of the wafer radius
+        #[arg(short, long, default_value_t = 0.8)]
+        middle: f64,
+
+        // the number of dies per reticle shot in x and y, like 2x3
+        #[arg(short, long, value_parser = parse_reticle_step, default_value = "1x1")]
+        reticle: (i16, i16),
+
+        // the x_coord and y_coord of a die in the first column and row of a shot, like 0,0
+        #[arg(long, value_parser = parse_reticle_offset, default_value = "0,0")]
+        offset: (i16, i16),
+
+        // how to tell apart tests sharing a test number: test_num, test_num_txt or test_num_type
+        #[arg(short, long, default_value_t = TestIdentity::TestNum)]
+        identity: TestIdentity,
+
+        // write the DataFrame to this CSV file instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+fn warn_collisions(test_data: &TestData) {
+    let collisions = test_data.collisions();
+    if !collisions.is_empty() {
+        eprintln!(
+            "Warning: {} test_num(s) shared by more than one test:",
+            collisions.len()
+        );
+        for collision in collisions {
+            eprintln!("  {collision}");
+        }
+    }
 }
 
 fn polars_config() {
@@ -31,14 +483,451 @@ fn polars_config() {
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
-    let fname = cli.fname;
+    match cli.command {
+        Some(Command::Export {
+            fname,
+            output,
+            format,
+            layout,
+            identity,
+        }) => export(&fname, &output, format, layout, identity),
+        Some(Command::Filter {
+            fname,
+            output,
+            wafer,
+            site,
+            part,
+            result,
+            test,
+        }) => {
+            let filter = StdfFilter {
+                wafer_ids: (!wafer.is_empty()).then_some(wafer),
+                site_nums: (!site.is_empty()).then_some(site),
+                part_ids: (!part.is_empty()).then_some(part),
+                part_result: result,
+                test_nums: (!test.is_empty()).then_some(test),
+            };
+            let n_parts = filter.apply_fname(&fname, &output)?;
+            println!("Wrote {n_parts} parts to {output}");
+            Ok(())
+        }
+        Some(Command::Anonymize {
+            fname,
+            output,
+            key,
+            redaction,
+            token,
+            field,
+            test_names,
+            text,
+        }) => {
+            let anonymizer = Anonymizer {
+                key: key.into_bytes(),
+                redaction,
+                token,
+                fields: match field.is_empty() {
+                    true => DEFAULT_FIELDS
+                        .iter()
+                        .map(|field| field.to_string())
+                        .collect(),
+                    false => field,
+                },
+                test_names,
+                text,
+            };
+            let n_replaced = anonymizer.apply_fname(&fname, &output)?;
+            println!("Replaced {n_replaced} strings, wrote {output}");
+            Ok(())
+        }
+        Some(Command::Validate { fname, errors_only }) => {
+            let mut report = validate_fname(&fname)?;
+            if errors_only {
+                report
+                    .issues
+                    .retain(|issue| issue.severity == Severity::Error);
+            }
+            println!("{report}");
+            if !report.is_valid() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Generate {
+            output,
+            config,
+            lots,
+            wafers,
+            dies,
+            sites,
+            seed,
+            retest,
+        }) => {
+            let mut config = match config {
+                Some(config) => GeneratorConfig::from_fname(&config)?,
+                None => GeneratorConfig::default(),
+            };
+            config.n_lots = lots.unwrap_or(config.n_lots);
+            config.n_wafers = wafers.unwrap_or(config.n_wafers);
+            config.n_dies = dies.unwrap_or(config.n_dies);
+            config.n_sites = sites.unwrap_or(config.n_sites);
+            config.seed = seed.unwrap_or(config.seed);
+            config.retest_fraction = retest.unwrap_or(config.retest_fraction);
+            for fname in config.write_fnames(&output)? {
+                println!("Wrote {fname}");
+            }
+            Ok(())
+        }
+        Some(Command::Stats {
+            fname,
+            group_by,
+            estimator,
+            percentile,
+            identity,
+            output,
+        }) => {
+            let mut statistics = TestStatistics {
+                group_by,
+                estimator,
+                ..Default::default()
+            };
+            if !percentile.is_empty() {
+                statistics.percentiles = check_percentiles(&percentile)?;
+            }
+            let stdf = STDF::from_fname_with_identity(&fname, false, identity)?;
+            warn_collisions(&stdf.test_data);
+            let mut df = statistics.to_df(&stdf.test_data);
+            match output {
+                Some(output) => {
+                    CsvWriter::new(File::create(&output)?).finish(&mut df)?;
+                    println!("Wrote {output}");
+                }
+                None => {
+                    polars_config();
+                    println!("{df}");
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Pat {
+            fname,
+            mode,
+            reference,
+            k,
+            estimator,
+            min_parts,
+            test,
+            identity,
+            output,
+        }) => {
+            let pat = Pat {
+                mode,
+                k,
+                estimator,
+                min_parts,
+                test_nums: (!test.is_empty()).then_some(test),
+                ..Default::default()
+            };
+            let stdf = STDF::from_fname_with_identity(&fname, false, identity)?;
+            warn_collisions(&stdf.test_data);
+            let reference = reference
+                .map(|reference| STDF::from_fname_with_identity(&reference, false, identity))
+                .transpose()?;
+            let report = pat.run(
+                &stdf.test_data,
+                reference.as_ref().map(|stdf| &stdf.test_data),
+            );
+            polars_config();
+            println!("{}", report.limits_to_df());
+            let mut df = report.outliers_to_df();
+            match output {
+                Some(output) => {
+                    CsvWriter::new(File::create(&output)?).finish(&mut df)?;
+                    println!("Wrote {} outliers to {output}", df.height());
+                }
+                None => println!("{df}"),
+            }
+            Ok(())
+        }
+        Some(Command::Spatial {
+            fname,
+            gdbn,
+            nnr,
+            weights,
+            threshold,
+            radius,
+            k,
+            test,
+            identity,
+            output,
+        }) => {
+            let (gdbn, nnr) = match (gdbn, nnr) {
+                (false, false) => (true, true),
+                chosen => chosen,
+            };
+            let screen = SpatialScreen {
+                gdbn: gdbn.then(|| Gdbn {
+                    weights: weights.unwrap_or(Gdbn::default().weights),
+                    threshold,
+                    ..Default::default()
+                }),
+                nnr: nnr.then(|| Nnr {
+                    radius,
+                    k,
+                    test_nums: (!test.is_empty()).then_some(test),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let stdf = STDF::from_fname_with_identity(&fname, false, identity)?;
+            warn_collisions(&stdf.test_data);
+            let mut df = screen.run(&stdf).to_df();
+            match output {
+                Some(output) => {
+                    CsvWriter::new(File::create(&output)?).finish(&mut df)?;
+                    println!("Wrote {} flagged dies to {output}", df.height());
+                }
+                None => {
+                    polars_config();
+                    println!("{df}");
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Wafermap {
+            fname,
+            layer,
+            format,
+            wafer,
+            color,
+            identity,
+            output,
+        }) => {
+            let stdf = STDF::from_fname_with_identity(&fname, false, identity)?;
+            warn_collisions(&stdf.test_data);
+            let maps = wafermaps(&stdf, layer)?;
+            let maps: Vec<&Wafermap> = maps
+                .iter()
+                .filter(|map| wafer.as_ref().is_none_or(|wafer| &map.wafer_id == wafer))
+                .collect();
+            if let Some(output) = &output {
+                fs::create_dir_all(output)?;
+            }
+            polars_config();
+            for (map, name) in maps.iter().zip(file_stems(&maps)) {
+                let Some(output) = &output else {
+                    match format {
+                        MapFormat::Ascii => println!("{}", map.to_ascii(color)),
+                        MapFormat::Svg => println!("{}", map.to_svg()),
+                        MapFormat::Grid => println!("{name}\n{}", map.to_grid_df()),
+                    }
+                    continue;
+                };
+                let extension = format.extension();
+                let path = Path::new(output).join(format!("{name}.{extension}"));
+                match format {
+                    MapFormat::Ascii => fs::write(&path, map.to_ascii(false))?,
+                    MapFormat::Svg => fs::write(&path, map.to_svg())?,
+                    MapFormat::Grid => {
+                        CsvWriter::new(File::create(&path)?).finish(&mut map.to_grid_df())?
+                    }
+                }
+                println!("Wrote {}", path.display());
+            }
+            Ok(())
+        }
+        Some(Command::MapExport {
+            fname,
+            output,
+            format,
+            hbin,
+            bin_codes,
+            pass_code,
+            fail_code,
+            null_code,
+        }) => {
+            let map_export = MapExport {
+                format,
+                hard_bins: hbin,
+                bin_codes: bin_codes.unwrap_or_default(),
+                pass_code,
+                fail_code,
+                null_code,
+            };
+            let stdf = STDF::from_fname(&fname, false)?;
+            for path in map_export.write(&stdf, &output)? {
+                println!("Wrote {}", path.display());
+            }
+            Ok(())
+        }
+        Some(Command::Composite {
+            fnames,
+            layer,
+            format,
+            df,
+            color,
+            identity,
+            output,
+        }) => {
+            let stdfs = fnames
+                .iter()
+                .map(|fname| STDF::from_fname_with_identity(fname, false, identity))
+                .collect::<std::io::Result<Vec<STDF>>>()?;
+            for stdf in &stdfs {
+                warn_collisions(&stdf.test_data);
+            }
+            let stdfs: Vec<&STDF> = stdfs.iter().collect();
+            let test_num = match layer {
+                MapLayer::Test(test_num) => Some(test_num),
+                _ => None,
+            };
+            let composite = CompositeMap::new(&stdfs, test_num)?;
+            let map = composite.to_wafermap(layer)?;
+            polars_config();
+            match (output, df, format) {
+                (None, true, _) => println!("{}", composite.to_df()),
+                (None, false, MapFormat::Ascii) => println!("{}", map.to_ascii(color)),
+                (None, false, MapFormat::Svg) => println!("{}", map.to_svg()),
+                (None, false, MapFormat::Grid) => println!("{}", map.to_grid_df()),
+                (Some(output), true, _) => {
+                    CsvWriter::new(File::create(&output)?).finish(&mut composite.to_df())?;
+                    println!("Wrote {output}");
+                }
+                (Some(output), false, MapFormat::Grid) => {
+                    CsvWriter::new(File::create(&output)?).finish(&mut map.to_grid_df())?;
+                    println!("Wrote {output}");
+                }
+                (Some(output), false, MapFormat::Ascii) => {
+                    fs::write(&output, map.to_ascii(false))?;
+                    println!("Wrote {output}");
+                }
+                (Some(output), false, MapFormat::Svg) => {
+                    fs::write(&output, map.to_svg())?;
+                    println!("Wrote {output}");
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Diff {
+            old,
+            new,
+            identity,
+            output,
+        }) => {
+            let diff = ProgramDiff::from_fnames(&old, &new, identity)?;
+            match output {
+                Some(output) => {
+                    CsvWriter::new(File::create(&output)?).finish(&mut diff.to_df())?;
+                    println!("Wrote {output}");
+                }
+                None => println!("{diff}"),
+            }
+            if !diff.is_empty() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Merge {
+            fnames,
+            output,
+            format,
+            threads,
+            identity,
+            naming,
+        }) => {
+            let merged = MergedStdf::from_fnames(&fnames, identity, threads)?;
+            warn_collisions(&merged.test_data);
+            polars_config();
+            if !merged.conflicts.is_empty() {
+                eprintln!(
+                    "Warning: {} test metadata conflict(s), using the first file with each test",
+                    merged.conflicts.len()
+                );
+                eprintln!("{}", merged.conflicts_to_df());
+            }
+            match output {
+                Some(output) => {
+                    merged.write(&output, format, &naming)?;
+                    println!(
+                        "Wrote {} parts from {} files to {output}",
+                        merged.sources.len(),
+                        fnames.len()
+                    );
+                }
+                None => println!("{}", merged.to_df(&naming, false)),
+            }
+            Ok(())
+        }
+        Some(Command::Zones {
+            fname,
+            group_by,
+            stats,
+            dies,
+            center,
+            middle,
+            reticle: (step_x, step_y),
+            offset: (offset_x, offset_y),
+            identity,
+            output,
+        }) => {
+            let zoning = Zoning {
+                center_radius: center,
+                middle_radius: middle,
+                reticle: Reticle {
+                    step_x,
+                    step_y,
+                    offset_x,
+                    offset_y,
+                },
+            };
+            let stdf = STDF::from_fname_with_identity(&fname, false, identity)?;
+            warn_collisions(&stdf.test_data);
+            let mut df = match (stats, dies) {
+                (_, true) => zoning.locations_to_df(&stdf),
+                (true, false) => zoning.statistics_to_df(&stdf, group_by),
+                (false, false) => zoning.yield_to_df(&stdf, group_by),
+            };
+            match output {
+                Some(output) => {
+                    CsvWriter::new(File::create(&output)?).finish(&mut df)?;
+                    println!("Wrote {output}");
+                }
+                None => {
+                    polars_config();
+                    println!("{df}");
+                }
+            }
+            Ok(())
+        }
+        None => inspect(cli),
+    }
+}
+
+fn export(
+    fname: &str,
+    output: &str,
+    format: ExportFormat,
+    layout: ParquetLayout,
+    identity: TestIdentity,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stdf = STDF::from_fname_with_identity(fname, false, identity)?;
+    warn_collisions(&stdf.test_data);
+    export::write(&stdf, output, format, layout)?;
+    Ok(())
+}
+
+fn inspect(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let fname = cli.fname.expect("fname is required without a subcommand");
     let verbose = cli.verbose;
     let verbose_df = cli.df;
+    let long_df = cli.long;
     let summarize = cli.summarize;
+    let identity = cli.identity;
+    let naming = cli.naming;
 
     polars_config();
 
-    if let Ok(stdf) = STDF::from_fname(&fname, verbose) {
+    if let Ok(stdf) = STDF::from_fname_with_identity(&fname, verbose, identity) {
+        warn_collisions(&stdf.test_data);
         if verbose {
             println!("{stdf:#?}");
         }
@@ -46,9 +935,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let df_fmti: DataFrame = (&stdf.test_data.test_information).into();
             let df_fmti_sorted = df_fmti.sort(["test_num"], Default::default()).unwrap();
             println!("{df_fmti_sorted}");
-            let df: DataFrame = (&stdf.test_data).into();
+            let df = stdf.test_data.to_df(&naming);
             println!("{df:#?}");
         }
+        if long_df {
+            let df_long = stdf.test_data.to_long_df();
+            println!("{df_long}");
+        }
     } else {
         eprintln!("Failed to parse file {fname}");
         Err("Failed to parse file {fnames}")?;
@@ -0,0 +1,614 @@
+//! Export of wafer maps to the formats read by inspection and inking tools
+//!
+//! Each wafer in `STDF.wafer_information` is written to its own file in one of these formats:
+//!
+//! | `MapFileFormat` | extension | contents                                                       |
+//! |-----------------|-----------|----------------------------------------------------------------|
+//! | `Klarf`         | `.klarf`  | KLARF 1.2, with a defect at the center of each failing die     |
+//! | `E142`          | `.xml`    | SEMI E142 substrate map, with a bin code per die               |
+//! | `Tsk`           | `.txt`    | TSK-style text map: a header, then a row of bin codes per line |
+//!
+//! The bin codes are the bin numbers unless mapped otherwise by `MapExport`. The wafer size, die
+//! size and flat come from the `WCR` if there is one. Dimensions are written in millimeters, and
+//! in micrometers for KLARF. Without a `WCR`, dies are taken to be 1 mm square.
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    data::STDF,
+    spatial::WaferGeometry,
+    wafermap::{MapBin, MapLayer, Wafermap, escape, file_stems, wafermaps},
+};
+
+/// The file format of an exported wafer map
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapFileFormat {
+    /// KLARF 1.2
+    Klarf,
+    /// SEMI E142 XML
+    E142,
+    /// TSK-style text map
+    #[default]
+    Tsk,
+}
+
+impl fmt::Display for MapFileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Klarf => write!(f, "klarf"),
+            Self::E142 => write!(f, "e142"),
+            Self::Tsk => write!(f, "tsk"),
+        }
+    }
+}
+
+/// Parses a `MapFileFormat` from `klarf`, `e142` or `tsk`
+impl FromStr for MapFileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "klarf" => Ok(Self::Klarf),
+            "e142" => Ok(Self::E142),
+            "tsk" => Ok(Self::Tsk),
+            _ => Err(format!(
+                "unknown map file format {s}, expected one of klarf, e142, tsk"
+            )),
+        }
+    }
+}
+
+impl MapFileFormat {
+    /// The file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Klarf => "klarf",
+            Self::E142 => "xml",
+            Self::Tsk => "txt",
+        }
+    }
+}
+
+/// The code written for each bin number
+pub type BinCodes = HashMap<u16, String>;
+
+/// The wafer map exporter and its bin code mapping
+#[derive(Debug, Clone)]
+pub struct MapExport {
+    pub format: MapFileFormat,
+    /// Map the hard bins instead of the soft bins
+    pub hard_bins: bool,
+    /// The code written for each bin
+    pub bin_codes: BinCodes,
+    /// The code written for passing bins not in `bin_codes`, otherwise the bin number
+    pub pass_code: Option<String>,
+    /// The code written for failing bins not in `bin_codes`, otherwise the bin number
+    pub fail_code: Option<String>,
+    /// The code written where there is no die, in the E142 and TSK formats
+    pub null_code: String,
+}
+
+impl Default for MapExport {
+    fn default() -> Self {
+        Self {
+            format: MapFileFormat::Tsk,
+            hard_bins: false,
+            bin_codes: HashMap::new(),
+            pass_code: None,
+            fail_code: None,
+            null_code: ".".to_string(),
+        }
+    }
+}
+
+impl MapExport {
+    /// The code written for a die in `bin`
+    pub fn code(&self, bin: u16, map_bin: Option<&MapBin>) -> String {
+        if let Some(code) = self.bin_codes.get(&bin) {
+            return code.clone();
+        }
+        let default = match map_bin {
+            Some(map_bin) if map_bin.pass => &self.pass_code,
+            _ => &self.fail_code,
+        };
+        default.clone().unwrap_or_else(|| bin.to_string())
+    }
+
+    /// Build the bin maps of `stdf` to export
+    pub fn wafermaps(&self, stdf: &STDF) -> io::Result<Vec<Wafermap>> {
+        let layer = if self.hard_bins {
+            MapLayer::HardBin
+        } else {
+            MapLayer::SoftBin
+        };
+        wafermaps(stdf, layer)
+    }
+
+    /// Render the `map` of the wafer in `slot`, counting from 1, of `stdf` in the `format`
+    pub fn render(&self, stdf: &STDF, map: &Wafermap, slot: usize) -> String {
+        match self.format {
+            MapFileFormat::Klarf => self.to_klarf(stdf, map, slot),
+            MapFileFormat::E142 => self.to_e142(stdf, map),
+            MapFileFormat::Tsk => self.to_tsk(stdf, map),
+        }
+    }
+
+    /// Write the map of each wafer of `stdf` into the directory `dir`, creating it if needed
+    ///
+    /// Each file is named after its `wafer_id`, see `wafermap::file_stems`. Returns the paths of
+    /// the files written.
+    ///
+    /// # Error
+    /// If for some reason the files cannot be written, returns an `std::io::Error`
+    pub fn write(&self, stdf: &STDF, dir: &str) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(dir)?;
+        let mut paths = Vec::new();
+        let maps = self.wafermaps(stdf)?;
+        let maps: Vec<&Wafermap> = maps.iter().collect();
+        for (i, (map, name)) in maps.iter().zip(file_stems(&maps)).enumerate() {
+            let extension = self.format.extension();
+            let path = Path::new(dir).join(format!("{name}.{extension}"));
+            fs::write(&path, self.render(stdf, map, i + 1))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// The codes of the rows of `map`, top to bottom, each padded to the widest code
+    fn code_rows(&self, map: &Wafermap) -> (usize, Vec<Vec<String>>) {
+        let columns = map.columns();
+        let codes: Vec<Vec<Option<String>>> = map
+            .rows()
+            .iter()
+            .map(|&y| {
+                columns
+                    .iter()
+                    .map(|&x| {
+                        let die = map.dies.get(&(x, y))?;
+                        Some(self.code(die.bin, map.bins.get(&die.bin)))
+                    })
+                    .collect()
+            })
+            .collect();
+        let width = codes
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|code| code.chars().count())
+            .chain([self.null_code.chars().count()])
+            .max()
+            .unwrap_or(1);
+        let rows = codes
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|code| format!("{:>width$}", code.unwrap_or(self.null_code.clone())))
+                    .collect()
+            })
+            .collect();
+        (width, rows)
+    }
+
+    /// The fixed-width codes of `map` for E142, which splits the `BinCode` rows into cells of the
+    /// width of the `NullBin`
+    ///
+    /// Returns the `BinType`, the null code, the code of each bin and the rows of codes, top to
+    /// bottom. If all of the codes are numbers they are zero-padded to the widest code, with the
+    /// `BinType` `Decimal`. Otherwise they are padded on the left with `_`, with the `BinType`
+    /// `ASCII`.
+    fn e142_codes(&self, map: &Wafermap) -> (&'static str, String, BinCodes, Vec<String>) {
+        let mut codes = BinCodes::new();
+        for die in map.dies.values() {
+            codes
+                .entry(die.bin)
+                .or_insert_with(|| self.code(die.bin, map.bins.get(&die.bin)));
+        }
+        for (&bin, map_bin) in &map.bins {
+            codes
+                .entry(bin)
+                .or_insert_with(|| self.code(bin, Some(map_bin)));
+        }
+        let all_codes = || codes.values().chain([&self.null_code]);
+        let width = all_codes()
+            .map(|code| code.chars().count())
+            .max()
+            .unwrap_or(1);
+        let decimal = all_codes().all(|code| code.chars().all(|c| c.is_ascii_digit()));
+        let (bin_type, fill) = if decimal {
+            ("Decimal", '0')
+        } else {
+            ("ASCII", '_')
+        };
+        let pad = |code: &str| {
+            let padding = width - code.chars().count();
+            std::iter::repeat_n(fill, padding)
+                .chain(code.chars())
+                .collect::<String>()
+        };
+        let null_code = pad(&self.null_code);
+        for code in codes.values_mut() {
+            *code = pad(code);
+        }
+
+        let columns = map.columns();
+        let rows = map
+            .rows()
+            .iter()
+            .map(|&y| {
+                columns
+                    .iter()
+                    .map(|&x| match map.dies.get(&(x, y)) {
+                        Some(die) => codes[&die.bin].as_str(),
+                        None => null_code.as_str(),
+                    })
+                    .collect()
+            })
+            .collect();
+        (bin_type, null_code, codes, rows)
+    }
+
+    /// Render the `map` as a TSK-style text map
+    fn to_tsk(&self, stdf: &STDF, map: &Wafermap) -> String {
+        let mir = &stdf.master_information;
+        let geometry = WaferGeometry::from_wcr(stdf.wafer_configuration.as_ref());
+        let columns = map.columns();
+        let rows = map.rows();
+        let (width, code_rows) = self.code_rows(map);
+        let separator = if width > 1 { " " } else { "" };
+
+        let mut text = String::new();
+        let _ = writeln!(text, "WAFER_ID {}", map.wafer_id);
+        let _ = writeln!(text, "LOT_ID {}", mir.lot_id);
+        let _ = writeln!(text, "DEVICE {}", mir.part_typ);
+        let _ = writeln!(
+            text,
+            "BIN_TYPE {}",
+            if self.hard_bins { "hard" } else { "soft" }
+        );
+        let _ = writeln!(text, "FLAT {}", geometry.flat);
+        let _ = writeln!(
+            text,
+            "DIE_SIZE {} {}",
+            geometry.die_width, geometry.die_height
+        );
+        let _ = writeln!(text, "ROWS {}", rows.len());
+        let _ = writeln!(text, "COLUMNS {}", columns.len());
+        let _ = writeln!(text, "FIRST_X {}", columns.first().unwrap_or(&0));
+        let _ = writeln!(text, "FIRST_Y {}", rows.first().unwrap_or(&0));
+        let _ = writeln!(
+            text,
+            "X_STEP {}",
+            if map.orientation.x_right { 1 } else { -1 }
+        );
+        let _ = writeln!(
+            text,
+            "Y_STEP {}",
+            if map.orientation.y_down { 1 } else { -1 }
+        );
+        let _ = writeln!(text, "NULL_CODE {}", self.null_code);
+        for (&bin, map_bin) in &map.bins {
+            let pf = if map_bin.pass { "pass" } else { "fail" };
+            let code = self.code(bin, Some(map_bin));
+            let _ = writeln!(
+                text,
+                "BIN {bin} {code} {pf} {} {}",
+                map_bin.count, map_bin.name
+            );
+        }
+        text.push_str("MAP\n");
+        for row in code_rows {
+            let _ = writeln!(text, "{}", row.join(separator));
+        }
+        text.push_str("END\n");
+        text
+    }
+
+    /// Render the `map` as a SEMI E142 substrate map
+    fn to_e142(&self, stdf: &STDF, map: &Wafermap) -> String {
+        let mir = &stdf.master_information;
+        let geometry = WaferGeometry::from_wcr(stdf.wafer_configuration.as_ref());
+        let columns = map.columns();
+        let rows = map.rows();
+        let (bin_type, null_code, codes, code_rows) = self.e142_codes(map);
+        let axis_direction = match (map.orientation.x_right, map.orientation.y_down) {
+            (true, true) => "DownRight",
+            (true, false) => "UpRight",
+            (false, true) => "DownLeft",
+            (false, false) => "UpLeft",
+        };
+
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(
+            "<MapData xmlns=\"urn:semi-org:xsd.E142-1.V1005.SubstrateMap\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+        );
+        xml.push_str("  <Layouts>\n");
+        xml.push_str(
+            "    <Layout LayoutId=\"WaferLayout\" DefaultUnits=\"mm\" TopLevel=\"true\">\n",
+        );
+        xml.push_str("      <Dimension X=\"1\" Y=\"1\"/>\n");
+        if geometry.diameter > 0. {
+            let _ = writeln!(
+                xml,
+                "      <DeviceSize X=\"{0}\" Y=\"{0}\"/>",
+                geometry.diameter
+            );
+        }
+        xml.push_str("      <ChildLayouts>\n");
+        xml.push_str("        <ChildLayout LayoutId=\"DieLayout\"/>\n");
+        xml.push_str("      </ChildLayouts>\n");
+        xml.push_str("    </Layout>\n");
+        xml.push_str("    <Layout LayoutId=\"DieLayout\" DefaultUnits=\"mm\">\n");
+        let _ = writeln!(
+            xml,
+            "      <Dimension X=\"{}\" Y=\"{}\"/>",
+            columns.len(),
+            rows.len()
+        );
+        let _ = writeln!(
+            xml,
+            "      <DeviceSize X=\"{}\" Y=\"{}\"/>",
+            geometry.die_width, geometry.die_height
+        );
+        xml.push_str("    </Layout>\n");
+        xml.push_str("  </Layouts>\n");
+        xml.push_str("  <Substrates>\n");
+        let _ = writeln!(
+            xml,
+            "    <Substrate SubstrateType=\"Wafer\" SubstrateId=\"{}\">",
+            escape(&map.wafer_id)
+        );
+        let _ = writeln!(xml, "      <LotId>{}</LotId>", escape(&mir.lot_id));
+        let _ = writeln!(
+            xml,
+            "      <AliasIds>\n        <AliasId Type=\"PartType\" Value=\"{}\"/>\n      </AliasIds>",
+            escape(&mir.part_typ)
+        );
+        xml.push_str("    </Substrate>\n");
+        xml.push_str("  </Substrates>\n");
+        xml.push_str("  <SubstrateMaps>\n");
+        let _ = writeln!(
+            xml,
+            "    <SubstrateMap SubstrateType=\"Wafer\" SubstrateId=\"{}\" \
+             LayoutSpecifier=\"WaferLayout/DieLayout\" SubstrateSide=\"TopSide\" \
+             OriginLocation=\"UpperLeft\" AxisDirection=\"{axis_direction}\">",
+            escape(&map.wafer_id)
+        );
+        let _ = writeln!(
+            xml,
+            "      <Overlay MapName=\"{}\" MapVersion=\"1\">",
+            if self.hard_bins {
+                "HardBins"
+            } else {
+                "SoftBins"
+            }
+        );
+        let _ = writeln!(
+            xml,
+            "        <BinCodeMap BinType=\"{bin_type}\" NullBin=\"{}\">",
+            escape(&null_code)
+        );
+        // the coordinates of the die in the upper left corner of the bin codes
+        xml.push_str("          <ReferenceDevices>\n");
+        xml.push_str("            <ReferenceDevice Name=\"FirstDie\">\n");
+        let _ = writeln!(
+            xml,
+            "              <Coordinates X=\"{}\" Y=\"{}\"/>",
+            columns.first().unwrap_or(&0),
+            rows.first().unwrap_or(&0)
+        );
+        xml.push_str("            </ReferenceDevice>\n");
+        xml.push_str("          </ReferenceDevices>\n");
+        xml.push_str("          <BinDefinitions>\n");
+        for (&bin, map_bin) in &map.bins {
+            let quality = if map_bin.pass { "Pass" } else { "Fail" };
+            let _ = writeln!(
+                xml,
+                "            <BinDefinition BinCode=\"{}\" BinCount=\"{}\" BinQuality=\"{quality}\" \
+                 BinDescription=\"{}\"/>",
+                escape(&codes[&bin]),
+                map_bin.count,
+                escape(format!("bin {bin} {}", map_bin.name).trim_end()),
+            );
+        }
+        xml.push_str("          </BinDefinitions>\n");
+        for row in code_rows {
+            let _ = writeln!(xml, "          <BinCode>{}</BinCode>", escape(&row));
+        }
+        xml.push_str("        </BinCodeMap>\n");
+        xml.push_str("      </Overlay>\n");
+        xml.push_str("    </SubstrateMap>\n");
+        xml.push_str("  </SubstrateMaps>\n");
+        xml.push_str("</MapData>\n");
+        xml
+    }
+
+    /// Render the `map` as KLARF 1.2, with a defect at the center of each die in a failing bin
+    ///
+    /// The class number of each defect is its bin code if that is a number, otherwise its bin.
+    fn to_klarf(&self, stdf: &STDF, map: &Wafermap, slot: usize) -> String {
+        let mir = &stdf.master_information;
+        let geometry = WaferGeometry::from_wcr(stdf.wafer_configuration.as_ref());
+        let (pitch_x, pitch_y) = (geometry.die_width * 1e3, geometry.die_height * 1e3);
+        // KLARF die indexes increase to the right and up
+        let index = |x: i16, y: i16| {
+            let (column, row) = map.orientation.map_offset(x, y);
+            (column as i32, -(row as i32))
+        };
+        let finish_t = stdf
+            .wafer_information
+            .iter()
+            .find(|wafer| Some(wafer.wafer_index) == map.wafer_index)
+            .map(|wafer| wafer.finish_t)
+            .unwrap_or(mir.finish_t);
+        let result_time = timestamp(finish_t);
+        let setup_time = timestamp(mir.setup_t);
+        let orientation_mark = match geometry.flat {
+            'U' => "UP",
+            'L' => "LEFT",
+            'R' => "RIGHT",
+            _ => "DOWN",
+        };
+
+        let mut text = String::new();
+        text.push_str("FileVersion 1 2;\n");
+        let _ = writeln!(text, "FileTimestamp {result_time};");
+        let _ = writeln!(
+            text,
+            "InspectionStationID \"STDF\" \"{}\" \"{}\";",
+            quote(&mir.tstr_typ),
+            quote(&mir.node_nam)
+        );
+        text.push_str("SampleType WAFER;\n");
+        let _ = writeln!(text, "ResultTimestamp {result_time};");
+        let _ = writeln!(text, "LotID \"{}\";", quote(&mir.lot_id));
+        let _ = writeln!(text, "SampleSize 1 {};", geometry.diameter.round());
+        let _ = writeln!(text, "DeviceID \"{}\";", quote(&mir.part_typ));
+        let _ = writeln!(text, "SetupID \"{}\" {setup_time};", quote(&mir.job_nam));
+        let _ = writeln!(text, "StepID \"{}\";", quote(&mir.test_cod));
+        text.push_str("SampleOrientationMarkType FLAT;\n");
+        let _ = writeln!(text, "OrientationMarkLocation {orientation_mark};");
+        let _ = writeln!(text, "DiePitch {} {};", sci(pitch_x), sci(pitch_y));
+        text.push_str("DieOrigin 0.000000e+00 0.000000e+00;\n");
+        let _ = writeln!(text, "WaferID \"{}\";", quote(&map.wafer_id));
+        let _ = writeln!(text, "Slot {slot};");
+        let (center_x, center_y) = match geometry.center {
+            Some((x, y)) => {
+                let (column, row) = index(x, y);
+                (
+                    (column as f64 + 0.5) * pitch_x,
+                    (row as f64 + 0.5) * pitch_y,
+                )
+            }
+            None => (0., 0.),
+        };
+        let _ = writeln!(
+            text,
+            "SampleCenterLocation {} {};",
+            sci(center_x),
+            sci(center_y)
+        );
+        text.push_str("InspectionTest 1;\n");
+        let _ = write!(text, "SampleTestPlan {}", map.dies.len());
+        for &(x, y) in map.dies.keys() {
+            let (column, row) = index(x, y);
+            let _ = write!(text, "\n {column} {row}");
+        }
+        text.push_str(";\n");
+        let die_area = pitch_x * pitch_y;
+        let area = die_area * map.dies.len() as f64;
+        let _ = writeln!(text, "AreaPerTest {};", sci(area));
+        text.push_str(
+            "DefectRecordSpec 13 DEFECTID XREL YREL XINDEX YINDEX XSIZE YSIZE DEFECTAREA DSIZE \
+             CLASSNUMBER TEST ROUGHBINNUMBER FINEBINNUMBER;\n",
+        );
+        text.push_str("DefectList");
+        let mut n_defects = 0;
+        for (&(x, y), die) in &map.dies {
+            let map_bin = map.bins.get(&die.bin);
+            if map_bin.is_some_and(|map_bin| map_bin.pass) {
+                continue;
+            }
+            n_defects += 1;
+            let (column, row) = index(x, y);
+            let code = self.code(die.bin, map_bin);
+            let class: u32 = code.parse().unwrap_or(die.bin as u32);
+            let _ = write!(
+                text,
+                "\n {n_defects} {} {} {column} {row} {} {} {} {} {class} 1 {class} {}",
+                sci(pitch_x / 2.),
+                sci(pitch_y / 2.),
+                sci(pitch_x),
+                sci(pitch_y),
+                sci(die_area),
+                sci(pitch_x),
+                die.bin,
+            );
+        }
+        text.push_str(";\n");
+        text.push_str("SummarySpec 5 TESTNO NDEFECT DEFDENSITY NCLUSTER AREA;\n");
+        // the defect density is per square centimeter
+        let density = if area > 0. {
+            n_defects as f64 / (area * 1e-8)
+        } else {
+            0.
+        };
+        let _ = writeln!(
+            text,
+            "SummaryList\n 1 {n_defects} {} 0 {};",
+            sci(density),
+            sci(area)
+        );
+        text.push_str("EndOfFile;\n");
+        text
+    }
+}
+
+/// Parse a bin code mapping from entries like `1=A,2=B,20=X`
+///
+/// Codes may not be empty or contain whitespace.
+pub fn parse_bin_codes(s: &str) -> Result<BinCodes, String> {
+    s.split(',')
+        .map(|entry| {
+            let (bin, code) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("invalid bin code {entry}, expected <bin>=<code>"))?;
+            let bin: u16 = bin
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid bin {bin}: {e}"))?;
+            let code = code.trim();
+            if code.is_empty() || code.chars().any(char::is_whitespace) {
+                return Err(format!("invalid code {code} for bin {bin}"));
+            }
+            Ok((bin, code.to_string()))
+        })
+        .collect()
+}
+
+/// Format a `u32` timestamp, in seconds since the Unix epoch, as KLARF does: `MM-DD-YY HH:MM:SS`
+fn timestamp(t: u32) -> String {
+    let days = (t / 86400) as i64;
+    let seconds = t % 86400;
+    // the proleptic Gregorian date of the days since 1970-01-01
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{month:02}-{day:02}-{:02} {:02}:{:02}:{:02}",
+        year % 100,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Format a number as KLARF does, like `1.500000e+03`
+fn sci(x: f64) -> String {
+    let formatted = format!("{x:.6e}");
+    match formatted.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let exponent: i32 = exponent.parse().unwrap_or(0);
+            format!("{mantissa}e{exponent:+03}")
+        }
+        None => formatted,
+    }
+}
+
+/// Make text safe to write inside a quoted KLARF string
+fn quote(text: &str) -> String {
+    text.replace('"', "'").replace(';', ",")
+}
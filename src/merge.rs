@@ -0,0 +1,230 @@
+//! Merging of several STDF files, e.g. the wafers, retests and resumed runs of a lot, into one
+//! dataset
+//!
+//! Each file is parsed on its own, in parallel when more than one thread is allowed, and the
+//! `TestData`s are concatenated in the order the files are given (see `TestData::concat`). The
+//! test columns are aligned by test key, so every test of every file gets a single column, and
+//! the parts of a file without a test are left unexecuted for it.
+//!
+//! The test information metadata of each test comes from the first file that has the test. Every
+//! later file whose limits, units, name or test type for the test differ is listed as a
+//! `Conflict`. A test whose type differs is also kept apart in a column of its own.
+use std::{collections::HashMap, fs::File, io, num::NonZeroUsize, thread};
+
+use polars::prelude::*;
+
+use crate::{
+    data::{STDF, TestData, WaferInformation},
+    export::ExportFormat,
+    test_information::{ColumnNaming, MergedTestInformation, TestIdentity, TestKey},
+};
+
+/// A test whose metadata in a later file differs from that in the first file with the test
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub test_key: TestKey,
+    /// The differing field, `low_limit`, `high_limit`, `units`, `test_name` or `test_type`
+    pub field: &'static str,
+    /// The first file with the test, whose metadata is used
+    pub first_file: String,
+    pub first_value: String,
+    /// The later file whose metadata differs
+    pub file: String,
+    pub value: String,
+}
+
+/// The test results of several STDF files merged into one `TestData`
+#[derive(Debug)]
+pub struct MergedStdf {
+    /// The merged files, in order
+    pub fnames: Vec<String>,
+    /// The index in `fnames` of the file of each part, parallel to `test_data.data`
+    pub sources: Vec<u32>,
+    /// The wafers of every file, with the `wafer_index` of the merged parts
+    pub wafer_information: Vec<WaferInformation>,
+    /// The parts of every file with the union of their tests
+    pub test_data: TestData,
+    /// The metadata conflicts between the files
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergedStdf {
+    /// Parse and merge the STDF files `fnames`, telling tests apart by `identity`
+    ///
+    /// The files are parsed on up to `n_threads` threads at once, or one per available CPU if
+    /// `n_threads` is 0.
+    ///
+    /// # Error
+    /// If any of the files cannot be parsed, returns an `std::io::Error` naming the file
+    pub fn from_fnames(
+        fnames: &[String],
+        identity: TestIdentity,
+        n_threads: usize,
+    ) -> io::Result<Self> {
+        let n_threads = match n_threads {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            n_threads => n_threads,
+        };
+        let parse = |fname: &String| {
+            STDF::from_fname_with_identity(fname, false, identity)
+                .map_err(|e| io::Error::new(e.kind(), format!("{fname}: {e}")))
+        };
+        let chunk_size = fnames.len().div_ceil(n_threads).max(1);
+        let stdfs: Vec<STDF> = thread::scope(|scope| {
+            let handles: Vec<_> = fnames
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(parse).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("a thread parsing an STDF panicked"))
+                .collect::<io::Result<_>>()
+        })?;
+        Ok(Self::from_stdfs(fnames, stdfs))
+    }
+
+    /// Merge the already parsed `stdfs`, read from the files `fnames` in the same order
+    pub fn from_stdfs(fnames: &[String], stdfs: Vec<STDF>) -> Self {
+        let conflicts = conflicts(fnames, &stdfs);
+        let mut sources = Vec::new();
+        let mut wafer_information = Vec::new();
+        let mut wafer_offset = 0;
+        let mut test_datas = Vec::new();
+        for (i, stdf) in stdfs.into_iter().enumerate() {
+            sources.extend(std::iter::repeat_n(i as u32, stdf.test_data.data.len()));
+            for mut wafer in stdf.wafer_information {
+                wafer.wafer_index += wafer_offset;
+                wafer_information.push(wafer);
+            }
+            wafer_offset += stdf.test_data.n_wafers();
+            test_datas.push(stdf.test_data);
+        }
+        Self {
+            fnames: fnames.to_vec(),
+            sources,
+            wafer_information,
+            test_data: TestData::concat(test_datas),
+            conflicts,
+        }
+    }
+
+    /// Convert into a wide `DataFrame` like `TestData::to_df`, preceded by the columns
+    /// `source_file`, the file of each part, and `insertion_index`, the index of the part in the
+    /// merged dataset
+    ///
+    /// With `flat` the multi-pin tests are split into one column per pin, as in
+    /// `TestData::to_flat_df`.
+    pub fn to_df(&self, naming: &ColumnNaming, flat: bool) -> DataFrame {
+        let df = match flat {
+            true => self.test_data.to_flat_df(naming),
+            false => self.test_data.to_df(naming),
+        };
+        let source_files: Vec<&str> = self
+            .sources
+            .iter()
+            .map(|&i| self.fnames[i as usize].as_str())
+            .collect();
+        let insertion_indexes: Vec<u32> = (0..self.sources.len() as u32).collect();
+        let mut columns = vec![
+            Column::new("source_file".into(), source_files),
+            Column::new("insertion_index".into(), insertion_indexes),
+        ];
+        columns.extend(df.get_columns().iter().cloned());
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Convert the `conflicts` into a `DataFrame`
+    ///
+    /// The columns are `test_key`, `test_num`, `field`, `first_file`, `first_value`, `file` and
+    /// `value`.
+    pub fn conflicts_to_df(&self) -> DataFrame {
+        let mut test_keys: Vec<String> = Vec::new();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut fields: Vec<&str> = Vec::new();
+        let mut first_files: Vec<&str> = Vec::new();
+        let mut first_values: Vec<&str> = Vec::new();
+        let mut files: Vec<&str> = Vec::new();
+        let mut values: Vec<&str> = Vec::new();
+        for conflict in &self.conflicts {
+            test_keys.push(conflict.test_key.to_string());
+            test_nums.push(conflict.test_key.test_num());
+            fields.push(conflict.field);
+            first_files.push(&conflict.first_file);
+            first_values.push(&conflict.first_value);
+            files.push(&conflict.file);
+            values.push(&conflict.value);
+        }
+        let columns = vec![
+            Column::new("test_key".into(), test_keys),
+            Column::new("test_num".into(), test_nums),
+            Column::new("field".into(), fields),
+            Column::new("first_file".into(), first_files),
+            Column::new("first_value".into(), first_values),
+            Column::new("file".into(), files),
+            Column::new("value".into(), values),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Write the merged test results to `fname` in the `format`, with the test columns named by
+    /// `naming`
+    ///
+    /// CSV cannot hold the multi-pin test columns, so they are split into one column per pin.
+    ///
+    /// # Error
+    /// If the file cannot be written, returns a `PolarsError`
+    pub fn write(
+        &self,
+        fname: &str,
+        format: ExportFormat,
+        naming: &ColumnNaming,
+    ) -> PolarsResult<()> {
+        let mut df = self.to_df(naming, format == ExportFormat::Csv);
+        let file = File::create(fname)?;
+        match format {
+            ExportFormat::Parquet => ParquetWriter::new(file).finish(&mut df).map(|_| ()),
+            ExportFormat::Ipc => IpcWriter::new(file).finish(&mut df),
+            ExportFormat::Csv => CsvWriter::new(file).finish(&mut df),
+        }
+    }
+}
+
+/// Find the tests whose limits, units, name or type differ from those in the first file with the
+/// test
+fn conflicts(fnames: &[String], stdfs: &[STDF]) -> Vec<Conflict> {
+    let mut firsts: HashMap<&TestKey, (usize, &MergedTestInformation)> = HashMap::new();
+    let mut conflicts = Vec::new();
+    for (i, stdf) in stdfs.iter().enumerate() {
+        let test_infos = &stdf.test_data.test_information.test_infos;
+        let mut test_infos: Vec<_> = test_infos.iter().collect();
+        test_infos.sort_by_key(|(test_key, _)| *test_key);
+        for (test_key, mti) in test_infos {
+            let Some(&(first, first_mti)) = firsts.get(test_key) else {
+                firsts.insert(test_key, (i, mti));
+                continue;
+            };
+            let differences = match first_mti.test_type == mti.test_type {
+                true => first_mti.differences(mti),
+                // the tests are kept apart, so only their types conflict
+                false => vec![(
+                    "test_type",
+                    first_mti.test_type.to_string(),
+                    mti.test_type.to_string(),
+                )],
+            };
+            for (field, first_value, value) in differences {
+                conflicts.push(Conflict {
+                    test_key: test_key.clone(),
+                    field,
+                    first_file: fnames[first].clone(),
+                    first_value,
+                    file: fnames[i].clone(),
+                    value,
+                });
+            }
+        }
+    }
+    conflicts
+}
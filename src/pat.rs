@@ -0,0 +1,372 @@
+//! Part Average Testing (PAT) per AEC-Q001
+//!
+//! PAT flags parts that pass every test but are outliers of the population, and so are likelier
+//! to fail in the field. For each parametric test the PAT limits are
+//! `center - k * sigma` and `center + k * sigma`, narrowed to the test limits, where `center` and
+//! `sigma` are the median and the interquartile range divided by 1.35 (see `stats::iqr_sigma`),
+//! or the mean and standard deviation with `Estimator::Classic`.
+//!
+//! * Static PAT computes one set of limits per test from a reference population, e.g. the parts
+//!   of earlier lots, and applies it to every part.
+//! * Dynamic PAT computes the limits from the parts themselves, per wafer or over the whole lot.
+//!
+//! Only the passing results of passing parts make up the population, and only passing parts
+//! can be outliers. A test whose population has fewer than `min_parts` results gets no PAT
+//! limits.
+//!
+//! `Pat::run` gives a `PatReport` of the limits and outliers, and `Pat::rebin` writes the PAT bins
+//! of the outliers back into the `Row`s of the `TestData`.
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use polars::prelude::*;
+
+use crate::{
+    data::{Row, TestData},
+    stats::{self, Estimator},
+    test_information::TestKey,
+};
+
+/// Whether the PAT limits come from a reference population or from the parts themselves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PatMode {
+    /// One set of limits from a reference population
+    #[default]
+    Static,
+    /// A set of limits per wafer, from the parts of that wafer
+    DynamicWafer,
+    /// One set of limits from all parts of the lot
+    DynamicLot,
+}
+
+impl fmt::Display for PatMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Static => write!(f, "static"),
+            Self::DynamicWafer => write!(f, "dynamic_wafer"),
+            Self::DynamicLot => write!(f, "dynamic_lot"),
+        }
+    }
+}
+
+/// Parses a `PatMode` from `static`, `dynamic_wafer` or `dynamic_lot`
+impl FromStr for PatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "static" => Ok(Self::Static),
+            "dynamic_wafer" => Ok(Self::DynamicWafer),
+            "dynamic_lot" => Ok(Self::DynamicLot),
+            _ => Err(format!(
+                "unknown PAT mode {s}, expected one of static, dynamic_wafer, dynamic_lot"
+            )),
+        }
+    }
+}
+
+/// The PAT engine
+#[derive(Debug, Clone)]
+pub struct Pat {
+    /// Where the limits come from
+    pub mode: PatMode,
+    /// The number of sigmas from the center to each limit
+    pub k: f64,
+    /// The estimators of center and spread, `Robust` being the median and `stats::iqr_sigma`
+    pub estimator: Estimator,
+    /// The smallest population to compute limits from
+    pub min_parts: usize,
+    /// Apply PAT to only these `test_num`s, otherwise to every parametric test
+    pub test_nums: Option<Vec<u32>>,
+    /// The soft bin given to outliers by `rebin`, otherwise their soft bin is kept
+    pub pat_sbin: Option<u16>,
+    /// The hard bin given to outliers by `rebin`, otherwise their hard bin is kept
+    pub pat_hbin: Option<u16>,
+}
+
+impl Default for Pat {
+    fn default() -> Self {
+        Self {
+            mode: PatMode::Static,
+            k: 6.,
+            estimator: Estimator::Robust,
+            min_parts: 30,
+            test_nums: None,
+            pat_sbin: None,
+            pat_hbin: None,
+        }
+    }
+}
+
+/// The PAT limits of one test, for one wafer with `PatMode::DynamicWafer`
+#[derive(Debug, Clone)]
+pub struct PatLimit {
+    pub test_key: TestKey,
+    /// The index of the wafer the limits apply to, `None` if they apply to every wafer
+    pub wafer_index: Option<u32>,
+    /// The wafer the limits apply to, empty if they apply to every wafer
+    pub wafer_id: String,
+    /// The number of results the limits were computed from
+    pub n: usize,
+    pub center: f64,
+    pub sigma: f64,
+    pub lo_limit: f64,
+    pub hi_limit: f64,
+}
+
+/// A result of a passing part outside the PAT limits of its test
+#[derive(Debug, Clone)]
+pub struct Outlier {
+    /// The index of the part in `TestData.data`
+    pub part_index: usize,
+    pub part_id: String,
+    pub wafer_index: Option<u32>,
+    pub wafer_id: String,
+    pub x_coord: i16,
+    pub y_coord: i16,
+    pub head_num: u8,
+    pub site_num: u8,
+    pub test_key: TestKey,
+    pub result: f32,
+    pub lo_limit: f64,
+    pub hi_limit: f64,
+}
+
+/// The PAT limits and the outliers found with them
+#[derive(Debug, Clone, Default)]
+pub struct PatReport {
+    pub limits: Vec<PatLimit>,
+    pub outliers: Vec<Outlier>,
+}
+
+impl Pat {
+    /// Compute the PAT limits and find the outliers of `test_data`
+    ///
+    /// With `PatMode::Static` the limits come from `reference`, or from `test_data` itself if
+    /// there is no `reference`. The dynamic modes ignore `reference`.
+    pub fn run(&self, test_data: &TestData, reference: Option<&TestData>) -> PatReport {
+        let limits = match self.mode {
+            PatMode::Static => self.limits(reference.unwrap_or(test_data), |_| None),
+            PatMode::DynamicWafer => self.limits(test_data, |row| row.wafer_index),
+            PatMode::DynamicLot => self.limits(test_data, |_| None),
+        };
+        let lookup: HashMap<(&TestKey, Option<u32>), &PatLimit> = limits
+            .iter()
+            .map(|limit| ((&limit.test_key, limit.wafer_index), limit))
+            .collect();
+        let wafer_index = |row: &Row| match self.mode {
+            PatMode::DynamicWafer => row.wafer_index,
+            _ => None,
+        };
+
+        let test_keys = test_data.parametric_test_keys();
+        let mut outliers = Vec::new();
+        for (part_index, row) in test_data.data.iter().enumerate() {
+            if !row.pass() {
+                continue;
+            }
+            let wafer_index = wafer_index(row);
+            for (i, &result) in row.results_parametric.iter().enumerate() {
+                let Some(limit) = lookup.get(&(test_keys[i], wafer_index)) else {
+                    continue;
+                };
+                let value = f64::from(result);
+                if row.passed_parametric[i].is_none()
+                    || result.is_nan()
+                    || (limit.lo_limit..=limit.hi_limit).contains(&value)
+                {
+                    continue;
+                }
+                outliers.push(Outlier {
+                    part_index,
+                    part_id: row.part_id.clone(),
+                    wafer_index: row.wafer_index,
+                    wafer_id: row.wafer_id.clone(),
+                    x_coord: row.x_coord,
+                    y_coord: row.y_coord,
+                    head_num: row.head_num,
+                    site_num: row.site_num,
+                    test_key: test_keys[i].clone(),
+                    result,
+                    lo_limit: limit.lo_limit,
+                    hi_limit: limit.hi_limit,
+                });
+            }
+        }
+
+        PatReport { limits, outliers }
+    }
+
+    /// Write the PAT bins of the `report` outliers back into the `Row`s of `test_data`
+    ///
+    /// Each outlier part is marked as failed in its `part_flg` and gets `pat_sbin` and
+    /// `pat_hbin`, where set. Returns the number of parts rebinned. `test_data` must be the one
+    /// the `report` was run on.
+    pub fn rebin(&self, test_data: &mut TestData, report: &PatReport) -> usize {
+        report
+            .outliers
+            .iter()
+            .filter(|outlier| {
+                test_data.data[outlier.part_index].rebin(self.pat_sbin, self.pat_hbin)
+            })
+            .count()
+    }
+
+    /// The limits of each test from the parts of `test_data`, per the wafer `wafer_index` gives,
+    /// or over all parts where it gives `None`
+    fn limits(
+        &self,
+        test_data: &TestData,
+        wafer_index: impl Fn(&Row) -> Option<u32>,
+    ) -> Vec<PatLimit> {
+        let test_keys = test_data.parametric_test_keys();
+        let mut populations: HashMap<(usize, Option<u32>), Vec<f64>> = HashMap::new();
+        let mut wafer_ids: HashMap<Option<u32>, &str> = HashMap::new();
+        for row in test_data.data.iter().filter(|row| row.pass()) {
+            let wafer_index = wafer_index(row);
+            if wafer_index.is_some() {
+                wafer_ids.insert(wafer_index, &row.wafer_id);
+            }
+            let results = row.results_parametric.iter().zip(&row.passed_parametric);
+            for (i, (&result, passed)) in results.enumerate() {
+                if *passed == Some(true) && !result.is_nan() && self.applies(test_keys[i]) {
+                    populations
+                        .entry((i, wafer_index))
+                        .or_default()
+                        .push(f64::from(result));
+                }
+            }
+        }
+
+        let mut limits: Vec<PatLimit> = populations
+            .into_iter()
+            .filter(|(_, values)| values.len() >= self.min_parts.max(2))
+            .filter_map(|((i, wafer_index), mut values)| {
+                values.sort_by(f64::total_cmp);
+                let (center, sigma) = match self.estimator {
+                    Estimator::Classic => (stats::mean(&values)?, stats::std_dev(&values)?),
+                    Estimator::Robust => (stats::median(&values)?, stats::iqr_sigma(&values)?),
+                };
+                let test_key = test_keys[i];
+                let mti = &test_data.test_information.test_infos[test_key];
+                let mut lo_limit = center - self.k * sigma;
+                let mut hi_limit = center + self.k * sigma;
+                if mti.low_limit.is_finite() {
+                    lo_limit = lo_limit.max(f64::from(mti.low_limit));
+                }
+                if mti.high_limit.is_finite() {
+                    hi_limit = hi_limit.min(f64::from(mti.high_limit));
+                }
+                Some(PatLimit {
+                    test_key: test_key.clone(),
+                    wafer_index,
+                    wafer_id: wafer_ids.get(&wafer_index).unwrap_or(&"").to_string(),
+                    n: values.len(),
+                    center,
+                    sigma,
+                    lo_limit,
+                    hi_limit,
+                })
+            })
+            .collect();
+        limits.sort_by(|a, b| (&a.test_key, a.wafer_index).cmp(&(&b.test_key, b.wafer_index)));
+        limits
+    }
+
+    /// Whether PAT applies to the test `test_key`
+    fn applies(&self, test_key: &TestKey) -> bool {
+        match &self.test_nums {
+            Some(test_nums) => test_nums.contains(&test_key.test_num()),
+            None => true,
+        }
+    }
+}
+
+impl PatReport {
+    /// Convert the `limits` into a `DataFrame`, one row per test (and wafer)
+    pub fn limits_to_df(&self) -> DataFrame {
+        let mut test_keys: Vec<String> = Vec::new();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut wafer_indexes: Vec<Option<u32>> = Vec::new();
+        let mut wafer_ids: Vec<&str> = Vec::new();
+        let mut ns: Vec<u32> = Vec::new();
+        let mut centers: Vec<f64> = Vec::new();
+        let mut sigmas: Vec<f64> = Vec::new();
+        let mut lo_limits: Vec<f64> = Vec::new();
+        let mut hi_limits: Vec<f64> = Vec::new();
+
+        for limit in &self.limits {
+            test_keys.push(limit.test_key.to_string());
+            test_nums.push(limit.test_key.test_num());
+            wafer_indexes.push(limit.wafer_index);
+            wafer_ids.push(&limit.wafer_id);
+            ns.push(limit.n as u32);
+            centers.push(limit.center);
+            sigmas.push(limit.sigma);
+            lo_limits.push(limit.lo_limit);
+            hi_limits.push(limit.hi_limit);
+        }
+        let columns = vec![
+            Column::new("test_key".into(), test_keys),
+            Column::new("test_num".into(), test_nums),
+            Column::new("wafer_index".into(), wafer_indexes),
+            Column::new("wafer_id".into(), wafer_ids),
+            Column::new("n".into(), ns),
+            Column::new("center".into(), centers),
+            Column::new("sigma".into(), sigmas),
+            Column::new("pat_lo_limit".into(), lo_limits),
+            Column::new("pat_hi_limit".into(), hi_limits),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Convert the `outliers` into a `DataFrame`, one row per outlying result
+    pub fn outliers_to_df(&self) -> DataFrame {
+        let mut part_indexes: Vec<u32> = Vec::new();
+        let mut part_ids: Vec<&str> = Vec::new();
+        let mut wafer_indexes: Vec<Option<u32>> = Vec::new();
+        let mut wafer_ids: Vec<&str> = Vec::new();
+        let mut x_coords: Vec<i16> = Vec::new();
+        let mut y_coords: Vec<i16> = Vec::new();
+        let mut head_nums: Vec<u8> = Vec::new();
+        let mut site_nums: Vec<u8> = Vec::new();
+        let mut test_keys: Vec<String> = Vec::new();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut results: Vec<f32> = Vec::new();
+        let mut lo_limits: Vec<f64> = Vec::new();
+        let mut hi_limits: Vec<f64> = Vec::new();
+
+        for outlier in &self.outliers {
+            part_indexes.push(outlier.part_index as u32);
+            part_ids.push(&outlier.part_id);
+            wafer_indexes.push(outlier.wafer_index);
+            wafer_ids.push(&outlier.wafer_id);
+            x_coords.push(outlier.x_coord);
+            y_coords.push(outlier.y_coord);
+            head_nums.push(outlier.head_num);
+            site_nums.push(outlier.site_num);
+            test_keys.push(outlier.test_key.to_string());
+            test_nums.push(outlier.test_key.test_num());
+            results.push(outlier.result);
+            lo_limits.push(outlier.lo_limit);
+            hi_limits.push(outlier.hi_limit);
+        }
+        let columns = vec![
+            Column::new("part_index".into(), part_indexes),
+            Column::new("part_id".into(), part_ids),
+            Column::new("wafer_index".into(), wafer_indexes),
+            Column::new("wafer_id".into(), wafer_ids),
+            Column::new("x_coord".into(), x_coords),
+            Column::new("y_coord".into(), y_coords),
+            Column::new("head_num".into(), head_nums),
+            Column::new("site_num".into(), site_nums),
+            Column::new("test_key".into(), test_keys),
+            Column::new("test_num".into(), test_nums),
+            Column::new("result".into(), results),
+            Column::new("pat_lo_limit".into(), lo_limits),
+            Column::new("pat_hi_limit".into(), hi_limits),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+}
@@ -0,0 +1,262 @@
+//! Reconciliation of the summary records with the parts and tests actually in an STDF file
+//!
+//! Testers write the `PCR`s, `HBR`s, `SBR`s and `TSR`s from their own running counts, which can
+//! drift from the `PRR`s and test records in the file, e.g. after a tester restart. `reconcile`
+//! recomputes every count from the `TestData` of an `STDF`, per site and over all sites, and
+//! lists each count of a summary record that disagrees.
+//!
+//! Optional counts a summary record leaves out (4294967295) are not compared. Bins and tests with
+//! parts or results but no summary record at all are listed with a missing `summary` count, as
+//! long as the file has any bin summaries for that site, or any `TSR`s, respectively. Tests the
+//! `TestIdentity` of the `STDF` merges into one test key cannot be compared, so a `TSR` is only
+//! checked if it is the only one for its test key at its location.
+use std::collections::{HashMap, HashSet};
+
+use polars::prelude::*;
+
+use crate::{data::STDF, filter::PartCounts, test_information::TestKey};
+
+/// A count in a summary record that disagrees with the parts or tests in the file
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Mismatch {
+    /// The type of the summary record, `PCR`, `HBR`, `SBR` or `TSR`
+    pub record: &'static str,
+    /// The `head_num`, 255 for the counts over all sites
+    pub head_num: u8,
+    /// The `site_num`, 255 for the counts over all sites
+    pub site_num: u8,
+    /// The bin number or test key the count belongs to, empty for a `PCR`
+    pub key: String,
+    /// The name of the count, e.g. `good_cnt`
+    pub field: &'static str,
+    /// The count in the summary record, `None` if there is no summary record
+    pub summary: Option<u32>,
+    /// The count recomputed from the parts and test results
+    pub actual: u32,
+}
+
+/// The counts of the `TSR`s for one test key at one location
+#[derive(Debug, Default)]
+struct TestSummary {
+    n_tsrs: u32,
+    exec_cnt: Option<u32>,
+    fail_cnt: Option<u32>,
+}
+
+/// Compare the summary records of `stdf` with the counts recomputed from its `test_data`
+///
+/// Returns the mismatches sorted by record type, `head_num`, `site_num`, key and count.
+pub fn reconcile(stdf: &STDF) -> Vec<Mismatch> {
+    let mut part_counts: HashMap<(u8, u8), PartCounts> = HashMap::new();
+    let mut sbin_counts: HashMap<(u8, u8, u16), u32> = HashMap::new();
+    let mut hbin_counts: HashMap<(u8, u8, u16), u32> = HashMap::new();
+    for row in &stdf.test_data.data {
+        for (head_num, site_num) in [(row.head_num, row.site_num), (255, 255)] {
+            part_counts
+                .entry((head_num, site_num))
+                .or_default()
+                .add_part_flg(row.part_flg);
+            *sbin_counts
+                .entry((head_num, site_num, row.sbin))
+                .or_default() += 1;
+            *hbin_counts
+                .entry((head_num, site_num, row.hbin))
+                .or_default() += 1;
+        }
+    }
+    // summaries over all sites may give any site_num
+    let key = |head_num: u8, site_num: u8| match head_num {
+        255 => (255, 255),
+        _ => (head_num, site_num),
+    };
+    let mut mismatches = Vec::new();
+    let mut mismatch =
+        |record, (head_num, site_num), key: String, field, summary: Option<u32>, actual| {
+            if summary != Some(actual) {
+                mismatches.push(Mismatch {
+                    record,
+                    head_num,
+                    site_num,
+                    key,
+                    field,
+                    summary,
+                    actual,
+                });
+            }
+        };
+
+    for pcr in stdf.part_counts.values() {
+        let (head_num, site_num) = key(pcr.head_num, pcr.site_num);
+        let default = PartCounts::default();
+        let counts = part_counts.get(&(head_num, site_num)).unwrap_or(&default);
+        let fields = [
+            ("part_cnt", pcr.part_cnt, counts.part_cnt),
+            ("rtst_cnt", pcr.rtst_cnt, counts.rtst_cnt),
+            ("abrt_cnt", pcr.abrt_cnt, counts.abrt_cnt),
+            ("good_cnt", pcr.good_cnt, counts.good_cnt),
+        ];
+        for (field, summary, actual) in fields {
+            if field == "part_cnt" || summary != u32::MAX {
+                let location = (head_num, site_num);
+                mismatch("PCR", location, String::new(), field, Some(summary), actual);
+            }
+        }
+    }
+
+    let bins = [
+        (
+            "SBR",
+            "sbin_cnt",
+            &sbin_counts,
+            stdf.soft_bins
+                .values()
+                .map(|sbr| (sbr.head_num, sbr.site_num, sbr.sbin_num, sbr.sbin_cnt))
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "HBR",
+            "hbin_cnt",
+            &hbin_counts,
+            stdf.hard_bins
+                .values()
+                .map(|hbr| (hbr.head_num, hbr.site_num, hbr.hbin_num, hbr.hbin_cnt))
+                .collect::<Vec<_>>(),
+        ),
+    ];
+    for (record, field, bin_counts, summaries) in bins {
+        for &(head_num, site_num, bin_num, summary) in &summaries {
+            let (head_num, site_num) = key(head_num, site_num);
+            let actual = bin_counts
+                .get(&(head_num, site_num, bin_num))
+                .copied()
+                .unwrap_or(0);
+            let location = (head_num, site_num);
+            mismatch(
+                record,
+                location,
+                bin_num.to_string(),
+                field,
+                Some(summary),
+                actual,
+            );
+        }
+        // a site with any bin summaries should have one for every bin its parts fell into
+        let summarized: HashSet<(u8, u8, u16)> = summaries
+            .iter()
+            .map(|&(head_num, site_num, bin_num, _)| {
+                let (head_num, site_num) = key(head_num, site_num);
+                (head_num, site_num, bin_num)
+            })
+            .collect();
+        let locations: HashSet<(u8, u8)> = summarized
+            .iter()
+            .map(|&(head_num, site_num, _)| (head_num, site_num))
+            .collect();
+        for (&(head_num, site_num, bin_num), &actual) in bin_counts {
+            let location = (head_num, site_num);
+            if locations.contains(&location) && !summarized.contains(&(head_num, site_num, bin_num))
+            {
+                mismatch(record, location, bin_num.to_string(), field, None, actual);
+            }
+        }
+    }
+
+    // a test_key shared by several TSRs at one location merges distinct tests into one column of
+    // the `TestData`, which keeps a single result per part, so their counts cannot be compared
+    let identity = &stdf.test_data.full_test_information.identity;
+    let mut test_summaries: HashMap<(TestKey, u8, u8), TestSummary> = HashMap::new();
+    for tsr in &stdf.test_summaries {
+        let test_key = identity.key(tsr.test_num, &tsr.test_nam, tsr.test_typ.into());
+        let (head_num, site_num) = key(tsr.head_num, tsr.site_num);
+        let summary = test_summaries
+            .entry((test_key, site_num, head_num))
+            .or_default();
+        summary.n_tsrs += 1;
+        summary.exec_cnt = Some(tsr.exec_cnt).filter(|&cnt| cnt != u32::MAX);
+        summary.fail_cnt = Some(tsr.fail_cnt).filter(|&cnt| cnt != u32::MAX);
+    }
+    let test_counts = stdf.test_data.test_counts();
+    for ((test_key, site_num, head_num), summary) in &test_summaries {
+        if summary.n_tsrs > 1 {
+            continue;
+        }
+        let (exec_cnt, fail_cnt) = test_counts
+            .get(&(test_key.clone(), *site_num, *head_num))
+            .copied()
+            .unwrap_or_default();
+        let location = (*head_num, *site_num);
+        if summary.exec_cnt.is_some() {
+            mismatch(
+                "TSR",
+                location,
+                test_key.to_string(),
+                "exec_cnt",
+                summary.exec_cnt,
+                exec_cnt,
+            );
+        }
+        if summary.fail_cnt.is_some() {
+            mismatch(
+                "TSR",
+                location,
+                test_key.to_string(),
+                "fail_cnt",
+                summary.fail_cnt,
+                fail_cnt,
+            );
+        }
+    }
+    if !test_summaries.is_empty() {
+        for ((test_key, site_num, head_num), (exec_cnt, _)) in &test_counts {
+            let summarized = test_summaries
+                .keys()
+                .any(|(summary_key, _, _)| summary_key == test_key);
+            if *head_num == 255 && !summarized {
+                let location = (*head_num, *site_num);
+                mismatch(
+                    "TSR",
+                    location,
+                    test_key.to_string(),
+                    "exec_cnt",
+                    None,
+                    *exec_cnt,
+                );
+            }
+        }
+    }
+
+    mismatches.sort();
+    mismatches
+}
+
+/// Convert the `mismatches` into a `DataFrame`
+pub fn mismatches_to_df(mismatches: &[Mismatch]) -> DataFrame {
+    let mut records: Vec<&str> = Vec::new();
+    let mut head_nums: Vec<u8> = Vec::new();
+    let mut site_nums: Vec<u8> = Vec::new();
+    let mut keys: Vec<&str> = Vec::new();
+    let mut fields: Vec<&str> = Vec::new();
+    let mut summaries: Vec<Option<u32>> = Vec::new();
+    let mut actuals: Vec<u32> = Vec::new();
+
+    for mismatch in mismatches {
+        records.push(mismatch.record);
+        head_nums.push(mismatch.head_num);
+        site_nums.push(mismatch.site_num);
+        keys.push(&mismatch.key);
+        fields.push(mismatch.field);
+        summaries.push(mismatch.summary);
+        actuals.push(mismatch.actual);
+    }
+    let columns = vec![
+        Column::new("record".into(), records),
+        Column::new("head_num".into(), head_nums),
+        Column::new("site_num".into(), site_nums),
+        Column::new("key".into(), keys),
+        Column::new("field".into(), fields),
+        Column::new("summary".into(), summaries),
+        Column::new("actual".into(), actuals),
+    ];
+
+    DataFrame::new(columns).unwrap()
+}
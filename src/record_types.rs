@@ -87,4 +87,40 @@ impl RecordType {
             _ => Self::InvalidRecord,
         }
     }
+
+    /// The (`rec_typ`, `rec_sub`) of the `RecordType`, the inverse of `RecordType::new`
+    ///
+    /// Returns `None` for `RecordType::InvalidRecord`
+    pub fn codes(&self) -> Option<(u8, u8)> {
+        match self {
+            Self::FAR => Some((0, 10)),
+            Self::ATR => Some((0, 20)),
+            Self::VUR => Some((0, 30)),
+            Self::MIR => Some((1, 10)),
+            Self::MRR => Some((1, 20)),
+            Self::PCR => Some((1, 30)),
+            Self::HBR => Some((1, 40)),
+            Self::SBR => Some((1, 50)),
+            Self::PMR => Some((1, 60)),
+            Self::PGR => Some((1, 62)),
+            Self::PLR => Some((1, 63)),
+            Self::RDR => Some((1, 70)),
+            Self::SDR => Some((1, 80)),
+            Self::PSR => Some((1, 90)),
+            Self::WIR => Some((2, 10)),
+            Self::WRR => Some((2, 20)),
+            Self::WCR => Some((2, 30)),
+            Self::PIR => Some((5, 10)),
+            Self::PRR => Some((5, 20)),
+            Self::TSR => Some((10, 30)),
+            Self::PTR => Some((15, 10)),
+            Self::MPR => Some((15, 15)),
+            Self::FTR => Some((15, 20)),
+            Self::BPS => Some((20, 10)),
+            Self::EPS => Some((20, 20)),
+            Self::GDR => Some((50, 10)),
+            Self::DTR => Some((50, 30)),
+            Self::InvalidRecord => None,
+        }
+    }
 }
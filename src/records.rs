@@ -36,6 +36,12 @@ impl Header {
         }
     }
 
+    /// Encode the `Header` into its 4 bytes
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let [len_lo, len_hi] = self.rec_len.to_le_bytes();
+        [len_lo, len_hi, self.rec_typ, self.rec_sub]
+    }
+
     /// Get the next `Header` from a `reader` (e.g. a file handle)
     pub fn from_file(reader: &mut impl Read) -> Result<Self, io::Error> {
         let mut buf: [u8; 4] = [0; 4];
@@ -59,7 +65,7 @@ impl Header {
 pub struct RawRecord {
     /// The owned record header
     pub header: Header,
-    /// The location of the `RawRecord` in the file
+    /// The location of the start of the `RawRecord`, i.e. its `Header`, in the file
     pub offset: usize,
     /// The raw unparsed contents of the `RawRecord`
     pub contents: Vec<u8>,
@@ -68,6 +74,52 @@ pub struct RawRecord {
 }
 
 impl RawRecord {
+    /// Create a `RawRecord` of type `rtype` holding the encoded `contents`
+    ///
+    /// The `Header` is derived from `rtype` and the length of `contents`. The `offset` is 0, as
+    /// the `RawRecord` has not come from a file.
+    ///
+    /// # Panics
+    /// If `rtype` is `RecordType::InvalidRecord` or `contents` is longer than the 65535 bytes a
+    /// record can hold, see `try_new` for a fallible version
+    pub fn new(rtype: RecordType, contents: Vec<u8>) -> Self {
+        Self::try_new(rtype, contents).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create a `RawRecord` of type `rtype` holding the encoded `contents`, like `new`
+    ///
+    /// # Error
+    /// If `rtype` is `RecordType::InvalidRecord` or `contents` is longer than the 65535 bytes a
+    /// record can hold, returns a `std::io::ErrorKind::InvalidInput` error
+    pub fn try_new(rtype: RecordType, contents: Vec<u8>) -> io::Result<Self> {
+        let (rec_typ, rec_sub) = rtype.codes().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot create a RawRecord of an invalid record type",
+            )
+        })?;
+        let rec_len = u16::try_from(contents.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Cannot create a {rtype:?} of {} bytes, longer than the 65535 a record can hold",
+                    contents.len()
+                ),
+            )
+        })?;
+        let header = Header {
+            rec_len,
+            rec_typ,
+            rec_sub,
+        };
+        Ok(Self {
+            header,
+            offset: 0,
+            contents,
+            rtype,
+        })
+    }
+
     /// Given a record header, get the record contents and determine the record type
     pub fn from_header(
         header: Header,
@@ -91,25 +143,58 @@ impl RawRecord {
     /// conrete record without hitting the file again.
     ///
     /// Resolving is the most expensive part of the process, so it is done only on-demand.
+    ///
+    /// # Panics
+    /// If the `contents` are malformed, see `try_resolve` for a fallible version
     pub fn resolve(&self) -> Option<Record> {
-        match self.rtype {
-            RecordType::MIR => Some(Record::MIR(self.into())),
-            RecordType::SDR => Some(Record::SDR(self.into())),
-            RecordType::TSR => Some(Record::TSR(self.into())),
-            RecordType::SBR => Some(Record::SBR(self.into())),
-            RecordType::HBR => Some(Record::HBR(self.into())),
-            RecordType::PCR => Some(Record::PCR(self.into())),
-            RecordType::MRR => Some(Record::MRR(self.into())),
-            RecordType::PIR => Some(Record::PIR(self.into())),
-            RecordType::PRR => Some(Record::PRR(self.into())),
-            RecordType::WIR => Some(Record::WIR(self.into())),
-            RecordType::WRR => Some(Record::WRR(self.into())),
-            RecordType::PTR => Some(Record::PTR(self.into())),
-            RecordType::FTR => Some(Record::FTR(self.into())),
-            RecordType::MPR => Some(Record::MPR(self.into())),
-            RecordType::PMR => Some(Record::PMR(self.into())),
-            _ => None,
-        }
+        self.try_resolve().unwrap_or_else(|e| {
+            panic!(
+                "Cannot resolve the {:?} at offset {}: {e}",
+                self.rtype, self.offset
+            )
+        })
+    }
+
+    /// Resolve a `RawRecord` into a concrete record type, like `resolve`
+    ///
+    /// Returns `None` for record types that are not resolved. Optional fields omitted from the end
+    /// of the record, as the specification allows, are given their missing values, e.g.
+    /// 4294967295 for counts and empty strings, and left out of the record's `n_optional`.
+    ///
+    /// # Error
+    /// If the `contents` are too short for the required fields of the record type, end in the
+    /// middle of a field or hold invalid text,
+    /// returns an `std::io::Error`
+    pub fn try_resolve(&self) -> io::Result<Option<Record>> {
+        let record = match self.rtype {
+            RecordType::FAR => Record::FAR(self.try_into()?),
+            RecordType::MIR => Record::MIR(self.try_into()?),
+            RecordType::SDR => Record::SDR(self.try_into()?),
+            RecordType::TSR => Record::TSR(self.try_into()?),
+            RecordType::SBR => Record::SBR(self.try_into()?),
+            RecordType::HBR => Record::HBR(self.try_into()?),
+            RecordType::PCR => Record::PCR(self.try_into()?),
+            RecordType::MRR => Record::MRR(self.try_into()?),
+            RecordType::PIR => Record::PIR(self.try_into()?),
+            RecordType::PRR => Record::PRR(self.try_into()?),
+            RecordType::WIR => Record::WIR(self.try_into()?),
+            RecordType::WRR => Record::WRR(self.try_into()?),
+            RecordType::WCR => Record::WCR(self.try_into()?),
+            RecordType::PTR => Record::PTR(self.try_into()?),
+            RecordType::FTR => Record::FTR(self.try_into()?),
+            RecordType::MPR => Record::MPR(self.try_into()?),
+            RecordType::PMR => Record::PMR(self.try_into()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(record))
+    }
+
+    /// Encode the `RawRecord` into the bytes of its `Header` followed by its `contents`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + self.contents.len());
+        bytes.extend_from_slice(&self.header.to_bytes());
+        bytes.extend_from_slice(&self.contents);
+        bytes
     }
 }
 
@@ -136,19 +221,22 @@ impl Records {
         let reader = BufReader::new(f);
         Ok(Self { reader, offset: 0 })
     }
+
+    /// The number of bytes of the complete records read so far, i.e. the offset of the next
+    /// record
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 }
 
 impl Iterator for Records {
     type Item = RawRecord;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match Header::from_file(&mut self.reader) {
-            Ok(header) => {
-                self.offset += header.rec_len as usize;
-                RawRecord::from_header(header, &mut self.reader, self.offset).ok()
-            }
-            Err(_) => None,
-        }
+        let header = Header::from_file(&mut self.reader).ok()?;
+        let raw_record = RawRecord::from_header(header, &mut self.reader, self.offset).ok()?;
+        self.offset += 4 + raw_record.contents.len();
+        Some(raw_record)
     }
 }
 
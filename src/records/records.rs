@@ -1,7 +1,9 @@
 use super::Records;
+use crate::record_types::RecordType;
 use crate::records::RawRecord;
 use crate::util::*;
 use pyo3::prelude::IntoPyObject;
+use serde::Serialize;
 use std::io;
 
 /// Master Information Record
@@ -46,52 +48,58 @@ pub struct MIR {
     pub rom_cod: String,
     pub serl_num: String,
     pub supr_nam: String,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for MIR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for MIR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let setup_t = U4(contents, &mut offset);
-        let start_t = U4(contents, &mut offset);
-        let stat_num = U1(contents, &mut offset);
-        let mode_cod = C1(contents, &mut offset);
-        let rtst_cod = C1(contents, &mut offset);
-        let prot_cod = C1(contents, &mut offset);
-        let burn_tim = U2(contents, &mut offset);
-        let cmod_cod = C1(contents, &mut offset);
-        let lot_id = Cn(contents, &mut offset);
-        let part_typ = Cn(contents, &mut offset);
-        let node_nam = Cn(contents, &mut offset);
-        let tstr_typ = Cn(contents, &mut offset);
-        let job_nam = Cn(contents, &mut offset);
-        let job_rev = Cn(contents, &mut offset);
-        let sblot_id = Cn(contents, &mut offset);
-        let oper_nam = Cn(contents, &mut offset);
-        let exec_typ = Cn(contents, &mut offset);
-        let exec_ver = Cn(contents, &mut offset);
-        let test_cod = Cn(contents, &mut offset);
-        let tst_temp = Cn(contents, &mut offset);
-        let user_txt = Cn(contents, &mut offset);
-        let aux_file = Cn(contents, &mut offset);
-        let pkg_typ = Cn(contents, &mut offset);
-        let famly_id = Cn(contents, &mut offset);
-        let date_cod = Cn(contents, &mut offset);
-        let facil_id = Cn(contents, &mut offset);
-        let floor_id = Cn(contents, &mut offset);
-        let proc_id = Cn(contents, &mut offset);
-        let oper_frq = Cn(contents, &mut offset);
-        let spec_nam = Cn(contents, &mut offset);
-        let spec_ver = Cn(contents, &mut offset);
-        let flow_id = Cn(contents, &mut offset);
-        let setup_id = Cn(contents, &mut offset);
-        let dsgn_rev = Cn(contents, &mut offset);
-        let eng_id = Cn(contents, &mut offset);
-        let rom_cod = Cn(contents, &mut offset);
-        let serl_num = Cn(contents, &mut offset);
-        let supr_nam = Cn(contents, &mut offset);
+        let mut n_optional = 0;
+        let setup_t = U4(contents, &mut offset)?;
+        let start_t = U4(contents, &mut offset)?;
+        let stat_num = U1(contents, &mut offset)?;
+        let mode_cod = C1(contents, &mut offset)?;
+        let rtst_cod = C1(contents, &mut offset)?;
+        let prot_cod = C1(contents, &mut offset)?;
+        let burn_tim = U2(contents, &mut offset)?;
+        let cmod_cod = C1(contents, &mut offset)?;
+        let lot_id = Cn(contents, &mut offset)?;
+        let part_typ = Cn(contents, &mut offset)?;
+        let node_nam = Cn(contents, &mut offset)?;
+        let tstr_typ = Cn(contents, &mut offset)?;
+        let job_nam = Cn(contents, &mut offset)?;
+        let job_rev = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let sblot_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let oper_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let exec_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let exec_ver = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let test_cod = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let tst_temp = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let user_txt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let aux_file = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let pkg_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let famly_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let date_cod = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let facil_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let floor_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let proc_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let oper_frq = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let spec_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let spec_ver = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let flow_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let setup_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let dsgn_rev = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let eng_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let rom_cod = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let serl_num = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let supr_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
 
-        Self {
+        Ok(Self {
             setup_t,
             start_t,
             stat_num,
@@ -130,6 +138,153 @@ impl From<&RawRecord> for MIR {
             rom_cod,
             serl_num,
             supr_nam,
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&MIR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &MIR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U4(&mut contents, record.setup_t);
+        push_U4(&mut contents, record.start_t);
+        push_U1(&mut contents, record.stat_num);
+        push_C1(&mut contents, record.mode_cod);
+        push_C1(&mut contents, record.rtst_cod);
+        push_C1(&mut contents, record.prot_cod);
+        push_U2(&mut contents, record.burn_tim);
+        push_C1(&mut contents, record.cmod_cod);
+        push_Cn(&mut contents, &record.lot_id);
+        push_Cn(&mut contents, &record.part_typ);
+        push_Cn(&mut contents, &record.node_nam);
+        push_Cn(&mut contents, &record.tstr_typ);
+        push_Cn(&mut contents, &record.job_nam);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.job_rev)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.sblot_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.oper_nam)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.exec_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.exec_ver)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.test_cod)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.tst_temp)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.user_txt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.aux_file)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.pkg_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.famly_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.date_cod)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.facil_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.floor_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.proc_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.oper_frq)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.spec_nam)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.spec_ver)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.flow_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.setup_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.dsgn_rev)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.eng_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.rom_cod)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.serl_num)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.supr_nam)
+        });
+        RawRecord::try_new(RecordType::MIR, contents)
+    }
+}
+
+/// An `MIR` for station 1 with every optional field set to its missing value: spaces for the
+/// characters, empty strings and 65535 for `burn_tim`
+impl Default for MIR {
+    fn default() -> Self {
+        Self {
+            setup_t: 0,
+            start_t: 0,
+            stat_num: 1,
+            mode_cod: ' ',
+            rtst_cod: ' ',
+            prot_cod: ' ',
+            burn_tim: u16::MAX,
+            cmod_cod: ' ',
+            lot_id: String::new(),
+            part_typ: String::new(),
+            node_nam: String::new(),
+            tstr_typ: String::new(),
+            job_nam: String::new(),
+            job_rev: String::new(),
+            sblot_id: String::new(),
+            oper_nam: String::new(),
+            exec_typ: String::new(),
+            exec_ver: String::new(),
+            test_cod: String::new(),
+            tst_temp: String::new(),
+            user_txt: String::new(),
+            aux_file: String::new(),
+            pkg_typ: String::new(),
+            famly_id: String::new(),
+            date_cod: String::new(),
+            facil_id: String::new(),
+            floor_id: String::new(),
+            proc_id: String::new(),
+            oper_frq: String::new(),
+            spec_nam: String::new(),
+            spec_ver: String::new(),
+            flow_id: String::new(),
+            setup_id: String::new(),
+            dsgn_rev: String::new(),
+            eng_id: String::new(),
+            rom_cod: String::new(),
+            serl_num: String::new(),
+            supr_nam: String::new(),
+            n_optional: u8::MAX,
         }
     }
 }
@@ -163,7 +318,7 @@ impl MIR {
 }
 
 /// Site Description Record
-#[derive(Debug, Clone, IntoPyObject)]
+#[derive(Debug, Clone, IntoPyObject, Serialize)]
 #[allow(dead_code)]
 pub struct SDR {
     pub head_num: u8,
@@ -186,34 +341,40 @@ pub struct SDR {
     pub lasr_id: String,
     pub extr_typ: String,
     pub extr_i: String,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for SDR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for SDR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_grp = U1(contents, &mut offset);
-        let site_cnt = U1(contents, &mut offset);
-        let site_num = kxU1(contents, site_cnt as usize, &mut offset);
-        let hand_typ = Cn(contents, &mut offset);
-        let hand_id = Cn(contents, &mut offset);
-        let card_typ = Cn(contents, &mut offset);
-        let card_id = Cn(contents, &mut offset);
-        let load_typ = Cn(contents, &mut offset);
-        let load_id = Cn(contents, &mut offset);
-        let dib_typ = Cn(contents, &mut offset);
-        let dib_id = Cn(contents, &mut offset);
-        let cabl_typ = Cn(contents, &mut offset);
-        let cabl_id = Cn(contents, &mut offset);
-        let cont_typ = Cn(contents, &mut offset);
-        let cont_id = Cn(contents, &mut offset);
-        let lasr_typ = Cn(contents, &mut offset);
-        let lasr_id = Cn(contents, &mut offset);
-        let extr_typ = Cn(contents, &mut offset);
-        let extr_i = Cn(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_grp = U1(contents, &mut offset)?;
+        let site_cnt = U1(contents, &mut offset)?;
+        let site_num = kxU1(contents, site_cnt as usize, &mut offset)?;
+        let hand_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let hand_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let card_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let card_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let load_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let load_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let dib_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let dib_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let cabl_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let cabl_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let cont_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let cont_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let lasr_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let lasr_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let extr_typ = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let extr_i = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_grp,
             site_cnt,
@@ -234,7 +395,70 @@ impl From<&RawRecord> for SDR {
             lasr_id,
             extr_typ,
             extr_i,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&SDR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &SDR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_grp);
+        push_U1(&mut contents, record.site_cnt);
+        push_kxU1(&mut contents, &record.site_num, record.site_cnt as usize);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.hand_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.hand_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.card_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.card_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.load_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.load_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.dib_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.dib_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.cabl_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.cabl_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.cont_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.cont_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.lasr_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.lasr_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.extr_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.extr_i)
+        });
+        RawRecord::try_new(RecordType::SDR, contents)
     }
 }
 
@@ -259,30 +483,36 @@ pub struct TSR {
     pub test_max: f32,
     pub tst_sums: f32,
     pub tst_sqrs: f32,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for TSR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for TSR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let test_typ: char = C1(contents, &mut offset);
-        let test_num = U4(contents, &mut offset);
-        let exec_cnt = U4(contents, &mut offset);
-        let fail_cnt = U4(contents, &mut offset);
-        let alrm_cnt = U4(contents, &mut offset);
-        let test_nam = Cn(contents, &mut offset);
-        let seq_name = Cn(contents, &mut offset);
-        let test_lbl = Cn(contents, &mut offset);
-        let opt_flag = U1(contents, &mut offset);
-        let test_tim = R4(contents, &mut offset);
-        let test_min = R4(contents, &mut offset);
-        let test_max = R4(contents, &mut offset);
-        let tst_sums = R4(contents, &mut offset);
-        let tst_sqrs = R4(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let test_typ: char = C1(contents, &mut offset)?;
+        let test_num = U4(contents, &mut offset)?;
+        let exec_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let fail_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let alrm_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let test_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let seq_name = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let test_lbl = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let opt_flag = optional(contents, &mut offset, &mut n_optional, U1, 0b1111_1111)?;
+        let test_tim = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let test_min = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let test_max = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let tst_sums = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let tst_sqrs = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_num,
             test_typ,
@@ -299,7 +529,58 @@ impl From<&RawRecord> for TSR {
             test_max,
             tst_sums,
             tst_sqrs,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&TSR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &TSR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_C1(&mut contents, record.test_typ);
+        push_U4(&mut contents, record.test_num);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.exec_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.fail_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.alrm_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.test_nam)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.seq_name)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.test_lbl)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.opt_flag)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.test_tim)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.test_min)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.test_max)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.tst_sums)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.tst_sqrs)
+        });
+        RawRecord::try_new(RecordType::TSR, contents)
     }
 }
 
@@ -314,27 +595,54 @@ pub struct SBR {
     pub sbin_cnt: u32,
     pub sbin_pf: char,
     pub sbin_nam: String,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for SBR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for SBR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let sbin_num = U2(contents, &mut offset);
-        let sbin_cnt = U4(contents, &mut offset);
-        let sbin_pf = C1(contents, &mut offset);
-        let sbin_nam = Cn(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let sbin_num = U2(contents, &mut offset)?;
+        let sbin_cnt = U4(contents, &mut offset)?;
+        let sbin_pf = optional(contents, &mut offset, &mut n_optional, C1, ' ')?;
+        let sbin_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_num,
             sbin_num,
             sbin_cnt,
             sbin_pf,
             sbin_nam,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&SBR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &SBR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_U2(&mut contents, record.sbin_num);
+        push_U4(&mut contents, record.sbin_cnt);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_C1(bytes, record.sbin_pf)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.sbin_nam)
+        });
+        RawRecord::try_new(RecordType::SBR, contents)
     }
 }
 
@@ -347,23 +655,46 @@ pub struct WIR {
     pub site_grp: u8,
     pub start_t: u32,
     pub wafer_id: String,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for WIR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for WIR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_grp = U1(contents, &mut offset);
-        let start_t = U4(contents, &mut offset);
-        let wafer_id = Cn(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_grp = U1(contents, &mut offset)?;
+        let start_t = U4(contents, &mut offset)?;
+        let wafer_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_grp,
             start_t,
             wafer_id,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&WIR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &WIR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_grp);
+        push_U4(&mut contents, record.start_t);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.wafer_id)
+        });
+        RawRecord::try_new(RecordType::WIR, contents)
     }
 }
 
@@ -386,28 +717,34 @@ pub struct WRR {
     pub mask_id: String,
     pub usr_desc: String,
     pub exc_desc: String,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for WRR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for WRR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_grp = U1(contents, &mut offset);
-        let finish_t = U4(contents, &mut offset);
-        let part_cnt = U4(contents, &mut offset);
-        let rtst_cnt = U4(contents, &mut offset);
-        let abrt_cnt = U4(contents, &mut offset);
-        let good_cnt = U4(contents, &mut offset);
-        let func_cnt = U4(contents, &mut offset);
-        let wafer_id = Cn(contents, &mut offset);
-        let fabwf_id = Cn(contents, &mut offset);
-        let frame_id = Cn(contents, &mut offset);
-        let mask_id = Cn(contents, &mut offset);
-        let usr_desc = Cn(contents, &mut offset);
-        let exc_desc = Cn(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_grp = U1(contents, &mut offset)?;
+        let finish_t = U4(contents, &mut offset)?;
+        let part_cnt = U4(contents, &mut offset)?;
+        let rtst_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let abrt_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let good_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let func_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let wafer_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let fabwf_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let frame_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let mask_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let usr_desc = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let exc_desc = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_grp,
             finish_t,
@@ -422,7 +759,140 @@ impl From<&RawRecord> for WRR {
             mask_id,
             usr_desc,
             exc_desc,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&WRR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &WRR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_grp);
+        push_U4(&mut contents, record.finish_t);
+        push_U4(&mut contents, record.part_cnt);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.rtst_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.abrt_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.good_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.func_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.wafer_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.fabwf_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.frame_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.mask_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.usr_desc)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.exc_desc)
+        });
+        RawRecord::try_new(RecordType::WRR, contents)
+    }
+}
+
+/// Wafer Configuration Record
+#[derive(Debug, Clone, IntoPyObject)]
+#[allow(dead_code)]
+#[allow(non_snake_case)]
+pub struct WCR {
+    pub wafr_siz: f32,
+    pub die_hght: f32,
+    pub die_wid: f32,
+    pub wf_units: u8,
+    pub wf_flat: char,
+    pub center_x: i16,
+    pub center_y: i16,
+    pub pos_x: char,
+    pub pos_y: char,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
+}
+
+impl TryFrom<&RawRecord> for WCR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
+        let contents = &record.contents;
+        let mut offset: usize = 0;
+        let mut n_optional = 0;
+        let wafr_siz = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let die_hght = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let die_wid = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let wf_units = optional(contents, &mut offset, &mut n_optional, U1, 0)?;
+        let wf_flat = optional(contents, &mut offset, &mut n_optional, C1, ' ')?;
+        let center_x = optional(contents, &mut offset, &mut n_optional, I2, i16::MIN)?;
+        let center_y = optional(contents, &mut offset, &mut n_optional, I2, i16::MIN)?;
+        let pos_x = optional(contents, &mut offset, &mut n_optional, C1, ' ')?;
+        let pos_y = optional(contents, &mut offset, &mut n_optional, C1, ' ')?;
+
+        Ok(Self {
+            wafr_siz,
+            die_hght,
+            die_wid,
+            wf_units,
+            wf_flat,
+            center_x,
+            center_y,
+            pos_x,
+            pos_y,
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&WCR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &WCR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.wafr_siz)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.die_hght)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.die_wid)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.wf_units)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_C1(bytes, record.wf_flat)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I2(bytes, record.center_x)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I2(bytes, record.center_y)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_C1(bytes, record.pos_x)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_C1(bytes, record.pos_y)
+        });
+        RawRecord::try_new(RecordType::WCR, contents)
     }
 }
 
@@ -437,27 +907,54 @@ pub struct HBR {
     pub hbin_cnt: u32,
     pub hbin_pf: char,
     pub hbin_nam: String,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for HBR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for HBR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let hbin_num = U2(contents, &mut offset);
-        let hbin_cnt = U4(contents, &mut offset);
-        let hbin_pf = C1(contents, &mut offset);
-        let hbin_nam = Cn(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let hbin_num = U2(contents, &mut offset)?;
+        let hbin_cnt = U4(contents, &mut offset)?;
+        let hbin_pf = optional(contents, &mut offset, &mut n_optional, C1, ' ')?;
+        let hbin_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_num,
             hbin_num,
             hbin_cnt,
             hbin_pf,
             hbin_nam,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&HBR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &HBR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_U2(&mut contents, record.hbin_num);
+        push_U4(&mut contents, record.hbin_cnt);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_C1(bytes, record.hbin_pf)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.hbin_nam)
+        });
+        RawRecord::try_new(RecordType::HBR, contents)
     }
 }
 
@@ -473,21 +970,27 @@ pub struct PCR {
     pub abrt_cnt: u32,
     pub good_cnt: u32,
     pub func_cnt: u32,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for PCR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for PCR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let part_cnt = U4(contents, &mut offset);
-        let rtst_cnt = U4(contents, &mut offset);
-        let abrt_cnt = U4(contents, &mut offset);
-        let good_cnt = U4(contents, &mut offset);
-        let func_cnt = U4(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let part_cnt = U4(contents, &mut offset)?;
+        let rtst_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let abrt_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let good_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
+        let func_cnt = optional(contents, &mut offset, &mut n_optional, U4, u32::MAX)?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_num,
             part_cnt,
@@ -495,7 +998,33 @@ impl From<&RawRecord> for PCR {
             abrt_cnt,
             good_cnt,
             func_cnt,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&PCR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &PCR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_U4(&mut contents, record.part_cnt);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.rtst_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.abrt_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.good_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.func_cnt)
+        });
+        RawRecord::try_new(RecordType::PCR, contents)
     }
 }
 
@@ -508,13 +1037,27 @@ pub struct PIR {
     pub site_num: u8,
 }
 
-impl From<&RawRecord> for PIR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for PIR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
-        let head_num = contents[0];
-        let site_num = contents[1];
+        let mut offset: usize = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+
+        Ok(Self { head_num, site_num })
+    }
+}
+
+impl TryFrom<&PIR> for RawRecord {
+    type Error = io::Error;
 
-        Self { head_num, site_num }
+    fn try_from(record: &PIR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        RawRecord::try_new(RecordType::PIR, contents)
     }
 }
 
@@ -535,26 +1078,32 @@ pub struct PRR {
     pub part_id: String,
     pub part_txt: String,
     pub part_fix: Vec<u8>,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for PRR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for PRR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let part_flg = U1(contents, &mut offset);
-        let num_test = U2(contents, &mut offset);
-        let hard_bin = U2(contents, &mut offset);
-        let soft_bin = U2(contents, &mut offset);
-        let x_coord = I2(contents, &mut offset);
-        let y_coord = I2(contents, &mut offset);
-        let test_t = U4(contents, &mut offset);
-        let part_id = Cn(contents, &mut offset);
-        let part_txt = Cn(contents, &mut offset);
-        let part_fix = Bn(contents, &mut offset);
+        let mut n_optional = 0;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let part_flg = U1(contents, &mut offset)?;
+        let num_test = U2(contents, &mut offset)?;
+        let hard_bin = U2(contents, &mut offset)?;
+        let soft_bin = optional(contents, &mut offset, &mut n_optional, U2, u16::MAX)?;
+        let x_coord = optional(contents, &mut offset, &mut n_optional, I2, i16::MIN)?;
+        let y_coord = optional(contents, &mut offset, &mut n_optional, I2, i16::MIN)?;
+        let test_t = optional(contents, &mut offset, &mut n_optional, U4, 0)?;
+        let part_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let part_txt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let part_fix = optional(contents, &mut offset, &mut n_optional, Bn, Vec::new())?;
 
-        Self {
+        Ok(Self {
             head_num,
             site_num,
             part_flg,
@@ -567,7 +1116,51 @@ impl From<&RawRecord> for PRR {
             part_id,
             part_txt,
             part_fix,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&PRR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &PRR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_U1(&mut contents, record.part_flg);
+        push_U2(&mut contents, record.num_test);
+        push_U2(&mut contents, record.hard_bin);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U2(bytes, record.soft_bin)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I2(bytes, record.x_coord)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I2(bytes, record.y_coord)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.test_t)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.part_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.part_txt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Bn(bytes, &record.part_fix)
+        });
+        RawRecord::try_new(RecordType::PRR, contents)
+    }
+}
+
+impl PRR {
+    /// Whether the part passed, i.e. it has a valid pass/fail flag that does not indicate a fail
+    pub fn pass(&self) -> bool {
+        self.part_flg & 0b1_1000 == 0
     }
 }
 
@@ -580,23 +1173,63 @@ pub struct MRR {
     pub disp_cod: char,
     pub usr_desc: String,
     pub exc_desc: String,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
+}
+
+/// An `MRR` with every optional field set to its missing value
+impl Default for MRR {
+    fn default() -> Self {
+        Self {
+            finish_t: 0,
+            disp_cod: ' ',
+            usr_desc: String::new(),
+            exc_desc: String::new(),
+            n_optional: u8::MAX,
+        }
+    }
 }
 
-impl From<&RawRecord> for MRR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for MRR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let finish_t = U4(contents, &mut offset);
-        let disp_cod = C1(contents, &mut offset);
-        let usr_desc = Cn(contents, &mut offset);
-        let exc_desc = Cn(contents, &mut offset);
+        let mut n_optional = 0;
+        let finish_t = U4(contents, &mut offset)?;
+        let disp_cod = optional(contents, &mut offset, &mut n_optional, C1, ' ')?;
+        let usr_desc = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let exc_desc = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
 
-        Self {
+        Ok(Self {
             finish_t,
             disp_cod,
             usr_desc,
             exc_desc,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&MRR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &MRR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U4(&mut contents, record.finish_t);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_C1(bytes, record.disp_cod)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.usr_desc)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.exc_desc)
+        });
+        RawRecord::try_new(RecordType::MRR, contents)
     }
 }
 
@@ -625,61 +1258,40 @@ pub struct PTR {
     pub c_hlmfmt: String,
     pub lo_spec: f32,
     pub hi_spec: f32,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them. Those omitted are zero or empty.
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for PTR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for PTR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let test_num = U4(contents, &mut offset);
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let test_flg = U1(contents, &mut offset);
-        let parm_flg = U1(contents, &mut offset);
-        let result = R4(contents, &mut offset);
-        let test_txt = Cn(contents, &mut offset);
-        let alarm_id = Cn(contents, &mut offset);
-        let opt_flag;
-        let res_scal;
-        let llm_scal;
-        let hlm_scal;
-        let lo_limit;
-        let hi_limit;
-        let units;
-        let c_resfmt;
-        let c_llmfmt;
-        let c_hlmfmt;
-        let lo_spec;
-        let hi_spec;
-        if offset < record.contents.len() {
-            opt_flag = U1(contents, &mut offset);
-            res_scal = I1(contents, &mut offset);
-            llm_scal = I1(contents, &mut offset);
-            hlm_scal = I1(contents, &mut offset);
-            lo_limit = R4(contents, &mut offset);
-            hi_limit = R4(contents, &mut offset);
-            units = Cn(contents, &mut offset);
-            c_resfmt = Cn(contents, &mut offset);
-            c_llmfmt = Cn(contents, &mut offset);
-            c_hlmfmt = Cn(contents, &mut offset);
-            lo_spec = R4(contents, &mut offset);
-            hi_spec = R4(contents, &mut offset);
-        } else {
-            opt_flag = 0;
-            res_scal = 0;
-            llm_scal = 0;
-            hlm_scal = 0;
-            lo_limit = 0.;
-            hi_limit = 0.;
-            units = "".to_string();
-            c_resfmt = "".to_string();
-            c_llmfmt = "".to_string();
-            c_hlmfmt = "".to_string();
-            lo_spec = 0.;
-            hi_spec = 0.;
-        }
+        let mut n_optional = 0;
+        let test_num = U4(contents, &mut offset)?;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let test_flg = U1(contents, &mut offset)?;
+        let parm_flg = U1(contents, &mut offset)?;
+        let result = R4(contents, &mut offset)?;
+        let test_txt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let alarm_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let opt_flag = optional(contents, &mut offset, &mut n_optional, U1, 0)?;
+        let res_scal = optional(contents, &mut offset, &mut n_optional, I1, 0)?;
+        let llm_scal = optional(contents, &mut offset, &mut n_optional, I1, 0)?;
+        let hlm_scal = optional(contents, &mut offset, &mut n_optional, I1, 0)?;
+        let lo_limit = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let hi_limit = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let units = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let c_resfmt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let c_llmfmt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let c_hlmfmt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let lo_spec = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let hi_spec = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
 
-        Self {
+        Ok(Self {
             test_num,
             head_num,
             site_num,
@@ -700,7 +1312,66 @@ impl From<&RawRecord> for PTR {
             c_hlmfmt,
             lo_spec,
             hi_spec,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&PTR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &PTR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U4(&mut contents, record.test_num);
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_U1(&mut contents, record.test_flg);
+        push_U1(&mut contents, record.parm_flg);
+        push_R4(&mut contents, record.result);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.test_txt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.alarm_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.opt_flag)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I1(bytes, record.res_scal)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I1(bytes, record.llm_scal)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I1(bytes, record.hlm_scal)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.lo_limit)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.hi_limit)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.units)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.c_resfmt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.c_llmfmt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.c_hlmfmt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.lo_spec)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.hi_spec)
+        });
+        RawRecord::try_new(RecordType::PTR, contents)
     }
 }
 
@@ -708,6 +1379,43 @@ impl PTR {
     pub fn pass(&self) -> bool {
         (self.test_flg >> 6) & 0b11 == 0
     }
+
+    /// The `lo_limit`, or `NAN` if the test has no low limit according to the `opt_flag`
+    pub fn low_limit(&self) -> f32 {
+        self.optional(0b0101_0000, self.lo_limit)
+    }
+
+    /// The `hi_limit`, or `NAN` if the test has no high limit according to the `opt_flag`
+    pub fn high_limit(&self) -> f32 {
+        self.optional(0b1010_0000, self.hi_limit)
+    }
+
+    /// The `lo_spec`, or `NAN` if the test has no low specification limit according to the
+    /// `opt_flag`
+    pub fn low_spec(&self) -> f32 {
+        self.optional(0b0000_0100, self.lo_spec)
+    }
+
+    /// The `hi_spec`, or `NAN` if the test has no high specification limit according to the
+    /// `opt_flag`
+    pub fn high_spec(&self) -> f32 {
+        self.optional(0b0000_1000, self.hi_spec)
+    }
+
+    /// Whether the record has the optional fields from `opt_flag` onwards, which are otherwise
+    /// all zero or empty
+    pub fn has_optional_data(&self) -> bool {
+        self.n_optional > 2
+    }
+
+    /// `value`, or `NAN` if the record omits its optional fields or any of the `invalid` bits
+    /// of the `opt_flag` are set
+    fn optional(&self, invalid: u8, value: f32) -> f32 {
+        match self.has_optional_data() && self.opt_flag & invalid == 0 {
+            true => value,
+            false => f32::NAN,
+        }
+    }
 }
 
 /// Functional Test Record
@@ -727,13 +1435,14 @@ pub struct FTR {
     pub xfail_ad: i32,
     pub yfail_ad: i32,
     pub vect_off: i16,
-    pub rtn_icnt: u16,      // j
-    pub pgm_icnt: u16,      // k
-    pub rtn_indx: Vec<u16>, // rtn_icnt
-    pub rtn_stat: Vec<u8>,  // rtn_icnt, nibbles
-    pub pgm_indx: Vec<u16>, // pgm_icnt
-    pub pgm_stat: Vec<u8>,  // pgm_icnt, nibbles
-    pub fail_pin: Vec<u8>,  // Dn type (first 2 bytes length)
+    pub rtn_icnt: u16,       // j
+    pub pgm_icnt: u16,       // k
+    pub rtn_indx: Vec<u16>,  // rtn_icnt
+    pub rtn_stat: Vec<u8>,   // rtn_icnt, nibbles
+    pub pgm_indx: Vec<u16>,  // pgm_icnt
+    pub pgm_stat: Vec<u8>,   // pgm_icnt, nibbles
+    pub fail_pin_nbits: u16, // number of bits in fail_pin
+    pub fail_pin: Vec<u8>,   // Dn type (first 2 bytes length)
     pub vect_nam: String,
     pub time_set: String,
     pub op_code: String,
@@ -742,44 +1451,77 @@ pub struct FTR {
     pub prog_txt: String,
     pub rslt_txt: String,
     pub patg_num: u8,
-    pub spin_map: Vec<u8>, // Dn type (first 2 bytes length)
+    pub spin_map_nbits: u16, // number of bits in spin_map
+    pub spin_map: Vec<u8>,   // Dn type (first 2 bytes length)
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for FTR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for FTR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
-        let test_num = U4(contents, &mut offset);
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let test_flg = U1(contents, &mut offset);
-        let opt_flag = U1(contents, &mut offset);
-        let cycl_cnt = U4(contents, &mut offset);
-        let rel_vadr = U4(contents, &mut offset);
-        let rept_cnt = U4(contents, &mut offset);
-        let num_fail = U4(contents, &mut offset);
-        let xfail_ad = I4(contents, &mut offset);
-        let yfail_ad = I4(contents, &mut offset);
-        let vect_off = I2(contents, &mut offset);
-        let rtn_icnt = U2(contents, &mut offset);
-        let pgm_icnt = U2(contents, &mut offset);
-        let rtn_indx = kxU2(contents, rtn_icnt.into(), &mut offset);
-        let rtn_stat = kxN1(contents, rtn_icnt.into(), &mut offset);
-        let pgm_indx = kxU2(contents, pgm_icnt.into(), &mut offset);
-        let pgm_stat = kxN1(contents, pgm_icnt.into(), &mut offset);
-        let fail_pin = Dn(contents, &mut offset);
-
-        let vect_nam = Cn(contents, &mut offset);
-        let time_set = Cn(contents, &mut offset);
-        let op_code = Cn(contents, &mut offset);
-        let test_txt = Cn(contents, &mut offset);
-        let alarm_id = Cn(contents, &mut offset);
-        let prog_txt = Cn(contents, &mut offset);
-        let rslt_txt = Cn(contents, &mut offset);
-        let patg_num = U1(contents, &mut offset);
-        let spin_map = Dn(contents, &mut offset);
+        let mut n_optional = 0;
+        let test_num = U4(contents, &mut offset)?;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let test_flg = U1(contents, &mut offset)?;
+        let opt_flag = optional(contents, &mut offset, &mut n_optional, U1, 0b1111_1111)?;
+        let cycl_cnt = optional(contents, &mut offset, &mut n_optional, U4, 0)?;
+        let rel_vadr = optional(contents, &mut offset, &mut n_optional, U4, 0)?;
+        let rept_cnt = optional(contents, &mut offset, &mut n_optional, U4, 0)?;
+        let num_fail = optional(contents, &mut offset, &mut n_optional, U4, 0)?;
+        let xfail_ad = optional(contents, &mut offset, &mut n_optional, I4, 0)?;
+        let yfail_ad = optional(contents, &mut offset, &mut n_optional, I4, 0)?;
+        let vect_off = optional(contents, &mut offset, &mut n_optional, I2, 0)?;
+        let rtn_icnt = optional(contents, &mut offset, &mut n_optional, U2, 0)?;
+        let pgm_icnt = optional(contents, &mut offset, &mut n_optional, U2, 0)?;
+        let rtn_indx = optional(
+            contents,
+            &mut offset,
+            &mut n_optional,
+            |contents, offset| kxU2(contents, rtn_icnt.into(), offset),
+            Vec::new(),
+        )?;
+        let rtn_stat = optional(
+            contents,
+            &mut offset,
+            &mut n_optional,
+            |contents, offset| kxN1(contents, rtn_icnt.into(), offset),
+            Vec::new(),
+        )?;
+        let pgm_indx = optional(
+            contents,
+            &mut offset,
+            &mut n_optional,
+            |contents, offset| kxU2(contents, pgm_icnt.into(), offset),
+            Vec::new(),
+        )?;
+        let pgm_stat = optional(
+            contents,
+            &mut offset,
+            &mut n_optional,
+            |contents, offset| kxN1(contents, pgm_icnt.into(), offset),
+            Vec::new(),
+        )?;
+        let (fail_pin_nbits, fail_pin) =
+            optional(contents, &mut offset, &mut n_optional, Dn, (0, Vec::new()))?;
 
-        Self {
+        let vect_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let time_set = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let op_code = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let test_txt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let alarm_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let prog_txt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let rslt_txt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let patg_num = optional(contents, &mut offset, &mut n_optional, U1, 255)?;
+        let (spin_map_nbits, spin_map) =
+            optional(contents, &mut offset, &mut n_optional, Dn, (0, Vec::new()))?;
+
+        Ok(Self {
             test_num,
             head_num,
             site_num,
@@ -798,6 +1540,7 @@ impl From<&RawRecord> for FTR {
             rtn_stat,
             pgm_indx,
             pgm_stat,
+            fail_pin_nbits,
             fail_pin,
             vect_nam,
             time_set,
@@ -807,8 +1550,96 @@ impl From<&RawRecord> for FTR {
             prog_txt,
             rslt_txt,
             patg_num,
+            spin_map_nbits,
             spin_map,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&FTR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &FTR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U4(&mut contents, record.test_num);
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_U1(&mut contents, record.test_flg);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.opt_flag)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.cycl_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.rel_vadr)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.rept_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U4(bytes, record.num_fail)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I4(bytes, record.xfail_ad)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I4(bytes, record.yfail_ad)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I2(bytes, record.vect_off)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U2(bytes, record.rtn_icnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U2(bytes, record.pgm_icnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_kxU2(bytes, &record.rtn_indx, record.rtn_icnt as usize)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_kxN1(bytes, &record.rtn_stat, record.rtn_icnt as usize)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_kxU2(bytes, &record.pgm_indx, record.pgm_icnt as usize)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_kxN1(bytes, &record.pgm_stat, record.pgm_icnt as usize)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Dn(bytes, record.fail_pin_nbits, &record.fail_pin)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.vect_nam)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.time_set)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.op_code)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.test_txt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.alarm_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.prog_txt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.rslt_txt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.patg_num)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Dn(bytes, record.spin_map_nbits, &record.spin_map)
+        });
+        RawRecord::try_new(RecordType::FTR, contents)
     }
 }
 
@@ -852,42 +1683,66 @@ pub struct MPR {
     pub c_hlmfmt: String,
     pub lo_spec: f32,
     pub hi_spec: f32,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for MPR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for MPR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
+        let mut n_optional = 0;
 
-        let test_num = U4(contents, &mut offset);
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
-        let test_flg = U1(contents, &mut offset);
-        let parm_flg = U1(contents, &mut offset);
-        let rtn_icnt = U2(contents, &mut offset);
-        let rslt_cnt = U2(contents, &mut offset);
-        let rtn_stat = kxN1(contents, rtn_icnt.into(), &mut offset);
-        let rtn_rslt = kxR4(contents, rslt_cnt.into(), &mut offset);
-        let test_txt = Cn(contents, &mut offset);
-        let alarm_id = Cn(contents, &mut offset);
-        let opt_flag = U1(contents, &mut offset);
-        let res_scal = I1(contents, &mut offset);
-        let llm_scal = I1(contents, &mut offset);
-        let hlm_scal = I1(contents, &mut offset);
-        let lo_limit = R4(contents, &mut offset);
-        let hi_limit = R4(contents, &mut offset);
-        let start_in = R4(contents, &mut offset);
-        let incr_in = R4(contents, &mut offset);
-        let rtn_indx = kxU2(contents, rtn_icnt.into(), &mut offset);
-        let units = Cn(contents, &mut offset);
-        let units_in = Cn(contents, &mut offset);
-        let c_resfmt = Cn(contents, &mut offset);
-        let c_llmfmt = Cn(contents, &mut offset);
-        let c_hlmfmt = Cn(contents, &mut offset);
-        let lo_spec = R4(contents, &mut offset);
-        let hi_spec = R4(contents, &mut offset);
+        let test_num = U4(contents, &mut offset)?;
+        let head_num = U1(contents, &mut offset)?;
+        let site_num = U1(contents, &mut offset)?;
+        let test_flg = U1(contents, &mut offset)?;
+        let parm_flg = U1(contents, &mut offset)?;
+        let rtn_icnt = optional(contents, &mut offset, &mut n_optional, U2, 0)?;
+        let rslt_cnt = optional(contents, &mut offset, &mut n_optional, U2, 0)?;
+        let rtn_stat = optional(
+            contents,
+            &mut offset,
+            &mut n_optional,
+            |contents, offset| kxN1(contents, rtn_icnt.into(), offset),
+            Vec::new(),
+        )?;
+        let rtn_rslt = optional(
+            contents,
+            &mut offset,
+            &mut n_optional,
+            |contents, offset| kxR4(contents, rslt_cnt.into(), offset),
+            Vec::new(),
+        )?;
+        let test_txt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let alarm_id = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let opt_flag = optional(contents, &mut offset, &mut n_optional, U1, 0b1111_1111)?;
+        let res_scal = optional(contents, &mut offset, &mut n_optional, I1, 0)?;
+        let llm_scal = optional(contents, &mut offset, &mut n_optional, I1, 0)?;
+        let hlm_scal = optional(contents, &mut offset, &mut n_optional, I1, 0)?;
+        let lo_limit = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let hi_limit = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let start_in = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let incr_in = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let rtn_indx = optional(
+            contents,
+            &mut offset,
+            &mut n_optional,
+            |contents, offset| kxU2(contents, rtn_icnt.into(), offset),
+            Vec::new(),
+        )?;
+        let units = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let units_in = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let c_resfmt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let c_llmfmt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let c_hlmfmt = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let lo_spec = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
+        let hi_spec = optional(contents, &mut offset, &mut n_optional, R4, 0.)?;
 
-        Self {
+        Ok(Self {
             test_num,
             head_num,
             site_num,
@@ -915,7 +1770,95 @@ impl From<&RawRecord> for MPR {
             c_hlmfmt,
             lo_spec,
             hi_spec,
-        }
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&MPR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &MPR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U4(&mut contents, record.test_num);
+        push_U1(&mut contents, record.head_num);
+        push_U1(&mut contents, record.site_num);
+        push_U1(&mut contents, record.test_flg);
+        push_U1(&mut contents, record.parm_flg);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U2(bytes, record.rtn_icnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U2(bytes, record.rslt_cnt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_kxN1(bytes, &record.rtn_stat, record.rtn_icnt as usize)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_kxR4(bytes, &record.rtn_rslt, record.rslt_cnt as usize)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.test_txt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.alarm_id)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.opt_flag)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I1(bytes, record.res_scal)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I1(bytes, record.llm_scal)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_I1(bytes, record.hlm_scal)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.lo_limit)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.hi_limit)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.start_in)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.incr_in)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_kxU2(bytes, &record.rtn_indx, record.rtn_icnt as usize)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.units)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.units_in)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.c_resfmt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.c_llmfmt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.c_hlmfmt)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.lo_spec)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_R4(bytes, record.hi_spec)
+        });
+        RawRecord::try_new(RecordType::MPR, contents)
+    }
+}
+
+impl MPR {
+    pub fn pass(&self) -> bool {
+        (self.test_flg >> 6) & 0b11 == 0
     }
 }
 
@@ -931,22 +1874,28 @@ pub struct PMR {
     pub log_nam: String,
     pub head_num: u8,
     pub site_num: u8,
+    /// The number of optional fields at the end of the record that are present, which are the
+    /// ones encoded; `u8::MAX` for all of them
+    pub n_optional: u8,
 }
 
-impl From<&RawRecord> for PMR {
-    fn from(record: &RawRecord) -> Self {
+impl TryFrom<&RawRecord> for PMR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
         let contents = &record.contents;
         let mut offset: usize = 0;
+        let mut n_optional = 0;
 
-        let pmr_indx = U2(contents, &mut offset);
-        let chan_typ = U2(contents, &mut offset);
-        let chan_nam = Cn(contents, &mut offset);
-        let phy_nam = Cn(contents, &mut offset);
-        let log_nam = Cn(contents, &mut offset);
-        let head_num = U1(contents, &mut offset);
-        let site_num = U1(contents, &mut offset);
+        let pmr_indx = U2(contents, &mut offset)?;
+        let chan_typ = optional(contents, &mut offset, &mut n_optional, U2, 0)?;
+        let chan_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let phy_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let log_nam = optional(contents, &mut offset, &mut n_optional, Cn, String::new())?;
+        let head_num = optional(contents, &mut offset, &mut n_optional, U1, 1)?;
+        let site_num = optional(contents, &mut offset, &mut n_optional, U1, 1)?;
 
-        Self {
+        Ok(Self {
             pmr_indx,
             chan_typ,
             chan_nam,
@@ -954,10 +1903,83 @@ impl From<&RawRecord> for PMR {
             log_nam,
             head_num,
             site_num,
+            n_optional,
+        })
+    }
+}
+
+impl TryFrom<&PMR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &PMR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        let mut n_optional = record.n_optional;
+        push_U2(&mut contents, record.pmr_indx);
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U2(bytes, record.chan_typ)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.chan_nam)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.phy_nam)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_Cn(bytes, &record.log_nam)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.head_num)
+        });
+        push_optional(&mut contents, &mut n_optional, |bytes| {
+            push_U1(bytes, record.site_num)
+        });
+        RawRecord::try_new(RecordType::PMR, contents)
+    }
+}
+
+/// File Attributes Record
+#[derive(Debug, Clone, IntoPyObject)]
+#[allow(dead_code)]
+#[allow(non_snake_case)]
+pub struct FAR {
+    pub cpu_typ: u8,
+    pub stdf_ver: u8,
+}
+
+/// The `FAR` of a little-endian (`cpu_typ` 2) STDF V4 file
+impl Default for FAR {
+    fn default() -> Self {
+        Self {
+            cpu_typ: 2,
+            stdf_ver: 4,
         }
     }
 }
 
+impl TryFrom<&RawRecord> for FAR {
+    type Error = io::Error;
+
+    fn try_from(record: &RawRecord) -> io::Result<Self> {
+        let contents = &record.contents;
+        let mut offset: usize = 0;
+        let cpu_typ = U1(contents, &mut offset)?;
+        let stdf_ver = U1(contents, &mut offset)?;
+
+        Ok(Self { cpu_typ, stdf_ver })
+    }
+}
+
+impl TryFrom<&FAR> for RawRecord {
+    type Error = io::Error;
+
+    fn try_from(record: &FAR) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        push_U1(&mut contents, record.cpu_typ);
+        push_U1(&mut contents, record.stdf_ver);
+        RawRecord::try_new(RecordType::FAR, contents)
+    }
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct NotImplementedRecord {}
@@ -965,7 +1987,7 @@ pub struct NotImplementedRecord {}
 /// An enum of all the concrete record types
 #[derive(Debug)]
 pub enum Record {
-    FAR(NotImplementedRecord),
+    FAR(FAR),
     ATR(NotImplementedRecord),
     MIR(MIR),
     MRR(MRR),
@@ -979,7 +2001,7 @@ pub enum Record {
     SDR(SDR),
     WIR(WIR),
     WRR(WRR),
-    WCR(NotImplementedRecord),
+    WCR(WCR),
     PIR(PIR),
     PRR(PRR),
     TSR(TSR),
@@ -992,3 +2014,36 @@ pub enum Record {
     DTR(NotImplementedRecord),
     InvalidRecord(NotImplementedRecord),
 }
+
+impl Record {
+    /// Encode the `Record` back into a `RawRecord` with a correct `Header`
+    ///
+    /// The counterpart of `RawRecord::try_resolve`. Returns `None` for the record types that are
+    /// not implemented.
+    ///
+    /// # Error
+    /// If the encoded record is longer than the 65535 bytes a record can hold, returns a
+    /// `std::io::ErrorKind::InvalidInput` error
+    pub fn to_raw_record(&self) -> io::Result<Option<RawRecord>> {
+        match self {
+            Record::FAR(far) => RawRecord::try_from(far).map(Some),
+            Record::MIR(mir) => RawRecord::try_from(mir).map(Some),
+            Record::SDR(sdr) => RawRecord::try_from(sdr).map(Some),
+            Record::TSR(tsr) => RawRecord::try_from(tsr).map(Some),
+            Record::SBR(sbr) => RawRecord::try_from(sbr).map(Some),
+            Record::HBR(hbr) => RawRecord::try_from(hbr).map(Some),
+            Record::PCR(pcr) => RawRecord::try_from(pcr).map(Some),
+            Record::MRR(mrr) => RawRecord::try_from(mrr).map(Some),
+            Record::PIR(pir) => RawRecord::try_from(pir).map(Some),
+            Record::PRR(prr) => RawRecord::try_from(prr).map(Some),
+            Record::WIR(wir) => RawRecord::try_from(wir).map(Some),
+            Record::WRR(wrr) => RawRecord::try_from(wrr).map(Some),
+            Record::WCR(wcr) => RawRecord::try_from(wcr).map(Some),
+            Record::PTR(ptr) => RawRecord::try_from(ptr).map(Some),
+            Record::FTR(ftr) => RawRecord::try_from(ftr).map(Some),
+            Record::MPR(mpr) => RawRecord::try_from(mpr).map(Some),
+            Record::PMR(pmr) => RawRecord::try_from(pmr).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! Spatial outlier screening of the dies on each wafer
+//!
+//! Two screens are available, both run per wafer (by `wafer_index`) on the die coordinates of
+//! the `Row`s:
+//!
+//! * Good Die in Bad Neighborhood (GDBN) scores each passing die by the weighted fraction of its
+//!   neighbors that failed. The weights are a square matrix centered on the die, laid out as the
+//!   wafer map is viewed, so row 0 is the top and column 0 the left.
+//! * Nearest Neighbor Residual (NNR) scores each passing die, for each parametric test, by the
+//!   difference between its result and the median result of its neighbors. The residuals of a
+//!   wafer are normalized by their median and `stats::iqr_sigma`.
+//!
+//! The direction of the die coordinates is taken from the `pos_x` and `pos_y` of the `WCR`, if
+//! there is one, and is otherwise assumed to be x increasing to the right and y increasing down.
+//!
+//! A die tested more than once is represented by its last `Row`. Dies without coordinates are
+//! ignored.
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::{
+    data::{Row, STDF, TestData},
+    records::records::WCR,
+    stats,
+    test_information::TestKey,
+};
+
+/// The directions the die coordinates increase in, as the wafer map is viewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Orientation {
+    /// Whether x increases to the right
+    pub x_right: bool,
+    /// Whether y increases down
+    pub y_down: bool,
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self {
+            x_right: true,
+            y_down: true,
+        }
+    }
+}
+
+impl Orientation {
+    /// The orientation given by the `WCR`, with unknown directions left at the default
+    pub fn from_wcr(wcr: Option<&WCR>) -> Self {
+        match wcr {
+            Some(wcr) => Self {
+                x_right: wcr.pos_x != 'L',
+                y_down: wcr.pos_y != 'U',
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// The (column, row) offset on the wafer map of a die offset (`dx`, `dy`)
+    pub fn map_offset(&self, dx: i16, dy: i16) -> (i16, i16) {
+        let column = if self.x_right { dx } else { -dx };
+        let row = if self.y_down { dy } else { -dy };
+        (column, row)
+    }
+}
+
+/// The dimensions of the wafer and its dies, in millimeters
+///
+/// Without a `WCR`, the wafer diameter is unknown and the dies are taken to be 1 mm square.
+#[derive(Debug, Clone, Copy)]
+pub struct WaferGeometry {
+    /// The wafer diameter, 0 if unknown
+    pub diameter: f64,
+    pub die_width: f64,
+    pub die_height: f64,
+    /// The flat or notch, one of `U`, `D`, `L` or `R`, as the wafer map is viewed
+    pub flat: char,
+    /// The (`x_coord`, `y_coord`) of the center die, if known
+    pub center: Option<(i16, i16)>,
+}
+
+impl WaferGeometry {
+    /// The geometry given by the `WCR`, with unknown dimensions left at the defaults
+    pub fn from_wcr(wcr: Option<&WCR>) -> Self {
+        let Some(wcr) = wcr else {
+            return Self {
+                diameter: 0.,
+                die_width: 1.,
+                die_height: 1.,
+                flat: 'D',
+                center: None,
+            };
+        };
+        // wf_units are 1 inches, 2 centimeters, 3 millimeters or 4 mils
+        let mm = match wcr.wf_units {
+            1 => 25.4,
+            2 => 10.,
+            4 => 0.0254,
+            _ => 1.,
+        };
+        let size = |size: f32| (size.is_finite() && size > 0.).then_some(size as f64 * mm);
+        Self {
+            diameter: size(wcr.wafr_siz).unwrap_or(0.),
+            die_width: size(wcr.die_wid).unwrap_or(1.),
+            die_height: size(wcr.die_hght).unwrap_or(1.),
+            flat: match wcr.wf_flat {
+                flat @ ('U' | 'D' | 'L' | 'R') => flat,
+                _ => 'D',
+            },
+            center: (wcr.center_x != i16::MIN && wcr.center_y != i16::MIN)
+                .then_some((wcr.center_x, wcr.center_y)),
+        }
+    }
+}
+
+/// A square matrix of neighbor weights with an odd number of rows, centered on the die
+pub type Weights = Vec<Vec<f64>>;
+
+/// Good Die in Bad Neighborhood screening
+#[derive(Debug, Clone)]
+pub struct Gdbn {
+    /// The weights of the neighbors, a square matrix with an odd number of rows
+    pub weights: Weights,
+    /// Passing dies scoring at least this are flagged
+    pub threshold: f64,
+    /// Dies with fewer neighbors on the wafer are not scored
+    pub min_neighbors: usize,
+}
+
+impl Default for Gdbn {
+    fn default() -> Self {
+        Self {
+            weights: vec![vec![1., 1., 1.], vec![1., 0., 1.], vec![1., 1., 1.]],
+            threshold: 0.5,
+            min_neighbors: 3,
+        }
+    }
+}
+
+/// Nearest Neighbor Residual screening
+#[derive(Debug, Clone)]
+pub struct Nnr {
+    /// The neighbors are the dies at most this many steps away in x and in y
+    pub radius: i16,
+    /// Passing dies with a normalized residual of more than this are flagged
+    pub k: f64,
+    /// Results with fewer neighboring results are not scored
+    pub min_neighbors: usize,
+    /// Screen only these `test_num`s, otherwise every parametric test
+    pub test_nums: Option<Vec<u32>>,
+}
+
+impl Default for Nnr {
+    fn default() -> Self {
+        Self {
+            radius: 2,
+            k: 6.,
+            min_neighbors: 3,
+            test_nums: None,
+        }
+    }
+}
+
+/// The spatial screens to run, and how to ink out the flagged dies
+#[derive(Debug, Clone, Default)]
+pub struct SpatialScreen {
+    /// Run GDBN if set
+    pub gdbn: Option<Gdbn>,
+    /// Run NNR if set
+    pub nnr: Option<Nnr>,
+    /// The soft bin given to flagged dies by `ink`, otherwise their soft bin is kept
+    pub ink_sbin: Option<u16>,
+    /// The hard bin given to flagged dies by `ink`, otherwise their hard bin is kept
+    pub ink_hbin: Option<u16>,
+}
+
+/// A passing die flagged by a spatial screen
+#[derive(Debug, Clone)]
+pub struct SpatialFlag {
+    /// The index of the part in `TestData.data`
+    pub part_index: usize,
+    pub part_id: String,
+    pub wafer_index: Option<u32>,
+    pub wafer_id: String,
+    pub x_coord: i16,
+    pub y_coord: i16,
+    pub head_num: u8,
+    pub site_num: u8,
+    /// The screen that flagged the die, `GDBN` or `NNR`
+    pub method: &'static str,
+    /// The test whose result was flagged by NNR, `None` for GDBN
+    pub test_key: Option<TestKey>,
+    /// The GDBN score or the normalized NNR residual
+    pub score: f64,
+}
+
+/// The dies flagged by the spatial screens
+#[derive(Debug, Clone, Default)]
+pub struct SpatialReport {
+    pub orientation: Orientation,
+    pub flags: Vec<SpatialFlag>,
+}
+
+/// The dies of one wafer, indexed by (`x_coord`, `y_coord`), holding the index of the last `Row`
+type WaferMap = HashMap<(i16, i16), usize>;
+
+impl SpatialScreen {
+    /// Run the screens on every wafer of `stdf`
+    pub fn run(&self, stdf: &STDF) -> SpatialReport {
+        let orientation = Orientation::from_wcr(stdf.wafer_configuration.as_ref());
+        let test_data = &stdf.test_data;
+        let mut flags = Vec::new();
+        for wafer in wafer_maps(test_data).values() {
+            if let Some(gdbn) = &self.gdbn {
+                flags.extend(gdbn.run(test_data, wafer, orientation));
+            }
+            if let Some(nnr) = &self.nnr {
+                flags.extend(nnr.run(test_data, wafer));
+            }
+        }
+        SpatialReport { orientation, flags }
+    }
+
+    /// Ink out the flagged dies of the `report` in the `Row`s of `test_data`
+    ///
+    /// Each flagged die is marked as failed in its `part_flg` and gets `ink_sbin` and `ink_hbin`,
+    /// where set. Returns the number of dies inked. `test_data` must be the one the `report` was
+    /// run on.
+    pub fn ink(&self, test_data: &mut TestData, report: &SpatialReport) -> usize {
+        let part_indexes: HashSet<usize> =
+            report.flags.iter().map(|flag| flag.part_index).collect();
+        part_indexes
+            .into_iter()
+            .filter(|&part_index| test_data.data[part_index].rebin(self.ink_sbin, self.ink_hbin))
+            .count()
+    }
+}
+
+impl Gdbn {
+    /// Score the passing dies of one `wafer`
+    fn run(
+        &self,
+        test_data: &TestData,
+        wafer: &WaferMap,
+        orientation: Orientation,
+    ) -> Vec<SpatialFlag> {
+        let radius = (self.weights.len() / 2) as i16;
+        let mut flags = Vec::new();
+        for (&(x, y), &part_index) in wafer {
+            let row = &test_data.data[part_index];
+            if !row.pass() {
+                continue;
+            }
+            let (mut n_neighbors, mut total, mut bad) = (0, 0., 0.);
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let (column, map_row) = orientation.map_offset(dx, dy);
+                    let weight = self
+                        .weights
+                        .get((map_row + radius) as usize)
+                        .and_then(|weights| weights.get((column + radius) as usize))
+                        .copied()
+                        .unwrap_or(0.);
+                    let neighbor = wafer.get(&(x.saturating_add(dx), y.saturating_add(dy)));
+                    let Some(&neighbor) = neighbor.filter(|_| (dx, dy) != (0, 0)) else {
+                        continue;
+                    };
+                    n_neighbors += 1;
+                    total += weight;
+                    if !test_data.data[neighbor].pass() {
+                        bad += weight;
+                    }
+                }
+            }
+            if n_neighbors < self.min_neighbors || total <= 0. {
+                continue;
+            }
+            let score = bad / total;
+            if score >= self.threshold {
+                flags.push(flag(row, part_index, "GDBN", None, score));
+            }
+        }
+        flags
+    }
+}
+
+impl Nnr {
+    /// Score the results of the passing dies of one `wafer`
+    fn run(&self, test_data: &TestData, wafer: &WaferMap) -> Vec<SpatialFlag> {
+        let test_keys = test_data.parametric_test_keys();
+        let mut flags = Vec::new();
+        for (i, &test_key) in test_keys.iter().enumerate() {
+            if let Some(test_nums) = &self.test_nums
+                && !test_nums.contains(&test_key.test_num())
+            {
+                continue;
+            }
+            let result = |part_index: usize| {
+                let row = &test_data.data[part_index];
+                let result = row.results_parametric[i];
+                (row.passed_parametric[i].is_some() && !result.is_nan())
+                    .then_some(f64::from(result))
+            };
+            let mut residuals: Vec<(usize, f64)> = Vec::new();
+            for (&(x, y), &part_index) in wafer {
+                let Some(value) = result(part_index) else {
+                    continue;
+                };
+                let mut neighbors: Vec<f64> = Vec::new();
+                for dy in -self.radius..=self.radius {
+                    for dx in -self.radius..=self.radius {
+                        if (dx, dy) == (0, 0) {
+                            continue;
+                        }
+                        let neighbor = wafer.get(&(x.saturating_add(dx), y.saturating_add(dy)));
+                        let neighbor =
+                            neighbor.filter(|&&neighbor| test_data.data[neighbor].pass());
+                        if let Some(value) = neighbor.and_then(|&neighbor| result(neighbor)) {
+                            neighbors.push(value);
+                        }
+                    }
+                }
+                if neighbors.len() < self.min_neighbors {
+                    continue;
+                }
+                neighbors.sort_by(f64::total_cmp);
+                if let Some(median) = stats::median(&neighbors) {
+                    residuals.push((part_index, value - median));
+                }
+            }
+
+            let mut sorted: Vec<f64> = residuals.iter().map(|(_, residual)| *residual).collect();
+            sorted.sort_by(f64::total_cmp);
+            let (Some(center), Some(sigma)) = (stats::median(&sorted), stats::iqr_sigma(&sorted))
+            else {
+                continue;
+            };
+            if sigma <= 0. {
+                continue;
+            }
+            for (part_index, residual) in residuals {
+                let row = &test_data.data[part_index];
+                let score = (residual - center).abs() / sigma;
+                if row.pass() && score > self.k {
+                    flags.push(flag(row, part_index, "NNR", Some(test_key.clone()), score));
+                }
+            }
+        }
+        flags
+    }
+}
+
+/// The dies of each wafer, by `wafer_index` as a `wafer_id` may be reused
+fn wafer_maps(test_data: &TestData) -> BTreeMap<Option<u32>, WaferMap> {
+    let mut wafers: BTreeMap<Option<u32>, WaferMap> = BTreeMap::new();
+    for (part_index, row) in test_data.data.iter().enumerate() {
+        if !row.has_coords() {
+            continue;
+        }
+        wafers
+            .entry(row.wafer_index)
+            .or_default()
+            .insert((row.x_coord, row.y_coord), part_index);
+    }
+    wafers
+}
+
+/// Build the `SpatialFlag` of the die `row`
+fn flag(
+    row: &Row,
+    part_index: usize,
+    method: &'static str,
+    test_key: Option<TestKey>,
+    score: f64,
+) -> SpatialFlag {
+    SpatialFlag {
+        part_index,
+        part_id: row.part_id.clone(),
+        wafer_index: row.wafer_index,
+        wafer_id: row.wafer_id.clone(),
+        x_coord: row.x_coord,
+        y_coord: row.y_coord,
+        head_num: row.head_num,
+        site_num: row.site_num,
+        method,
+        test_key,
+        score,
+    }
+}
+
+/// Parse GDBN weights from rows separated by `;` of weights separated by `,`, like
+/// `1,2,1;2,0,2;1,2,1`
+///
+/// The weights must form a square matrix with an odd number of rows.
+pub fn parse_weights(s: &str) -> Result<Weights, String> {
+    let weights: Weights = s
+        .split(';')
+        .map(|row| {
+            row.split(',')
+                .map(|weight| {
+                    weight
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|e| format!("invalid weight {weight}: {e}"))
+                })
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+    let n = weights.len();
+    if n.is_multiple_of(2) || weights.iter().any(|row| row.len() != n) {
+        return Err(format!(
+            "weights must be a square matrix with an odd number of rows, got {s}"
+        ));
+    }
+    Ok(weights)
+}
+
+impl SpatialReport {
+    /// Convert the `flags` into a `DataFrame`, one row per flagged die (and test for NNR)
+    pub fn to_df(&self) -> DataFrame {
+        let mut part_indexes: Vec<u32> = Vec::new();
+        let mut part_ids: Vec<&str> = Vec::new();
+        let mut wafer_indexes: Vec<Option<u32>> = Vec::new();
+        let mut wafer_ids: Vec<&str> = Vec::new();
+        let mut x_coords: Vec<i16> = Vec::new();
+        let mut y_coords: Vec<i16> = Vec::new();
+        let mut head_nums: Vec<u8> = Vec::new();
+        let mut site_nums: Vec<u8> = Vec::new();
+        let mut methods: Vec<&str> = Vec::new();
+        let mut test_keys: Vec<Option<String>> = Vec::new();
+        let mut scores: Vec<f64> = Vec::new();
+
+        let flags = self.flags.iter().sorted_by(|a, b| {
+            (a.wafer_index, a.part_index, a.method, &a.test_key).cmp(&(
+                b.wafer_index,
+                b.part_index,
+                b.method,
+                &b.test_key,
+            ))
+        });
+        for flag in flags {
+            part_indexes.push(flag.part_index as u32);
+            part_ids.push(&flag.part_id);
+            wafer_indexes.push(flag.wafer_index);
+            wafer_ids.push(&flag.wafer_id);
+            x_coords.push(flag.x_coord);
+            y_coords.push(flag.y_coord);
+            head_nums.push(flag.head_num);
+            site_nums.push(flag.site_num);
+            methods.push(flag.method);
+            test_keys.push(flag.test_key.as_ref().map(|test_key| test_key.to_string()));
+            scores.push(flag.score);
+        }
+        let columns = vec![
+            Column::new("part_index".into(), part_indexes),
+            Column::new("part_id".into(), part_ids),
+            Column::new("wafer_index".into(), wafer_indexes),
+            Column::new("wafer_id".into(), wafer_ids),
+            Column::new("x_coord".into(), x_coords),
+            Column::new("y_coord".into(), y_coords),
+            Column::new("head_num".into(), head_nums),
+            Column::new("site_num".into(), site_nums),
+            Column::new("method".into(), methods),
+            Column::new("test_key".into(), test_keys),
+            Column::new("score".into(), scores),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+}
@@ -0,0 +1,414 @@
+//! Per-test statistics and process capability of the parametric tests in a `TestData`
+//!
+//! `TestStatistics::to_df` gives one row per parametric test, or per test and group when
+//! `group_by` is set, with the counts, moments, percentiles and the Cp/Cpk of the results against
+//! the limits in the `FullMergedTestInformation`. Cp/Cpk against the specification limits are
+//! given too, where the test has them. `TestStatistics::to_df_by` computes the same for any other
+//! grouping of the parts, such as the zones of `zones::Zoning`.
+//!
+//! Results of parts that did not execute a test count as `n_missing`, and `NAN` results as
+//! `n_nan`. Neither contributes to any statistic.
+//!
+//! The center and spread used for Cp/Cpk are chosen by `Estimator`. `Robust` uses the median and
+//! the median absolute deviation scaled by 1.4826, which matches the standard deviation of a
+//! normal distribution but is not pulled by outliers.
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use polars::prelude::*;
+
+use crate::data::TestData;
+
+/// Scales the median absolute deviation to the standard deviation of a normal distribution
+pub const MAD_TO_SIGMA: f64 = 1.4826;
+
+/// How the parts are grouped before computing the statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupBy {
+    /// One row per test over all parts
+    #[default]
+    None,
+    /// One row per test and `head_num`
+    Head,
+    /// One row per test and (`head_num`, `site_num`)
+    Site,
+    /// One row per test and wafer, by `wafer_index` as a `wafer_id` may be reused
+    Wafer,
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Head => write!(f, "head"),
+            Self::Site => write!(f, "site"),
+            Self::Wafer => write!(f, "wafer"),
+        }
+    }
+}
+
+/// Parses a `GroupBy` from `none`, `head`, `site` or `wafer`
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "head" => Ok(Self::Head),
+            "site" => Ok(Self::Site),
+            "wafer" => Ok(Self::Wafer),
+            _ => Err(format!(
+                "unknown grouping {s}, expected one of none, head, site, wafer"
+            )),
+        }
+    }
+}
+
+/// The estimators of center and spread used for Cp/Cpk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Estimator {
+    /// The mean and the sample standard deviation
+    #[default]
+    Classic,
+    /// The median and the median absolute deviation scaled by `MAD_TO_SIGMA`
+    Robust,
+}
+
+impl fmt::Display for Estimator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Classic => write!(f, "classic"),
+            Self::Robust => write!(f, "robust"),
+        }
+    }
+}
+
+/// Parses an `Estimator` from `classic` or `robust`
+impl FromStr for Estimator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(Self::Classic),
+            "robust" => Ok(Self::Robust),
+            _ => Err(format!(
+                "unknown estimator {s}, expected one of classic, robust"
+            )),
+        }
+    }
+}
+
+/// Computes the statistics of each parametric test
+#[derive(Debug, Clone)]
+pub struct TestStatistics {
+    /// How the parts are grouped
+    pub group_by: GroupBy,
+    /// The estimators of center and spread used for Cp/Cpk
+    pub estimator: Estimator,
+    /// The percentiles to compute, between 0 and 100, each giving a column like `p95`
+    pub percentiles: Vec<f64>,
+}
+
+impl Default for TestStatistics {
+    fn default() -> Self {
+        Self {
+            group_by: GroupBy::None,
+            estimator: Estimator::Classic,
+            percentiles: vec![1., 5., 25., 50., 75., 95., 99.],
+        }
+    }
+}
+
+/// The group a part falls into, with the fields not used by the `GroupBy` left at their defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Group {
+    head_num: u8,
+    site_num: u8,
+    wafer_index: Option<u32>,
+    wafer_id: String,
+}
+
+/// The results of one test in one group
+#[derive(Debug, Default)]
+struct Results {
+    values: Vec<f64>,
+    n_missing: u32,
+    n_nan: u32,
+    n_fail: u32,
+}
+
+impl TestStatistics {
+    /// Compute the statistics of each parametric test in `test_data`
+    ///
+    /// Has one row per test (and group), sorted by `test_key` and then the group columns. The
+    /// group columns are `head_num` for `Head`, `head_num` and `site_num` for `Site` and
+    /// `wafer_index` and `wafer_id` for `Wafer`.
+    /// Statistics that cannot be computed, e.g. the standard deviation of a single result or the
+    /// Cp of a test without both limits, are null.
+    pub fn to_df(&self, test_data: &TestData) -> DataFrame {
+        let groups = test_data.data.iter().map(|row| {
+            Some(match self.group_by {
+                GroupBy::None => Group::default(),
+                GroupBy::Head => Group {
+                    head_num: row.head_num,
+                    ..Default::default()
+                },
+                GroupBy::Site => Group {
+                    head_num: row.head_num,
+                    site_num: row.site_num,
+                    ..Default::default()
+                },
+                GroupBy::Wafer => Group {
+                    wafer_index: row.wafer_index,
+                    wafer_id: row.wafer_id.clone(),
+                    ..Default::default()
+                },
+            })
+        });
+        self.to_df_by(test_data, groups, |groups| self.group_columns(groups))
+    }
+
+    /// The group columns for the `group_by`
+    fn group_columns(&self, groups: &[Group]) -> Vec<Column> {
+        let head_nums: Vec<u8> = groups.iter().map(|group| group.head_num).collect();
+        match self.group_by {
+            GroupBy::None => vec![],
+            GroupBy::Head => vec![Column::new("head_num".into(), head_nums)],
+            GroupBy::Site => {
+                let site_nums: Vec<u8> = groups.iter().map(|group| group.site_num).collect();
+                vec![
+                    Column::new("head_num".into(), head_nums),
+                    Column::new("site_num".into(), site_nums),
+                ]
+            }
+            GroupBy::Wafer => {
+                let wafer_indexes: Vec<Option<u32>> =
+                    groups.iter().map(|group| group.wafer_index).collect();
+                let wafer_ids: Vec<&str> =
+                    groups.iter().map(|group| group.wafer_id.as_str()).collect();
+                vec![
+                    Column::new("wafer_index".into(), wafer_indexes),
+                    Column::new("wafer_id".into(), wafer_ids),
+                ]
+            }
+        }
+    }
+
+    /// Compute the statistics of each parametric test in `test_data`, grouping the parts by a
+    /// key of the caller's choosing
+    ///
+    /// `groups` gives the group of each `Row` of `test_data`, in order, where `None` leaves the
+    /// part out. `group_columns` turns the group of every output row into the group columns,
+    /// which come after `units`. The `group_by` is ignored, otherwise this is the same as `to_df`.
+    pub fn to_df_by<G: Clone + Ord>(
+        &self,
+        test_data: &TestData,
+        groups: impl IntoIterator<Item = Option<G>>,
+        group_columns: impl FnOnce(&[G]) -> Vec<Column>,
+    ) -> DataFrame {
+        let test_keys = test_data.parametric_test_keys();
+        let mut results: BTreeMap<(usize, G), Results> = BTreeMap::new();
+        for (row, group) in test_data.data.iter().zip(groups) {
+            let Some(group) = group else {
+                continue;
+            };
+            let executed = row.results_parametric.iter().zip(&row.passed_parametric);
+            for (i, (&result, passed)) in executed.enumerate() {
+                let test_results = results.entry((i, group.clone())).or_default();
+                match passed {
+                    None => test_results.n_missing += 1,
+                    Some(_) if result.is_nan() => test_results.n_nan += 1,
+                    Some(passed) => {
+                        test_results.values.push(f64::from(result));
+                        test_results.n_fail += u32::from(!passed);
+                    }
+                }
+            }
+        }
+        let mut results: Vec<((usize, G), Results)> = results.into_iter().collect();
+        results.sort_by(|((i, group), _), ((j, other), _)| {
+            (test_keys[*i], group).cmp(&(test_keys[*j], other))
+        });
+
+        let mut test_keys_col: Vec<String> = Vec::new();
+        let mut test_nums: Vec<u32> = Vec::new();
+        let mut test_names: Vec<Option<String>> = Vec::new();
+        let mut unitss: Vec<String> = Vec::new();
+        let mut groups: Vec<G> = Vec::new();
+        let mut ns: Vec<u32> = Vec::new();
+        let mut n_missings: Vec<u32> = Vec::new();
+        let mut n_nans: Vec<u32> = Vec::new();
+        let mut n_fails: Vec<u32> = Vec::new();
+        let mut means: Vec<Option<f64>> = Vec::new();
+        let mut stds: Vec<Option<f64>> = Vec::new();
+        let mut medians: Vec<Option<f64>> = Vec::new();
+        let mut robust_sigmas: Vec<Option<f64>> = Vec::new();
+        let mut mins: Vec<Option<f64>> = Vec::new();
+        let mut maxs: Vec<Option<f64>> = Vec::new();
+        let mut percentiles: Vec<Vec<Option<f64>>> = vec![Vec::new(); self.percentiles.len()];
+        let mut lo_limits: Vec<Option<f64>> = Vec::new();
+        let mut hi_limits: Vec<Option<f64>> = Vec::new();
+        let mut cps: Vec<Option<f64>> = Vec::new();
+        let mut cpks: Vec<Option<f64>> = Vec::new();
+        let mut lo_specs: Vec<Option<f64>> = Vec::new();
+        let mut hi_specs: Vec<Option<f64>> = Vec::new();
+        let mut cp_specs: Vec<Option<f64>> = Vec::new();
+        let mut cpk_specs: Vec<Option<f64>> = Vec::new();
+
+        for ((i, group), mut test_results) in results {
+            let test_key = test_keys[i];
+            let mti = &test_data.test_information.test_infos[test_key];
+            let values = &mut test_results.values;
+            values.sort_by(f64::total_cmp);
+            let (center, sigma) = match self.estimator {
+                Estimator::Classic => (mean(values), std_dev(values)),
+                Estimator::Robust => (median(values), robust_sigma(values)),
+            };
+            let limit = |limit: f32| Some(f64::from(limit)).filter(|limit| limit.is_finite());
+            let (lo_limit, hi_limit) = (limit(mti.low_limit), limit(mti.high_limit));
+            let (lo_spec, hi_spec) = (limit(mti.lo_spec), limit(mti.hi_spec));
+
+            test_keys_col.push(test_key.to_string());
+            test_nums.push(mti.test_num);
+            test_names.push(mti.display_name().map(|name| name.to_string()));
+            unitss.push(mti.units.clone());
+            groups.push(group);
+            ns.push(values.len() as u32);
+            n_missings.push(test_results.n_missing);
+            n_nans.push(test_results.n_nan);
+            n_fails.push(test_results.n_fail);
+            means.push(mean(values));
+            stds.push(std_dev(values));
+            medians.push(median(values));
+            robust_sigmas.push(robust_sigma(values));
+            mins.push(values.first().copied());
+            maxs.push(values.last().copied());
+            for (column, &p) in percentiles.iter_mut().zip(&self.percentiles) {
+                column.push(percentile(values, p));
+            }
+            lo_limits.push(lo_limit);
+            hi_limits.push(hi_limit);
+            cps.push(cp(sigma, lo_limit, hi_limit));
+            cpks.push(cpk(center, sigma, lo_limit, hi_limit));
+            lo_specs.push(lo_spec);
+            hi_specs.push(hi_spec);
+            cp_specs.push(cp(sigma, lo_spec, hi_spec));
+            cpk_specs.push(cpk(center, sigma, lo_spec, hi_spec));
+        }
+
+        let mut columns = vec![
+            Column::new("test_key".into(), test_keys_col),
+            Column::new("test_num".into(), test_nums),
+            Column::new("test_name".into(), test_names),
+            Column::new("units".into(), unitss),
+        ];
+        columns.extend(group_columns(&groups));
+        columns.extend([
+            Column::new("n".into(), ns),
+            Column::new("n_missing".into(), n_missings),
+            Column::new("n_nan".into(), n_nans),
+            Column::new("fail_cnt".into(), n_fails),
+            Column::new("mean".into(), means),
+            Column::new("std".into(), stds),
+            Column::new("median".into(), medians),
+            Column::new("robust_sigma".into(), robust_sigmas),
+            Column::new("min".into(), mins),
+            Column::new("max".into(), maxs),
+        ]);
+        for (column, p) in percentiles.into_iter().zip(&self.percentiles) {
+            columns.push(Column::new(format!("p{p}").into(), column));
+        }
+        columns.extend([
+            Column::new("low_limit".into(), lo_limits),
+            Column::new("high_limit".into(), hi_limits),
+            Column::new("cp".into(), cps),
+            Column::new("cpk".into(), cpks),
+            Column::new("lo_spec".into(), lo_specs),
+            Column::new("hi_spec".into(), hi_specs),
+            Column::new("cp_spec".into(), cp_specs),
+            Column::new("cpk_spec".into(), cpk_specs),
+        ]);
+
+        DataFrame::new(columns).unwrap()
+    }
+}
+
+/// The mean of the `values`, `None` if there are none
+pub fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// The sample standard deviation of the `values`, `None` if there are fewer than 2
+pub fn std_dev(values: &[f64]) -> Option<f64> {
+    let mean = mean(values).filter(|_| values.len() > 1)?;
+    let sqrs: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+    Some((sqrs / (values.len() - 1) as f64).sqrt())
+}
+
+/// The median of the sorted `values`, `None` if there are none
+pub fn median(sorted: &[f64]) -> Option<f64> {
+    percentile(sorted, 50.)
+}
+
+/// The median absolute deviation of the sorted `values` scaled by `MAD_TO_SIGMA`, `None` if
+/// there are fewer than 2
+pub fn robust_sigma(sorted: &[f64]) -> Option<f64> {
+    let median = median(sorted).filter(|_| sorted.len() > 1)?;
+    let mut deviations: Vec<f64> = sorted.iter().map(|value| (value - median).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+    Some(MAD_TO_SIGMA * self::median(&deviations)?)
+}
+
+/// The interquartile range of the sorted `values` divided by 1.35, the robust sigma of AEC-Q001,
+/// `None` if there are fewer than 2
+pub fn iqr_sigma(sorted: &[f64]) -> Option<f64> {
+    let q1 = percentile(sorted, 25.).filter(|_| sorted.len() > 1)?;
+    Some((percentile(sorted, 75.)? - q1) / 1.35)
+}
+
+/// The `p`th percentile of the sorted `values`, interpolating linearly between the closest ranks
+///
+/// `p` is clamped to between 0 and 100. `None` if there are no values.
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = p.clamp(0., 100.) / 100. * last as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[below] + (rank - below as f64) * (sorted[above] - sorted[below]))
+}
+
+/// Check the `percentiles` to compute, dropping any repeated ones
+///
+/// # Error
+/// If any of the `percentiles` is not a number between 0 and 100, returns a message naming it
+pub fn check_percentiles(percentiles: &[f64]) -> Result<Vec<f64>, String> {
+    let mut checked: Vec<f64> = Vec::with_capacity(percentiles.len());
+    for &p in percentiles {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(format!("invalid percentile {p}, expected 0-100"));
+        }
+        if !checked.contains(&p) {
+            checked.push(p);
+        }
+    }
+    Ok(checked)
+}
+
+/// The process capability of a spread `sigma` within the limits, `None` without both limits
+pub fn cp(sigma: Option<f64>, lo_limit: Option<f64>, hi_limit: Option<f64>) -> Option<f64> {
+    let sigma = sigma.filter(|&sigma| sigma > 0.)?;
+    Some((hi_limit? - lo_limit?) / (6. * sigma))
+}
+
+/// The process capability of a `center` and spread `sigma` to the closer limit
+///
+/// A test with only one limit uses that one. `None` without either limit.
+pub fn cpk(
+    center: Option<f64>,
+    sigma: Option<f64>,
+    lo_limit: Option<f64>,
+    hi_limit: Option<f64>,
+) -> Option<f64> {
+    let (center, sigma) = (center?, sigma.filter(|&sigma| sigma > 0.)?);
+    let lower = lo_limit.map(|lo_limit| (center - lo_limit) / (3. * sigma));
+    let upper = hi_limit.map(|hi_limit| (hi_limit - center) / (3. * sigma));
+    [lower, upper].into_iter().flatten().reduce(f64::min)
+}
@@ -3,22 +3,32 @@ use crate::records::Records;
 use crate::records::records::PTR;
 use crate::records::records::Record;
 use crate::records::records::TSR;
+use itertools::Itertools;
 use polars::frame::DataFrame;
 use polars::prelude::Column;
 use pyo3::Bound;
 use pyo3::IntoPyObject;
+use pyo3::PyAny;
+use pyo3::PyErr;
 use pyo3::Python;
 use pyo3::types::PyString;
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::convert::Infallible;
 use std::fmt;
+use std::str::FromStr;
 
 /// `TestInformation` for a single test
 ///
 /// The metadata for a test is uniquely determined by (`test_num`, `head_num`, `site_num`). This
 /// structure does not contain information about any executions of the test, it only contains
 /// metadata associated with the test.
+///
+/// The limits and specification limits are `NAN` if the test has none, either because the `PTR`
+/// marks them absent in its `opt_flag` or because only a `TSR` has been seen.
 #[derive(Debug, IntoPyObject)]
 pub struct TestInformation {
     pub test_num: u32,
@@ -92,11 +102,11 @@ impl TestInformation {
         let llm_scal = ptr.llm_scal;
         let hlm_scal = ptr.hlm_scal;
         let res_scal = ptr.res_scal;
-        let lo_spec = ptr.lo_spec;
-        let hi_spec = ptr.hi_spec;
+        let lo_spec = ptr.low_spec();
+        let hi_spec = ptr.high_spec();
         let test_text = ptr.test_txt.clone();
-        let low_limit = ptr.lo_limit;
-        let high_limit = ptr.hi_limit;
+        let low_limit = ptr.low_limit();
+        let high_limit = ptr.high_limit();
         let units = ptr.units.clone();
         let complete = Complete::PTR;
 
@@ -132,13 +142,7 @@ impl TestInformation {
             panic!("head_num/site_num/test_num from TSR does not match!");
         }
         if let Complete::PTR = self.complete {
-            self.test_type = match tsr.test_typ {
-                'P' => TestType::P,
-                'F' => TestType::F,
-                'M' => TestType::M,
-                'S' => TestType::S,
-                _ => TestType::Unknown,
-            };
+            self.test_type = tsr.test_typ.into();
             self.execution_count = tsr.exec_cnt;
             self.test_name = tsr.test_nam.clone();
             self.sequence_name = tsr.seq_name.clone();
@@ -153,13 +157,7 @@ impl TestInformation {
         let test_num = tsr.test_num;
         let head_num = tsr.head_num;
         let site_num = tsr.site_num;
-        let test_type = match tsr.test_typ {
-            'P' => TestType::P,
-            'F' => TestType::F,
-            'M' => TestType::M,
-            'S' => TestType::S,
-            _ => TestType::Unknown,
-        };
+        let test_type = tsr.test_typ.into();
         let execution_count = tsr.exec_cnt;
         let test_name = tsr.test_nam.clone();
         let sequence_name = tsr.seq_name.clone();
@@ -211,11 +209,11 @@ impl TestInformation {
             self.llm_scal = ptr.llm_scal;
             self.hlm_scal = ptr.hlm_scal;
             self.res_scal = ptr.res_scal;
-            self.lo_spec = ptr.lo_spec;
-            self.hi_spec = ptr.hi_spec;
+            self.lo_spec = ptr.low_spec();
+            self.hi_spec = ptr.high_spec();
             self.test_text = ptr.test_txt.clone();
-            self.low_limit = ptr.lo_limit;
-            self.high_limit = ptr.hi_limit;
+            self.low_limit = ptr.low_limit();
+            self.high_limit = ptr.high_limit();
             self.units = ptr.units.clone();
             self.complete = Complete::Complete;
         }
@@ -223,7 +221,7 @@ impl TestInformation {
 }
 
 /// `TestType` describes the category of test
-#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord, Serialize)]
 pub enum TestType {
     /// A parametric test, i.e. one that measures a value
     P,
@@ -243,6 +241,32 @@ impl fmt::Display for TestType {
     }
 }
 
+/// Converts the `test_typ` character of a `TSR` into a `TestType`
+impl From<char> for TestType {
+    fn from(test_typ: char) -> Self {
+        match test_typ {
+            'P' => Self::P,
+            'F' => Self::F,
+            'M' => Self::M,
+            'S' => Self::S,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Converts a `TestType` into the `test_typ` character of a `TSR`, a space if unknown
+impl From<TestType> for char {
+    fn from(test_type: TestType) -> Self {
+        match test_type {
+            TestType::P => 'P',
+            TestType::F => 'F',
+            TestType::M => 'M',
+            TestType::S => 'S',
+            TestType::Unknown => ' ',
+        }
+    }
+}
+
 /// Determines how to convert `TestType` into Python objects
 ///
 /// Can't derive `IntoPyObject` for enums, so implement manually.
@@ -264,27 +288,197 @@ impl<'py> IntoPyObject<'py> for TestType {
     }
 }
 
+/// `TestIdentity` determines which fields tell two tests apart
+///
+/// Test programs occasionally reuse a `test_num` for tests with different names, or for both a
+/// parametric and a multi-pin test. With the default `TestNum` identity such tests are treated as
+/// one and their results collide. The other identities keep them separate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TestIdentity {
+    /// Tests are identified by `test_num` alone
+    #[default]
+    TestNum,
+    /// Tests are identified by (`test_num`, `test_txt`)
+    ///
+    /// The `TSR` `test_nam` is matched against the `test_txt` of the result records.
+    TestNumAndText,
+    /// Tests are identified by (`test_num`, `test_type`)
+    TestNumAndType,
+}
+
+impl TestIdentity {
+    /// Build the `TestKey` for a test under this identity
+    pub fn key(&self, test_num: u32, test_txt: &str, test_type: TestType) -> TestKey {
+        match self {
+            Self::TestNum => TestKey::Num(test_num),
+            Self::TestNumAndText => TestKey::NumText(test_num, test_txt.to_string()),
+            Self::TestNumAndType => TestKey::NumType(test_num, test_type),
+        }
+    }
+}
+
+impl fmt::Display for TestIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::TestNum => "test_num",
+            Self::TestNumAndText => "test_num_txt",
+            Self::TestNumAndType => "test_num_type",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Parses a `TestIdentity` from `test_num`, `test_num_txt` or `test_num_type`
+impl FromStr for TestIdentity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "test_num" => Ok(Self::TestNum),
+            "test_num_txt" => Ok(Self::TestNumAndText),
+            "test_num_type" => Ok(Self::TestNumAndType),
+            _ => Err(format!(
+                "unknown test identity {s}, expected one of test_num, test_num_txt, test_num_type"
+            )),
+        }
+    }
+}
+
+/// Determines how to convert `TestIdentity` into Python objects
+///
+/// The variants are converted to the same strings accepted by `FromStr`
+impl<'py> IntoPyObject<'py> for TestIdentity {
+    type Target = PyString;
+    type Output = Bound<'py, Self::Target>;
+    type Error = Infallible;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        self.to_string().into_pyobject(py)
+    }
+}
+
+/// The identity of a test, as determined by a `TestIdentity`
+///
+/// Sorts by `test_num` first, so iterating over sorted keys gives the tests in test number order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TestKey {
+    /// Identified by `test_num`
+    Num(u32),
+    /// Identified by (`test_num`, `test_txt`)
+    NumText(u32, String),
+    /// Identified by (`test_num`, `test_type`)
+    NumType(u32, TestType),
+}
+
+impl TestKey {
+    /// The `test_num` of the test
+    pub fn test_num(&self) -> u32 {
+        match self {
+            Self::Num(test_num) | Self::NumText(test_num, _) | Self::NumType(test_num, _) => {
+                *test_num
+            }
+        }
+    }
+}
+
+/// Formats as `test_num`, `test_num:test_txt` or `test_num:test_type`
+impl fmt::Display for TestKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Num(test_num) => write!(f, "{test_num}"),
+            Self::NumText(test_num, test_txt) => write!(f, "{test_num}:{test_txt}"),
+            Self::NumType(test_num, test_type) => write!(f, "{test_num}:{test_type}"),
+        }
+    }
+}
+
+/// Determines how to convert `TestKey` into Python objects
+///
+/// `Num` becomes a plain `int`, so the default identity looks the same as a bare `test_num`.
+/// The other variants become `(test_num, test_txt)` or `(test_num, test_type)` tuples.
+impl<'py> IntoPyObject<'py> for TestKey {
+    type Target = PyAny;
+    type Output = Bound<'py, Self::Target>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        let obj = match self {
+            Self::Num(test_num) => test_num.into_pyobject(py)?.into_any(),
+            Self::NumText(test_num, test_txt) => (test_num, test_txt).into_pyobject(py)?.into_any(),
+            Self::NumType(test_num, test_type) => {
+                (test_num, test_type).into_pyobject(py)?.into_any()
+            }
+        };
+        Ok(obj)
+    }
+}
+
+/// A `test_num` shared by more than one test, told apart by their `test_txt` and `test_type`
+#[derive(Debug, Clone, PartialEq, Eq, IntoPyObject)]
+pub struct Collision {
+    pub test_num: u32,
+    /// The (`test_txt`, `test_type`) of each test, where the `test_txt` is empty for a test type
+    /// whose records never name it
+    pub tests: Vec<(String, TestType)>,
+    /// Whether the `TestIdentity` keeps the tests in separate columns, otherwise their results
+    /// are merged into one
+    pub separated: bool,
+}
+
+/// Formats as e.g. `test_num 1000 shared by VDD (P), VDD (M), merged into one column`
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tests = self
+            .tests
+            .iter()
+            .map(|(test_txt, test_type)| format!("{test_txt} ({test_type})").trim().to_string())
+            .join(", ");
+        let columns = match self.separated {
+            true => "kept in separate columns",
+            false => "merged into one column",
+        };
+        write!(f, "test_num {} shared by {tests}, {columns}", self.test_num)
+    }
+}
+
 /// A collection of all `TestInformation`s in a STDF file
 ///
-/// Indexed by (`test_num`, `site_num`, `head_num`)
+/// Indexed by (`test_key`, `site_num`, `head_num`), where the `TestKey` is built according to
+/// `identity`
 #[derive(Debug, IntoPyObject)]
 pub struct FullTestInformation {
-    pub test_infos: HashMap<(u32, u8, u8), TestInformation>,
+    /// How tests are told apart
+    pub identity: TestIdentity,
+    pub test_infos: HashMap<(TestKey, u8, u8), TestInformation>,
+    /// The distinct (`test_num`, `test_txt`, `test_type`) of the `PTR`s and `TSR`s added,
+    /// whatever the `identity`
+    pub raw_tests: BTreeSet<(u32, String, TestType)>,
 }
 
 impl FullTestInformation {
-    /// Initialize with an empty HashMap
+    /// Initialize with an empty HashMap and the default `TestIdentity`
     pub fn new() -> Self {
+        Self::with_identity(TestIdentity::default())
+    }
+
+    /// Initialize with an empty HashMap, telling tests apart by `identity`
+    pub fn with_identity(identity: TestIdentity) -> Self {
         let test_infos = HashMap::new();
-        Self { test_infos }
+        Self {
+            identity,
+            test_infos,
+            raw_tests: BTreeSet::new(),
+        }
     }
 
     /// Add the metadata from a `PTR`.
     ///
-    /// Looks up the appropriate `TestInformation` using the (`test_num`, `site_num`, `head_num`)
+    /// Looks up the appropriate `TestInformation` using the (`test_key`, `site_num`, `head_num`)
     /// in the `PTR` and adds to this `TestInformation`
     pub fn add_from_ptr(&mut self, ptr: &PTR) {
-        let key = (ptr.test_num, ptr.site_num, ptr.head_num);
+        self.add_raw_test(ptr.test_num, &ptr.test_txt, TestType::P);
+        let test_key = self.identity.key(ptr.test_num, &ptr.test_txt, TestType::P);
+        let key = (test_key, ptr.site_num, ptr.head_num);
         self.test_infos
             .entry(key)
             .and_modify(|e| e.add_from_ptr(ptr))
@@ -293,29 +487,82 @@ impl FullTestInformation {
 
     /// Add the metadata from a `TSR`.
     ///
-    /// Looks up the appropriate `TestInformation` using the (`test_num`, `site_num`, `head_num`)
+    /// Looks up the appropriate `TestInformation` using the (`test_key`, `site_num`, `head_num`)
     /// in the `TSR` and adds to this `TestInformation`
     pub fn add_from_tsr(&mut self, tsr: &TSR) {
         if tsr.head_num == 255 {
             return;
         }
-        let key = (tsr.test_num, tsr.site_num, tsr.head_num);
+        self.add_raw_test(tsr.test_num, &tsr.test_nam, tsr.test_typ.into());
+        let test_key = self
+            .identity
+            .key(tsr.test_num, &tsr.test_nam, tsr.test_typ.into());
+        let key = (test_key, tsr.site_num, tsr.head_num);
         self.test_infos
             .entry(key)
             .and_modify(|e| e.add_from_tsr(tsr))
             .or_insert(TestInformation::new_from_tsr(tsr));
     }
 
+    fn add_raw_test(&mut self, test_num: u32, test_txt: &str, test_type: TestType) {
+        self.raw_tests
+            .insert((test_num, test_txt.to_string(), test_type));
+    }
+
+    /// Find the `test_num`s shared by more than one test
+    ///
+    /// Tests are told apart by their raw `test_txt` and `test_type` whatever the `identity`, so
+    /// this also finds the tests whose results the `identity` merges into one column. Records
+    /// without a `test_txt` belong to the test of the same `test_type` that has one, as some
+    /// testers only write it on the first record of a test.
+    pub fn collisions(&self) -> Vec<Collision> {
+        let mut by_test_num: BTreeMap<u32, BTreeMap<TestType, BTreeSet<&str>>> = BTreeMap::new();
+        for (test_num, test_txt, test_type) in &self.raw_tests {
+            by_test_num
+                .entry(*test_num)
+                .or_default()
+                .entry(*test_type)
+                .or_default()
+                .insert(test_txt);
+        }
+        let mut collisions = Vec::new();
+        for (test_num, by_type) in by_test_num {
+            let mut tests = Vec::new();
+            for (test_type, mut test_txts) in by_type {
+                if test_txts.len() > 1 {
+                    test_txts.remove("");
+                }
+                tests.extend(
+                    test_txts
+                        .into_iter()
+                        .map(|txt| (txt.to_string(), test_type)),
+                );
+            }
+            if tests.len() > 1 {
+                let test_keys: HashSet<TestKey> = tests
+                    .iter()
+                    .map(|(test_txt, test_type)| self.identity.key(test_num, test_txt, *test_type))
+                    .collect();
+                collisions.push(Collision {
+                    test_num,
+                    separated: test_keys.len() == tests.len(),
+                    tests,
+                });
+            }
+        }
+        collisions
+    }
+
     /// Merges down the `FullTestInformation` to a `FullMergedTestInformation`
     ///
-    /// The `FullMergedTestInformation` is indexed by only `test_num` rather than
-    /// (`test_num`, `site_num`, `head_num`). Usually all sites and all heads implement the
+    /// The `FullMergedTestInformation` is indexed by only `test_key` rather than
+    /// (`test_key`, `site_num`, `head_num`). Usually all sites and all heads implement the
     /// same tests and just run them in parallel, so it's not necessary to keep track of the
     /// `site_num` and `head_num`.
     pub fn merge(&self) -> FullMergedTestInformation {
         let mut merged_test_info = FullMergedTestInformation::new();
-        for ti in self.test_infos.values() {
-            merged_test_info.add_from_test_information(ti);
+        for ((test_key, _, _), ti) in &self.test_infos {
+            merged_test_info.add_from_test_information(test_key, ti);
         }
         merged_test_info
     }
@@ -332,8 +579,23 @@ impl FullTestInformation {
     /// # Errors
     /// If for some reason the file can't be parsed, returns a std::io::Error
     pub fn from_fname(fname: &str, verbose: bool) -> std::io::Result<Self> {
-        let records = Records::new(&fname)?;
-        let mut test_info = Self::new();
+        Self::from_fname_with_identity(fname, verbose, TestIdentity::default())
+    }
+
+    /// Gather all of the test information from a STDF specified by `fname`, telling tests apart
+    /// by `identity`
+    ///
+    /// See `from_fname`.
+    ///
+    /// # Errors
+    /// If for some reason the file can't be parsed, returns a std::io::Error
+    pub fn from_fname_with_identity(
+        fname: &str,
+        verbose: bool,
+        identity: TestIdentity,
+    ) -> std::io::Result<Self> {
+        let records = Records::new(fname)?;
+        let mut test_info = Self::with_identity(identity);
 
         for record in records {
             if let Some(resolved) = record.resolve() {
@@ -412,8 +674,8 @@ impl FullTestInformation {
 }
 
 impl IntoIterator for FullTestInformation {
-    type Item = ((u32, u8, u8), TestInformation);
-    type IntoIter = <HashMap<(u32, u8, u8), TestInformation> as IntoIterator>::IntoIter;
+    type Item = ((TestKey, u8, u8), TestInformation);
+    type IntoIter = <HashMap<(TestKey, u8, u8), TestInformation> as IntoIterator>::IntoIter;
     fn into_iter(self) -> Self::IntoIter {
         self.test_infos.into_iter()
     }
@@ -455,7 +717,7 @@ impl MergedTestInformation {
     /// Initialize a new `MergedTestInformation` from a `TestInformation` record
     pub fn new_from_test_information(test_information: &TestInformation) -> Self {
         let test_num = test_information.test_num;
-        let test_type = test_information.test_type.clone();
+        let test_type = test_information.test_type;
         let execution_count = test_information.execution_count;
         let test_name = test_information.test_name.clone();
         let sequence_name = test_information.sequence_name.clone();
@@ -490,6 +752,17 @@ impl MergedTestInformation {
         }
     }
 
+    /// The human readable name of the test
+    ///
+    /// The `test_name` from the `TSR`, or the `test_text` from the `PTR` if there is no name.
+    /// `None` if neither is available.
+    pub fn display_name(&self) -> Option<&str> {
+        [&self.test_name, &self.test_text]
+            .into_iter()
+            .find(|name| !name.is_empty())
+            .map(|name| name.as_str())
+    }
+
     /// Add the `execution_count` from a `TestInformation` to an existing `MergedTestInformation`
     pub fn add(&mut self, test_information: &TestInformation) {
         if self.test_num != test_information.test_num {
@@ -497,14 +770,49 @@ impl MergedTestInformation {
         }
         self.execution_count += test_information.execution_count;
     }
+
+    /// The `test_name`, `low_limit`, `high_limit` and `units` that differ in the same test of
+    /// another file, as (`field`, `value`, `other_value`)
+    ///
+    /// The `test_name` is the `display_name`, empty if there is none, and the limits are
+    /// formatted by `format_limit`.
+    pub fn differences(&self, other: &Self) -> Vec<(&'static str, String, String)> {
+        let name = |mti: &Self| mti.display_name().unwrap_or_default().to_string();
+        let fields = [
+            ("test_name", name(self), name(other)),
+            (
+                "low_limit",
+                format_limit(self.low_limit),
+                format_limit(other.low_limit),
+            ),
+            (
+                "high_limit",
+                format_limit(self.high_limit),
+                format_limit(other.high_limit),
+            ),
+            ("units", self.units.clone(), other.units.clone()),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, value, other_value)| value != other_value)
+            .collect()
+    }
+}
+
+/// Format a limit for comparison between files, empty if there is none
+pub fn format_limit(limit: f32) -> String {
+    match limit.is_nan() {
+        true => String::new(),
+        false => limit.to_string(),
+    }
 }
 
 /// A collection of all `MergedTestInformation`s in a STDF file
 ///
-/// Indexed by `test_num`
+/// Indexed by `test_key`
 #[derive(Debug, IntoPyObject)]
 pub struct FullMergedTestInformation {
-    pub test_infos: HashMap<u32, MergedTestInformation>,
+    pub test_infos: HashMap<TestKey, MergedTestInformation>,
 }
 impl FullMergedTestInformation {
     /// Initialize a new `FullMergedTestInformation` with an empty HashMap
@@ -515,13 +823,16 @@ impl FullMergedTestInformation {
 
     /// Adds the metadata from a `TestInformation` record
     ///
-    /// If there is not a corresponding `MergedTestInformation` for the `test_num`, a new one is
+    /// If there is not a corresponding `MergedTestInformation` for the `test_key`, a new one is
     /// made. If there is already a corresponding `MergedTestInformation`, adds the
     /// `execution_count`.
-    pub fn add_from_test_information(&mut self, test_information: &TestInformation) {
-        let key = test_information.test_num;
+    pub fn add_from_test_information(
+        &mut self,
+        test_key: &TestKey,
+        test_information: &TestInformation,
+    ) {
         self.test_infos
-            .entry(key)
+            .entry(test_key.clone())
             .and_modify(|e| e.add(test_information))
             .or_insert(MergedTestInformation::new_from_test_information(
                 test_information,
@@ -536,12 +847,166 @@ impl FullMergedTestInformation {
             .collect::<Vec<_>>()
             .len()
     }
+
+    /// Find the `test_num`s that are shared by more than one test
+    ///
+    /// Only tests with a parametric, functional or multi-pin `TestType` are considered, since
+    /// those are the ones that receive columns of results. With the default `TestIdentity` there
+    /// can be no collisions, since every `test_num` is a single test.
+    pub fn collisions(&self) -> BTreeMap<u32, Vec<TestKey>> {
+        let mut by_test_num: BTreeMap<u32, Vec<TestKey>> = BTreeMap::new();
+        for (test_key, mti) in &self.test_infos {
+            if matches!(mti.test_type, TestType::P | TestType::F | TestType::M) {
+                by_test_num
+                    .entry(test_key.test_num())
+                    .or_default()
+                    .push(test_key.clone());
+            }
+        }
+        by_test_num.retain(|_, test_keys| test_keys.len() > 1);
+        for test_keys in by_test_num.values_mut() {
+            test_keys.sort();
+        }
+        by_test_num
+    }
+
+    /// The column names for every test, indexed by `test_key`
+    ///
+    /// Tests that do not share their `test_num` with another test are named by `test_num` alone.
+    /// Colliding tests are named by their full `TestKey`, e.g. `1000:VDD_LEAK` or `1000:M`.
+    pub fn column_names(&self) -> HashMap<TestKey, String> {
+        let collisions = self.collisions();
+        self.test_infos
+            .keys()
+            .map(|test_key| {
+                let name = if collisions.contains_key(&test_key.test_num()) {
+                    test_key.to_string()
+                } else {
+                    test_key.test_num().to_string()
+                };
+                (test_key.clone(), name)
+            })
+            .collect()
+    }
+
+    /// The column names for every test with a column of results according to `naming`, indexed by
+    /// `test_key`
+    ///
+    /// As in `collisions`, only tests with a parametric, functional or multi-pin `TestType` are
+    /// named, so a test that never receives a column cannot force another to be renamed. Names are
+    /// guaranteed to be unique and to not clash with any of the `reserved` names (e.g.
+    /// the per-part columns of the results `DataFrame`). Tests whose name clashes are renamed to
+    /// `name_testkey`, e.g. `VDD_1000`, visiting the tests in `test_key` order. Any clash that
+    /// remains after that gets a numeric suffix, so the result is always deterministic.
+    pub fn column_names_with(
+        &self,
+        naming: &ColumnNaming,
+        reserved: &[&str],
+    ) -> HashMap<TestKey, String> {
+        let default_names = self.column_names();
+        let names = self
+            .test_infos
+            .iter()
+            .filter(|(_, mti)| matches!(mti.test_type, TestType::P | TestType::F | TestType::M))
+            .sorted_by_key(|(test_key, _)| *test_key)
+            .map(|(test_key, mti)| {
+                let default_name = &default_names[test_key];
+                let name = match naming {
+                    ColumnNaming::Number => default_name.clone(),
+                    ColumnNaming::Name => mti.display_name().unwrap_or(default_name).to_string(),
+                    ColumnNaming::NumberName => match mti.display_name() {
+                        Some(name) => format!("{}:{name}", mti.test_num),
+                        None => default_name.clone(),
+                    },
+                    ColumnNaming::Template(template) => template
+                        .replace("{test_num}", &mti.test_num.to_string())
+                        .replace("{test_key}", default_name)
+                        .replace("{test_txt}", &mti.test_text)
+                        .replace("{test_name}", &mti.test_name)
+                        .replace("{units}", &mti.units)
+                        .replace("{sequence_name}", &mti.sequence_name),
+                };
+                (test_key, name)
+            })
+            .collect::<Vec<_>>();
+
+        let mut counts: HashMap<&str, usize> = reserved.iter().map(|name| (*name, 1)).collect();
+        for (_, name) in &names {
+            *counts.entry(name.as_str()).or_insert(0) += 1;
+        }
+        let mut used: HashSet<String> = reserved.iter().map(|name| name.to_string()).collect();
+        let mut column_names = HashMap::new();
+        for (test_key, name) in &names {
+            let mut column_name = if counts[name.as_str()] > 1 {
+                format!("{name}_{}", default_names[*test_key])
+            } else {
+                name.clone()
+            };
+            let mut suffix = 2;
+            while used.contains(&column_name) {
+                column_name = format!("{name}_{}_{suffix}", default_names[*test_key]);
+                suffix += 1;
+            }
+            used.insert(column_name.clone());
+            column_names.insert((*test_key).clone(), column_name);
+        }
+        column_names
+    }
+}
+
+/// `ColumnNaming` determines how test result columns are named when converting a `TestData` into
+/// a `DataFrame`
+///
+/// In every case the names are made unique, see `FullMergedTestInformation::column_names_with`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ColumnNaming {
+    /// The `test_num`, e.g. `1000`. Tests sharing a `test_num` are named by their `TestKey`.
+    #[default]
+    Number,
+    /// The `test_name` from the `TSR`, falling back to the `test_txt` and then the number
+    Name,
+    /// The number and the name, e.g. `1000:VDD_LEAK`
+    NumberName,
+    /// A template where `{test_num}`, `{test_key}`, `{test_txt}`, `{test_name}`, `{units}` and
+    /// `{sequence_name}` are replaced by the corresponding test information
+    Template(String),
+}
+
+/// Parses a `ColumnNaming` from `num`, `name` or `num:name`
+///
+/// Any string containing a `{` is treated as a `Template`.
+impl FromStr for ColumnNaming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "num" => Ok(Self::Number),
+            "name" => Ok(Self::Name),
+            "num:name" => Ok(Self::NumberName),
+            _ if s.contains('{') => Ok(Self::Template(s.to_string())),
+            _ => Err(format!(
+                "unknown column naming {s}, expected one of num, name, num:name or a template"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ColumnNaming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number => write!(f, "num"),
+            Self::Name => write!(f, "name"),
+            Self::NumberName => write!(f, "num:name"),
+            Self::Template(template) => write!(f, "{template}"),
+        }
+    }
 }
 
 /// Make a DataFrame containing the info in a `FullMergedTestInformation`
 impl Into<DataFrame> for &FullMergedTestInformation {
     fn into(self) -> DataFrame {
         let mut test_nums: Vec<u32> = Vec::new();
+        let mut test_keys: Vec<String> = Vec::new();
         let mut test_types: Vec<String> = Vec::new();
         let mut execution_counts: Vec<u32> = Vec::new();
         let mut test_names: Vec<String> = Vec::new();
@@ -558,8 +1023,10 @@ impl Into<DataFrame> for &FullMergedTestInformation {
         let mut high_limits: Vec<f32> = Vec::new();
         let mut unitss: Vec<String> = Vec::new();
 
-        for (tnum, mti) in &self.test_infos {
-            test_nums.push(*tnum);
+        let column_names = self.column_names();
+        for (test_key, mti) in &self.test_infos {
+            test_nums.push(mti.test_num);
+            test_keys.push(column_names[test_key].clone());
             test_types.push(mti.test_type.to_string());
             execution_counts.push(mti.execution_count);
             test_names.push(mti.test_name.clone());
@@ -579,6 +1046,7 @@ impl Into<DataFrame> for &FullMergedTestInformation {
 
         let mut columns: Vec<Column> = Vec::new();
         columns.push(Column::new("test_num".into(), test_nums));
+        columns.push(Column::new("test_key".into(), test_keys));
         columns.push(Column::new("test_type".into(), test_types));
         columns.push(Column::new("execution_count".into(), execution_counts));
         columns.push(Column::new("test_name".into(), test_names));
@@ -1,131 +1,275 @@
-//! utility functions for parsing STDF data types
+//! utility functions for parsing and encoding STDF data types
 
 #![allow(non_snake_case)]
 
+use std::io;
+
+/// Take the next `n` bytes and advance the `offset`
+///
+/// # Error
+/// If fewer than `n` bytes are left, returns an `std::io::Error` of kind `UnexpectedEof`
+fn take<'a>(bytes: &'a [u8], offset: &mut usize, n: usize) -> io::Result<&'a [u8]> {
+    match bytes.get(*offset..*offset + n) {
+        Some(x) => {
+            *offset += n;
+            Ok(x)
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "expected {n} bytes at offset {offset}, but there are only {}",
+                bytes.len().saturating_sub(*offset)
+            ),
+        )),
+    }
+}
+
 /// Parse a uint8 and advance the `offset`
-pub fn U1(bytes: &[u8], offset: &mut usize) -> u8 {
-    let x = bytes[*offset];
-    *offset += 1;
-    x
+pub fn U1(bytes: &[u8], offset: &mut usize) -> io::Result<u8> {
+    Ok(take(bytes, offset, 1)?[0])
 }
 
 /// Parse a uint16 and advance the `offset`
-pub fn U2(bytes: &[u8], offset: &mut usize) -> u16 {
-    let x = u16::from_le_bytes(bytes[*offset..*offset + 2].try_into().unwrap());
-    *offset += 2;
-    x
+pub fn U2(bytes: &[u8], offset: &mut usize) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(
+        take(bytes, offset, 2)?.try_into().unwrap(),
+    ))
 }
 
 /// Parse a uint32 and advance the `offset`
-pub fn U4(bytes: &[u8], offset: &mut usize) -> u32 {
-    let x = u32::from_le_bytes(bytes[*offset..*offset + 4].try_into().unwrap());
-    *offset += 4;
-    x
+pub fn U4(bytes: &[u8], offset: &mut usize) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(
+        take(bytes, offset, 4)?.try_into().unwrap(),
+    ))
 }
 
 /// Parse a int8 and advance the `offset`
-pub fn I1(bytes: &[u8], offset: &mut usize) -> i8 {
-    let x = bytes[*offset] as i8;
-    *offset += 1;
-    x
+pub fn I1(bytes: &[u8], offset: &mut usize) -> io::Result<i8> {
+    Ok(take(bytes, offset, 1)?[0] as i8)
 }
 
 /// Parse a int16 and advance the `offset`
-pub fn I2(bytes: &[u8], offset: &mut usize) -> i16 {
-    let x = i16::from_le_bytes(bytes[*offset..*offset + 2].try_into().unwrap());
-    *offset += 2;
-    x
+pub fn I2(bytes: &[u8], offset: &mut usize) -> io::Result<i16> {
+    Ok(i16::from_le_bytes(
+        take(bytes, offset, 2)?.try_into().unwrap(),
+    ))
 }
 
 /// Parse a int32 and advance the `offset`
-pub fn I4(bytes: &[u8], offset: &mut usize) -> i32 {
-    let x = i32::from_le_bytes(bytes[*offset..*offset + 4].try_into().unwrap());
-    *offset += 4;
-    x
+pub fn I4(bytes: &[u8], offset: &mut usize) -> io::Result<i32> {
+    Ok(i32::from_le_bytes(
+        take(bytes, offset, 4)?.try_into().unwrap(),
+    ))
 }
 
 /// Parse a 32-bit float and advance the `offset`
-pub fn R4(bytes: &[u8], offset: &mut usize) -> f32 {
-    let x = f32::from_le_bytes(bytes[*offset..*offset + 4].try_into().unwrap());
-    *offset += 4;
-    x
+pub fn R4(bytes: &[u8], offset: &mut usize) -> io::Result<f32> {
+    Ok(f32::from_le_bytes(
+        take(bytes, offset, 4)?.try_into().unwrap(),
+    ))
 }
 
 /// Parse a single 8-bit character and advance the `offset`
-pub fn C1(bytes: &[u8], offset: &mut usize) -> char {
-    let x = char::from_u32(bytes[*offset] as u32)
-        .expect("Failed to parse C1 from {offset} from\n{bytes:#?}");
-    *offset += 1;
-    x
+pub fn C1(bytes: &[u8], offset: &mut usize) -> io::Result<char> {
+    Ok(U1(bytes, offset)? as char)
 }
 
 /// Parse a string and advance the `offset`
-pub fn Cn(bytes: &[u8], offset: &mut usize) -> String {
-    let length = bytes[*offset] as usize;
-    let result = String::from_utf8(bytes[*offset + 1..*offset + 1 + length].to_vec());
-    if let Ok(s) = result {
-        *offset += 1 + length;
-        s
-    } else {
-        panic!("Failed to parse Cn from {offset} with length {length} from\n{bytes:#?}");
-    }
+///
+/// # Error
+/// Besides running past `bytes`, if the string is not valid UTF-8, returns an `std::io::Error`
+/// of kind `InvalidData`
+pub fn Cn(bytes: &[u8], offset: &mut usize) -> io::Result<String> {
+    let start = *offset;
+    let length = U1(bytes, offset)? as usize;
+    let x = take(bytes, offset, length)?;
+    String::from_utf8(x.to_vec()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid string of length {length} at offset {start}: {e}"),
+        )
+    })
 }
 
 /// Parse an array of bits and advance the `offset`
-pub fn Bn(bytes: &[u8], offset: &mut usize) -> Vec<u8> {
-    let length = bytes[*offset] as usize;
-    let x = bytes[*offset + 1..*offset + 1 + length].to_vec();
-    *offset += 1 + length;
-    x
+pub fn Bn(bytes: &[u8], offset: &mut usize) -> io::Result<Vec<u8>> {
+    let length = U1(bytes, offset)? as usize;
+    Ok(take(bytes, offset, length)?.to_vec())
 }
 
-/// Parse an array of uint8 and advance the offset
-pub fn Dn(bytes: &[u8], offset: &mut usize) -> Vec<u8> {
-    let nbits = u16::from_le_bytes(bytes[*offset..*offset + 2].try_into().unwrap()) as usize;
-    let length = nbits.div_ceil(8);
-    let dn = bytes[*offset + 1..*offset + 1 + length].to_vec();
-    *offset += 2 + length;
-    dn
+/// Parse an array of bits and advance the offset
+///
+/// Returns the number of bits along with the bytes holding them
+pub fn Dn(bytes: &[u8], offset: &mut usize) -> io::Result<(u16, Vec<u8>)> {
+    let nbits = U2(bytes, offset)?;
+    let length = (nbits as usize).div_ceil(8);
+    Ok((nbits, take(bytes, offset, length)?.to_vec()))
+}
+
+/// Parse an optional field with `parse` and advance the `offset`, counting it in `n_optional`
+///
+/// STDF allows omitting optional fields from the end of a record. If the `bytes` end at the
+/// `offset`, the field is omitted and `missing` is returned without advancing the `offset` or
+/// counting it.
+pub fn optional<T>(
+    bytes: &[u8],
+    offset: &mut usize,
+    n_optional: &mut u8,
+    parse: impl FnOnce(&[u8], &mut usize) -> io::Result<T>,
+    missing: T,
+) -> io::Result<T> {
+    match *offset < bytes.len() {
+        true => {
+            *n_optional += 1;
+            parse(bytes, offset)
+        }
+        false => Ok(missing),
+    }
 }
 
 /// Parse an array of uint8 and advance the offset
-pub fn kxU1(contents: &[u8], num: usize, offset: &mut usize) -> Vec<u8> {
-    let x = contents[*offset..*offset + num].to_vec();
-    *offset += num;
-    x
+pub fn kxU1(contents: &[u8], num: usize, offset: &mut usize) -> io::Result<Vec<u8>> {
+    Ok(take(contents, offset, num)?.to_vec())
 }
 
 /// Parse an array of uint16 and advance the offset
-pub fn kxU2(contents: &[u8], num: usize, offset: &mut usize) -> Vec<u16> {
-    let mut v = Vec::with_capacity(num as usize);
-    for _ in 0..num {
-        let x = u16::from_le_bytes(contents[*offset..*offset + 2].try_into().unwrap());
-        v.push(x);
-        *offset += 2;
-    }
-    v
+pub fn kxU2(contents: &[u8], num: usize, offset: &mut usize) -> io::Result<Vec<u16>> {
+    (0..num).map(|_| U2(contents, offset)).collect()
 }
 
 /// Parse an array of f32 and advance the offset
-pub fn kxR4(contents: &[u8], num: usize, offset: &mut usize) -> Vec<f32> {
-    let mut v = Vec::with_capacity(num);
-    for _ in 0..num {
-        let x = f32::from_le_bytes(contents[*offset..*offset + 4].try_into().unwrap());
-        v.push(x);
-        *offset += 4;
-    }
-    v
+pub fn kxR4(contents: &[u8], num: usize, offset: &mut usize) -> io::Result<Vec<f32>> {
+    (0..num).map(|_| R4(contents, offset)).collect()
 }
 
 /// Parse an array of uint4 and advance the offset
-pub fn kxN1(contents: &[u8], num: usize, offset: &mut usize) -> Vec<u8> {
-    let nbytes = num.div_ceil(2) as usize;
-    let mut v = Vec::with_capacity(num as usize);
-    for _ in 0..nbytes {
-        let x = contents[*offset];
-        v.push((x >> 0) & 0xf); // lower nibble
+pub fn kxN1(contents: &[u8], num: usize, offset: &mut usize) -> io::Result<Vec<u8>> {
+    let nbytes = num.div_ceil(2);
+    let mut v = Vec::with_capacity(num);
+    for x in take(contents, offset, nbytes)? {
+        v.push(x & 0xf); // lower nibble
         v.push((x >> 4) & 0xf); // upper nibble
-        *offset += 1;
     }
-    v
+    Ok(v)
+}
+
+/// Encode a uint8 onto the end of `bytes`
+pub fn push_U1(bytes: &mut Vec<u8>, x: u8) {
+    bytes.push(x);
+}
+
+/// Encode a uint16 onto the end of `bytes`
+pub fn push_U2(bytes: &mut Vec<u8>, x: u16) {
+    bytes.extend_from_slice(&x.to_le_bytes());
+}
+
+/// Encode a uint32 onto the end of `bytes`
+pub fn push_U4(bytes: &mut Vec<u8>, x: u32) {
+    bytes.extend_from_slice(&x.to_le_bytes());
+}
+
+/// Encode a int8 onto the end of `bytes`
+pub fn push_I1(bytes: &mut Vec<u8>, x: i8) {
+    bytes.extend_from_slice(&x.to_le_bytes());
+}
+
+/// Encode a int16 onto the end of `bytes`
+pub fn push_I2(bytes: &mut Vec<u8>, x: i16) {
+    bytes.extend_from_slice(&x.to_le_bytes());
+}
+
+/// Encode a int32 onto the end of `bytes`
+pub fn push_I4(bytes: &mut Vec<u8>, x: i32) {
+    bytes.extend_from_slice(&x.to_le_bytes());
+}
+
+/// Encode a 32-bit float onto the end of `bytes`
+pub fn push_R4(bytes: &mut Vec<u8>, x: f32) {
+    bytes.extend_from_slice(&x.to_le_bytes());
+}
+
+/// Encode a single 8-bit character onto the end of `bytes`
+///
+/// Characters that do not fit in a single byte are written as a space.
+pub fn push_C1(bytes: &mut Vec<u8>, x: char) {
+    bytes.push(u8::try_from(x).unwrap_or(b' '));
+}
+
+/// Encode a string onto the end of `bytes`
+///
+/// Strings longer than the 255 bytes allowed by STDF are truncated at the last character
+/// boundary that fits.
+pub fn push_Cn(bytes: &mut Vec<u8>, x: &str) {
+    let mut length = x.len().min(u8::MAX as usize);
+    while !x.is_char_boundary(length) {
+        length -= 1;
+    }
+    bytes.push(length as u8);
+    bytes.extend_from_slice(&x.as_bytes()[..length]);
+}
+
+/// Encode an array of bits onto the end of `bytes`
+///
+/// Arrays longer than 255 bytes are truncated.
+pub fn push_Bn(bytes: &mut Vec<u8>, x: &[u8]) {
+    let length = x.len().min(u8::MAX as usize);
+    bytes.push(length as u8);
+    bytes.extend_from_slice(&x[..length]);
+}
+
+/// Encode an array of `nbits` bits onto the end of `bytes`
+///
+/// Exactly `nbits.div_ceil(8)` bytes are written, padding `x` with zeros if it is too short.
+pub fn push_Dn(bytes: &mut Vec<u8>, nbits: u16, x: &[u8]) {
+    let length = (nbits as usize).div_ceil(8);
+    push_U2(bytes, nbits);
+    for i in 0..length {
+        bytes.push(x.get(i).copied().unwrap_or(0));
+    }
+}
+
+/// Encode an optional field onto the end of `bytes` with `push`, if any of the `n_optional`
+/// fields left to encode remain
+///
+/// The counterpart of `optional`: encoding the fields a record was decoded with, and omitting
+/// the rest, reproduces its bytes.
+pub fn push_optional(bytes: &mut Vec<u8>, n_optional: &mut u8, push: impl FnOnce(&mut Vec<u8>)) {
+    if *n_optional > 0 {
+        *n_optional -= 1;
+        push(bytes);
+    }
+}
+
+/// Encode `num` uint8 from `x` onto the end of `bytes`, padding with zeros if `x` is too short
+pub fn push_kxU1(bytes: &mut Vec<u8>, x: &[u8], num: usize) {
+    for i in 0..num {
+        push_U1(bytes, x.get(i).copied().unwrap_or(0));
+    }
+}
+
+/// Encode `num` uint16 from `x` onto the end of `bytes`, padding with zeros if `x` is too short
+pub fn push_kxU2(bytes: &mut Vec<u8>, x: &[u16], num: usize) {
+    for i in 0..num {
+        push_U2(bytes, x.get(i).copied().unwrap_or(0));
+    }
+}
+
+/// Encode `num` f32 from `x` onto the end of `bytes`, padding with zeros if `x` is too short
+pub fn push_kxR4(bytes: &mut Vec<u8>, x: &[f32], num: usize) {
+    for i in 0..num {
+        push_R4(bytes, x.get(i).copied().unwrap_or(0.));
+    }
+}
+
+/// Encode `num` uint4 from `x` onto the end of `bytes`, two per byte
+///
+/// `x` holds one nibble per element, as parsed by `kxN1`, and is padded with zeros if it is too
+/// short.
+pub fn push_kxN1(bytes: &mut Vec<u8>, x: &[u8], num: usize) {
+    let nbytes = num.div_ceil(2);
+    for i in 0..nbytes {
+        let lower = x.get(2 * i).copied().unwrap_or(0) & 0xf;
+        let upper = x.get(2 * i + 1).copied().unwrap_or(0) & 0xf;
+        bytes.push(lower | (upper << 4));
+    }
 }
@@ -0,0 +1,846 @@
+//! Validation of STDF files against the V4 specification
+//!
+//! `validate_fname` walks the `Records` of a file and checks
+//! * the record order: the `FAR` first, then any `ATR`s, the `MIR`, an optional `RDR` and any
+//!   `SDR`s, and nothing after the `MRR`
+//! * the nesting of the `WIR`/`WRR`, `PIR`/`PRR` and `BPS`/`EPS` pairs, and that test records
+//!   belong to an open part
+//! * the ranges of the flags and bin numbers
+//! * that the `num_test` of each `PRR` matches the test records of its part
+//! * that the counts in the `PCR`s, `WRR`s, `HBR`s, `SBR`s and `TSR`s match the parts and tests
+//!   in the file
+//!
+//! Nothing is assumed to be well-formed, so unlike `TestData` the validator does not panic on a
+//! malformed file. Every problem is recorded as an `Issue` in the `ValidationReport`, with the
+//! offset of the offending record.
+use std::{collections::HashMap, fmt, fs, io};
+
+use polars::prelude::*;
+
+use crate::{
+    filter::{PartCounts, TestCounts, test_result},
+    record_types::RecordType,
+    records::{RawRecord, Records, records::*},
+};
+
+/// How serious an `Issue` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The file is technically compliant, but unusual in a way that may trip up readers
+    Warning,
+    /// The file violates the specification
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single problem found in an STDF file
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// The offset of the offending record in the file, or of the end of the file for missing
+    /// records
+    pub offset: usize,
+    /// The index of the offending record in the file, counting from 0
+    pub record_index: usize,
+    /// The type of the offending record, `None` for missing records
+    pub rtype: Option<RecordType>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rtype {
+            Some(rtype) => write!(
+                f,
+                "{} at offset {} (record {}, {rtype:?}): {}",
+                self.severity, self.offset, self.record_index, self.message
+            ),
+            None => write!(
+                f,
+                "{} at offset {}: {}",
+                self.severity, self.offset, self.message
+            ),
+        }
+    }
+}
+
+/// The result of validating an STDF file
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// The number of records read
+    pub n_records: usize,
+    /// The issues found, in file order
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// The issues with `Severity::Error`
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    /// The issues with `Severity::Warning`
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    /// Whether the file has no errors, warnings are allowed
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Convert the `issues` into a `DataFrame`
+    pub fn to_df(&self) -> DataFrame {
+        let mut severities: Vec<String> = Vec::new();
+        let mut offsets: Vec<u64> = Vec::new();
+        let mut record_indices: Vec<u64> = Vec::new();
+        let mut rtypes: Vec<Option<String>> = Vec::new();
+        let mut messages: Vec<String> = Vec::new();
+
+        for issue in &self.issues {
+            severities.push(issue.severity.to_string());
+            offsets.push(issue.offset as u64);
+            record_indices.push(issue.record_index as u64);
+            rtypes.push(issue.rtype.map(|rtype| format!("{rtype:?}")));
+            messages.push(issue.message.clone());
+        }
+        let columns = vec![
+            Column::new("severity".into(), severities),
+            Column::new("offset".into(), offsets),
+            Column::new("record_index".into(), record_indices),
+            Column::new("rtype".into(), rtypes),
+            Column::new("message".into(), messages),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{issue}")?;
+        }
+        write!(
+            f,
+            "{} errors, {} warnings in {} records",
+            self.errors().count(),
+            self.warnings().count(),
+            self.n_records
+        )
+    }
+}
+
+/// Validate the STDF file at `fname`
+///
+/// Also reports a truncated last record.
+///
+/// # Error
+/// If the file cannot be read, returns a `std::io::Error`
+pub fn validate_fname(fname: &str) -> io::Result<ValidationReport> {
+    let file_len = fs::metadata(fname)?.len() as usize;
+    let mut records = Records::new(fname)?;
+    let mut report = validate(&mut records);
+    if records.offset() < file_len {
+        report.issues.push(Issue {
+            severity: Severity::Error,
+            offset: records.offset(),
+            record_index: report.n_records,
+            rtype: None,
+            message: format!(
+                "the file ends in a truncated record of {} bytes",
+                file_len - records.offset()
+            ),
+        });
+    }
+    Ok(report)
+}
+
+/// Validate the `records` of an STDF file
+pub fn validate(records: impl Iterator<Item = RawRecord>) -> ValidationReport {
+    let mut validator = Validator::default();
+    for raw_record in records {
+        validator.add(&raw_record);
+    }
+    validator.finish()
+}
+
+/// Where an open `WIR`, `PIR` or `BPS` was found
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    offset: usize,
+    record_index: usize,
+    rtype: RecordType,
+}
+
+/// The (`head_num`, `site_num`, `test_num`, `test_typ`) of the `TSR`s for a test, the location
+/// of the first, and their summed `exec_cnt` and `fail_cnt`, `None` if any `TSR` omits the count
+type TsrSums = ((u8, u8, u32, char), Location, Option<u32>, Option<u32>);
+
+/// A wafer whose `WIR` has been read but not its `WRR`
+struct OpenWafer {
+    location: Location,
+    wafer_id: String,
+    counts: PartCounts,
+}
+
+/// A part whose `PIR` has been read but not its `PRR`
+struct OpenPart {
+    location: Location,
+    // The number of test records of the part so far
+    n_tests: usize,
+}
+
+/// The state of the validation while iterating through the records
+#[derive(Default)]
+struct Validator {
+    issues: Vec<Issue>,
+    n_records: usize,
+    // The location of the current record
+    location: Option<Location>,
+    // The offset of the next record
+    offset: usize,
+    mir_seen: bool,
+    mrr_seen: bool,
+    sdr_seen: bool,
+    // Whether any record beyond the FAR, ATRs, MIR, RDR and SDRs has been seen
+    header_done: bool,
+    // Whether a record was already reported for coming before the MIR
+    early_record_reported: bool,
+    mir_start_t: u32,
+    wir_seen: bool,
+    // The open wafers indexed by `head_num`
+    wafers: HashMap<u8, OpenWafer>,
+    // The open parts indexed by (`head_num`, `site_num`)
+    parts: HashMap<(u8, u8), OpenPart>,
+    bps: Option<Location>,
+    // The summary records, checked once all parts are counted
+    summaries: Vec<(Location, Record)>,
+    // The part counts indexed by (`head_num`, `site_num`), or (255, 255) for all parts
+    part_counts: HashMap<(u8, u8), PartCounts>,
+    // The soft bin counts indexed by (`head_num`, `site_num`, `sbin_num`)
+    sbin_counts: HashMap<(u8, u8, u16), u32>,
+    // The hard bin counts indexed by (`head_num`, `site_num`, `hbin_num`)
+    hbin_counts: HashMap<(u8, u8, u16), u32>,
+    // The test counts indexed by (`head_num`, `site_num`, `test_num`, `test_typ`)
+    test_counts: HashMap<(u8, u8, u32, char), TestCounts>,
+}
+
+impl Validator {
+    /// Record an issue with the current record
+    fn issue(&mut self, severity: Severity, message: String) {
+        let location = self.location.expect("issue outside of a record");
+        self.issue_at(severity, location, message);
+    }
+
+    /// Record an issue with the record at `location`
+    fn issue_at(&mut self, severity: Severity, location: Location, message: String) {
+        self.issues.push(Issue {
+            severity,
+            offset: location.offset,
+            record_index: location.record_index,
+            rtype: Some(location.rtype),
+            message,
+        });
+    }
+
+    /// Record an issue at the end of the file, e.g. a missing record
+    fn issue_at_end(&mut self, severity: Severity, message: String) {
+        self.issues.push(Issue {
+            severity,
+            offset: self.offset,
+            record_index: self.n_records,
+            rtype: None,
+            message,
+        });
+    }
+
+    fn add(&mut self, raw_record: &RawRecord) {
+        let rtype = raw_record.rtype;
+        self.location = Some(Location {
+            offset: raw_record.offset,
+            record_index: self.n_records,
+            rtype,
+        });
+        self.n_records += 1;
+        self.offset = raw_record.offset + 4 + raw_record.contents.len();
+
+        self.check_order(raw_record);
+        match raw_record.try_resolve() {
+            Ok(Some(record)) => self.check_record(record),
+            Ok(None) => self.check_unresolved(rtype),
+            Err(e) => {
+                let message = format!(
+                    "cannot decode the {} bytes of contents: {e}",
+                    raw_record.contents.len()
+                );
+                self.issue(Severity::Error, message);
+            }
+        }
+    }
+
+    /// Check the position of a record in the file
+    fn check_order(&mut self, raw_record: &RawRecord) {
+        let rtype = raw_record.rtype;
+        if self.n_records == 1 && rtype != RecordType::FAR {
+            self.issue(
+                Severity::Error,
+                format!("the first record must be the FAR, not {rtype:?}"),
+            );
+        }
+        if self.mrr_seen {
+            self.issue(Severity::Error, "no record may follow the MRR".to_string());
+        }
+        match rtype {
+            RecordType::FAR if self.n_records > 1 => self.issue(
+                Severity::Error,
+                "the FAR may only be the first record".to_string(),
+            ),
+            RecordType::FAR => {}
+            RecordType::ATR if self.mir_seen => {
+                self.issue(Severity::Error, "ATRs must come before the MIR".to_string())
+            }
+            RecordType::ATR => {}
+            RecordType::MIR if self.mir_seen => {
+                self.issue(Severity::Error, "only one MIR is allowed".to_string())
+            }
+            RecordType::MIR => self.mir_seen = true,
+            RecordType::InvalidRecord => {
+                let header = &raw_record.header;
+                self.issue(
+                    Severity::Warning,
+                    format!(
+                        "unknown record type rec_typ {} rec_sub {}",
+                        header.rec_typ, header.rec_sub
+                    ),
+                );
+            }
+            _ if !self.mir_seen => {
+                if !self.early_record_reported {
+                    self.early_record_reported = true;
+                    self.issue(
+                        Severity::Error,
+                        format!("{rtype:?} before the MIR, only the FAR and ATRs may precede it"),
+                    );
+                }
+            }
+            RecordType::RDR if self.header_done || self.sdr_seen => self.issue(
+                Severity::Warning,
+                "the RDR should directly follow the MIR".to_string(),
+            ),
+            RecordType::RDR => {}
+            RecordType::SDR if self.header_done => self.issue(
+                Severity::Warning,
+                "SDRs should directly follow the MIR or RDR".to_string(),
+            ),
+            RecordType::SDR => self.sdr_seen = true,
+            RecordType::MRR => {
+                self.mrr_seen = true;
+                self.header_done = true;
+            }
+            _ => self.header_done = true,
+        }
+    }
+
+    /// Check the nesting of record types that cannot be resolved
+    fn check_unresolved(&mut self, rtype: RecordType) {
+        match rtype {
+            RecordType::BPS => {
+                if self.bps.is_some() {
+                    self.issue(
+                        Severity::Warning,
+                        "BPS inside of an open program section".to_string(),
+                    );
+                }
+                self.bps = self.location;
+            }
+            RecordType::EPS => {
+                let bps = self.bps.take();
+                if bps.is_none() {
+                    self.issue(Severity::Error, "EPS without a BPS".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Check the fields and nesting of a resolved record
+    fn check_record(&mut self, record: Record) {
+        match &record {
+            Record::FAR(far) => {
+                if far.cpu_typ != 2 {
+                    self.issue(
+                        Severity::Error,
+                        format!(
+                            "cpu_typ {} is not supported, only little-endian files (2) are",
+                            far.cpu_typ
+                        ),
+                    );
+                }
+                if far.stdf_ver != 4 {
+                    self.issue(
+                        Severity::Error,
+                        format!("stdf_ver {} is not STDF V4", far.stdf_ver),
+                    );
+                }
+            }
+            Record::MIR(mir) => self.mir_start_t = mir.start_t,
+            Record::MRR(mrr) if mrr.finish_t != 0 && mrr.finish_t < self.mir_start_t => {
+                self.issue(
+                    Severity::Warning,
+                    format!(
+                        "finish_t {} is before the start_t {} of the MIR",
+                        mrr.finish_t, self.mir_start_t
+                    ),
+                );
+            }
+            Record::WIR(wir) => self.open_wafer(wir),
+            Record::WRR(wrr) => self.close_wafer(wrr),
+            Record::PIR(pir) => self.open_part(pir),
+            Record::PRR(prr) => self.close_part(prr),
+            Record::PTR(_) | Record::MPR(_) | Record::FTR(_) => self.add_test(&record),
+            Record::TSR(_) | Record::PCR(_) | Record::HBR(_) | Record::SBR(_) => {
+                self.check_summary(&record);
+                let location = self.location.expect("summary outside of a record");
+                self.summaries.push((location, record));
+            }
+            _ => {}
+        }
+    }
+
+    fn open_wafer(&mut self, wir: &WIR) {
+        self.wir_seen = true;
+        let location = self.location.expect("WIR outside of a record");
+        let wafer = OpenWafer {
+            location,
+            wafer_id: wir.wafer_id.clone(),
+            counts: PartCounts::default(),
+        };
+        if let Some(previous) = self.wafers.insert(wir.head_num, wafer) {
+            self.issue_at(
+                Severity::Error,
+                previous.location,
+                format!(
+                    "WIR of wafer {} on head {} has no WRR",
+                    previous.wafer_id, wir.head_num
+                ),
+            );
+        }
+    }
+
+    fn close_wafer(&mut self, wrr: &WRR) {
+        let Some(wafer) = self.wafers.remove(&wrr.head_num) else {
+            self.issue(
+                Severity::Error,
+                format!("WRR without a WIR on head {}", wrr.head_num),
+            );
+            return;
+        };
+        if wafer.wafer_id != wrr.wafer_id {
+            self.issue(
+                Severity::Warning,
+                format!(
+                    "wafer_id {} does not match the wafer_id {} of the WIR",
+                    wrr.wafer_id, wafer.wafer_id
+                ),
+            );
+        }
+        let counts = [wrr.part_cnt, wrr.rtst_cnt, wrr.abrt_cnt, wrr.good_cnt];
+        self.check_part_counts("WRR", counts, &wafer.counts, None);
+    }
+
+    fn open_part(&mut self, pir: &PIR) {
+        let (head_num, site_num) = (pir.head_num, pir.site_num);
+        self.check_head_num(head_num);
+        if self.wir_seen && !self.wafers.contains_key(&head_num) {
+            self.issue(
+                Severity::Warning,
+                format!("PIR on head {head_num} outside of a WIR/WRR pair"),
+            );
+        }
+        let location = self.location.expect("PIR outside of a record");
+        let part = OpenPart {
+            location,
+            n_tests: 0,
+        };
+        if let Some(previous) = self.parts.insert((head_num, site_num), part) {
+            self.issue_at(
+                Severity::Error,
+                previous.location,
+                format!("PIR on head {head_num} site {site_num} has no PRR"),
+            );
+        }
+    }
+
+    fn close_part(&mut self, prr: &PRR) {
+        let (head_num, site_num) = (prr.head_num, prr.site_num);
+        self.check_head_num(head_num);
+        match self.parts.remove(&(head_num, site_num)) {
+            Some(part) if part.n_tests != prr.num_test as usize => self.issue(
+                Severity::Warning,
+                format!(
+                    "num_test {} does not match the {} test records of the part",
+                    prr.num_test, part.n_tests
+                ),
+            ),
+            Some(_) => {}
+            None => self.issue(
+                Severity::Error,
+                format!("PRR without a PIR on head {head_num} site {site_num}"),
+            ),
+        }
+        if prr.part_flg & 0b11 == 0b11 {
+            self.issue(
+                Severity::Error,
+                "part_flg bits 0 and 1 may not both be set".to_string(),
+            );
+        }
+        if prr.hard_bin > 32767 {
+            self.issue(
+                Severity::Error,
+                format!("hard_bin {} is outside of 0-32767", prr.hard_bin),
+            );
+        }
+        if prr.soft_bin > 32767 && prr.soft_bin != u16::MAX {
+            self.issue(
+                Severity::Error,
+                format!("soft_bin {} is outside of 0-32767", prr.soft_bin),
+            );
+        }
+
+        if let Some(wafer) = self.wafers.get_mut(&head_num) {
+            wafer.counts.add(prr);
+        }
+        for (head_num, site_num) in [(head_num, site_num), (255, 255)] {
+            self.part_counts
+                .entry((head_num, site_num))
+                .or_default()
+                .add(prr);
+            *self
+                .sbin_counts
+                .entry((head_num, site_num, prr.soft_bin))
+                .or_default() += 1;
+            *self
+                .hbin_counts
+                .entry((head_num, site_num, prr.hard_bin))
+                .or_default() += 1;
+        }
+    }
+
+    fn add_test(&mut self, record: &Record) {
+        let (head_num, site_num) = match record {
+            Record::PTR(ptr) => {
+                let lo_valid = ptr.opt_flag & 0b0101_0000 == 0;
+                let hi_valid = ptr.opt_flag & 0b1010_0000 == 0;
+                if lo_valid && hi_valid && ptr.lo_limit > ptr.hi_limit {
+                    self.issue(
+                        Severity::Warning,
+                        format!(
+                            "lo_limit {} is above hi_limit {} for test {}",
+                            ptr.lo_limit, ptr.hi_limit, ptr.test_num
+                        ),
+                    );
+                }
+                (ptr.head_num, ptr.site_num)
+            }
+            Record::MPR(mpr) => (mpr.head_num, mpr.site_num),
+            Record::FTR(ftr) => (ftr.head_num, ftr.site_num),
+            _ => unreachable!("only test records are added"),
+        };
+        self.check_head_num(head_num);
+        if let Some(part) = self.parts.get_mut(&(head_num, site_num)) {
+            part.n_tests += 1;
+        } else {
+            let rtype = self.location.expect("test outside of a record").rtype;
+            self.issue(
+                Severity::Error,
+                format!("{rtype:?} on head {head_num} site {site_num} outside of a PIR/PRR pair"),
+            );
+        }
+        if let Some((test_typ, test_num, passed, result)) = test_result(record) {
+            for (head_num, site_num) in [(head_num, site_num), (255, 255)] {
+                self.test_counts
+                    .entry((head_num, site_num, test_num, test_typ))
+                    .or_default()
+                    .add(passed, result);
+            }
+        }
+    }
+
+    /// Check that a `head_num` of a part or test is not the 255 reserved for summaries
+    fn check_head_num(&mut self, head_num: u8) {
+        if head_num == 255 {
+            self.issue(
+                Severity::Error,
+                "head_num 255 is reserved for summary records".to_string(),
+            );
+        }
+    }
+
+    /// Check the fields of a summary record, its counts are checked by `check_summary_counts`
+    fn check_summary(&mut self, record: &Record) {
+        if !self.parts.is_empty() {
+            self.issue(
+                Severity::Warning,
+                "summary record while parts are still open".to_string(),
+            );
+        }
+        let (bin, bin_num, bin_pf) = match record {
+            Record::HBR(hbr) => ("hbin_num", hbr.hbin_num, hbr.hbin_pf),
+            Record::SBR(sbr) => ("sbin_num", sbr.sbin_num, sbr.sbin_pf),
+            _ => return,
+        };
+        if bin_num > 32767 {
+            self.issue(
+                Severity::Error,
+                format!("{bin} {bin_num} is outside of 0-32767"),
+            );
+        }
+        if !matches!(bin_pf, 'P' | 'F' | ' ') {
+            self.issue(
+                Severity::Warning,
+                format!("unknown pass/fail indication {bin_pf:?} of bin {bin_num}"),
+            );
+        }
+    }
+
+    /// Check the counts of a `PCR` or `WRR` against the parts counted in `counts`
+    ///
+    /// `counts_found` are the `part_cnt`, `rtst_cnt`, `abrt_cnt` and `good_cnt` of the record,
+    /// where all but `part_cnt` are optional.
+    fn check_part_counts(
+        &mut self,
+        rtype: &str,
+        counts_found: [u32; 4],
+        counts: &PartCounts,
+        location: Option<Location>,
+    ) {
+        let location = location
+            .or(self.location)
+            .expect("counts outside of a record");
+        let expected = [
+            counts.part_cnt,
+            counts.rtst_cnt,
+            counts.abrt_cnt,
+            counts.good_cnt,
+        ];
+        let names = ["part_cnt", "rtst_cnt", "abrt_cnt", "good_cnt"];
+        for (i, name) in names.iter().enumerate() {
+            if (i == 0 || counts_found[i] != u32::MAX) && counts_found[i] != expected[i] {
+                self.issue_at(
+                    Severity::Error,
+                    location,
+                    format!(
+                        "{rtype} {name} {} does not match the {} counted from the PRRs",
+                        counts_found[i], expected[i]
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Check the counts of the summary records against the parts and tests in the file
+    fn check_summary_counts(&mut self) {
+        let key = |head_num: u8, site_num: u8| match head_num {
+            255 => (255, 255),
+            _ => (head_num, site_num),
+        };
+        let mut hbins_found = Vec::new();
+        let mut sbins_found = Vec::new();
+        let mut tsr_sums: Vec<TsrSums> = Vec::new();
+        for (location, record) in std::mem::take(&mut self.summaries) {
+            match record {
+                Record::PCR(pcr) => {
+                    let counts = self
+                        .part_counts
+                        .remove(&key(pcr.head_num, pcr.site_num))
+                        .unwrap_or_default();
+                    let counts_found = [pcr.part_cnt, pcr.rtst_cnt, pcr.abrt_cnt, pcr.good_cnt];
+                    self.check_part_counts("PCR", counts_found, &counts, Some(location));
+                    self.part_counts
+                        .insert(key(pcr.head_num, pcr.site_num), counts);
+                }
+                Record::HBR(hbr) => {
+                    let (head_num, site_num) = key(hbr.head_num, hbr.site_num);
+                    let bin_key = (head_num, site_num, hbr.hbin_num);
+                    let count = self.hbin_counts.get(&bin_key).copied().unwrap_or(0);
+                    hbins_found.push(bin_key);
+                    if hbr.hbin_cnt != count {
+                        self.issue_at(
+                            Severity::Error,
+                            location,
+                            format!(
+                                "hbin_cnt {} of hard bin {} does not match the {count} PRRs in it",
+                                hbr.hbin_cnt, hbr.hbin_num
+                            ),
+                        );
+                    }
+                }
+                Record::SBR(sbr) => {
+                    let (head_num, site_num) = key(sbr.head_num, sbr.site_num);
+                    let bin_key = (head_num, site_num, sbr.sbin_num);
+                    let count = self.sbin_counts.get(&bin_key).copied().unwrap_or(0);
+                    sbins_found.push(bin_key);
+                    if sbr.sbin_cnt != count {
+                        self.issue_at(
+                            Severity::Error,
+                            location,
+                            format!(
+                                "sbin_cnt {} of soft bin {} does not match the {count} PRRs in it",
+                                sbr.sbin_cnt, sbr.sbin_num
+                            ),
+                        );
+                    }
+                }
+                Record::TSR(tsr) => {
+                    let (head_num, site_num) = key(tsr.head_num, tsr.site_num);
+                    let tsr_key = (head_num, site_num, tsr.test_num, tsr.test_typ);
+                    let sums = match tsr_sums.iter_mut().find(|(key, ..)| *key == tsr_key) {
+                        Some(sums) => sums,
+                        None => {
+                            tsr_sums.push((tsr_key, location, Some(0), Some(0)));
+                            tsr_sums.last_mut().unwrap()
+                        }
+                    };
+                    for (name, sum, cnt) in [
+                        ("exec_cnt", &mut sums.2, tsr.exec_cnt),
+                        ("fail_cnt", &mut sums.3, tsr.fail_cnt),
+                    ] {
+                        *sum = match (*sum, cnt) {
+                            (_, u32::MAX) | (None, _) => None,
+                            (Some(sum), cnt) => {
+                                let checked_sum = sum.checked_add(cnt);
+                                if checked_sum.is_none() {
+                                    self.issue_at(
+                                        Severity::Error,
+                                        location,
+                                        format!(
+                                            "the {name} sum of the TSRs of test {} overflows",
+                                            tsr.test_num
+                                        ),
+                                    );
+                                }
+                                checked_sum
+                            }
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // tests sharing a test_num have one TSR each, so the TSRs are summed before comparing
+        for ((head_num, site_num, test_num, test_typ), location, exec_sum, fail_sum) in tsr_sums {
+            let test_typs = match test_typ {
+                'P' | 'F' | 'M' => vec![test_typ],
+                _ => vec!['P', 'F', 'M'],
+            };
+            let (exec_cnt, fail_cnt) = test_typs
+                .iter()
+                .filter_map(|test_typ| {
+                    self.test_counts
+                        .get(&(head_num, site_num, test_num, *test_typ))
+                })
+                .fold((0, 0), |(exec_cnt, fail_cnt), counts| {
+                    (exec_cnt + counts.exec_cnt, fail_cnt + counts.fail_cnt)
+                });
+            if let Some(exec_sum) = exec_sum.filter(|exec_sum| *exec_sum != exec_cnt) {
+                self.issue_at(
+                    Severity::Error,
+                    location,
+                    format!(
+                        "exec_cnt {exec_sum} of test {test_num} does not match the {exec_cnt} executions"
+                    ),
+                );
+            }
+            if let Some(fail_sum) = fail_sum.filter(|fail_sum| *fail_sum != fail_cnt) {
+                self.issue_at(
+                    Severity::Error,
+                    location,
+                    format!(
+                        "fail_cnt {fail_sum} of test {test_num} does not match the {fail_cnt} failures"
+                    ),
+                );
+            }
+        }
+
+        // only files with overall bin summaries are expected to summarize every bin
+        for (bin, bins_found, bin_counts) in [
+            ("hard", hbins_found, &self.hbin_counts),
+            ("soft", sbins_found, &self.sbin_counts),
+        ] {
+            if !bins_found.iter().any(|(head_num, _, _)| *head_num == 255) {
+                continue;
+            }
+            let mut missing: Vec<_> = bin_counts
+                .iter()
+                .filter(|(bin_key, _)| bin_key.0 == 255 && !bins_found.contains(bin_key))
+                .map(|((_, _, bin_num), count)| (*bin_num, *count))
+                .collect();
+            missing.sort();
+            for (bin_num, count) in missing {
+                self.issues.push(Issue {
+                    severity: Severity::Warning,
+                    offset: self.offset,
+                    record_index: self.n_records,
+                    rtype: None,
+                    message: format!("{bin} bin {bin_num} of {count} parts has no summary"),
+                });
+            }
+        }
+    }
+
+    /// Check everything that is only known at the end of the file and produce the report
+    fn finish(mut self) -> ValidationReport {
+        if self.n_records == 0 {
+            self.issue_at_end(Severity::Error, "the file contains no records".to_string());
+        } else {
+            if !self.mir_seen {
+                self.issue_at_end(Severity::Error, "the file has no MIR".to_string());
+            }
+            if !self.mrr_seen {
+                self.issue_at_end(Severity::Error, "the file has no MRR".to_string());
+            }
+        }
+        let parts: Vec<_> = self.parts.drain().collect();
+        for ((head_num, site_num), part) in parts {
+            let message = format!("PIR on head {head_num} site {site_num} has no PRR");
+            self.issue_at(Severity::Error, part.location, message);
+        }
+        let wafers: Vec<_> = self.wafers.drain().collect();
+        for (head_num, wafer) in wafers {
+            let message = format!(
+                "WIR of wafer {} on head {head_num} has no WRR",
+                wafer.wafer_id
+            );
+            self.issue_at(Severity::Error, wafer.location, message);
+        }
+        if let Some(location) = self.bps {
+            self.issue_at(
+                Severity::Warning,
+                location,
+                "BPS without an EPS".to_string(),
+            );
+        }
+        self.check_summary_counts();
+
+        self.issues
+            .sort_by_key(|issue| (issue.offset, issue.record_index));
+        ValidationReport {
+            n_records: self.n_records,
+            issues: self.issues,
+        }
+    }
+}
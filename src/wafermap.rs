@@ -0,0 +1,636 @@
+//! Wafer maps of bins or parametric test results
+//!
+//! `wafermaps` builds one `Wafermap` per wafer in `STDF.wafer_information`, which may then be
+//! rendered as a grid `DataFrame`, as text for the terminal, or as an SVG image with a legend.
+//!
+//! The maps are laid out as the wafer is viewed, using the orientation of the `WCR` if there is
+//! one, see `spatial::Orientation`. A die tested more than once shows its last `Row`.
+//!
+//! Bins are colored by the pass/fail field of their SBR or HBR, falling back to the `part_flg` of
+//! the dies in the bin when it is blank. Test results and failure rates are colored on a scale from
+//! the lowest to the highest value on the map.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    io,
+    str::FromStr,
+};
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::{data::STDF, spatial::Orientation};
+
+/// What each die of a `Wafermap` shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapLayer {
+    /// The soft bin
+    #[default]
+    SoftBin,
+    /// The hard bin
+    HardBin,
+    /// The result of the parametric test with this `test_num`
+    Test(u32),
+    /// 1 for failing dies and 0 for passing dies, or the failure rate of a composite map
+    FailRate,
+}
+
+impl fmt::Display for MapLayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SoftBin => write!(f, "sbin"),
+            Self::HardBin => write!(f, "hbin"),
+            Self::Test(test_num) => write!(f, "{test_num}"),
+            Self::FailRate => write!(f, "fail_rate"),
+        }
+    }
+}
+
+/// Parses a `MapLayer` from `sbin`, `hbin`, `fail_rate` or a `test_num`
+impl FromStr for MapLayer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sbin" => Ok(Self::SoftBin),
+            "hbin" => Ok(Self::HardBin),
+            "fail_rate" => Ok(Self::FailRate),
+            _ => s.parse().map(Self::Test).map_err(|_| {
+                format!(
+                    "unknown map layer {s}, expected one of sbin, hbin, fail_rate or a test_num"
+                )
+            }),
+        }
+    }
+}
+
+impl MapLayer {
+    /// Whether the layer shows bins rather than values
+    pub fn is_bins(&self) -> bool {
+        matches!(self, Self::SoftBin | Self::HardBin)
+    }
+}
+
+/// How a `Wafermap` is rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapFormat {
+    /// Text for the terminal, see `Wafermap::to_ascii`
+    #[default]
+    Ascii,
+    /// An SVG image, see `Wafermap::to_svg`
+    Svg,
+    /// A grid `DataFrame`, see `Wafermap::to_grid_df`
+    Grid,
+}
+
+impl MapFormat {
+    /// The file extension used for maps written in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ascii => "txt",
+            Self::Svg => "svg",
+            Self::Grid => "csv",
+        }
+    }
+}
+
+impl fmt::Display for MapFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ascii => write!(f, "ascii"),
+            Self::Svg => write!(f, "svg"),
+            Self::Grid => write!(f, "grid"),
+        }
+    }
+}
+
+/// Parses a `MapFormat` from `ascii`, `svg` or `grid`
+impl FromStr for MapFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Self::Ascii),
+            "svg" => Ok(Self::Svg),
+            "grid" => Ok(Self::Grid),
+            _ => Err(format!(
+                "unknown map format {s}, expected one of ascii, svg, grid"
+            )),
+        }
+    }
+}
+
+/// One die of a `Wafermap`
+#[derive(Debug, Clone)]
+pub struct Die {
+    /// The index of the part in `TestData.data`, `None` on composite maps
+    pub part_index: Option<usize>,
+    /// The soft or hard bin, the soft bin for other layers
+    pub bin: u16,
+    /// The test result or failure, `None` for a bin layer or where the test was not run
+    pub result: Option<f32>,
+}
+
+/// A bin appearing on a `Wafermap`
+#[derive(Debug, Clone)]
+pub struct MapBin {
+    /// The name from the SBR or HBR, empty if there is none
+    pub name: String,
+    /// Whether the bin is a passing bin
+    pub pass: bool,
+    /// The number of dies in the bin on this wafer
+    pub count: usize,
+}
+
+/// The map of one wafer
+#[derive(Debug, Clone)]
+pub struct Wafermap {
+    pub wafer_id: String,
+    /// The index of the `WIR` of the wafer in file order, see `WaferInformation.wafer_index`,
+    /// `None` on composite maps
+    pub wafer_index: Option<u32>,
+    pub head_num: u8,
+    pub layer: MapLayer,
+    pub orientation: Orientation,
+    /// The dies indexed by (`x_coord`, `y_coord`)
+    pub dies: BTreeMap<(i16, i16), Die>,
+    /// The bins of the dies, empty for other layers
+    pub bins: BTreeMap<u16, MapBin>,
+}
+
+/// The fill colors of passing bins, in order of bin number
+const PASS_COLORS: [&str; 4] = ["#2ca02c", "#98df8a", "#1b7837", "#a6d96a"];
+/// The fill colors of failing bins, in order of bin number
+const FAIL_COLORS: [&str; 8] = [
+    "#d62728", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#bcbd22", "#17becf", "#ff9896",
+];
+/// The color scale of test results, from lowest to highest
+const SCALE_COLORS: [(u8, u8, u8); 5] = [
+    (0x44, 0x01, 0x54),
+    (0x3b, 0x52, 0x8b),
+    (0x21, 0x91, 0x8c),
+    (0x5e, 0xc9, 0x62),
+    (0xfd, 0xe7, 0x25),
+];
+/// The fill color of dies without a result
+const MISSING_COLOR: &str = "#d9d9d9";
+/// The number of steps in the legend and text rendering of test results
+const SCALE_STEPS: usize = 10;
+
+/// Build the map of the `layer` of every wafer in `stdf`
+///
+/// # Error
+/// Returns an `std::io::Error` if the `layer` is a test that is not a parametric test of `stdf`
+pub fn wafermaps(stdf: &STDF, layer: MapLayer) -> io::Result<Vec<Wafermap>> {
+    let test_data = &stdf.test_data;
+    let test_index = match layer {
+        MapLayer::Test(test_num) => {
+            let index = test_data
+                .parametric_test_keys()
+                .iter()
+                .position(|test_key| test_key.test_num() == test_num);
+            let Some(index) = index else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("test {test_num} is not a parametric test"),
+                ));
+            };
+            Some(index)
+        }
+        _ => None,
+    };
+    let orientation = Orientation::from_wcr(stdf.wafer_configuration.as_ref());
+
+    let mut maps = Vec::new();
+    for wafer in &stdf.wafer_information {
+        let mut dies = BTreeMap::new();
+        for (part_index, row) in test_data.data.iter().enumerate() {
+            if row.wafer_index != Some(wafer.wafer_index) || !row.has_coords() {
+                continue;
+            }
+            let bin = match layer {
+                MapLayer::HardBin => row.hbin,
+                _ => row.sbin,
+            };
+            let result = match (layer, test_index) {
+                (MapLayer::FailRate, _) => Some(if row.pass() { 0. } else { 1. }),
+                (_, Some(i)) => {
+                    let result = row.results_parametric[i];
+                    (row.passed_parametric[i].is_some() && !result.is_nan()).then_some(result)
+                }
+                _ => None,
+            };
+            let die = Die {
+                part_index: Some(part_index),
+                bin,
+                result,
+            };
+            dies.insert((row.x_coord, row.y_coord), die);
+        }
+
+        let mut bins: BTreeMap<u16, MapBin> = BTreeMap::new();
+        if layer.is_bins() {
+            let mut counts: BTreeMap<u16, (usize, bool)> = BTreeMap::new();
+            for die in dies.values() {
+                let (count, any_pass) = counts.entry(die.bin).or_default();
+                *count += 1;
+                *any_pass |= die
+                    .part_index
+                    .is_some_and(|part_index| test_data.data[part_index].pass());
+            }
+            for (bin, (count, any_pass)) in counts {
+                let (name, pass) = bin_summary(stdf, layer, wafer.head_num, bin);
+                let pass = pass.unwrap_or(any_pass);
+                bins.insert(bin, MapBin { name, pass, count });
+            }
+        }
+
+        maps.push(Wafermap {
+            wafer_id: wafer.wafer_id.clone(),
+            wafer_index: Some(wafer.wafer_index),
+            head_num: wafer.head_num,
+            layer,
+            orientation,
+            dies,
+            bins,
+        });
+    }
+    Ok(maps)
+}
+
+/// The file name stem of each of `maps`, in order
+///
+/// A stem is the `wafer_id` with any path separators replaced by `_`, or `wafer_<slot>` if the
+/// `wafer_id` is empty, where the slot counts the maps from 1. A stem shared by more than one map
+/// gets the `wafer_index` of each as a suffix, e.g. `W01_3`, so no map overwrites another.
+pub fn file_stems(maps: &[&Wafermap]) -> Vec<String> {
+    let stems: Vec<String> = maps
+        .iter()
+        .enumerate()
+        .map(|(i, map)| match map.wafer_id.as_str() {
+            "" => format!("wafer_{}", i + 1),
+            wafer_id => wafer_id.replace(['/', '\\'], "_"),
+        })
+        .collect();
+    let counts = stems.iter().counts();
+    stems
+        .iter()
+        .zip(maps)
+        .enumerate()
+        .map(|(i, (stem, map))| match counts[stem] {
+            1 => stem.clone(),
+            _ => format!("{stem}_{}", map.wafer_index.unwrap_or(i as u32)),
+        })
+        .collect()
+}
+
+/// The name and pass/fail of a bin from its SBR or HBR, preferring the summary over all sites
+fn bin_summary(stdf: &STDF, layer: MapLayer, head_num: u8, bin: u16) -> (String, Option<bool>) {
+    let summaries: Vec<(u8, &str, char)> = match layer {
+        MapLayer::HardBin => stdf
+            .hard_bins
+            .values()
+            .filter(|hbr| hbr.hbin_num == bin)
+            .map(|hbr| (hbr.head_num, hbr.hbin_nam.as_str(), hbr.hbin_pf))
+            .collect(),
+        _ => stdf
+            .soft_bins
+            .values()
+            .filter(|sbr| sbr.sbin_num == bin)
+            .map(|sbr| (sbr.head_num, sbr.sbin_nam.as_str(), sbr.sbin_pf))
+            .collect(),
+    };
+    let summary = summaries
+        .iter()
+        .filter(|(head, _, pf)| (*head == 255 || *head == head_num) && matches!(pf, 'P' | 'F'))
+        .min_by_key(|(head, _, _)| *head != 255)
+        .or_else(|| summaries.iter().find(|(_, name, _)| !name.is_empty()));
+    match summary {
+        Some(&(_, name, pf)) => (
+            name.to_string(),
+            match pf {
+                'P' => Some(true),
+                'F' => Some(false),
+                _ => None,
+            },
+        ),
+        None => (String::new(), None),
+    }
+}
+
+impl Wafermap {
+    /// The `x_coord`s of the columns of the map, left to right
+    pub fn columns(&self) -> Vec<i16> {
+        let (Some(min), Some(max)) = (
+            self.dies.keys().map(|&(x, _)| x).min(),
+            self.dies.keys().map(|&(x, _)| x).max(),
+        ) else {
+            return Vec::new();
+        };
+        let mut columns: Vec<i16> = (min..=max).collect();
+        if !self.orientation.x_right {
+            columns.reverse();
+        }
+        columns
+    }
+
+    /// The `y_coord`s of the rows of the map, top to bottom
+    pub fn rows(&self) -> Vec<i16> {
+        let (Some(min), Some(max)) = (
+            self.dies.keys().map(|&(_, y)| y).min(),
+            self.dies.keys().map(|&(_, y)| y).max(),
+        ) else {
+            return Vec::new();
+        };
+        let mut rows: Vec<i16> = (min..=max).collect();
+        if !self.orientation.y_down {
+            rows.reverse();
+        }
+        rows
+    }
+
+    /// The lowest and highest test result on the map
+    fn result_range(&self) -> Option<(f32, f32)> {
+        let results = self.dies.values().filter_map(|die| die.result);
+        results.fold(None, |range, result| match range {
+            None => Some((result, result)),
+            Some((min, max)) => Some((min.min(result), max.max(result))),
+        })
+    }
+
+    /// The fill color of each bin on the map
+    fn bin_colors(&self) -> HashMap<u16, &'static str> {
+        let (mut n_pass, mut n_fail) = (0, 0);
+        let mut colors = HashMap::new();
+        for (&bin, map_bin) in &self.bins {
+            let color = if map_bin.pass {
+                n_pass += 1;
+                PASS_COLORS[(n_pass - 1) % PASS_COLORS.len()]
+            } else {
+                n_fail += 1;
+                FAIL_COLORS[(n_fail - 1) % FAIL_COLORS.len()]
+            };
+            colors.insert(bin, color);
+        }
+        colors
+    }
+
+    /// Convert the map into a `DataFrame` laid out as the wafer is viewed
+    ///
+    /// There is a `y_coord` column followed by one column per `x_coord`, named by the `x_coord`.
+    /// Each cell holds the bin, or the test result or failure for other layers, and is null where
+    /// there is no die.
+    pub fn to_grid_df(&self) -> DataFrame {
+        let rows = self.rows();
+        let mut columns = vec![Column::new("y_coord".into(), rows.clone())];
+        for x in self.columns() {
+            let dies = rows.iter().map(|&y| self.dies.get(&(x, y)));
+            let column = match self.layer {
+                MapLayer::Test(_) | MapLayer::FailRate => {
+                    let results: Vec<Option<f32>> =
+                        dies.map(|die| die.and_then(|die| die.result)).collect();
+                    Column::new(x.to_string().into(), results)
+                }
+                _ => {
+                    let bins: Vec<Option<u16>> = dies.map(|die| die.map(|die| die.bin)).collect();
+                    Column::new(x.to_string().into(), bins)
+                }
+            };
+            columns.push(column);
+        }
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Render the map as text, one line per row of dies, followed by a legend
+    ///
+    /// Dies show their bin, or the step of their test result on a scale of 0 to 9, with `-` for
+    /// dies without a result. With `color`, passing bins are printed in green and failing bins in
+    /// red using ANSI escape codes.
+    pub fn to_ascii(&self, color: bool) -> String {
+        let range = self.result_range();
+        let width = match self.layer {
+            MapLayer::Test(_) | MapLayer::FailRate => 1,
+            _ => self
+                .bins
+                .keys()
+                .map(|bin| bin.to_string().len())
+                .max()
+                .unwrap_or(1),
+        };
+        let rows = self.rows();
+        let y_width = rows.iter().map(|y| y.to_string().len()).max().unwrap_or(1);
+        let columns = self.columns();
+
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "wafer {} head {} {}: x {} to {} left to right, y {} to {} top to bottom",
+            self.wafer_id,
+            self.head_num,
+            self.layer_name(),
+            columns.first().unwrap_or(&0),
+            columns.last().unwrap_or(&0),
+            rows.first().unwrap_or(&0),
+            rows.last().unwrap_or(&0),
+        );
+        for &y in &rows {
+            let _ = write!(text, "{y:>y_width$} ");
+            for &x in &columns {
+                let cell = match (self.dies.get(&(x, y)), self.layer) {
+                    (None, _) => format!("{:width$}", ""),
+                    (Some(die), MapLayer::Test(_) | MapLayer::FailRate) => match die.result {
+                        Some(result) => scale_step(result, range).to_string(),
+                        None => "-".to_string(),
+                    },
+                    (Some(die), _) => {
+                        let cell = format!("{:>width$}", die.bin);
+                        let pass = self.bins.get(&die.bin).is_some_and(|bin| bin.pass);
+                        match (color, pass) {
+                            (false, _) => cell,
+                            (true, true) => format!("\x1b[32m{cell}\x1b[0m"),
+                            (true, false) => format!("\x1b[31m{cell}\x1b[0m"),
+                        }
+                    }
+                };
+                let _ = write!(text, " {cell}");
+            }
+            text.push('\n');
+        }
+
+        match (self.layer, range) {
+            (MapLayer::Test(_) | MapLayer::FailRate, Some((min, max))) => {
+                let step = (max - min) / SCALE_STEPS as f32;
+                for i in 0..SCALE_STEPS {
+                    let (low, high) = (min + step * i as f32, min + step * (i + 1) as f32);
+                    let _ = writeln!(text, "{i}: {low} to {high}");
+                }
+            }
+            (MapLayer::Test(_) | MapLayer::FailRate, None) => {
+                let _ = writeln!(text, "no results");
+            }
+            _ => {
+                for (bin, map_bin) in &self.bins {
+                    let _ = writeln!(text, "{}", bin_label(*bin, map_bin));
+                }
+            }
+        }
+        text
+    }
+
+    /// Render the map as an SVG image, with the bins or the color scale in a legend on the right
+    pub fn to_svg(&self) -> String {
+        const CELL: usize = 12;
+        const MARGIN: usize = 20;
+        const TITLE: usize = 30;
+        const LEGEND_ROW: usize = 18;
+        const LEGEND_WIDTH: usize = 260;
+
+        let rows = self.rows();
+        let columns = self.columns();
+        let map_width = columns.len() * CELL;
+        let map_height = rows.len() * CELL;
+        let bin_colors = self.bin_colors();
+        let range = self.result_range();
+
+        let mut legend: Vec<(String, String)> = Vec::new();
+        match (self.layer, range) {
+            (MapLayer::Test(_) | MapLayer::FailRate, Some((min, max))) => {
+                let step = (max - min) / SCALE_STEPS as f32;
+                for i in (0..SCALE_STEPS).rev() {
+                    let (low, high) = (min + step * i as f32, min + step * (i + 1) as f32);
+                    let color = scale_color(i as f32 / (SCALE_STEPS - 1) as f32);
+                    legend.push((color, format!("{low:.4} to {high:.4}")));
+                }
+                legend.push((MISSING_COLOR.to_string(), "no result".to_string()));
+            }
+            (MapLayer::Test(_) | MapLayer::FailRate, None) => {
+                legend.push((MISSING_COLOR.to_string(), "no result".to_string()));
+            }
+            _ => {
+                for (bin, map_bin) in &self.bins {
+                    legend.push((bin_colors[bin].to_string(), bin_label(*bin, map_bin)));
+                }
+            }
+        }
+
+        let width = MARGIN * 3 + map_width + LEGEND_WIDTH;
+        let height = TITLE + MARGIN * 2 + map_height.max(legend.len() * LEGEND_ROW);
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif" font-size="11">"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect width="{width}" height="{height}" fill="white"/>"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{MARGIN}" y="{}" font-size="14">{}</text>"#,
+            MARGIN + 4,
+            escape(&format!(
+                "wafer {} head {} {}",
+                self.wafer_id,
+                self.head_num,
+                self.layer_name()
+            )),
+        );
+
+        for (i, &y) in rows.iter().enumerate() {
+            for (j, &x) in columns.iter().enumerate() {
+                let Some(die) = self.dies.get(&(x, y)) else {
+                    continue;
+                };
+                let (fill, value) = match self.layer {
+                    MapLayer::Test(_) | MapLayer::FailRate => match die.result {
+                        Some(result) => (
+                            scale_color(
+                                scale_step(result, range) as f32 / (SCALE_STEPS - 1) as f32,
+                            ),
+                            result.to_string(),
+                        ),
+                        None => (MISSING_COLOR.to_string(), "no result".to_string()),
+                    },
+                    _ => (bin_colors[&die.bin].to_string(), format!("bin {}", die.bin)),
+                };
+                let _ = writeln!(
+                    svg,
+                    r##"<rect x="{}" y="{}" width="{CELL}" height="{CELL}" fill="{fill}" stroke="#ffffff" stroke-width="0.5"><title>x {x} y {y}: {value}</title></rect>"##,
+                    MARGIN + j * CELL,
+                    TITLE + MARGIN + i * CELL,
+                );
+            }
+        }
+
+        let legend_x = MARGIN * 2 + map_width;
+        for (i, (color, label)) in legend.iter().enumerate() {
+            let y = TITLE + MARGIN + i * LEGEND_ROW;
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{legend_x}" y="{y}" width="12" height="12" fill="{color}" stroke="#999999" stroke-width="0.5"/>"##
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}">{}</text>"#,
+                legend_x + 18,
+                y + 10,
+                escape(label),
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The name of the `layer` for titles
+    fn layer_name(&self) -> String {
+        match self.layer {
+            MapLayer::SoftBin => "soft bins".to_string(),
+            MapLayer::HardBin => "hard bins".to_string(),
+            MapLayer::Test(test_num) => format!("test {test_num}"),
+            MapLayer::FailRate => "fail rate".to_string(),
+        }
+    }
+}
+
+/// The legend label of a bin
+fn bin_label(bin: u16, map_bin: &MapBin) -> String {
+    let pf = if map_bin.pass { "pass" } else { "fail" };
+    let mut label = format!("bin {bin} {pf}");
+    if !map_bin.name.is_empty() {
+        let _ = write!(label, " {}", map_bin.name);
+    }
+    let _ = write!(label, " ({})", map_bin.count);
+    label
+}
+
+/// The step of the color scale of a test `result`, from 0 to `SCALE_STEPS - 1`, given the
+/// `Wafermap::result_range` of its map
+fn scale_step(result: f32, range: Option<(f32, f32)>) -> usize {
+    match range {
+        Some((min, max)) if max > min => {
+            let fraction = (result - min) / (max - min);
+            ((fraction * SCALE_STEPS as f32) as usize).min(SCALE_STEPS - 1)
+        }
+        _ => 0,
+    }
+}
+
+/// The color of the scale at `fraction` between 0 and 1, interpolating `SCALE_COLORS`
+fn scale_color(fraction: f32) -> String {
+    let position = fraction.clamp(0., 1.) * (SCALE_COLORS.len() - 1) as f32;
+    let i = (position as usize).min(SCALE_COLORS.len() - 2);
+    let t = position - i as f32;
+    let (r0, g0, b0) = SCALE_COLORS[i];
+    let (r1, g1, b1) = SCALE_COLORS[i + 1];
+    let mix = |c0: u8, c1: u8| (c0 as f32 + (c1 as f32 - c0 as f32) * t).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", mix(r0, r1), mix(g0, g1), mix(b0, b1))
+}
+
+/// Escape text for use in XML, e.g. SVG
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
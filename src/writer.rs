@@ -0,0 +1,172 @@
+//! Writing of STDF files
+//!
+//! Every concrete record can be encoded back into a `RawRecord` (e.g. `RawRecord::try_from(&ptr)`
+//! or `Record::to_raw_record`), which can be turned into bytes with `RawRecord::to_bytes`. Encoding
+//! an untouched record reproduces the bytes it was parsed from.
+//!
+//! `StdfWriter` writes those records out in a well-ordered file.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    record_types::RecordType,
+    records::{RawRecord, Records, records::*},
+};
+
+/// Writes records into a well-ordered STDF file
+///
+/// The `FAR` is written when the `StdfWriter` is created. After it, only `ATR`s may come before
+/// the `MIR`, and nothing may come after the `MRR`. Records that would break this order are
+/// rejected with a `std::io::ErrorKind::InvalidInput` error and nothing is written.
+pub struct StdfWriter<W: Write> {
+    writer: W,
+    mir_written: bool,
+    mrr_written: bool,
+}
+
+impl StdfWriter<BufWriter<File>> {
+    /// Create a new STDF file at `fname` and write the default `FAR` to it
+    ///
+    /// # Error
+    /// If the file cannot be created or written, returns a `std::io::Error`
+    pub fn from_fname(fname: &str) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(fname)?))
+    }
+}
+
+impl<W: Write> StdfWriter<W> {
+    /// Wrap `writer` and write the default `FAR`, for a little-endian STDF V4 file
+    ///
+    /// # Error
+    /// If the `FAR` cannot be written, returns a `std::io::Error`
+    pub fn new(writer: W) -> io::Result<Self> {
+        Self::with_far(writer, &FAR::default())
+    }
+
+    /// Wrap `writer` and write the given `far`
+    ///
+    /// Only little-endian files (`cpu_typ` 2) can be written.
+    ///
+    /// # Error
+    /// If the `far` is not little-endian STDF V4 or cannot be written, returns a
+    /// `std::io::Error`
+    pub fn with_far(mut writer: W, far: &FAR) -> io::Result<Self> {
+        if far.cpu_typ != 2 || far.stdf_ver != 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Can only write little-endian STDF V4, not cpu_typ {} stdf_ver {}",
+                    far.cpu_typ, far.stdf_ver
+                ),
+            ));
+        }
+        writer.write_all(&RawRecord::try_from(far)?.to_bytes())?;
+        Ok(Self {
+            writer,
+            mir_written: false,
+            mrr_written: false,
+        })
+    }
+
+    /// Encode and write a concrete `Record`
+    ///
+    /// # Error
+    /// If the record type is not implemented, is too long to encode, is out of order, or cannot be
+    /// written, returns a `std::io::Error`
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        match record.to_raw_record()? {
+            Some(raw_record) => self.write_raw_record(&raw_record),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Cannot encode the unimplemented record {record:?}"),
+            )),
+        }
+    }
+
+    /// Write a `RawRecord` as is
+    ///
+    /// Allows passing through record types that cannot be resolved, such as `ATR` or `GDR`.
+    ///
+    /// # Error
+    /// If the record is out of order or cannot be written, returns a `std::io::Error`
+    pub fn write_raw_record(&mut self, raw_record: &RawRecord) -> io::Result<()> {
+        self.check_order(raw_record.rtype)?;
+        self.writer.write_all(&raw_record.to_bytes())?;
+        match raw_record.rtype {
+            RecordType::MIR => self.mir_written = true,
+            RecordType::MRR => self.mrr_written = true,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Check that a record of type `rtype` may be written next
+    fn check_order(&self, rtype: RecordType) -> io::Result<()> {
+        let problem = match rtype {
+            _ if self.mrr_written => Some("no record may follow the MRR"),
+            RecordType::FAR => Some("the FAR is written when the StdfWriter is created"),
+            RecordType::InvalidRecord => Some("invalid records cannot be written"),
+            RecordType::ATR if self.mir_written => Some("ATRs must come before the MIR"),
+            RecordType::ATR => None,
+            RecordType::MIR if self.mir_written => Some("only one MIR may be written"),
+            RecordType::MIR => None,
+            _ if !self.mir_written => Some("the MIR must come before any other record"),
+            _ => None,
+        };
+        match problem {
+            Some(problem) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Cannot write {rtype:?}: {problem}"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush and return the underlying writer
+    ///
+    /// # Error
+    /// If no `MRR` has been written or the writer cannot be flushed, returns a `std::io::Error`
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.mrr_written {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot finish an STDF file without an MRR",
+            ));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Copy the STDF file at `fname` to `output`, re-encoding every record that can be resolved
+///
+/// Records that are not resolved, or cannot be decoded, are passed through as is. Since
+/// re-encoding an untouched record reproduces its original bytes, the copy is byte-for-byte
+/// identical to the original.
+///
+/// # Error
+/// If either file cannot be read or written, or the records in `fname` are out of order, returns
+/// a `std::io::Error`
+pub fn copy_fname(fname: &str, output: &str) -> io::Result<()> {
+    let mut records = Records::new(fname)?;
+    let far = match records.next().map(|raw_record| raw_record.try_resolve()) {
+        Some(Ok(Some(Record::FAR(far)))) => far,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{fname} does not start with a FAR"),
+            ));
+        }
+    };
+    let mut writer = StdfWriter::with_far(BufWriter::new(File::create(output)?), &far)?;
+    for raw_record in records {
+        match raw_record.try_resolve() {
+            Ok(Some(record)) => writer.write_record(&record)?,
+            Ok(None) | Err(_) => writer.write_raw_record(&raw_record)?,
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
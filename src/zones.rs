@@ -0,0 +1,380 @@
+//! Radial zones and reticle shots of the dies on a wafer
+//!
+//! Each die with coordinates is placed in a radial `Zone` by its distance from the wafer center as
+//! a fraction of the wafer radius, and in a reticle shot by the `Reticle` step and offset. Yield
+//! and parametric statistics may then be aggregated per zone or per position within the reticle.
+//!
+//! The wafer center, wafer size and die size come from the `WCR` if there is one, see
+//! `spatial::WaferGeometry`. Otherwise the center is the middle of the die coordinates and the
+//! radius reaches the outermost die.
+//!
+//! Every `Row` with die coordinates is counted, including retests of the same die.
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use polars::prelude::*;
+
+use crate::{
+    data::{Row, STDF},
+    spatial::WaferGeometry,
+    stats::TestStatistics,
+};
+
+/// The radial zone of a die
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Zone {
+    Center,
+    Middle,
+    Edge,
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Center => write!(f, "center"),
+            Self::Middle => write!(f, "middle"),
+            Self::Edge => write!(f, "edge"),
+        }
+    }
+}
+
+/// What the yield and statistics are aggregated by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZoneGroupBy {
+    /// The radial `Zone`
+    #[default]
+    Zone,
+    /// The position of the die within its reticle shot
+    Reticle,
+}
+
+impl fmt::Display for ZoneGroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Zone => write!(f, "zone"),
+            Self::Reticle => write!(f, "reticle"),
+        }
+    }
+}
+
+/// Parses a `ZoneGroupBy` from `zone` or `reticle`
+impl FromStr for ZoneGroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zone" => Ok(Self::Zone),
+            "reticle" => Ok(Self::Reticle),
+            _ => Err(format!(
+                "unknown zone grouping {s}, expected one of zone, reticle"
+            )),
+        }
+    }
+}
+
+/// The reticle shots, as a grid of `step_x` by `step_y` dies
+#[derive(Debug, Clone, Copy)]
+pub struct Reticle {
+    /// The number of dies per shot in x
+    pub step_x: i16,
+    /// The number of dies per shot in y
+    pub step_y: i16,
+    /// The `x_coord` of a die in the first column of a shot
+    pub offset_x: i16,
+    /// The `y_coord` of a die in the first row of a shot
+    pub offset_y: i16,
+}
+
+impl Default for Reticle {
+    fn default() -> Self {
+        Self {
+            step_x: 1,
+            step_y: 1,
+            offset_x: 0,
+            offset_y: 0,
+        }
+    }
+}
+
+impl Reticle {
+    /// The (column, row) of the shot of the die at (`x`, `y`)
+    pub fn shot(&self, x: i16, y: i16) -> (i16, i16) {
+        let (dx, dy, step_x, step_y) = self.steps(x, y);
+        (dx.div_euclid(step_x) as i16, dy.div_euclid(step_y) as i16)
+    }
+
+    /// The (column, row) of the die at (`x`, `y`) within its shot
+    pub fn position(&self, x: i16, y: i16) -> (i16, i16) {
+        let (dx, dy, step_x, step_y) = self.steps(x, y);
+        (dx.rem_euclid(step_x) as i16, dy.rem_euclid(step_y) as i16)
+    }
+
+    /// The offset of (`x`, `y`) from the first shot and the steps, widened to avoid overflow
+    fn steps(&self, x: i16, y: i16) -> (i32, i32, i32, i32) {
+        (
+            i32::from(x) - i32::from(self.offset_x),
+            i32::from(y) - i32::from(self.offset_y),
+            i32::from(self.step_x.max(1)),
+            i32::from(self.step_y.max(1)),
+        )
+    }
+}
+
+/// The zone radii and reticle of a wafer
+#[derive(Debug, Clone, Copy)]
+pub struct Zoning {
+    /// Dies at most this fraction of the wafer radius from the center are in the center zone
+    pub center_radius: f64,
+    /// Other dies at most this fraction of the wafer radius from the center are in the middle zone,
+    /// and the rest on the edge
+    pub middle_radius: f64,
+    pub reticle: Reticle,
+}
+
+impl Default for Zoning {
+    fn default() -> Self {
+        Self {
+            center_radius: 0.5,
+            middle_radius: 0.8,
+            reticle: Reticle::default(),
+        }
+    }
+}
+
+/// Where a die lies on the wafer
+#[derive(Debug, Clone, Copy)]
+pub struct DieLocation {
+    /// The distance from the wafer center as a fraction of the wafer radius
+    pub radius: f64,
+    pub zone: Zone,
+    /// The (column, row) of the reticle shot
+    pub shot: (i16, i16),
+    /// The (column, row) within the reticle shot
+    pub position: (i16, i16),
+}
+
+/// The group of a die, with the field not used by the `ZoneGroupBy` left at its default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Group {
+    zone: Option<Zone>,
+    position: (i16, i16),
+}
+
+impl Zoning {
+    /// The location of each `Row` of `stdf`, `None` for parts without die coordinates
+    pub fn locations(&self, stdf: &STDF) -> Vec<Option<DieLocation>> {
+        let geometry = WaferGeometry::from_wcr(stdf.wafer_configuration.as_ref());
+        let rows = &stdf.test_data.data;
+        let coords = rows.iter().filter(|row| row.has_coords());
+        let (center_x, center_y) = match geometry.center {
+            Some((x, y)) => (f64::from(x), f64::from(y)),
+            None => {
+                let (mut min_x, mut max_x, mut min_y, mut max_y) =
+                    (i16::MAX, i16::MIN, i16::MAX, i16::MIN);
+                for row in coords.clone() {
+                    min_x = min_x.min(row.x_coord);
+                    max_x = max_x.max(row.x_coord);
+                    min_y = min_y.min(row.y_coord);
+                    max_y = max_y.max(row.y_coord);
+                }
+                (
+                    (f64::from(min_x) + f64::from(max_x)) / 2.,
+                    (f64::from(min_y) + f64::from(max_y)) / 2.,
+                )
+            }
+        };
+        let distance = |row: &Row| {
+            let dx = (f64::from(row.x_coord) - center_x) * geometry.die_width;
+            let dy = (f64::from(row.y_coord) - center_y) * geometry.die_height;
+            dx.hypot(dy)
+        };
+        let wafer_radius = if geometry.diameter > 0. {
+            geometry.diameter / 2.
+        } else {
+            let half_die = geometry.die_width.hypot(geometry.die_height) / 2.;
+            coords.map(distance).fold(0., f64::max) + half_die
+        };
+
+        rows.iter()
+            .map(|row| {
+                if !row.has_coords() {
+                    return None;
+                }
+                let radius = distance(row) / wafer_radius;
+                let zone = if radius <= self.center_radius {
+                    Zone::Center
+                } else if radius <= self.middle_radius {
+                    Zone::Middle
+                } else {
+                    Zone::Edge
+                };
+                Some(DieLocation {
+                    radius,
+                    zone,
+                    shot: self.reticle.shot(row.x_coord, row.y_coord),
+                    position: self.reticle.position(row.x_coord, row.y_coord),
+                })
+            })
+            .collect()
+    }
+
+    /// The group of each `Row` of `stdf`, `None` for parts without die coordinates
+    fn groups(&self, stdf: &STDF, group_by: ZoneGroupBy) -> Vec<Option<Group>> {
+        self.locations(stdf)
+            .into_iter()
+            .map(|location| {
+                let location = location?;
+                Some(match group_by {
+                    ZoneGroupBy::Zone => Group {
+                        zone: Some(location.zone),
+                        position: (0, 0),
+                    },
+                    ZoneGroupBy::Reticle => Group {
+                        zone: None,
+                        position: location.position,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Convert the locations of the parts of `stdf` into a `DataFrame`, one row per part with
+    /// die coordinates
+    ///
+    /// The columns are `part_id`, `wafer_id`, `x_coord`, `y_coord`, `radius`, `zone`, `shot_x`,
+    /// `shot_y`, `reticle_x` and `reticle_y`.
+    pub fn locations_to_df(&self, stdf: &STDF) -> DataFrame {
+        let mut part_ids: Vec<&str> = Vec::new();
+        let mut wafer_ids: Vec<&str> = Vec::new();
+        let mut x_coords: Vec<i16> = Vec::new();
+        let mut y_coords: Vec<i16> = Vec::new();
+        let mut radii: Vec<f64> = Vec::new();
+        let mut zones: Vec<String> = Vec::new();
+        let mut shot_xs: Vec<i16> = Vec::new();
+        let mut shot_ys: Vec<i16> = Vec::new();
+        let mut reticle_xs: Vec<i16> = Vec::new();
+        let mut reticle_ys: Vec<i16> = Vec::new();
+        let locations = stdf.test_data.data.iter().zip(self.locations(stdf));
+        for (row, location) in locations {
+            let Some(location) = location else {
+                continue;
+            };
+            part_ids.push(&row.part_id);
+            wafer_ids.push(&row.wafer_id);
+            x_coords.push(row.x_coord);
+            y_coords.push(row.y_coord);
+            radii.push(location.radius);
+            zones.push(location.zone.to_string());
+            shot_xs.push(location.shot.0);
+            shot_ys.push(location.shot.1);
+            reticle_xs.push(location.position.0);
+            reticle_ys.push(location.position.1);
+        }
+        let columns = vec![
+            Column::new("part_id".into(), part_ids),
+            Column::new("wafer_id".into(), wafer_ids),
+            Column::new("x_coord".into(), x_coords),
+            Column::new("y_coord".into(), y_coords),
+            Column::new("radius".into(), radii),
+            Column::new("zone".into(), zones),
+            Column::new("shot_x".into(), shot_xs),
+            Column::new("shot_y".into(), shot_ys),
+            Column::new("reticle_x".into(), reticle_xs),
+            Column::new("reticle_y".into(), reticle_ys),
+        ];
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// The group columns of a `DataFrame` aggregated by `group_by`
+    fn group_columns(group_by: ZoneGroupBy, groups: &[Group]) -> Vec<Column> {
+        match group_by {
+            ZoneGroupBy::Zone => {
+                let zones: Vec<Option<String>> = groups
+                    .iter()
+                    .map(|group| group.zone.map(|zone| zone.to_string()))
+                    .collect();
+                vec![Column::new("zone".into(), zones)]
+            }
+            ZoneGroupBy::Reticle => {
+                let reticle_xs: Vec<i16> = groups.iter().map(|group| group.position.0).collect();
+                let reticle_ys: Vec<i16> = groups.iter().map(|group| group.position.1).collect();
+                vec![
+                    Column::new("reticle_x".into(), reticle_xs),
+                    Column::new("reticle_y".into(), reticle_ys),
+                ]
+            }
+        }
+    }
+
+    /// Compute the yield of the parts of `stdf` per zone or reticle position
+    ///
+    /// Has the group columns, `zone` or `reticle_x` and `reticle_y`, followed by `n_parts`,
+    /// `n_pass` and `yield`.
+    pub fn yield_to_df(&self, stdf: &STDF, group_by: ZoneGroupBy) -> DataFrame {
+        let mut counts: BTreeMap<Group, (u32, u32)> = BTreeMap::new();
+        let groups = stdf.test_data.data.iter().zip(self.groups(stdf, group_by));
+        for (row, group) in groups {
+            let Some(group) = group else {
+                continue;
+            };
+            let (n_parts, n_pass) = counts.entry(group).or_default();
+            *n_parts += 1;
+            *n_pass += u32::from(row.pass());
+        }
+
+        let groups: Vec<Group> = counts.keys().copied().collect();
+        let mut columns = Self::group_columns(group_by, &groups);
+        let n_parts: Vec<u32> = counts.values().map(|(n_parts, _)| *n_parts).collect();
+        let n_pass: Vec<u32> = counts.values().map(|(_, n_pass)| *n_pass).collect();
+        let yields: Vec<f64> = counts
+            .values()
+            .map(|&(n_parts, n_pass)| n_pass as f64 / n_parts as f64)
+            .collect();
+        columns.push(Column::new("n_parts".into(), n_parts));
+        columns.push(Column::new("n_pass".into(), n_pass));
+        columns.push(Column::new("yield".into(), yields));
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Compute the statistics of each parametric test of `stdf` per zone or reticle position
+    ///
+    /// Has the columns of `TestStatistics::to_df` without any percentiles, with the group columns
+    /// `zone` or `reticle_x` and `reticle_y`. Parts without die coordinates are left out.
+    pub fn statistics_to_df(&self, stdf: &STDF, group_by: ZoneGroupBy) -> DataFrame {
+        let statistics = TestStatistics {
+            percentiles: vec![],
+            ..Default::default()
+        };
+        statistics.to_df_by(&stdf.test_data, self.groups(stdf, group_by), |groups| {
+            Self::group_columns(group_by, groups)
+        })
+    }
+}
+
+/// Parse the dies per reticle shot in x and y, like `2x3`
+pub fn parse_reticle_step(s: &str) -> Result<(i16, i16), String> {
+    let parse = |n: &str| match n.trim().parse::<i16>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "invalid reticle step {s}, expected positive steps like 2x3"
+        )),
+    };
+    let (x, y) = s
+        .split_once('x')
+        .ok_or_else(|| format!("invalid reticle step {s}, expected a step like 2x3"))?;
+    Ok((parse(x)?, parse(y)?))
+}
+
+/// Parse the reticle offset `x_coord` and `y_coord`, like `0,1`
+pub fn parse_reticle_offset(s: &str) -> Result<(i16, i16), String> {
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<i16>()
+            .map_err(|e| format!("invalid reticle offset {s}: {e}"))
+    };
+    let (x, y) = s
+        .split_once(',')
+        .ok_or_else(|| format!("invalid reticle offset {s}, expected an offset like 0,1"))?;
+    Ok((parse(x)?, parse(y)?))
+}
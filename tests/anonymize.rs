@@ -0,0 +1,77 @@
+//! Redaction of identifying strings in STDF files
+mod common;
+
+use stupidf::{
+    anonymize::Anonymizer,
+    record_types::RecordType,
+    records::{RawRecord, records::Record},
+    validate::validate,
+};
+
+/// Anonymize the STDF file in `bytes`, returning the number of strings replaced and the new file
+fn anonymize(anonymizer: &Anonymizer, bytes: &[u8]) -> (usize, Vec<u8>) {
+    let mut output = Vec::new();
+    let n_replaced = anonymizer
+        .apply(common::raw_records(bytes).into_iter(), &mut output)
+        .unwrap();
+    (n_replaced, output)
+}
+
+fn records_of(bytes: &[u8], rtype: RecordType) -> Vec<RawRecord> {
+    common::raw_records(bytes)
+        .into_iter()
+        .filter(|raw_record| raw_record.rtype == rtype)
+        .collect()
+}
+
+#[test]
+fn default_fields_are_pseudonymized() {
+    let bytes = common::wafer().to_bytes().unwrap();
+    let anonymizer = Anonymizer::new(b"secret");
+    let (n_replaced, output) = anonymize(&anonymizer, &bytes);
+    // the lot_id, the wafer_id of the WIR and WRR, and the four part_ids
+    assert_eq!(n_replaced, 7);
+    assert!(
+        validate(common::raw_records(&output).into_iter())
+            .issues
+            .is_empty()
+    );
+
+    let Some(Record::MIR(mir)) = records_of(&output, RecordType::MIR)[0].resolve() else {
+        panic!("no MIR");
+    };
+    assert_eq!(mir.lot_id, anonymizer.pseudonym(b"LOT1"));
+    // the program name is kept
+    assert_eq!(mir.job_nam, "PROG");
+    assert_ne!(mir.lot_id, Anonymizer::new(b"other").pseudonym(b"LOT1"));
+    let wafer_ids: Vec<String> = common::raw_records(&output)
+        .iter()
+        .filter_map(|raw_record| match raw_record.resolve() {
+            Some(Record::WIR(wir)) => Some(wir.wafer_id),
+            Some(Record::WRR(wrr)) => Some(wrr.wafer_id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(wafer_ids, vec![anonymizer.pseudonym(b"W01"); 2]);
+}
+
+#[test]
+fn omitted_optional_fields_are_accepted() {
+    let bytes = common::omit_optional_fields(&common::wafer().to_bytes().unwrap());
+    let mut anonymizer = Anonymizer::new(b"secret");
+    let (n_replaced, output) = anonymize(&anonymizer, &bytes);
+    assert_eq!(n_replaced, 7);
+    assert!(validate(common::raw_records(&output).into_iter()).is_valid());
+
+    // records without a selected field are copied without being decoded
+    anonymizer.fields = vec!["MIR.lot_id".to_string()];
+    let (n_replaced, output) = anonymize(&anonymizer, &bytes);
+    assert_eq!(n_replaced, 1);
+    let prrs = |bytes: &[u8]| -> Vec<Vec<u8>> {
+        records_of(bytes, RecordType::PRR)
+            .iter()
+            .map(RawRecord::to_bytes)
+            .collect()
+    };
+    assert_eq!(prrs(&output), prrs(&bytes));
+}
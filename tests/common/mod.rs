@@ -0,0 +1,77 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::{env, fs, path::PathBuf};
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    record_types::RecordType,
+    records::{Header, RawRecord},
+};
+
+/// A fresh temporary directory for the test `name`
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("stupidf-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Split the bytes of an STDF file into its records
+pub fn raw_records(bytes: &[u8]) -> Vec<RawRecord> {
+    let mut reader = bytes;
+    let mut raw_records = Vec::new();
+    let mut offset = 0;
+    while let Ok(header) = Header::from_file(&mut reader) {
+        let raw_record = RawRecord::from_header(header, &mut reader, offset).unwrap();
+        offset += 4 + raw_record.contents.len();
+        raw_records.push(raw_record);
+    }
+    raw_records
+}
+
+/// Join records back into the bytes of an STDF file
+pub fn to_bytes(raw_records: &[RawRecord]) -> Vec<u8> {
+    raw_records
+        .iter()
+        .flat_map(|raw_record| raw_record.to_bytes())
+        .collect()
+}
+
+/// A small wafer of four parts on two sites, one of them failing test 100
+pub fn wafer() -> StdfBuilder {
+    StdfBuilder::new()
+        .lot_id("LOT1")
+        .job_nam("PROG")
+        .sites(&[1, 2])
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .functional_test(200, "FUNC")
+        .wafer("W01")
+        .part(Part::new(1).at(0, 0).result(100, 1.0).result(200, true))
+        .part(Part::new(2).at(1, 0).result(100, 1.05).result(200, true))
+        .part(Part::new(1).at(0, 1).result(100, 1.3).result(200, true))
+        .part(Part::new(2).at(1, 1).result(100, 0.95).result(200, true))
+}
+
+/// Drop the optional fields from the end of every `PRR` and the `MIR`, as some testers do
+///
+/// The `PRR`s lose their empty `part_txt` and `part_fix`, and the `MIR` every empty string after
+/// the `job_nam`.
+pub fn omit_optional_fields(bytes: &[u8]) -> Vec<u8> {
+    let raw_records: Vec<RawRecord> = raw_records(bytes)
+        .into_iter()
+        .map(|raw_record| {
+            let n_omitted = match raw_record.rtype {
+                RecordType::PRR => 2,
+                RecordType::MIR => 25,
+                _ => return raw_record,
+            };
+            let mut contents = raw_record.contents;
+            let new_len = contents.len() - n_omitted;
+            assert!(contents[new_len..].iter().all(|&byte| byte == 0));
+            contents.truncate(new_len);
+            RawRecord::new(raw_record.rtype, contents)
+        })
+        .collect();
+    to_bytes(&raw_records)
+}
@@ -0,0 +1,92 @@
+//! Composite wafer maps stacking the wafers of several STDF files
+mod common;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    composite::CompositeMap,
+    data::STDF,
+    wafermap::MapLayer,
+};
+
+/// Write a file with the single wafer `wafer_id` of `parts`, each with its test 100 result
+///
+/// Results outside of the limits 0.9 and 1.1 fail.
+fn wafer(wafer_id: &str, parts: Vec<Part>) -> STDF {
+    let mut builder = StdfBuilder::new()
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .soft_bin(1, "GOOD", true)
+        .soft_bin(2, "HIGH", false)
+        .soft_bin(3, "LOW", false)
+        .wafer(wafer_id);
+    for part in parts {
+        builder = builder.part(part);
+    }
+    let fname = common::temp_dir(&format!("composite-{wafer_id}")).join("lot.stdf");
+    let fname = fname.to_str().unwrap();
+    builder.write_fname(fname).unwrap();
+    STDF::from_fname(fname, false).unwrap()
+}
+
+#[test]
+fn two_wafers_are_stacked() {
+    let die = |x, y, result| Part::new(1).at(x, y).result(100, result);
+    let w01 = wafer(
+        "W01",
+        vec![
+            die(0, 0, 1.0).bins(1, 1),
+            die(1, 0, 1.5).bins(5, 2),
+            die(0, 1, 1.0).bins(1, 1),
+        ],
+    );
+    let w02 = wafer(
+        "W02",
+        vec![die(0, 0, 0.5).bins(6, 3), die(1, 0, 1.5).bins(5, 2)],
+    );
+    let composite = CompositeMap::new(&[&w01, &w02], Some(100)).unwrap();
+    assert_eq!(composite.n_wafers, 2);
+
+    // (0, 0) fails on one wafer, into a bin as common as its passing bin, so the lower wins
+    let df = composite.to_df();
+    let column = |name: &str| df.column(name).unwrap().as_materialized_series().clone();
+    let positions: Vec<(i16, i16)> = composite.dies.keys().copied().collect();
+    assert_eq!(positions, vec![(0, 0), (0, 1), (1, 0)]);
+    let u32s = |name: &str| column(name).u32().unwrap().into_no_null_iter().collect();
+    let n_wafers: Vec<u32> = u32s("n_wafers");
+    assert_eq!(n_wafers, vec![2, 1, 2]);
+    let n_fail: Vec<u32> = u32s("n_fail");
+    assert_eq!(n_fail, vec![1, 0, 2]);
+    let fail_rates: Vec<f64> = column("fail_rate")
+        .f64()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(fail_rates, vec![0.5, 0., 1.]);
+    let sbin_modes: Vec<Option<u16>> = column("sbin_mode").u16().unwrap().into_iter().collect();
+    assert_eq!(sbin_modes, vec![Some(1), Some(1), Some(2)]);
+    let hbin_modes: Vec<Option<u16>> = column("hbin_mode").u16().unwrap().into_iter().collect();
+    assert_eq!(hbin_modes, vec![Some(1), Some(1), Some(5)]);
+    let medians: Vec<Option<f64>> = column("median").f64().unwrap().into_iter().collect();
+    assert_eq!(medians, vec![Some(0.75), Some(1.0), Some(1.5)]);
+
+    let map = composite.to_wafermap(MapLayer::FailRate).unwrap();
+    assert_eq!(map.wafer_id, "composite of 2 wafers");
+    assert_eq!((map.wafer_index, map.head_num), (None, 255));
+    assert_eq!(map.dies[&(0, 0)].result, Some(0.5));
+
+    // the bins of the modes, named by the SBRs
+    let map = composite.to_wafermap(MapLayer::SoftBin).unwrap();
+    let bins: Vec<(u16, &str, bool, usize)> = map
+        .bins
+        .iter()
+        .map(|(&bin, map_bin)| (bin, map_bin.name.as_str(), map_bin.pass, map_bin.count))
+        .collect();
+    assert_eq!(bins, vec![(1, "GOOD", true, 2), (2, "HIGH", false, 1)]);
+
+    let map = composite.to_wafermap(MapLayer::Test(100)).unwrap();
+    assert_eq!(map.dies[&(0, 0)].result, Some(0.75));
+    assert!(composite.to_wafermap(MapLayer::Test(200)).is_err());
+
+    // without a test there are no medians
+    let composite = CompositeMap::new(&[&w01, &w02], None).unwrap();
+    assert_eq!(composite.to_df().column("median").unwrap().null_count(), 3);
+}
@@ -0,0 +1,141 @@
+//! Differences between the test programs of two STDF files
+mod common;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    diff::{ChangeKind, ProgramDiff},
+    records::records::MIR,
+    test_information::{FullTestInformation, TestIdentity, TestKey, TestType},
+};
+
+/// Write an old and a new revision of a test program, returning their names
+///
+/// Between the two, `job_rev` goes from 1 to 2, the low limit of test 100 from 0.9 to 0.8, the
+/// units of test 200 from mA to uA, and test 300 is renamed from VOL to VOH. Test 400 is removed
+/// and test 500 added.
+fn revisions(name: &str) -> (String, String) {
+    let dir = common::temp_dir(&format!("diff-{name}"));
+    let mir = |job_rev: &str| MIR {
+        job_rev: job_rev.to_string(),
+        ..Default::default()
+    };
+    let old = StdfBuilder::new()
+        .mir(mir("1"))
+        .job_nam("PROG")
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .parametric_test(200, "IDD", "mA", 1.0, 5.0)
+        .parametric_test(300, "VOL", "V", 0.0, 0.4)
+        .functional_test(400, "FUNC")
+        .part(
+            Part::new(1)
+                .result(100, 1.0)
+                .result(200, 2.0)
+                .result(300, 0.1)
+                .result(400, true),
+        );
+    let new = StdfBuilder::new()
+        .mir(mir("2"))
+        .job_nam("PROG")
+        .parametric_test(100, "VDD", "V", 0.8, 1.1)
+        .parametric_test(200, "IDD", "uA", 1.0, 5.0)
+        .parametric_test(300, "VOH", "V", 0.0, 0.4)
+        .functional_test(500, "SCAN")
+        .part(
+            Part::new(1)
+                .result(100, 1.0)
+                .result(200, 2.0)
+                .result(300, 0.1)
+                .result(500, true),
+        );
+    let write = |fname: &str, builder: StdfBuilder| {
+        let fname = dir.join(fname).to_string_lossy().into_owned();
+        builder.write_fname(&fname).unwrap();
+        fname
+    };
+    (write("old.stdf", old), write("new.stdf", new))
+}
+
+#[test]
+fn each_kind_of_change_is_found() {
+    let (old, new) = revisions("kinds");
+    let diff = ProgramDiff::from_fnames(&old, &new, TestIdentity::TestNum).unwrap();
+    let changes: Vec<(ChangeKind, Option<u32>, &str, &str, &str)> = diff
+        .changes
+        .iter()
+        .map(|change| {
+            (
+                change.kind,
+                change.test_key.as_ref().map(|test_key| test_key.test_num()),
+                change.field,
+                change.old.as_str(),
+                change.new.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (ChangeKind::Program, None, "job_rev", "1", "2"),
+            (ChangeKind::Limit, Some(100), "low_limit", "0.9", "0.8"),
+            (ChangeKind::Units, Some(200), "units", "mA", "uA"),
+            (ChangeKind::Renamed, Some(300), "test_name", "VOL", "VOH"),
+            (ChangeKind::Removed, Some(400), "test_name", "FUNC", ""),
+            (ChangeKind::Added, Some(500), "test_name", "", "SCAN"),
+        ]
+    );
+    assert_eq!(diff.n_changed_tests(), 3);
+    assert_eq!(diff.to_df().height(), 6);
+    assert!(
+        diff.to_string()
+            .ends_with("1 tests added, 1 removed, 3 changed")
+    );
+
+    // a file compared with itself has no changes
+    let diff = ProgramDiff::from_fnames(&old, &old, TestIdentity::TestNum).unwrap();
+    assert!(diff.is_empty());
+}
+
+#[test]
+fn renamed_tests_are_new_tests_when_told_apart_by_name() {
+    let (old, new) = revisions("identity");
+    let diff = ProgramDiff::from_fnames(&old, &new, TestIdentity::TestNumAndText).unwrap();
+    assert_eq!(diff.count(ChangeKind::Renamed), 0);
+    assert_eq!(diff.count(ChangeKind::Removed), 2);
+    assert_eq!(diff.count(ChangeKind::Added), 2);
+    let removed: Vec<String> = diff
+        .changes
+        .iter()
+        .filter(|change| change.kind == ChangeKind::Removed)
+        .map(|change| change.test_key.as_ref().unwrap().to_string())
+        .collect();
+    assert!(removed.iter().any(|test_key| test_key.contains("VOL")));
+}
+
+#[test]
+fn scale_and_test_type_changes() {
+    let (old, _) = revisions("scale");
+    let mir = MIR::from_fname(&old).unwrap();
+    let old_info = FullTestInformation::from_fname(&old, false)
+        .unwrap()
+        .merge();
+    let mut new_info = FullTestInformation::from_fname(&old, false)
+        .unwrap()
+        .merge();
+    let vdd = new_info.test_infos.get_mut(&TestKey::Num(100)).unwrap();
+    vdd.res_scal = 3;
+    let idd = new_info.test_infos.get_mut(&TestKey::Num(200)).unwrap();
+    idd.test_type = TestType::M;
+
+    let diff = ProgramDiff::new(&mir, &old_info, &mir, &new_info);
+    assert_eq!(diff.count(ChangeKind::Scale), 1);
+    assert_eq!(diff.count(ChangeKind::TestType), 1);
+    let fields: Vec<(&str, &str, &str)> = diff
+        .changes
+        .iter()
+        .map(|change| (change.field, change.old.as_str(), change.new.as_str()))
+        .collect();
+    assert_eq!(
+        fields,
+        vec![("res_scal", "0", "3"), ("test_type", "P", "M")]
+    );
+}
@@ -0,0 +1,198 @@
+//! Export of a parsed STDF to Parquet, Arrow IPC and CSV, read back with polars
+mod common;
+
+use std::{fs::File, io::Cursor, path::Path};
+
+use polars::prelude::*;
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::STDF,
+    export::{self, ExportFormat, METADATA_KEY_PREFIX, ParquetLayout},
+};
+
+/// Parse a file of two parts on sites 1 and 2, each running a parametric, a functional and a
+/// multi-pin test, and return it along with the directory to export it into
+fn lot(name: &str) -> (STDF, String) {
+    let dir = common::temp_dir(&format!("export-{name}"));
+    let fname = dir.join("lot.stdf");
+    let fname = fname.to_str().unwrap();
+    StdfBuilder::new()
+        .lot_id("LOT1")
+        .sites(&[1, 2])
+        .pin(1, "A")
+        .pin(2, "B")
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .functional_test(200, "FUNC")
+        .multi_pin_test(300, "IOL", "mA", 0.0, 1.0, &[1, 2])
+        .wafer("W01")
+        .part(
+            Part::new(1)
+                .at(0, 0)
+                .result(100, 1.0)
+                .result(200, true)
+                .result(300, vec![0.5, 0.75]),
+        )
+        .part(
+            Part::new(2)
+                .at(1, 0)
+                .result(100, 1.5)
+                .result(200, false)
+                .result(300, vec![0.25, 0.5]),
+        )
+        .write_fname(fname)
+        .unwrap();
+    let stdf = STDF::from_fname(fname, false).unwrap();
+    (stdf, dir.to_string_lossy().into_owned())
+}
+
+/// The names of the non-result tables, in the order they are written
+fn table_names(stdf: &STDF) -> Vec<&'static str> {
+    export::stdf_tables(stdf)
+        .into_iter()
+        .map(|(table, _)| table)
+        .collect()
+}
+
+#[test]
+fn parquet_with_tables_in_metadata() {
+    let (stdf, dir) = lot("parquet-metadata");
+    let fname = format!("{dir}/lot.parquet");
+    export::write(
+        &stdf,
+        &fname,
+        ExportFormat::Parquet,
+        ParquetLayout::Metadata,
+    )
+    .unwrap();
+
+    let mut reader = ParquetReader::new(File::open(&fname).unwrap());
+    let tables: Vec<(String, String)> = reader
+        .get_metadata()
+        .unwrap()
+        .key_value_metadata
+        .iter()
+        .flatten()
+        .filter_map(|key_value| {
+            let table = key_value.key.strip_prefix(METADATA_KEY_PREFIX)?;
+            Some((table.to_string(), key_value.value.clone()?))
+        })
+        .collect();
+    let df = reader.finish().unwrap();
+    let expected: DataFrame = (&stdf.test_data).into();
+    assert!(df.equals_missing(&expected), "{df}\n{expected}");
+
+    // one key per table, each a JSON array with one object per row
+    let names: Vec<&str> = tables.iter().map(|(table, _)| table.as_str()).collect();
+    assert_eq!(names, table_names(&stdf));
+    for ((table, json), (_, table_df)) in tables.iter().zip(export::stdf_tables(&stdf)) {
+        let read = JsonReader::new(Cursor::new(json)).finish().unwrap();
+        assert_eq!(read.shape(), table_df.shape(), "{table}");
+        assert_eq!(read.get_column_names(), table_df.get_column_names());
+    }
+    let master_information = JsonReader::new(Cursor::new(&tables[0].1)).finish().unwrap();
+    let lot_id = master_information.column("lot_id").unwrap().str().unwrap();
+    assert_eq!(lot_id.get(0), Some("LOT1"));
+
+    // no sidecar files are written
+    assert!(!Path::new(&format!("{dir}/lot.soft_bins.parquet")).exists());
+}
+
+#[test]
+fn parquet_with_tables_in_sidecars() {
+    let (stdf, dir) = lot("parquet-sidecar");
+    let fname = format!("{dir}/lot.parquet");
+    export::write(&stdf, &fname, ExportFormat::Parquet, ParquetLayout::Sidecar).unwrap();
+
+    let mut reader = ParquetReader::new(File::open(&fname).unwrap());
+    let metadata = reader.get_metadata().unwrap();
+    let mut keys = metadata.key_value_metadata.iter().flatten();
+    assert!(!keys.any(|key_value| key_value.key.starts_with(METADATA_KEY_PREFIX)));
+    let df = reader.finish().unwrap();
+    assert!(df.equals_missing(&(&stdf.test_data).into()));
+
+    for (table, table_df) in export::stdf_tables(&stdf) {
+        let table_fname = format!("{dir}/lot.{table}.parquet");
+        assert_eq!(
+            export::sidecar_fname(&fname, table, ExportFormat::Parquet),
+            table_fname
+        );
+        let read = ParquetReader::new(File::open(&table_fname).unwrap())
+            .finish()
+            .unwrap();
+        assert!(
+            read.equals_missing(&table_df),
+            "{table}: {read}\n{table_df}"
+        );
+    }
+}
+
+#[test]
+fn ipc_with_tables_in_sidecars() {
+    let (stdf, dir) = lot("ipc");
+    let fname = format!("{dir}/lot.arrow");
+    export::write(&stdf, &fname, ExportFormat::Ipc, ParquetLayout::Metadata).unwrap();
+
+    let df = IpcReader::new(File::open(&fname).unwrap())
+        .finish()
+        .unwrap();
+    assert!(df.equals_missing(&(&stdf.test_data).into()));
+    for (table, table_df) in export::stdf_tables(&stdf) {
+        let table_fname = export::sidecar_fname(&fname, table, ExportFormat::Ipc);
+        assert_eq!(table_fname, format!("{dir}/lot.{table}.arrow"));
+        let read = IpcReader::new(File::open(&table_fname).unwrap())
+            .finish()
+            .unwrap();
+        assert!(
+            read.equals_missing(&table_df),
+            "{table}: {read}\n{table_df}"
+        );
+    }
+}
+
+#[test]
+fn csv_with_flat_multi_pin_columns() {
+    let (stdf, dir) = lot("csv");
+    let fname = format!("{dir}/lot.csv");
+    export::write(&stdf, &fname, ExportFormat::Csv, ParquetLayout::Metadata).unwrap();
+    let read_csv = |fname: &str| {
+        CsvReadOptions::default()
+            .try_into_reader_with_file_path(Some(fname.into()))
+            .unwrap()
+            .finish()
+            .unwrap()
+    };
+
+    // the multi-pin test 300 is split into a column per pin
+    let df = read_csv(&fname);
+    let names: Vec<&str> = df
+        .get_column_names()
+        .iter()
+        .map(|name| name.as_str())
+        .collect();
+    assert_eq!(&names[10..], ["100", "200", "300[1]", "300[2]"]);
+    let pin = |name: &str| -> Vec<f64> {
+        let column = df.column(name).unwrap();
+        column.f64().unwrap().into_no_null_iter().collect()
+    };
+    assert_eq!(pin("300[1]"), vec![0.5, 0.25]);
+    assert_eq!(pin("300[2]"), vec![0.75, 0.5]);
+
+    for (table, table_df) in export::stdf_tables(&stdf) {
+        let table_fname = export::sidecar_fname(&fname, table, ExportFormat::Csv);
+        assert_eq!(table_fname, format!("{dir}/lot.{table}.csv"));
+        let read = read_csv(&table_fname);
+        assert_eq!(read.shape(), table_df.shape(), "{table}");
+        assert_eq!(read.get_column_names(), table_df.get_column_names());
+    }
+
+    // list columns are joined by ;
+    let site_information = stdf.site_information_to_df();
+    let list_column = site_information
+        .get_columns()
+        .iter()
+        .find(|column| matches!(column.dtype(), DataType::List(_)))
+        .unwrap();
+    let read = read_csv(&format!("{dir}/lot.site_information.csv"));
+    let joined = read.column(list_column.name()).unwrap().str().unwrap();
+    assert_eq!(joined.get(0), Some("1;2"));
+}
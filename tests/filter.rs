@@ -0,0 +1,70 @@
+//! Filtered rewriting of STDF files
+mod common;
+
+use stupidf::{
+    filter::StdfFilter,
+    record_types::RecordType,
+    records::{RawRecord, records::Record},
+    validate::validate,
+};
+
+/// Filter the STDF file in `bytes`, returning the number of parts kept and the new file
+fn filter(filter: &StdfFilter, bytes: &[u8]) -> (usize, Vec<u8>) {
+    let mut output = Vec::new();
+    let n_parts = filter
+        .apply(common::raw_records(bytes).into_iter(), &mut output)
+        .unwrap();
+    (n_parts, output)
+}
+
+fn prrs(bytes: &[u8]) -> Vec<RawRecord> {
+    common::raw_records(bytes)
+        .into_iter()
+        .filter(|raw_record| raw_record.rtype == RecordType::PRR)
+        .collect()
+}
+
+#[test]
+fn site_filter_recomputes_summaries() {
+    let bytes = common::wafer().to_bytes().unwrap();
+    let site_filter = StdfFilter {
+        site_nums: Some(vec![1]),
+        ..Default::default()
+    };
+    let (n_parts, output) = filter(&site_filter, &bytes);
+    assert_eq!(n_parts, 2);
+    let report = validate(common::raw_records(&output).into_iter());
+    assert!(report.issues.is_empty(), "{report}");
+
+    let pcr_counts: Vec<(u8, u32)> = common::raw_records(&output)
+        .iter()
+        .filter_map(|raw_record| match raw_record.resolve() {
+            Some(Record::PCR(pcr)) => Some((pcr.site_num, pcr.part_cnt)),
+            _ => None,
+        })
+        .collect();
+    assert!(pcr_counts.contains(&(1, 2)));
+    assert!(pcr_counts.iter().all(|(site_num, _)| *site_num != 2));
+}
+
+#[test]
+fn omitted_optional_fields_are_kept() {
+    let bytes = common::omit_optional_fields(&common::wafer().to_bytes().unwrap());
+    let site_filter = StdfFilter {
+        site_nums: Some(vec![1]),
+        ..Default::default()
+    };
+    let (n_parts, output) = filter(&site_filter, &bytes);
+    assert_eq!(n_parts, 2);
+    let report = validate(common::raw_records(&output).into_iter());
+    assert!(report.is_valid(), "{report}");
+
+    // the kept PRRs are not changed by the filter, so are passed through as they were
+    let kept: Vec<Vec<u8>> = prrs(&output).iter().map(RawRecord::to_bytes).collect();
+    let original: Vec<Vec<u8>> = prrs(&bytes)
+        .iter()
+        .filter(|raw_record| raw_record.contents[1] == 1)
+        .map(RawRecord::to_bytes)
+        .collect();
+    assert_eq!(kept, original);
+}
@@ -0,0 +1,93 @@
+//! The long `DataFrame` of test results, one row per part per executed test
+mod common;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::TestData,
+};
+
+#[test]
+fn one_row_per_part_test_and_pin() {
+    let fname = common::temp_dir("long").join("lot.stdf");
+    let fname = fname.to_str().unwrap();
+    StdfBuilder::new()
+        .pin(1, "A")
+        .pin(2, "B")
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .functional_test(200, "FUNC")
+        .multi_pin_test(300, "IOL", "mA", 0.0, 1.0, &[1, 2])
+        .wafer("W01")
+        .part(
+            Part::new(1)
+                .id("P1")
+                .at(0, 0)
+                .result(100, 1.0)
+                .result(200, true)
+                .result(300, vec![0.5, 0.75]),
+        )
+        .part(
+            Part::new(1)
+                .id("P2")
+                .at(1, 0)
+                .result(100, 1.5)
+                .result(200, false),
+        )
+        .write_fname(fname)
+        .unwrap();
+    let test_data = TestData::from_fname(fname, false).unwrap();
+    let df = test_data.to_long_df();
+
+    // the second part did not run test 300, so it has no rows for it
+    assert_eq!(df.height(), 6);
+    let column = |name: &str| df.column(name).unwrap().as_materialized_series().clone();
+    let strs = |name: &str| -> Vec<String> {
+        column(name)
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .map(str::to_string)
+            .collect()
+    };
+    let part_indexes: Vec<u32> = column("part_index")
+        .u32()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(part_indexes, vec![0, 0, 0, 0, 1, 1]);
+    assert_eq!(strs("part_id"), vec!["P1", "P1", "P1", "P1", "P2", "P2"]);
+    assert_eq!(strs("wafer_id"), vec!["W01"; 6]);
+    let x_coords: Vec<i16> = column("x_coord")
+        .i16()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(x_coords, vec![0, 0, 0, 0, 1, 1]);
+    let test_nums: Vec<u32> = column("test_num")
+        .u32()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(test_nums, vec![100, 200, 300, 300, 100, 200]);
+    assert_eq!(
+        strs("test_name"),
+        vec!["VDD", "FUNC", "IOL", "IOL", "VDD", "FUNC"]
+    );
+    let pins: Vec<Option<u16>> = column("pin").u16().unwrap().into_iter().collect();
+    assert_eq!(pins, vec![None, None, Some(1), Some(2), None, None]);
+    let values: Vec<Option<f32>> = column("value").f32().unwrap().into_iter().collect();
+    assert_eq!(
+        values,
+        vec![Some(1.0), None, Some(0.5), Some(0.75), Some(1.5), None]
+    );
+    let passed: Vec<bool> = column("passed")
+        .bool()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(passed, vec![true, true, true, true, false, false]);
+    // the units and limits come from the PTRs, the test information does not read the MPRs
+    let units = strs("units");
+    assert_eq!((units[0].as_str(), units[1].as_str()), ("V", ""));
+    let limit = |name: &str| column(name).f32().unwrap().get(0).unwrap();
+    assert_eq!((limit("lo_limit"), limit("hi_limit")), (0.9, 1.1));
+}
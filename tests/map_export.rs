@@ -0,0 +1,220 @@
+//! Export of wafer maps for inspection and inking tools
+mod common;
+
+use std::fs;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::STDF,
+    map_export::{MapExport, MapFileFormat, parse_bin_codes},
+};
+
+/// A wafer of three dies, the one at (0, 1) failing into soft bin 2 and hard bin 5
+///
+/// The passing dies at (0, 0) and (1, 0) are in soft and hard bin 1, and there is no die at
+/// (1, 1).
+fn wafer(name: &str) -> STDF {
+    let fname = common::temp_dir(&format!("map-export-{name}")).join("lot.stdf");
+    let fname = fname.to_str().unwrap();
+    StdfBuilder::new()
+        .lot_id("LOT1")
+        .part_typ("DEV")
+        .job_nam("PROG")
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .soft_bin(1, "GOOD", true)
+        .soft_bin(2, "VDD", false)
+        .hard_bin(1, "PASS", true)
+        .hard_bin(5, "FAIL", false)
+        .wafer("W01")
+        .part(Part::new(1).at(0, 0).bins(1, 1).result(100, 1.0))
+        .part(Part::new(1).at(1, 0).bins(1, 1).result(100, 1.05))
+        .part(Part::new(1).at(0, 1).bins(5, 2).result(100, 1.3))
+        .write_fname(fname)
+        .unwrap();
+    STDF::from_fname(fname, false).unwrap()
+}
+
+/// Render the only wafer of `stdf` with `export`
+fn render(stdf: &STDF, export: &MapExport) -> String {
+    let maps = export.wafermaps(stdf).unwrap();
+    assert_eq!(maps.len(), 1);
+    export.render(stdf, &maps[0], 1)
+}
+
+#[test]
+fn tsk_map() {
+    let stdf = wafer("tsk");
+    let text = render(&stdf, &MapExport::default());
+    assert_eq!(
+        text,
+        "WAFER_ID W01\n\
+         LOT_ID LOT1\n\
+         DEVICE DEV\n\
+         BIN_TYPE soft\n\
+         FLAT D\n\
+         DIE_SIZE 1 1\n\
+         ROWS 2\n\
+         COLUMNS 2\n\
+         FIRST_X 0\n\
+         FIRST_Y 0\n\
+         X_STEP 1\n\
+         Y_STEP 1\n\
+         NULL_CODE .\n\
+         BIN 1 1 pass 2 GOOD\n\
+         BIN 2 2 fail 1 VDD\n\
+         MAP\n\
+         11\n\
+         2.\n\
+         END\n"
+    );
+
+    let export = MapExport {
+        hard_bins: true,
+        ..Default::default()
+    };
+    let text = render(&stdf, &export);
+    assert!(text.contains("BIN_TYPE hard\n"), "{text}");
+    assert!(
+        text.contains("BIN 5 5 fail 1 FAIL\nMAP\n11\n5.\n"),
+        "{text}"
+    );
+}
+
+#[test]
+fn e142_map() {
+    let stdf = wafer("e142");
+    let export = MapExport {
+        format: MapFileFormat::E142,
+        ..Default::default()
+    };
+    let xml = render(&stdf, &export);
+    for line in [
+        "<Dimension X=\"2\" Y=\"2\"/>",
+        "<DeviceSize X=\"1\" Y=\"1\"/>",
+        "<Substrate SubstrateType=\"Wafer\" SubstrateId=\"W01\">",
+        "<LotId>LOT1</LotId>",
+        "<AliasId Type=\"PartType\" Value=\"DEV\"/>",
+        "AxisDirection=\"DownRight\">",
+        "<Overlay MapName=\"SoftBins\" MapVersion=\"1\">",
+        "<BinCodeMap BinType=\"ASCII\" NullBin=\".\">",
+        "<Coordinates X=\"0\" Y=\"0\"/>",
+        "<BinDefinition BinCode=\"1\" BinCount=\"2\" BinQuality=\"Pass\" \
+         BinDescription=\"bin 1 GOOD\"/>",
+        "<BinDefinition BinCode=\"2\" BinCount=\"1\" BinQuality=\"Fail\" \
+         BinDescription=\"bin 2 VDD\"/>",
+        "<BinCode>11</BinCode>\n          <BinCode>2.</BinCode>\n",
+    ] {
+        assert!(xml.contains(line), "{line} not in {xml}");
+    }
+}
+
+#[test]
+fn klarf_map() {
+    let stdf = wafer("klarf");
+    let export = MapExport {
+        format: MapFileFormat::Klarf,
+        ..Default::default()
+    };
+    let text = render(&stdf, &export);
+    // KLARF rows count up, so the die at (0, 1) is at index (0, -1)
+    for line in [
+        "LotID \"LOT1\";\n",
+        "DeviceID \"DEV\";\n",
+        "SetupID \"PROG\" 01-01-70 00:00:00;\n",
+        "DiePitch 1.000000e+03 1.000000e+03;\n",
+        "WaferID \"W01\";\nSlot 1;\n",
+        "SampleTestPlan 3\n 0 0\n 0 -1\n 1 0;\n",
+        "AreaPerTest 3.000000e+06;\n",
+        "DefectList\n 1 5.000000e+02 5.000000e+02 0 -1 1.000000e+03 1.000000e+03 1.000000e+06 \
+         1.000000e+03 2 1 2 2;\n",
+        "SummaryList\n 1 1 3.333333e+01 0 3.000000e+06;\n",
+    ] {
+        assert!(text.contains(line), "{line} not in {text}");
+    }
+    assert!(text.starts_with("FileVersion 1 2;\n"));
+    assert!(text.ends_with("EndOfFile;\n"));
+}
+
+#[test]
+fn bin_codes_are_mapped() {
+    let stdf = wafer("bin-codes");
+
+    // bins without a code fall back to the pass or fail code, then to the bin number
+    let export = MapExport {
+        bin_codes: parse_bin_codes("2=X").unwrap(),
+        pass_code: Some("P".to_string()),
+        null_code: "-".to_string(),
+        ..Default::default()
+    };
+    let text = render(&stdf, &export);
+    assert!(
+        text.contains("BIN 1 P pass 2 GOOD\nBIN 2 X fail 1 VDD\nMAP\nPP\nX-\n"),
+        "{text}"
+    );
+    let export = MapExport {
+        fail_code: Some("F".to_string()),
+        ..Default::default()
+    };
+    assert!(render(&stdf, &export).contains("MAP\n11\nF.\n"));
+
+    // codes of several characters are padded and separated, zero-padded for E142 if all numbers
+    let export = MapExport {
+        bin_codes: parse_bin_codes("2=20").unwrap(),
+        null_code: "0".to_string(),
+        ..Default::default()
+    };
+    assert!(render(&stdf, &export).contains("MAP\n 1  1\n20  0\n"));
+    let export = MapExport {
+        format: MapFileFormat::E142,
+        ..export
+    };
+    let xml = render(&stdf, &export);
+    assert!(xml.contains("<BinCodeMap BinType=\"Decimal\" NullBin=\"00\">"));
+    assert!(xml.contains("<BinCode>0101</BinCode>\n          <BinCode>2000</BinCode>\n"));
+    let export = MapExport {
+        null_code: ".".to_string(),
+        ..export
+    };
+    let xml = render(&stdf, &export);
+    assert!(xml.contains("<BinCodeMap BinType=\"ASCII\" NullBin=\"_.\">"));
+    assert!(xml.contains("<BinCode>_1_1</BinCode>\n          <BinCode>20_.</BinCode>\n"));
+
+    // a KLARF class is the bin code if it is a number, otherwise the bin
+    let export = MapExport {
+        format: MapFileFormat::Klarf,
+        ..export
+    };
+    assert!(render(&stdf, &export).contains(" 1.000000e+03 20 1 20 2;\n"));
+    let export = MapExport {
+        format: MapFileFormat::Klarf,
+        bin_codes: parse_bin_codes("2=X").unwrap(),
+        ..Default::default()
+    };
+    assert!(render(&stdf, &export).contains(" 1.000000e+03 2 1 2 2;\n"));
+
+    assert!(parse_bin_codes("1=A,2").is_err());
+    assert!(parse_bin_codes("1=A B").is_err());
+    assert!(parse_bin_codes("x=A").is_err());
+}
+
+#[test]
+fn one_file_per_wafer() {
+    let stdf = wafer("files");
+    let dir = common::temp_dir("map-export-files-out");
+    for format in [
+        MapFileFormat::Klarf,
+        MapFileFormat::E142,
+        MapFileFormat::Tsk,
+    ] {
+        let export = MapExport {
+            format,
+            ..Default::default()
+        };
+        let paths = export.write(&stdf, dir.to_str().unwrap()).unwrap();
+        assert_eq!(paths, vec![dir.join(format!("W01.{}", format.extension()))]);
+        assert_eq!(
+            fs::read_to_string(&paths[0]).unwrap(),
+            render(&stdf, &export)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Merging of several STDF files into one dataset
+mod common;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    merge::{Conflict, MergedStdf},
+    test_information::{ColumnNaming, TestIdentity, TestKey, TestType},
+};
+
+/// Write the two files of a lot, returning their names
+///
+/// `a.stdf` has wafer W01 with tests 100 and 200, `b.stdf` wafer W02 with tests 100 and 300. The
+/// limits of test 100 are 0.9 and 1.1 in `a.stdf` but 0.8 and 1.2 in `b.stdf`.
+fn lot(name: &str) -> Vec<String> {
+    let dir = common::temp_dir(&format!("merge-{name}"));
+    let a = StdfBuilder::new()
+        .sites(&[1])
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .parametric_test(200, "IDD", "mA", 1.0, 5.0)
+        .wafer("W01")
+        .part(Part::new(1).at(0, 0).result(100, 1.0).result(200, 2.0))
+        .part(Part::new(1).at(1, 0).result(100, 1.05).result(200, 3.0));
+    let b = StdfBuilder::new()
+        .sites(&[1])
+        .parametric_test(100, "VDD", "V", 0.8, 1.2)
+        .parametric_test(300, "VOL", "V", 0.0, 0.4)
+        .wafer("W02")
+        .part(Part::new(1).at(0, 0).result(100, 0.85).result(300, 0.1))
+        .part(Part::new(1).at(1, 0).result(100, 0.95).result(300, 0.2));
+    [("a.stdf", a), ("b.stdf", b)]
+        .into_iter()
+        .map(|(fname, builder)| {
+            let fname = dir.join(fname).to_string_lossy().into_owned();
+            builder.write_fname(&fname).unwrap();
+            fname
+        })
+        .collect()
+}
+
+#[test]
+fn tests_are_aligned_by_test_key() {
+    let fnames = lot("aligned");
+    let merged = MergedStdf::from_fnames(&fnames, TestIdentity::TestNum, 2).unwrap();
+    assert_eq!(merged.sources, vec![0, 0, 1, 1]);
+    let wafers: Vec<(u32, &str)> = merged
+        .wafer_information
+        .iter()
+        .map(|wafer| (wafer.wafer_index, wafer.wafer_id.as_str()))
+        .collect();
+    assert_eq!(wafers.len(), 2);
+    assert_ne!(wafers[0].0, wafers[1].0);
+    let wafer_indexes: Vec<Option<u32>> = merged
+        .test_data
+        .data
+        .iter()
+        .map(|row| row.wafer_index)
+        .collect();
+    assert_eq!(
+        wafer_indexes,
+        vec![
+            Some(wafers[0].0),
+            Some(wafers[0].0),
+            Some(wafers[1].0),
+            Some(wafers[1].0)
+        ]
+    );
+
+    let df = merged.to_df(&ColumnNaming::Number, false);
+    let source_files: Vec<&str> = df
+        .column("source_file")
+        .unwrap()
+        .str()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(
+        source_files,
+        vec![&fnames[0], &fnames[0], &fnames[1], &fnames[1]]
+    );
+    let results = |name: &str| -> Vec<f32> {
+        df.column(name)
+            .unwrap()
+            .f32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    };
+    // every test gets a single column, unexecuted in the parts of the file without it
+    assert_eq!(results("100"), vec![1.0, 1.05, 0.85, 0.95]);
+    let idd = results("200");
+    assert_eq!(&idd[..2], &[2.0, 3.0]);
+    assert!(idd[2..].iter().all(|result| result.is_nan()));
+    let vol = results("300");
+    assert!(vol[..2].iter().all(|result| result.is_nan()));
+    assert_eq!(&vol[2..], &[0.1, 0.2]);
+    let passed: Vec<Option<bool>> = merged
+        .test_data
+        .data
+        .iter()
+        .map(|row| row.passed_parametric[2])
+        .collect();
+    assert_eq!(passed, vec![None, None, Some(true), Some(true)]);
+}
+
+#[test]
+fn differing_metadata_is_a_conflict() {
+    let fnames = lot("conflicts");
+    let merged = MergedStdf::from_fnames(&fnames, TestIdentity::TestNum, 1).unwrap();
+    let conflict = |field, first_value: &str, value: &str| Conflict {
+        test_key: TestKey::Num(100),
+        field,
+        first_file: fnames[0].clone(),
+        first_value: first_value.to_string(),
+        file: fnames[1].clone(),
+        value: value.to_string(),
+    };
+    assert_eq!(
+        merged.conflicts,
+        vec![
+            conflict("low_limit", "0.9", "0.8"),
+            conflict("high_limit", "1.1", "1.2")
+        ]
+    );
+    // the metadata comes from the first file with the test
+    let mti = &merged.test_data.test_information.test_infos[&TestKey::Num(100)];
+    assert_eq!((mti.low_limit, mti.high_limit), (0.9, 1.1));
+    assert_eq!(merged.conflicts_to_df().height(), 2);
+
+    // a file merged with itself has no conflicts
+    let fnames = vec![fnames[0].clone(), fnames[0].clone()];
+    let merged = MergedStdf::from_fnames(&fnames, TestIdentity::TestNum, 1).unwrap();
+    assert!(merged.conflicts.is_empty());
+    assert_eq!(merged.test_data.data.len(), 4);
+}
+
+#[test]
+fn a_test_changing_type_is_kept_apart() {
+    let dir = common::temp_dir("merge-test-type");
+    let parametric = StdfBuilder::new()
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .wafer("W01")
+        .part(Part::new(1).at(0, 0).result(100, 1.0));
+    let functional = StdfBuilder::new()
+        .functional_test(100, "VDD")
+        .wafer("W02")
+        .part(Part::new(1).at(0, 0).result(100, false));
+    let fnames: Vec<String> = [("p.stdf", parametric), ("f.stdf", functional)]
+        .into_iter()
+        .map(|(fname, builder)| {
+            let fname = dir.join(fname).to_string_lossy().into_owned();
+            builder.write_fname(&fname).unwrap();
+            fname
+        })
+        .collect();
+
+    let merged = MergedStdf::from_fnames(&fnames, TestIdentity::TestNum, 1).unwrap();
+    assert_eq!(
+        merged.conflicts,
+        vec![Conflict {
+            test_key: TestKey::Num(100),
+            field: "test_type",
+            first_file: fnames[0].clone(),
+            first_value: "P".to_string(),
+            file: fnames[1].clone(),
+            value: "F".to_string(),
+        }]
+    );
+    let test_infos = &merged.test_data.test_information.test_infos;
+    assert_eq!(test_infos[&TestKey::Num(100)].test_type, TestType::P);
+    assert_eq!(
+        test_infos[&TestKey::NumType(100, TestType::F)].test_type,
+        TestType::F
+    );
+
+    let df = merged.to_df(&ColumnNaming::Number, false);
+    let parametric: Vec<Option<f32>> = df
+        .column("100")
+        .unwrap()
+        .f32()
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(parametric[0], Some(1.0));
+    assert!(parametric[1].unwrap().is_nan());
+    let passed: Vec<Option<bool>> = merged
+        .test_data
+        .data
+        .iter()
+        .map(|row| row.passed_functional[0])
+        .collect();
+    assert_eq!(passed, vec![None, Some(false)]);
+    assert!(df.column("100:F").is_ok());
+}
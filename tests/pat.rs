@@ -0,0 +1,141 @@
+//! Part Average Testing limits and outliers
+mod common;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::TestData,
+    pat::{Pat, PatMode},
+    stats::Estimator,
+};
+
+/// Two wafers of ten parts passing test 100, whose limits are 0 and 10
+///
+/// The results of W01 are 1.0 to 1.8 in steps of 0.1 and 5.0, and of W02 3.0 to 3.8 and 9.0.
+/// The file is written for the test `name`.
+fn lot(name: &str, wafers: &[&str]) -> TestData {
+    let mut builder = StdfBuilder::new()
+        .sites(&[1])
+        .parametric_test(100, "VDD", "V", 0.0, 10.0);
+    for wafer_id in wafers {
+        let (start, outlier) = match *wafer_id {
+            "W01" => (1.0, 5.0),
+            _ => (3.0, 9.0),
+        };
+        builder = builder.wafer(wafer_id);
+        let results = (0..9).map(|i| start + 0.1 * i as f32).chain([outlier]);
+        for (x, result) in results.enumerate() {
+            builder = builder.part(Part::new(1).at(x as i16, 0).result(100, result));
+        }
+    }
+    let fname = common::temp_dir(&format!("pat-{name}")).join("lot.stdf");
+    let fname = fname.to_str().unwrap();
+    builder.write_fname(fname).unwrap();
+    TestData::from_fname(fname, false).unwrap()
+}
+
+fn pat(mode: PatMode) -> Pat {
+    Pat {
+        mode,
+        k: 3.,
+        min_parts: 5,
+        ..Default::default()
+    }
+}
+
+fn assert_close(value: f64, expected: f64) {
+    assert!(
+        (value - expected).abs() < 1e-4,
+        "expected {expected}, got {value}"
+    );
+}
+
+#[test]
+fn dynamic_wafer_limits() {
+    let test_data = lot("dynamic-wafer", &["W01", "W02"]);
+    let report = pat(PatMode::DynamicWafer).run(&test_data, None);
+    assert_eq!(report.limits.len(), 2);
+    // the quartiles of W01 are 1.225 and 1.675, so sigma is 0.45 / 1.35
+    let (w01, w02) = (&report.limits[0], &report.limits[1]);
+    assert_eq!((w01.wafer_id.as_str(), w01.n), ("W01", 10));
+    assert_close(w01.center, 1.45);
+    assert_close(w01.sigma, 1. / 3.);
+    assert_close(w01.lo_limit, 0.45);
+    assert_close(w01.hi_limit, 2.45);
+    assert_eq!(w02.wafer_id, "W02");
+    assert_close(w02.center, 3.45);
+
+    let outliers: Vec<(&str, f32)> = report
+        .outliers
+        .iter()
+        .map(|outlier| (outlier.wafer_id.as_str(), outlier.result))
+        .collect();
+    assert_eq!(outliers, vec![("W01", 5.0), ("W02", 9.0)]);
+}
+
+#[test]
+fn dynamic_lot_limits_are_narrowed_to_the_test_limits() {
+    let test_data = lot("dynamic-lot", &["W01", "W02"]);
+    let report = pat(PatMode::DynamicLot).run(&test_data, None);
+    assert_eq!(report.limits.len(), 1);
+    let limit = &report.limits[0];
+    assert_eq!((limit.wafer_index, limit.n), (None, 20));
+    // the median is 3.05 and the quartiles 1.475 and 3.525
+    assert_close(limit.center, 3.05);
+    assert_close(limit.sigma, 2.05 / 1.35);
+    assert_close(limit.lo_limit, 0.);
+    assert_close(limit.hi_limit, 3.05 + 3. * 2.05 / 1.35);
+    assert_eq!(report.outliers.len(), 1);
+    assert_eq!(report.outliers[0].result, 9.0);
+
+    let wide = Pat {
+        k: 100.,
+        ..pat(PatMode::DynamicLot)
+    };
+    let report = wide.run(&test_data, None);
+    assert_eq!(
+        (report.limits[0].lo_limit, report.limits[0].hi_limit),
+        (0., 10.)
+    );
+    assert!(report.outliers.is_empty());
+}
+
+#[test]
+fn static_limits_come_from_the_reference() {
+    let reference = lot("static-reference", &["W01"]);
+    let test_data = lot("static", &["W01", "W02"]);
+    let report = pat(PatMode::Static).run(&test_data, Some(&reference));
+    assert_eq!(report.limits.len(), 1);
+    assert_close(report.limits[0].hi_limit, 2.45);
+    // the outlier of W01 and every part of W02
+    assert_eq!(report.outliers.len(), 11);
+
+    let classic = Pat {
+        estimator: Estimator::Classic,
+        ..pat(PatMode::Static)
+    };
+    let report = classic.run(&reference, None);
+    let mean = ((0..9).map(|i| 1.0 + 0.1 * i as f64).sum::<f64>() + 5.0) / 10.;
+    assert_close(report.limits[0].center, mean);
+
+    // too few parts for any limits
+    let report = Pat::default().run(&test_data, None);
+    assert!(report.limits.is_empty() && report.outliers.is_empty());
+}
+
+#[test]
+fn outliers_are_rebinned() {
+    let mut test_data = lot("rebin", &["W01", "W02"]);
+    let pat = Pat {
+        pat_sbin: Some(90),
+        pat_hbin: Some(9),
+        ..pat(PatMode::DynamicWafer)
+    };
+    let report = pat.run(&test_data, None);
+    assert_eq!(pat.rebin(&mut test_data, &report), 2);
+    for outlier in &report.outliers {
+        let row = &test_data.data[outlier.part_index];
+        assert!(!row.pass());
+        assert_eq!((row.sbin, row.hbin), (90, 9));
+    }
+    assert_eq!(test_data.data.iter().filter(|row| row.pass()).count(), 18);
+}
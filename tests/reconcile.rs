@@ -0,0 +1,136 @@
+//! Reconciliation of the summary records with the parts and tests in an STDF file
+mod common;
+
+use std::fs;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::STDF,
+    reconcile::{Mismatch, reconcile},
+    records::{RawRecord, records::Record},
+    test_information::TestIdentity,
+};
+
+/// Write `raw_records` to a file of the test `name`, returning its name
+fn write_file(name: &str, raw_records: &[RawRecord]) -> String {
+    let fname = common::temp_dir(&format!("reconcile-{name}")).join("lot.stdf");
+    fs::write(&fname, common::to_bytes(raw_records)).unwrap();
+    fname.to_string_lossy().into_owned()
+}
+
+/// A `Mismatch` of the `record` at `(head_num, site_num)`
+fn mismatch(
+    record: &'static str,
+    (head_num, site_num): (u8, u8),
+    key: &str,
+    field: &'static str,
+    summary: Option<u32>,
+    actual: u32,
+) -> Mismatch {
+    Mismatch {
+        record,
+        head_num,
+        site_num,
+        key: key.to_string(),
+        field,
+        summary,
+        actual,
+    }
+}
+
+#[test]
+fn built_file_reconciles() {
+    let bytes = common::wafer().to_bytes().unwrap();
+    let fname = write_file("built", &common::raw_records(&bytes));
+    let stdf = STDF::from_fname(&fname, false).unwrap();
+    assert!(reconcile(&stdf).is_empty());
+    assert_eq!(stdf.reconciliation_to_df().height(), 0);
+}
+
+#[test]
+fn wrong_and_missing_counts_are_listed() {
+    // of the four parts, the one on site 1 failing test 100 falls into bin 0
+    let bytes = common::wafer().to_bytes().unwrap();
+    let raw_records: Vec<RawRecord> = common::raw_records(&bytes)
+        .into_iter()
+        .filter_map(|raw_record| match raw_record.resolve() {
+            Some(Record::PCR(mut pcr)) if pcr.head_num == 255 => {
+                pcr.part_cnt += 1;
+                Some(RawRecord::try_from(&pcr).unwrap())
+            }
+            Some(Record::PCR(mut pcr)) if pcr.site_num == 2 => {
+                pcr.good_cnt = u32::MAX;
+                Some(RawRecord::try_from(&pcr).unwrap())
+            }
+            Some(Record::HBR(mut hbr)) if hbr.site_num == 1 && hbr.hbin_num == 1 => {
+                hbr.hbin_cnt += 2;
+                Some(RawRecord::try_from(&hbr).unwrap())
+            }
+            Some(Record::SBR(sbr)) if sbr.head_num == 255 && sbr.sbin_num == 0 => None,
+            Some(Record::TSR(mut tsr)) if tsr.head_num == 255 && tsr.test_num == 200 => {
+                tsr.fail_cnt = 1;
+                tsr.exec_cnt = u32::MAX;
+                Some(RawRecord::try_from(&tsr).unwrap())
+            }
+            Some(Record::TSR(mut tsr)) if tsr.site_num == 1 && tsr.test_num == 100 => {
+                tsr.exec_cnt += 1;
+                Some(RawRecord::try_from(&tsr).unwrap())
+            }
+            _ => Some(raw_record),
+        })
+        .collect();
+    let fname = write_file("counts", &raw_records);
+    let stdf = STDF::from_fname(&fname, false).unwrap();
+
+    // the good_cnt left out of the PCR of site 2 and the exec_cnt of test 200 are not compared,
+    // while the bin 0 of the failing part has lost its SBR over all sites
+    assert_eq!(
+        reconcile(&stdf),
+        vec![
+            mismatch("HBR", (1, 1), "1", "hbin_cnt", Some(3), 1),
+            mismatch("PCR", (255, 255), "", "part_cnt", Some(5), 4),
+            mismatch("SBR", (255, 255), "0", "sbin_cnt", None, 1),
+            mismatch("TSR", (1, 1), "100", "exec_cnt", Some(3), 2),
+            mismatch("TSR", (255, 255), "200", "fail_cnt", Some(1), 0),
+        ]
+    );
+    let df = stdf.reconciliation_to_df();
+    assert_eq!(df.height(), 5);
+    assert_eq!(df.column("summary").unwrap().null_count(), 1);
+}
+
+#[test]
+fn shared_test_keys_are_not_compared() {
+    // tests 100 and 101 both become test 100, told apart only by their test_txt
+    let bytes = StdfBuilder::new()
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .parametric_test(101, "IDD", "mA", 1.0, 5.0)
+        .part(Part::new(1).result(100, 1.0).result(101, 2.0))
+        .part(Part::new(1).result(100, 1.3))
+        .to_bytes()
+        .unwrap();
+    let raw_records: Vec<RawRecord> = common::raw_records(&bytes)
+        .into_iter()
+        .map(|raw_record| match raw_record.resolve() {
+            Some(Record::PTR(mut ptr)) if ptr.test_num == 101 => {
+                ptr.test_num = 100;
+                RawRecord::try_from(&ptr).unwrap()
+            }
+            Some(Record::TSR(mut tsr)) if tsr.test_num == 101 => {
+                tsr.test_num = 100;
+                RawRecord::try_from(&tsr).unwrap()
+            }
+            _ => raw_record,
+        })
+        .collect();
+    let fname = write_file("shared", &raw_records);
+
+    // one column holds both tests, so neither TSR matches its counts, but neither is compared
+    let stdf = STDF::from_fname_with_identity(&fname, false, TestIdentity::TestNum).unwrap();
+    assert!(reconcile(&stdf).is_empty());
+
+    // told apart by name, each TSR has its own test key and matches its counts
+    let stdf = STDF::from_fname_with_identity(&fname, false, TestIdentity::TestNumAndText).unwrap();
+    assert!(reconcile(&stdf).is_empty());
+    assert_eq!(stdf.test_data.test_counts().len(), 4);
+}
@@ -0,0 +1,251 @@
+//! Re-encoding an untouched record reproduces the bytes it was parsed from
+mod common;
+
+use std::{env, fs, io};
+
+use stupidf::{
+    generate::GeneratorConfig,
+    record_types::RecordType,
+    records::{
+        RawRecord, Records,
+        records::{FTR, MIR, MPR, MRR, PTR, Record},
+    },
+    writer::{StdfWriter, copy_fname},
+};
+
+#[test]
+fn generated_file_roundtrips() {
+    let dir = env::temp_dir().join(format!("stupidf-roundtrip-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let fname = dir.join("lot.stdf");
+    let config = GeneratorConfig {
+        n_wafers: 2,
+        n_dies: 50,
+        n_sites: 2,
+        ..Default::default()
+    };
+    let fnames = config.write_fnames(fname.to_str().unwrap()).unwrap();
+
+    let mut n_records = 0;
+    for fname in &fnames {
+        for raw_record in Records::new(fname).unwrap() {
+            let record = raw_record.resolve().unwrap();
+            let encoded = record.to_raw_record().unwrap().unwrap();
+            assert_eq!(
+                encoded.to_bytes(),
+                raw_record.to_bytes(),
+                "{:?} at offset {} does not roundtrip",
+                raw_record.rtype,
+                raw_record.offset
+            );
+            n_records += 1;
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(n_records > 100);
+}
+
+#[test]
+fn omitted_optional_fields_roundtrip() {
+    let bytes = common::omit_optional_fields(&common::wafer().to_bytes().unwrap());
+    let dir = common::temp_dir("roundtrip-omitted");
+    let fname = dir.join("wafer.stdf").to_string_lossy().into_owned();
+    let copy = dir.join("copy.stdf").to_string_lossy().into_owned();
+    fs::write(&fname, &bytes).unwrap();
+    copy_fname(&fname, &copy).unwrap();
+    assert_eq!(fs::read(&copy).unwrap(), bytes);
+
+    // every record cut off after any of its optional fields, which is every prefix that decodes
+    let mut n_prefixes = 0;
+    for raw_record in common::raw_records(&bytes) {
+        for len in 0..raw_record.contents.len() {
+            let prefix = RawRecord::new(raw_record.rtype, raw_record.contents[..len].to_vec());
+            let Ok(Some(record)) = prefix.try_resolve() else {
+                continue;
+            };
+            let encoded = record.to_raw_record().unwrap().unwrap();
+            assert_eq!(
+                encoded.to_bytes(),
+                prefix.to_bytes(),
+                "{:?} cut off after {len} bytes does not roundtrip",
+                raw_record.rtype
+            );
+            n_prefixes += 1;
+        }
+    }
+    assert!(n_prefixes > 100);
+}
+
+#[test]
+fn ptr_optional_data_roundtrips() {
+    let mut required = Vec::new();
+    required.extend(1000u32.to_le_bytes()); // test_num
+    required.extend([1, 1, 0, 0]); // head_num, site_num, test_flg, parm_flg
+    required.extend(1.5f32.to_le_bytes()); // result
+    required.extend([0, 0]); // empty test_txt and alarm_id
+
+    // an optional block present but all zero, i.e. every limit valid and 0
+    let mut with_zeros = required.clone();
+    with_zeros.extend([0; 12]); // opt_flag, scales, lo_limit and hi_limit
+    with_zeros.extend([0; 4]); // empty units and formats
+    with_zeros.extend([0; 8]); // lo_spec and hi_spec
+
+    for contents in [required, with_zeros] {
+        let raw_record = RawRecord::new(RecordType::PTR, contents);
+        let ptr = PTR::try_from(&raw_record).unwrap();
+        assert_eq!(
+            RawRecord::try_from(&ptr).unwrap().to_bytes(),
+            raw_record.to_bytes()
+        );
+    }
+}
+
+#[test]
+fn ftr_roundtrips() {
+    let mut contents = Vec::new();
+    contents.extend(2000u32.to_le_bytes()); // test_num
+    contents.extend([1, 2, 0b1000_0000, 0]); // head_num, site_num, test_flg, opt_flag
+    contents.extend(7u32.to_le_bytes()); // cycl_cnt
+    contents.extend(8u32.to_le_bytes()); // rel_vadr
+    contents.extend(1u32.to_le_bytes()); // rept_cnt
+    contents.extend(2u32.to_le_bytes()); // num_fail
+    contents.extend((-3i32).to_le_bytes()); // xfail_ad
+    contents.extend(4i32.to_le_bytes()); // yfail_ad
+    contents.extend((-1i16).to_le_bytes()); // vect_off
+    contents.extend(3u16.to_le_bytes()); // rtn_icnt
+    contents.extend(1u16.to_le_bytes()); // pgm_icnt
+    for rtn_indx in [1u16, 2, 3] {
+        contents.extend(rtn_indx.to_le_bytes());
+    }
+    contents.extend([0x21, 0x03]); // rtn_stat, three nibbles
+    contents.extend(5u16.to_le_bytes()); // pgm_indx
+    contents.extend([0x04]); // pgm_stat, one nibble
+    contents.extend(11u16.to_le_bytes()); // fail_pin, 11 bits
+    contents.extend([0b1010_0101, 0b0000_0110]);
+    for text in ["VEC", "TS1", "", "FUNC", "", "", "FAIL"] {
+        contents.push(text.len() as u8);
+        contents.extend(text.as_bytes());
+    }
+    contents.push(3); // patg_num
+    contents.extend(5u16.to_le_bytes()); // spin_map, 5 bits
+    contents.extend([0b0001_0011]);
+
+    let raw_record = RawRecord::new(RecordType::FTR, contents);
+    let ftr = FTR::try_from(&raw_record).unwrap();
+    assert_eq!(ftr.rtn_indx, vec![1, 2, 3]);
+    // the pad nibble of an odd count is decoded as well
+    assert_eq!(ftr.rtn_stat, vec![1, 2, 3, 0]);
+    assert_eq!(ftr.pgm_stat, vec![4, 0]);
+    assert_eq!(
+        (ftr.fail_pin_nbits, ftr.fail_pin.clone()),
+        (11, vec![0b1010_0101, 0b0000_0110])
+    );
+    assert_eq!(
+        (ftr.spin_map_nbits, ftr.spin_map.clone()),
+        (5, vec![0b0001_0011])
+    );
+    assert_eq!(
+        (ftr.test_txt.as_str(), ftr.rslt_txt.as_str()),
+        ("FUNC", "FAIL")
+    );
+    assert_eq!(
+        RawRecord::try_from(&ftr).unwrap().to_bytes(),
+        raw_record.to_bytes()
+    );
+}
+
+/// An `MPR` with `n_pins` pins, every one of them returning a result
+fn mpr_contents(n_pins: u16) -> Vec<u8> {
+    let mut contents = Vec::new();
+    contents.extend(3000u32.to_le_bytes()); // test_num
+    contents.extend([1, 1, 0, 0]); // head_num, site_num, test_flg, parm_flg
+    contents.extend(n_pins.to_le_bytes()); // rtn_icnt
+    contents.extend(n_pins.to_le_bytes()); // rslt_cnt
+    contents.extend((0..n_pins.div_ceil(2)).map(|_| 0x10)); // rtn_stat, nibbles 0 and 1
+    for i in 0..n_pins {
+        contents.extend((0.5 * i as f32).to_le_bytes()); // rtn_rslt
+    }
+    contents.extend([4, b'I', b'D', b'D', b'Q', 0]); // test_txt and alarm_id
+    contents.extend([0b0000_1110, 0, 0, 0]); // opt_flag, res_scal, llm_scal, hlm_scal
+    for limit in [0.0f32, 10.0, 0.1, 0.2] {
+        contents.extend(limit.to_le_bytes()); // lo_limit, hi_limit, start_in, incr_in
+    }
+    for i in 0..n_pins {
+        contents.extend((i + 1).to_le_bytes()); // rtn_indx
+    }
+    contents.extend([2, b'u', b'A', 1, b'V', 0, 0, 0]); // units, units_in and the formats
+    contents.extend([0; 8]); // lo_spec and hi_spec
+    contents
+}
+
+#[test]
+fn mpr_roundtrips() {
+    let raw_record = RawRecord::new(RecordType::MPR, mpr_contents(3));
+    let mpr = MPR::try_from(&raw_record).unwrap();
+    assert_eq!(mpr.rtn_stat, vec![0, 1, 0, 1]);
+    assert_eq!(mpr.rtn_rslt, vec![0.0, 0.5, 1.0]);
+    assert_eq!(mpr.rtn_indx, vec![1, 2, 3]);
+    assert_eq!((mpr.test_txt.as_str(), mpr.units.as_str()), ("IDDQ", "uA"));
+    assert_eq!(
+        RawRecord::try_from(&mpr).unwrap().to_bytes(),
+        raw_record.to_bytes()
+    );
+}
+
+#[test]
+fn oversized_record_is_an_error() {
+    // 20000 pins take 130000 bytes, more than a record can hold
+    assert!(RawRecord::try_new(RecordType::MPR, mpr_contents(20000)).is_err());
+    let raw_record = RawRecord::new(RecordType::MPR, mpr_contents(3));
+    let mut mpr = MPR::try_from(&raw_record).unwrap();
+    mpr.rtn_icnt = 20000;
+    mpr.rslt_cnt = 20000;
+
+    let mut writer = StdfWriter::new(Vec::new()).unwrap();
+    writer.write_record(&Record::MIR(MIR::default())).unwrap();
+    let error = writer.write_record(&Record::MPR(mpr)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    writer.write_record(&Record::MRR(MRR::default())).unwrap();
+    writer.finish().unwrap();
+}
+
+#[test]
+fn unimplemented_records_pass_through() {
+    // a PLR for one group, and a GDR with a Cn, a Dn and a U2 field, e.g. as written by a tester
+    let mut plr = Vec::new();
+    plr.extend(1u16.to_le_bytes()); // grp_cnt
+    plr.extend(32768u16.to_le_bytes()); // grp_indx
+    plr.extend(0u16.to_le_bytes()); // grp_mode
+    plr.extend([0]); // grp_radx
+    plr.extend([1, b'H', 1, b'L', 0, 0, 0, 0]); // pgm_char, rtn_char, pgm_chal, rtn_chal
+    let mut gdr = Vec::new();
+    gdr.extend(3u16.to_le_bytes()); // fld_cnt
+    gdr.extend([10, 3, b'A', b'B', b'C']); // Cn
+    gdr.extend([12, 9, 0, 0xff, 0x01]); // Dn of 9 bits
+    gdr.extend([0, 2, 0x34, 0x12]); // a pad byte, then a U2
+    let raw_records = [
+        RawRecord::new(RecordType::PLR, plr),
+        RawRecord::new(RecordType::GDR, gdr),
+    ];
+
+    let mut writer = StdfWriter::new(Vec::new()).unwrap();
+    writer.write_record(&Record::MIR(MIR::default())).unwrap();
+    for raw_record in &raw_records {
+        // neither is decoded, so they can only be written as they are
+        assert!(raw_record.try_resolve().unwrap().is_none());
+        writer.write_raw_record(raw_record).unwrap();
+    }
+    writer.write_record(&Record::MRR(MRR::default())).unwrap();
+    let bytes = writer.finish().unwrap();
+
+    let mut reader = bytes.as_slice();
+    let written: Vec<Vec<u8>> = std::iter::from_fn(|| {
+        let header = stupidf::records::Header::from_file(&mut reader).ok()?;
+        RawRecord::from_header(header, &mut reader, 0).ok()
+    })
+    .filter(|raw_record| matches!(raw_record.rtype, RecordType::PLR | RecordType::GDR))
+    .map(|raw_record| raw_record.to_bytes())
+    .collect();
+    let expected: Vec<Vec<u8>> = raw_records.iter().map(RawRecord::to_bytes).collect();
+    assert_eq!(written, expected);
+}
@@ -0,0 +1,144 @@
+//! Spatial outlier screening with GDBN and NNR
+mod common;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::STDF,
+    spatial::{Gdbn, Nnr, SpatialScreen, parse_weights},
+    test_information::TestKey,
+};
+
+/// A wafer of 5 by 5 dies with results of test 100 between 1.0 and 1.04
+///
+/// The diagonal neighbors of the center die (2, 2) fail with a result of 20, and the die at
+/// (4, 0) passes with the outlying result 5. The test limits are 0 and 10.
+fn wafer(name: &str) -> STDF {
+    let mut builder = StdfBuilder::new()
+        .sites(&[1])
+        .parametric_test(100, "VDD", "V", 0.0, 10.0)
+        .wafer("W01");
+    for y in 0..5i16 {
+        for x in 0..5i16 {
+            let result = match (x, y) {
+                (1 | 3, 1 | 3) => 20.,
+                (4, 0) => 5.,
+                _ => 1. + 0.01 * ((7 * x + 3 * y) % 5) as f32,
+            };
+            builder = builder.part(Part::new(1).at(x, y).result(100, result));
+        }
+    }
+    let fname = common::temp_dir(&format!("spatial-{name}")).join("wafer.stdf");
+    let fname = fname.to_str().unwrap();
+    builder.write_fname(fname).unwrap();
+    STDF::from_fname(fname, false).unwrap()
+}
+
+/// The (`method`, `x_coord`, `y_coord`) of each flagged die, sorted
+fn flagged(stdf: &STDF, screen: &SpatialScreen) -> Vec<(&'static str, i16, i16)> {
+    let mut flagged: Vec<_> = screen
+        .run(stdf)
+        .flags
+        .iter()
+        .map(|flag| (flag.method, flag.x_coord, flag.y_coord))
+        .collect();
+    flagged.sort();
+    flagged
+}
+
+#[test]
+fn gdbn_flags_good_dies_among_failures() {
+    let stdf = wafer("gdbn");
+    let screen = SpatialScreen {
+        gdbn: Some(Gdbn::default()),
+        ..Default::default()
+    };
+    // four of the eight neighbors of the center fail, every other die has at most two in five
+    let report = screen.run(&stdf);
+    assert_eq!(report.flags.len(), 1);
+    assert_eq!((report.flags[0].x_coord, report.flags[0].y_coord), (2, 2));
+    assert_eq!(report.flags[0].score, 0.5);
+    assert_eq!(report.flags[0].test_key, None);
+
+    // weighing only the four direct neighbors, none of the center's fail, but two of each of
+    // its own direct neighbors do
+    let screen = SpatialScreen {
+        gdbn: Some(Gdbn {
+            weights: parse_weights("0,1,0;1,0,1;0,1,0").unwrap(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    assert_eq!(
+        flagged(&stdf, &screen),
+        vec![
+            ("GDBN", 1, 2),
+            ("GDBN", 2, 1),
+            ("GDBN", 2, 3),
+            ("GDBN", 3, 2)
+        ]
+    );
+
+    // too few neighbors to be scored
+    let screen = SpatialScreen {
+        gdbn: Some(Gdbn {
+            min_neighbors: 9,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    assert!(flagged(&stdf, &screen).is_empty());
+}
+
+#[test]
+fn nnr_flags_results_unlike_their_neighbors() {
+    let stdf = wafer("nnr");
+    let screen = SpatialScreen {
+        nnr: Some(Nnr::default()),
+        ..Default::default()
+    };
+    let report = screen.run(&stdf);
+    assert_eq!(report.flags.len(), 1);
+    let flag = &report.flags[0];
+    assert_eq!((flag.method, flag.x_coord, flag.y_coord), ("NNR", 4, 0));
+    assert_eq!(flag.test_key, Some(TestKey::Num(100)));
+    assert!(flag.score > 6.);
+
+    let screen = SpatialScreen {
+        nnr: Some(Nnr {
+            test_nums: Some(vec![200]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    assert!(flagged(&stdf, &screen).is_empty());
+}
+
+#[test]
+fn flagged_dies_are_inked() {
+    let mut stdf = wafer("ink");
+    let screen = SpatialScreen {
+        gdbn: Some(Gdbn::default()),
+        nnr: Some(Nnr::default()),
+        ink_sbin: Some(80),
+        ink_hbin: Some(8),
+    };
+    assert_eq!(flagged(&stdf, &screen), vec![("GDBN", 2, 2), ("NNR", 4, 0)]);
+    let report = screen.run(&stdf);
+    assert_eq!(screen.ink(&mut stdf.test_data, &report), 2);
+    for flag in &report.flags {
+        let row = &stdf.test_data.data[flag.part_index];
+        assert!(!row.pass());
+        assert_eq!((row.sbin, row.hbin), (80, 8));
+    }
+}
+
+#[test]
+fn weights_are_parsed() {
+    assert_eq!(
+        parse_weights("1,2,1; 2,0,2; 1,2,1"),
+        Ok(vec![vec![1., 2., 1.], vec![2., 0., 2.], vec![1., 2., 1.]])
+    );
+    assert!(parse_weights("1,1;1,1").is_err());
+    assert!(parse_weights("1,1,1;1,0").is_err());
+    assert!(parse_weights("1,x,1;1,0,1;1,1,1").is_err());
+}
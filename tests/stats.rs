@@ -0,0 +1,116 @@
+//! Per-test statistics and process capability
+mod common;
+
+use polars::prelude::*;
+use stupidf::{
+    data::TestData,
+    stats::{
+        Estimator, GroupBy, MAD_TO_SIGMA, TestStatistics, check_percentiles, cp, cpk, iqr_sigma,
+        mean, median, percentile, robust_sigma, std_dev,
+    },
+};
+
+fn assert_close(value: Option<f64>, expected: f64) {
+    let value = value.unwrap_or_else(|| panic!("expected {expected}, got None"));
+    assert!(
+        (value - expected).abs() < 1e-4,
+        "expected {expected}, got {value}"
+    );
+}
+
+#[test]
+fn estimators_of_known_values() {
+    let values = [1., 2., 3., 4., 5.];
+    assert_close(mean(&values), 3.);
+    assert_close(std_dev(&values), 2.5f64.sqrt());
+    assert_close(median(&values), 3.);
+    // the absolute deviations are 0, 1, 1, 2, 2
+    assert_close(robust_sigma(&values), MAD_TO_SIGMA);
+    assert_close(iqr_sigma(&values), 2. / 1.35);
+    assert_close(percentile(&values, 25.), 2.);
+    assert_close(percentile(&values, 90.), 4.6);
+    assert_close(percentile(&values, 150.), 5.);
+
+    // an outlier pulls the classic estimators but not the robust ones
+    let outlier = [1., 2., 3., 4., 100.];
+    assert_close(mean(&outlier), 22.);
+    assert_close(median(&outlier), 3.);
+    assert_close(robust_sigma(&outlier), MAD_TO_SIGMA);
+
+    assert_eq!(mean(&[]), None);
+    assert_eq!(median(&[]), None);
+    assert_eq!(std_dev(&[1.]), None);
+    assert_eq!(robust_sigma(&[1.]), None);
+}
+
+#[test]
+fn capability_of_known_values() {
+    assert_close(cp(Some(1.), Some(0.), Some(6.)), 1.);
+    assert_close(cpk(Some(2.), Some(1.), Some(0.), Some(6.)), 2. / 3.);
+    // with one limit, that limit is used
+    assert_close(cpk(Some(2.), Some(1.), None, Some(6.)), 4. / 3.);
+    assert_close(cpk(Some(2.), Some(1.), Some(0.), None), 2. / 3.);
+
+    assert_eq!(cp(Some(1.), None, Some(6.)), None);
+    assert_eq!(cp(Some(0.), Some(0.), Some(6.)), None);
+    assert_eq!(cpk(Some(2.), Some(1.), None, None), None);
+    assert_eq!(cpk(None, Some(1.), Some(0.), Some(6.)), None);
+}
+
+#[test]
+fn percentiles_are_checked() {
+    assert_eq!(check_percentiles(&[5., 95., 5.]), Ok(vec![5., 95.]));
+    assert!(check_percentiles(&[101.]).is_err());
+}
+
+fn column(df: &DataFrame, name: &str) -> Vec<Option<f64>> {
+    df.column(name)
+        .unwrap()
+        .cast(&DataType::Float64)
+        .unwrap()
+        .f64()
+        .unwrap()
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn statistics_of_a_wafer() {
+    let fname = common::temp_dir("statistics").join("wafer.stdf");
+    let fname = fname.to_str().unwrap();
+    common::wafer().write_fname(fname).unwrap();
+    let test_data = TestData::from_fname(fname, false).unwrap();
+
+    // test 100 has the results 1.0, 1.05, 1.3 and 0.95 within the limits 0.9 and 1.1
+    let classic = TestStatistics::default().to_df(&test_data);
+    assert_eq!(classic.height(), 1);
+    assert_eq!(column(&classic, "n"), vec![Some(4.)]);
+    assert_eq!(column(&classic, "fail_cnt"), vec![Some(1.)]);
+    let sigma = (0.0725f64 / 3.).sqrt();
+    assert_close(column(&classic, "mean")[0], 1.075);
+    assert_close(column(&classic, "std")[0], sigma);
+    assert_close(column(&classic, "p50")[0], 1.025);
+    assert_close(column(&classic, "cp")[0], 0.2 / (6. * sigma));
+    assert_close(column(&classic, "cpk")[0], 0.025 / (3. * sigma));
+    assert_eq!(column(&classic, "cp_spec"), vec![None]);
+
+    // the absolute deviations from the median 1.025 have the median 0.05
+    let robust = TestStatistics {
+        estimator: Estimator::Robust,
+        ..Default::default()
+    }
+    .to_df(&test_data);
+    let sigma = 0.05 * MAD_TO_SIGMA;
+    assert_close(column(&robust, "robust_sigma")[0], sigma);
+    assert_close(column(&robust, "cp")[0], 0.2 / (6. * sigma));
+    assert_close(column(&robust, "cpk")[0], 0.075 / (3. * sigma));
+
+    let by_site = TestStatistics {
+        group_by: GroupBy::Site,
+        ..Default::default()
+    }
+    .to_df(&test_data);
+    assert_eq!(column(&by_site, "site_num"), vec![Some(1.), Some(2.)]);
+    assert_eq!(column(&by_site, "fail_cnt"), vec![Some(1.), Some(0.)]);
+    assert_close(column(&by_site, "mean")[1], 1.0);
+}
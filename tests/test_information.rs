@@ -0,0 +1,159 @@
+//! Identification and naming of the tests in STDF files
+mod common;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::TestData,
+    records::{RawRecord, records::Record},
+    test_information::{ColumnNaming, TestIdentity, TestKey, TestType},
+};
+
+/// Write `raw_records` to a file in a fresh temporary directory for the test `name`
+fn write_file(name: &str, raw_records: &[RawRecord]) -> String {
+    let fname = common::temp_dir(name).join("test.stdf");
+    std::fs::write(&fname, common::to_bytes(raw_records)).unwrap();
+    fname.to_string_lossy().into_owned()
+}
+
+#[test]
+fn identities_key_tests() {
+    let key = |identity: TestIdentity| identity.key(100, "VDD", TestType::P);
+    assert_eq!(key(TestIdentity::TestNum), TestKey::Num(100));
+    assert_eq!(
+        key(TestIdentity::TestNumAndText),
+        TestKey::NumText(100, "VDD".to_string())
+    );
+    assert_eq!(
+        key(TestIdentity::TestNumAndType),
+        TestKey::NumType(100, TestType::P)
+    );
+    for identity in [
+        TestIdentity::TestNum,
+        TestIdentity::TestNumAndText,
+        TestIdentity::TestNumAndType,
+    ] {
+        assert_eq!(identity.to_string().parse::<TestIdentity>(), Ok(identity));
+    }
+    assert!("test_txt".parse::<TestIdentity>().is_err());
+}
+
+#[test]
+fn unnamed_results_do_not_rename_the_test() {
+    // test 100 is named VDD by its TSR and first PTR, the later PTRs leave the test_txt empty
+    let bytes = StdfBuilder::new()
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .part(Part::new(1).result(100, 1.0))
+        .part(Part::new(1).result(100, 1.05))
+        .part(Part::new(1).result(100, 0.95))
+        .to_bytes()
+        .unwrap();
+    let mut n_ptrs = 0;
+    let raw_records: Vec<RawRecord> = common::raw_records(&bytes)
+        .into_iter()
+        .map(|raw_record| match raw_record.resolve() {
+            Some(Record::PTR(mut ptr)) => {
+                n_ptrs += 1;
+                if n_ptrs > 1 {
+                    ptr.test_txt.clear();
+                }
+                RawRecord::try_from(&ptr).unwrap()
+            }
+            _ => raw_record,
+        })
+        .collect();
+    assert_eq!(n_ptrs, 3);
+
+    let fname = write_file("unnamed-results", &raw_records);
+    let test_data =
+        TestData::from_fname_with_identity(&fname, false, TestIdentity::TestNumAndText).unwrap();
+    let df = test_data.to_df(&ColumnNaming::Number);
+    let test_columns: Vec<&str> = df
+        .get_column_names_str()
+        .into_iter()
+        .filter(|name| name.starts_with("100"))
+        .collect();
+    assert_eq!(test_columns, vec!["100"]);
+    // the unnamed results fall back to the only parametric test 100
+    let results: Vec<f32> = df
+        .column("100")
+        .unwrap()
+        .f32()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(results, vec![1.0, 1.05, 0.95]);
+    assert!(test_data.collisions().is_empty());
+}
+
+/// Test 100 is shared by the parametric VDD and IDD, given 1.0 and 2.0 by the first part and 1.05
+/// and 3.0 by the second
+fn shared_test_num() -> Vec<RawRecord> {
+    let bytes = StdfBuilder::new()
+        .parametric_test(100, "VDD", "V", 0.9, 1.1)
+        .parametric_test(101, "IDD", "mA", 1.0, 5.0)
+        .part(Part::new(1).result(100, 1.0).result(101, 2.0))
+        .part(Part::new(1).result(100, 1.05).result(101, 3.0))
+        .to_bytes()
+        .unwrap();
+    common::raw_records(&bytes)
+        .into_iter()
+        .map(|raw_record| match raw_record.resolve() {
+            Some(Record::PTR(mut ptr)) if ptr.test_num == 101 => {
+                ptr.test_num = 100;
+                RawRecord::try_from(&ptr).unwrap()
+            }
+            Some(Record::TSR(mut tsr)) if tsr.test_num == 101 => {
+                tsr.test_num = 100;
+                RawRecord::try_from(&tsr).unwrap()
+            }
+            _ => raw_record,
+        })
+        .collect()
+}
+
+#[test]
+fn collisions_by_identity() {
+    let fname = write_file("collisions", &shared_test_num());
+    let tests = vec![
+        ("IDD".to_string(), TestType::P),
+        ("VDD".to_string(), TestType::P),
+    ];
+    for (identity, separated, columns) in [
+        (TestIdentity::TestNum, false, vec!["100"]),
+        (
+            TestIdentity::TestNumAndText,
+            true,
+            vec!["100:IDD", "100:VDD"],
+        ),
+        (TestIdentity::TestNumAndType, false, vec!["100"]),
+    ] {
+        let test_data = TestData::from_fname_with_identity(&fname, false, identity).unwrap();
+        let collisions = test_data.collisions();
+        assert_eq!(collisions.len(), 1, "{identity}");
+        assert_eq!(collisions[0].test_num, 100);
+        assert_eq!(collisions[0].tests, tests);
+        assert_eq!(collisions[0].separated, separated, "{identity}");
+
+        let df = test_data.to_df(&ColumnNaming::Number);
+        let test_columns: Vec<&str> = df
+            .get_column_names_str()
+            .into_iter()
+            .filter(|name| name.starts_with("100"))
+            .collect();
+        assert_eq!(test_columns, columns, "{identity}");
+    }
+
+    let test_data =
+        TestData::from_fname_with_identity(&fname, false, TestIdentity::TestNumAndText).unwrap();
+    let df = test_data.to_df(&ColumnNaming::Number);
+    let results = |name: &str| -> Vec<f32> {
+        df.column(name)
+            .unwrap()
+            .f32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    };
+    assert_eq!(results("100:VDD"), vec![1.0, 1.05]);
+    assert_eq!(results("100:IDD"), vec![2.0, 3.0]);
+}
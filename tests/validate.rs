@@ -0,0 +1,144 @@
+//! Validation of STDF files against the V4 specification
+mod common;
+
+use stupidf::{
+    record_types::RecordType,
+    records::{RawRecord, records::Record},
+    validate::validate,
+};
+
+#[test]
+fn built_file_is_valid() {
+    let bytes = common::wafer().to_bytes().unwrap();
+    let report = validate(common::raw_records(&bytes).into_iter());
+    assert!(report.issues.is_empty(), "{report}");
+}
+
+#[test]
+fn omitted_optional_fields_are_valid() {
+    let bytes = common::omit_optional_fields(&common::wafer().to_bytes().unwrap());
+    let raw_records = common::raw_records(&bytes);
+    let prr = raw_records
+        .iter()
+        .find(|raw_record| raw_record.rtype == RecordType::PRR)
+        .unwrap();
+    let Some(Record::PRR(prr)) = prr.try_resolve().unwrap() else {
+        panic!("not a PRR");
+    };
+    assert!(prr.part_txt.is_empty() && prr.part_fix.is_empty());
+
+    // the PRRs are still counted, so the summaries match
+    let report = validate(raw_records.into_iter());
+    assert!(report.issues.is_empty(), "{report}");
+    assert!(report.is_valid());
+}
+
+#[test]
+fn truncated_required_field_is_an_error() {
+    let bytes = common::wafer().to_bytes().unwrap();
+    let raw_records: Vec<RawRecord> = common::raw_records(&bytes)
+        .into_iter()
+        .map(|raw_record| match raw_record.rtype {
+            // cut into the hard_bin
+            RecordType::PRR => RawRecord::new(RecordType::PRR, raw_record.contents[..6].to_vec()),
+            _ => raw_record,
+        })
+        .collect();
+    let report = validate(raw_records.into_iter());
+    assert!(!report.is_valid());
+    assert!(
+        report
+            .errors()
+            .any(|issue| issue.rtype == Some(RecordType::PRR)
+                && issue.message.starts_with("cannot decode"))
+    );
+}
+
+/// Validate the built wafer after `edit` changes its records, returning the messages of the
+/// issues of each severity
+fn issues_after(edit: impl FnOnce(&mut Vec<RawRecord>)) -> (Vec<String>, Vec<String>) {
+    let bytes = common::wafer().to_bytes().unwrap();
+    let mut raw_records = common::raw_records(&bytes);
+    edit(&mut raw_records);
+    let report = validate(raw_records.into_iter());
+    let messages = |issues: Vec<&stupidf::validate::Issue>| -> Vec<String> {
+        issues.iter().map(|issue| issue.message.clone()).collect()
+    };
+    (
+        messages(report.errors().collect()),
+        messages(report.warnings().collect()),
+    )
+}
+
+fn position(raw_records: &[RawRecord], rtype: RecordType) -> usize {
+    raw_records
+        .iter()
+        .position(|raw_record| raw_record.rtype == rtype)
+        .unwrap()
+}
+
+#[test]
+fn record_order_is_checked() {
+    let (errors, _) = issues_after(|raw_records| {
+        let contents = raw_records[position(raw_records, RecordType::MIR)]
+            .contents
+            .clone();
+        raw_records.push(RawRecord::new(RecordType::MIR, contents));
+    });
+    assert!(errors.contains(&"only one MIR is allowed".to_string()));
+    assert!(errors.contains(&"no record may follow the MRR".to_string()));
+
+    let (errors, _) = issues_after(|raw_records| {
+        raw_records.swap(0, 1);
+    });
+    assert!(errors.contains(&"the first record must be the FAR, not MIR".to_string()));
+}
+
+#[test]
+fn unpaired_part_records_are_errors() {
+    let (errors, _) = issues_after(|raw_records| {
+        raw_records.remove(position(raw_records, RecordType::PIR));
+    });
+    assert!(
+        errors
+            .iter()
+            .any(|message| message.starts_with("PRR without a PIR")),
+        "{errors:?}"
+    );
+
+    let (errors, _) = issues_after(|raw_records| {
+        raw_records.remove(position(raw_records, RecordType::PRR));
+    });
+    assert!(
+        errors.iter().any(|message| message.ends_with("has no PRR")),
+        "{errors:?}"
+    );
+}
+
+#[test]
+fn summary_counts_are_checked() {
+    let (errors, warnings) = issues_after(|raw_records| {
+        for raw_record in raw_records.iter_mut() {
+            match raw_record.resolve() {
+                Some(Record::PCR(mut pcr)) if pcr.head_num == 255 => {
+                    pcr.part_cnt += 1;
+                    *raw_record = RawRecord::try_from(&pcr).unwrap();
+                }
+                Some(Record::PRR(mut prr)) if prr.part_id == "1" => {
+                    prr.num_test += 1;
+                    *raw_record = RawRecord::try_from(&prr).unwrap();
+                }
+                _ => {}
+            }
+        }
+    });
+    assert_eq!(
+        errors,
+        vec!["PCR part_cnt 5 does not match the 4 counted from the PRRs".to_string()]
+    );
+    // a num_test that does not match is only a warning
+    assert_eq!(
+        warnings,
+        vec!["num_test 3 does not match the 2 test records of the part".to_string()]
+    );
+}
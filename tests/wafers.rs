@@ -0,0 +1,125 @@
+//! Wafers on several heads, open at the same time
+mod common;
+
+use std::fs;
+
+use stupidf::{
+    builder::{Part, StdfBuilder},
+    data::STDF,
+    record_types::RecordType,
+    records::RawRecord,
+};
+
+/// The records of a file with the wafers of a single head
+struct HeadFile {
+    /// The `FAR`, `MIR` and `SDR`
+    header: Vec<RawRecord>,
+    /// The `WIR`, the parts from `PIR` to `PRR` and the `WRR` of each wafer
+    wafers: Vec<(RawRecord, Vec<Vec<RawRecord>>, RawRecord)>,
+    /// The summary records and the `MRR`
+    summary: Vec<RawRecord>,
+}
+
+impl HeadFile {
+    /// Build the file of the wafers `wafer_ids` on `head_num`, with two parts on each
+    fn new(head_num: u8, wafer_ids: &[&str]) -> Self {
+        let mut builder = StdfBuilder::new()
+            .head_num(head_num)
+            .parametric_test(100, "VDD", "V", 0.9, 1.1);
+        for wafer_id in wafer_ids {
+            builder = builder.wafer(wafer_id);
+            for x in 0..2 {
+                let part_id = format!("{wafer_id}-{x}");
+                builder = builder.part(Part::new(1).id(&part_id).at(x, 0).result(100, 1.0));
+            }
+        }
+        let mut file = Self {
+            header: Vec::new(),
+            wafers: Vec::new(),
+            summary: Vec::new(),
+        };
+        let mut wir = None;
+        let mut parts = Vec::new();
+        let mut part = Vec::new();
+        for raw_record in common::raw_records(&builder.to_bytes().unwrap()) {
+            match raw_record.rtype {
+                RecordType::FAR | RecordType::MIR | RecordType::SDR => file.header.push(raw_record),
+                RecordType::WIR => wir = Some(raw_record),
+                RecordType::PRR => {
+                    part.push(raw_record);
+                    parts.push(std::mem::take(&mut part));
+                }
+                RecordType::WRR => {
+                    let parts = std::mem::take(&mut parts);
+                    file.wafers.push((wir.take().unwrap(), parts, raw_record));
+                }
+                RecordType::PIR | RecordType::PTR => part.push(raw_record),
+                _ => file.summary.push(raw_record),
+            }
+        }
+        file
+    }
+}
+
+#[test]
+fn wafers_are_paired_by_head() {
+    let mut head_1 = HeadFile::new(1, &["A1", "A2"]);
+    let mut head_2 = HeadFile::new(2, &["B1"]);
+    let (b1_wir, mut b1_parts, b1_wrr) = head_2.wafers.remove(0);
+    let (a2_wir, mut a2_parts, a2_wrr) = head_1.wafers.remove(1);
+    let (a1_wir, mut a1_parts, a1_wrr) = head_1.wafers.remove(0);
+
+    // B1 opens while A1 is open, and A2 opens before B1 closes
+    let mut raw_records = head_1.header;
+    raw_records.extend(head_2.header.into_iter().skip(2));
+    raw_records.push(a1_wir);
+    raw_records.push(b1_wir);
+    raw_records.extend(a1_parts.remove(0));
+    raw_records.extend(b1_parts.remove(0));
+    raw_records.extend(a1_parts.remove(0));
+    raw_records.push(a1_wrr);
+    raw_records.push(a2_wir);
+    raw_records.extend(b1_parts.remove(0));
+    raw_records.extend(a2_parts.remove(0));
+    raw_records.push(b1_wrr);
+    raw_records.extend(a2_parts.remove(0));
+    raw_records.push(a2_wrr);
+    raw_records.extend(head_1.summary);
+
+    let fname = common::temp_dir("wafers-heads").join("lot.stdf");
+    fs::write(&fname, common::to_bytes(&raw_records)).unwrap();
+    let stdf = STDF::from_fname(fname.to_str().unwrap(), false).unwrap();
+
+    let parts: Vec<(&str, &str, Option<u32>)> = stdf
+        .test_data
+        .data
+        .iter()
+        .map(|row| (row.part_id.as_str(), row.wafer_id.as_str(), row.wafer_index))
+        .collect();
+    assert_eq!(
+        parts,
+        vec![
+            ("A1-0", "A1", Some(0)),
+            ("B1-0", "B1", Some(1)),
+            ("A1-1", "A1", Some(0)),
+            ("B1-1", "B1", Some(1)),
+            ("A2-0", "A2", Some(2)),
+            ("A2-1", "A2", Some(2)),
+        ]
+    );
+
+    // every WIR is paired with the WRR of its own head
+    let mut wafers: Vec<(u32, u8, &str, u32)> = stdf
+        .wafer_information
+        .iter()
+        .map(|wafer| {
+            let wafer_id = wafer.wafer_id.as_str();
+            (wafer.wafer_index, wafer.head_num, wafer_id, wafer.part_cnt)
+        })
+        .collect();
+    wafers.sort();
+    assert_eq!(
+        wafers,
+        vec![(0, 1, "A1", 2), (1, 2, "B1", 2), (2, 1, "A2", 2)]
+    );
+}
@@ -0,0 +1,61 @@
+//! Yield and parametric statistics per zone and reticle position
+mod common;
+
+use stupidf::{
+    data::STDF,
+    zones::{Reticle, ZoneGroupBy, Zoning},
+};
+
+#[test]
+fn statistics_per_reticle_position() {
+    let fname = common::temp_dir("zone-statistics").join("wafer.stdf");
+    let fname = fname.to_str().unwrap();
+    common::wafer().write_fname(fname).unwrap();
+    let stdf = STDF::from_fname(fname, false).unwrap();
+    // shots of two dies in x, so the dies at x_coord 0 and 1 are in different positions
+    let zoning = Zoning {
+        reticle: Reticle {
+            step_x: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let df = zoning.statistics_to_df(&stdf, ZoneGroupBy::Reticle);
+    for name in ["reticle_x", "reticle_y", "n_missing", "cpk"] {
+        assert!(df.column(name).is_ok(), "no column {name}");
+    }
+    assert!(df.column("zone").is_err());
+    let reticle_xs: Vec<i16> = df
+        .column("reticle_x")
+        .unwrap()
+        .i16()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(reticle_xs, vec![0, 1]);
+    let ns: Vec<u32> = df
+        .column("n")
+        .unwrap()
+        .u32()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(ns, vec![2, 2]);
+    let fail_cnts: Vec<u32> = df
+        .column("fail_cnt")
+        .unwrap()
+        .u32()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert_eq!(fail_cnts, vec![1, 0]);
+    let means: Vec<f64> = df
+        .column("mean")
+        .unwrap()
+        .f64()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    assert!((means[0] - 1.15).abs() < 1e-6 && (means[1] - 1.0).abs() < 1e-6);
+}